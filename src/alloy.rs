@@ -0,0 +1,242 @@
+//! alloy's [`Signer`](alloy_signer::Signer) and [`TxSigner`], for services
+//! migrating from ethers-rs, e.g. through an
+//! [`EthereumWallet`](alloy_network::EthereumWallet). Signing goes through
+//! the same policies, scopes, replay protection and audit sinks as the
+//! ethers [`Signer`], and alloy's signatures carry the y-parity of
+//! whichever `v` the ethers one uses.
+
+use alloy_consensus::SignableTransaction;
+use alloy_dyn_abi::eip712::TypedData;
+use alloy_network::TxSigner;
+use alloy_primitives::{Address, ChainId, Signature, B256, U256};
+use alloy_signer::{Error, Result};
+use alloy_sol_types::{Eip712Domain, SolStruct};
+use async_trait::async_trait;
+use ethers::{signers::Signer, types::H256};
+
+use crate::{
+    audit::AuditOperation, scope::ScopeRequest, typed_data_digest, GcpKmsSigner,
+    RecoverableSignature, TxType,
+};
+
+impl From<RecoverableSignature> for Signature {
+    fn from(signature: RecoverableSignature) -> Self {
+        let (r, s, y_parity) = signature.into_parts();
+        Signature::new(U256::from_be_bytes(r), U256::from_be_bytes(s), y_parity)
+    }
+}
+
+fn to_alloy(signature: ethers::types::Signature) -> Result<Signature> {
+    RecoverableSignature::from_ethers(&signature)
+        .map(Signature::from)
+        .map_err(Error::other)
+}
+
+fn tx_type(ty: u8) -> TxType {
+    match ty {
+        0x00 => TxType::Legacy,
+        0x01 => TxType::Eip2930,
+        0x02 => TxType::Eip1559,
+        0x03 => TxType::Eip4844,
+        0x04 => TxType::Eip7702,
+        _ => TxType::Other,
+    }
+}
+
+/// Gives a legacy transaction without a chain id the signer's, as the
+/// ethers [`Signer::sign_transaction`] does, unless legacy replay protection
+/// is disabled. alloy's typed transactions always have a chain id.
+fn with_default_chain_id(
+    tx: &mut dyn SignableTransaction<Signature>,
+    chain_id: ChainId,
+    replay_protection: bool,
+) {
+    if tx.chain_id().is_none() && replay_protection {
+        tx.set_chain_id(chain_id);
+    }
+}
+
+#[async_trait]
+impl alloy_signer::Signer for GcpKmsSigner {
+    async fn sign_hash(&self, hash: &B256) -> Result<Signature> {
+        to_alloy(
+            GcpKmsSigner::sign_hash(self, H256(hash.0))
+                .await
+                .map_err(Error::other)?,
+        )
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        to_alloy(
+            Signer::sign_message(self, message)
+                .await
+                .map_err(Error::other)?,
+        )
+    }
+
+    /// Signs `payload` as the ethers [`Signer::sign_typed_data`] does, with
+    /// replay protection and scopes bound to `domain`'s chain id
+    async fn sign_typed_data<T: SolStruct + Send + Sync>(
+        &self,
+        payload: &T,
+        domain: &Eip712Domain,
+    ) -> Result<Signature> {
+        let domain_separator = H256(domain.separator().0);
+        let struct_hash = H256(payload.eip712_hash_struct().0);
+        let request = ScopeRequest {
+            operation: AuditOperation::TypedData,
+            chain_id: domain
+                .chain_id
+                .and_then(|chain_id| u64::try_from(chain_id).ok()),
+            domain_separator: Some(domain_separator),
+        };
+        let signature = self
+            .sign_typed_digest(
+                &self.snapshot(),
+                typed_data_digest(domain_separator, struct_hash),
+                domain_separator,
+                struct_hash,
+                request,
+            )
+            .await
+            .map_err(Error::other)?;
+        to_alloy(signature)
+    }
+
+    /// Signs `payload` as [`GcpKmsSigner::sign_typed_data_json`] does
+    async fn sign_dynamic_typed_data(&self, payload: &TypedData) -> Result<Signature> {
+        let payload = serde_json::to_value(payload).map_err(Error::other)?;
+        to_alloy(
+            self.sign_typed_data_json(&payload)
+                .await
+                .map_err(Error::other)?,
+        )
+    }
+
+    /// Panics for a lazy signer which has not been resolved; see
+    /// [`GcpKmsSigner::resolve_address`]
+    fn address(&self) -> Address {
+        Address::from(Signer::address(self).0)
+    }
+
+    fn chain_id(&self) -> Option<ChainId> {
+        Some(Signer::chain_id(self))
+    }
+
+    /// Sets the chain id of this signer, but not of other clones. `None`
+    /// leaves it unchanged, as this signer always has one.
+    fn set_chain_id(&mut self, chain_id: Option<ChainId>) {
+        if let Some(chain_id) = chain_id {
+            *self = Signer::with_chain_id(self.clone(), chain_id);
+        }
+    }
+}
+
+#[async_trait]
+impl TxSigner<Signature> for GcpKmsSigner {
+    fn address(&self) -> Address {
+        alloy_signer::Signer::address(self)
+    }
+
+    /// Signs any of alloy's Ethereum envelopes, including EIP-7702 ones,
+    /// subject to the signer's transaction policies. Unlike alloy's local
+    /// signers, a transaction for another chain is only refused with
+    /// [`GcpKmsSigner::with_strict_chain_id`].
+    async fn sign_transaction(
+        &self,
+        tx: &mut dyn SignableTransaction<Signature>,
+    ) -> Result<Signature> {
+        let snapshot = self.snapshot();
+        with_default_chain_id(tx, snapshot.chain_id, snapshot.replay_protection);
+        let signature = self
+            .sign_envelope_sighash(
+                &snapshot,
+                H256(tx.signature_hash().0),
+                tx_type(tx.ty()),
+                tx.chain_id(),
+                Ok(()),
+            )
+            .await
+            .map_err(Error::other)?;
+        to_alloy(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_consensus::Typed2718;
+    use ethers::types::{transaction::eip712::Eip712, Signature as EthersSignature};
+
+    #[test]
+    fn converts_signatures_by_y_parity() {
+        let r = [0x11; 32];
+        let s = [0x22; 32];
+        for (v, y_parity) in [(0, false), (28, true), (37, false), (38, true)] {
+            let signature = to_alloy(EthersSignature {
+                r: r.into(),
+                s: s.into(),
+                v,
+            })
+            .unwrap();
+            assert_eq!(signature.r(), U256::from_be_bytes(r), "v = {v}");
+            assert_eq!(signature.s(), U256::from_be_bytes(s), "v = {v}");
+            assert_eq!(signature.v(), y_parity, "v = {v}");
+        }
+        assert!(to_alloy(EthersSignature {
+            r: r.into(),
+            s: s.into(),
+            v: 2,
+        })
+        .is_err());
+    }
+
+    // only needs to compile
+    #[allow(dead_code)]
+    fn fits_alloys_wallet(signer: GcpKmsSigner) -> alloy_network::EthereumWallet {
+        alloy_network::EthereumWallet::from(signer)
+    }
+
+    #[test]
+    fn legacy_transactions_get_the_signers_chain_id() {
+        let mut tx = alloy_consensus::TxLegacy::default();
+        with_default_chain_id(&mut tx, 5, false);
+        assert_eq!(tx.chain_id, None);
+        with_default_chain_id(&mut tx, 5, true);
+        assert_eq!(tx.chain_id, Some(5));
+        with_default_chain_id(&mut tx, 1, true);
+        assert_eq!(tx.chain_id, Some(5));
+
+        let tx = alloy_consensus::TxEip7702 {
+            chain_id: 1,
+            ..Default::default()
+        };
+        assert_eq!(tx_type(tx.ty()), TxType::Eip7702);
+    }
+
+    #[test]
+    fn dynamic_typed_data_keeps_its_digest_as_json() {
+        let payload: TypedData = serde_json::from_value(serde_json::json!({
+            "types": {
+                "EIP712Domain": [
+                    {"name": "name", "type": "string"},
+                    {"name": "chainId", "type": "uint256"}
+                ],
+                "Vote": [
+                    {"name": "proposal", "type": "uint256"},
+                    {"name": "support", "type": "bool"}
+                ]
+            },
+            "primaryType": "Vote",
+            "domain": {"name": "Governor", "chainId": 1},
+            "message": {"proposal": 7, "support": true}
+        }))
+        .unwrap();
+        let json = serde_json::to_value(&payload).unwrap();
+        let digest = crate::typed_data::parse_typed_data(&json)
+            .unwrap()
+            .encode_eip712()
+            .unwrap();
+        assert_eq!(digest, payload.eip712_signing_hash().unwrap().0);
+    }
+}
@@ -0,0 +1,138 @@
+use std::{fmt, sync::Arc};
+
+use ethers::types::{Address, H256};
+use serde::Serialize;
+use tracing::info;
+
+#[cfg(feature = "bigquery")]
+mod bigquery;
+#[cfg(feature = "bigquery")]
+pub use bigquery::{BigQueryAuditSink, BigQueryTable};
+
+#[cfg(feature = "monitoring")]
+mod monitoring;
+#[cfg(feature = "monitoring")]
+pub use monitoring::{CloudMonitoringSink, SIGN_REQUESTS_METRIC};
+
+/// The kind of signing operation an [`AuditEvent`] describes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum AuditOperation {
+    Digest,
+    Message,
+    Transaction,
+    TypedData,
+    /// The attestation over a [`SigningReceipt`](crate::SigningReceipt)
+    Receipt,
+}
+
+/// How a signing operation ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum AuditOutcome {
+    Signed,
+    /// Refused by a local policy before KMS was called
+    Denied,
+    Failed,
+}
+
+/// A structured record of one signing operation, delivered to every
+/// [`AuditSink`] configured on the signer
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct AuditEvent {
+    /// Milliseconds since the unix epoch
+    pub timestamp_ms: u64,
+    pub operation: AuditOperation,
+    /// Resource name of the crypto key
+    pub key_name: String,
+    pub key_version: u64,
+    pub address: Address,
+    pub chain_id: Option<u64>,
+    pub digest: H256,
+    pub outcome: AuditOutcome,
+    pub error: Option<String>,
+    /// Free-form annotations added by signing features
+    pub notes: Vec<String>,
+}
+
+/// Receives an [`AuditEvent`] for every signing operation. Sinks are called
+/// inline on the signing path, so they should hand events off rather than
+/// block.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, event: &AuditEvent);
+}
+
+/// An [`AuditSink`] which emits each event as a `tracing` event at info level
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TracingAuditSink;
+
+impl AuditSink for TracingAuditSink {
+    fn record(&self, event: &AuditEvent) {
+        info!(
+            operation = ?event.operation,
+            key_name = event.key_name.as_str(),
+            key_version = event.key_version,
+            address = ?event.address,
+            chain_id = ?event.chain_id,
+            digest = ?event.digest,
+            outcome = ?event.outcome,
+            error = ?event.error,
+            notes = ?event.notes,
+            "KMS signing audit event"
+        );
+    }
+}
+
+/// The set of sinks attached to a signer
+#[derive(Clone, Default)]
+pub(crate) struct AuditSinks(Vec<Arc<dyn AuditSink>>);
+
+impl AuditSinks {
+    pub(crate) fn push(&mut self, sink: Arc<dyn AuditSink>) {
+        self.0.push(sink);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn record(&self, event: &AuditEvent) {
+        for sink in &self.0 {
+            sink.record(event);
+        }
+    }
+}
+
+impl fmt::Debug for AuditSinks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AuditSinks({})", self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_serializes_flat() {
+        let event = AuditEvent {
+            timestamp_ms: 1,
+            operation: AuditOperation::TypedData,
+            key_name: "projects/p/locations/l/keyRings/r/cryptoKeys/k".to_string(),
+            key_version: 2,
+            address: Address::zero(),
+            chain_id: Some(1),
+            digest: H256::zero(),
+            outcome: AuditOutcome::Denied,
+            error: None,
+            notes: vec![],
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["operation"], "typed_data");
+        assert_eq!(json["outcome"], "denied");
+        assert_eq!(json["chain_id"], 1);
+    }
+}
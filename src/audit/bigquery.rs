@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use ethers::utils::{hex, keccak256};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use super::{AuditEvent, AuditSink};
+use crate::{credentials::Tokens, CKMSError, CredentialSource};
+
+/// Events buffered before new ones are dropped
+const CHANNEL_CAPACITY: usize = 10_000;
+
+/// The BigQuery table audit events are streamed into. Its schema should have a
+/// column for each [`AuditEvent`] field: `timestamp_ms` INT64, `key_version`
+/// and `chain_id` INT64, `notes` REPEATED STRING, and STRING for the rest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BigQueryTable {
+    pub project_id: String,
+    pub dataset_id: String,
+    pub table_id: String,
+}
+
+impl BigQueryTable {
+    pub fn new(project_id: &str, dataset_id: &str, table_id: &str) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+            dataset_id: dataset_id.to_string(),
+            table_id: table_id.to_string(),
+        }
+    }
+
+    fn insert_all_url(&self) -> String {
+        format!(
+            "https://bigquery.googleapis.com/bigquery/v2/projects/{}/datasets/{}/tables/{}/insertAll",
+            self.project_id, self.dataset_id, self.table_id
+        )
+    }
+}
+
+/// An [`AuditSink`] which batches events into a BigQuery table using the
+/// streaming `tabledata.insertAll` API.
+///
+/// Events are queued without blocking the signing path and written by a
+/// background task, either when `batch_size` events are pending or every
+/// `flush_interval`. Failed writes are logged and dropped.
+#[derive(Clone, Debug)]
+pub struct BigQueryAuditSink {
+    tx: mpsc::Sender<AuditEvent>,
+}
+
+impl BigQueryAuditSink {
+    /// Creates the sink and spawns its writer task on the current tokio
+    /// runtime. Pass the provider's credential source to reuse its identity:
+    /// `provider.credential_source().cloned()`, if set.
+    pub async fn new(
+        table: BigQueryTable,
+        credential_source: CredentialSource,
+        batch_size: usize,
+        flush_interval: Duration,
+    ) -> Result<Self, CKMSError> {
+        let tokens = Tokens::new(credential_source).await?;
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+
+        let writer = Writer {
+            table,
+            tokens,
+            http: reqwest::Client::new(),
+        };
+        tokio::spawn(writer.run(rx, batch_size.max(1), flush_interval));
+
+        Ok(Self { tx })
+    }
+}
+
+impl AuditSink for BigQueryAuditSink {
+    fn record(&self, event: &AuditEvent) {
+        if self.tx.try_send(event.clone()).is_err() {
+            warn!("BigQuery audit sink is backed up, dropping event");
+        }
+    }
+}
+
+struct Writer {
+    table: BigQueryTable,
+    tokens: Tokens,
+    http: reqwest::Client,
+}
+
+impl Writer {
+    async fn run(self, mut rx: mpsc::Receiver<AuditEvent>, batch_size: usize, interval: Duration) {
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            let closed = tokio::select! {
+                event = rx.recv() => match event {
+                    Some(event) => {
+                        batch.push(event);
+                        if batch.len() < batch_size {
+                            continue;
+                        }
+                        false
+                    }
+                    None => true,
+                },
+                _ = ticker.tick() => false,
+            };
+
+            if !batch.is_empty() {
+                if let Err(e) = self.insert(&batch).await {
+                    warn!(
+                        "Failed to write {} audit events to BigQuery: {}",
+                        batch.len(),
+                        e
+                    );
+                }
+                batch.clear();
+            }
+            if closed {
+                break;
+            }
+        }
+    }
+
+    async fn insert(&self, events: &[AuditEvent]) -> Result<(), String> {
+        let authorization = self
+            .tokens
+            .authorization()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let response = self
+            .http
+            .post(self.table.insert_all_url())
+            .header("authorization", authorization)
+            .json(&insert_all(events))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = response.status();
+        let body: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!("HTTP {status}: {body}"));
+        }
+        if let Some(errors) = body.get("insertErrors") {
+            return Err(format!("rows rejected: {errors}"));
+        }
+
+        debug!("Wrote {} audit events to BigQuery", events.len());
+        Ok(())
+    }
+}
+
+/// The `tabledata.insertAll` request body for the given events
+fn insert_all(events: &[AuditEvent]) -> Value {
+    let rows: Vec<_> = events
+        .iter()
+        .map(|event| json!({ "insertId": insert_id(event), "json": event }))
+        .collect();
+    json!({ "rows": rows })
+}
+
+/// Lets BigQuery de-duplicate retried inserts. Ids are at most 128
+/// characters, so the fields identifying the event are hashed.
+fn insert_id(event: &AuditEvent) -> String {
+    let id = format!(
+        "{}/{}-{:?}-{:?}-{}-{:?}",
+        event.key_name,
+        event.key_version,
+        event.operation,
+        event.digest,
+        event.timestamp_ms,
+        event.outcome
+    );
+    hex::encode(keccak256(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Address, H256};
+
+    use super::*;
+    use crate::audit::{AuditOperation, AuditOutcome};
+
+    #[test]
+    fn insert_ids_distinguish_keys_and_operations() {
+        let event = AuditEvent {
+            timestamp_ms: 1,
+            operation: AuditOperation::Transaction,
+            key_name: "projects/p/locations/l/keyRings/r/cryptoKeys/a".to_string(),
+            key_version: 2,
+            address: Address::zero(),
+            chain_id: Some(1),
+            digest: H256::zero(),
+            outcome: AuditOutcome::Signed,
+            error: None,
+            notes: vec![],
+        };
+        let other_key = AuditEvent {
+            key_name: "projects/p/locations/l/keyRings/r/cryptoKeys/b".to_string(),
+            ..event.clone()
+        };
+        let other_operation = AuditEvent {
+            operation: AuditOperation::Receipt,
+            ..event.clone()
+        };
+        let body = insert_all(&[event.clone(), other_key, other_operation, event]);
+
+        let rows = body["rows"].as_array().unwrap();
+        let ids: Vec<_> = rows.iter().map(|row| &row["insertId"]).collect();
+        assert_ne!(ids[0], ids[1]);
+        assert_ne!(ids[0], ids[2]);
+        // a retry of the same event keeps its id
+        assert_eq!(ids[0], ids[3]);
+        assert!(ids[0].as_str().unwrap().len() <= 128);
+        assert_eq!(rows[0]["json"]["operation"], "transaction");
+        assert_eq!(rows[0]["json"]["chain_id"], 1);
+    }
+}
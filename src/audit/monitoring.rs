@@ -0,0 +1,252 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use super::{AuditEvent, AuditOperation, AuditOutcome, AuditSink};
+use crate::{credentials::Tokens, CKMSError, Clock, CredentialSource, SystemClock};
+
+/// The custom metric signing counts are written to
+pub const SIGN_REQUESTS_METRIC: &str = "custom.googleapis.com/gcp_kms_signer/sign_requests";
+
+/// Cloud Monitoring rejects points written to a series more often than this
+const MIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The most series `timeSeries.create` accepts per call
+const MAX_SERIES_PER_REQUEST: usize = 200;
+
+/// The labels of one time series
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Series {
+    key_name: String,
+    key_version: u64,
+    chain_id: Option<u64>,
+    operation: AuditOperation,
+    outcome: AuditOutcome,
+}
+
+type Counts = Mutex<HashMap<Series, u64>>;
+
+/// An [`AuditSink`] which counts signing operations and writes the counts to
+/// Google Cloud Monitoring as the cumulative custom metric
+/// [`SIGN_REQUESTS_METRIC`], labelled by key name, key version, chain id,
+/// operation and outcome. Error rates and usage against KMS quota can then be
+/// charted and alerted on from the metric alone.
+///
+/// Counting is inline and cheap; a background task writes every
+/// `interval`, which is at least ten seconds. Failed writes are logged and
+/// retried with the next interval's totals.
+#[derive(Clone, Debug)]
+pub struct CloudMonitoringSink {
+    counts: Arc<Counts>,
+}
+
+impl CloudMonitoringSink {
+    /// Creates the sink and spawns its writer task on the current tokio
+    /// runtime, which stops when the sink and its clones are dropped. Pass
+    /// the provider's credential source to reuse its identity:
+    /// `provider.credential_source().cloned()`, if set.
+    pub async fn new(
+        project_id: &str,
+        credential_source: CredentialSource,
+        interval: Duration,
+    ) -> Result<Self, CKMSError> {
+        Self::new_with_clock(
+            project_id,
+            credential_source,
+            interval,
+            Arc::new(SystemClock),
+        )
+        .await
+    }
+
+    /// Like [`CloudMonitoringSink::new`], with `clock` timing the points
+    /// written, e.g. the signer's clock
+    pub async fn new_with_clock(
+        project_id: &str,
+        credential_source: CredentialSource,
+        interval: Duration,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, CKMSError> {
+        let tokens = Tokens::new(credential_source).await?;
+        let counts = Arc::new(Counts::default());
+
+        let writer = Writer {
+            project_id: project_id.to_string(),
+            tokens,
+            http: reqwest::Client::new(),
+            started: clock.now(),
+            clock,
+        };
+        tokio::spawn(writer.run(Arc::downgrade(&counts), interval.max(MIN_INTERVAL)));
+
+        Ok(Self { counts })
+    }
+}
+
+impl AuditSink for CloudMonitoringSink {
+    fn record(&self, event: &AuditEvent) {
+        let series = Series {
+            key_name: event.key_name.clone(),
+            key_version: event.key_version,
+            chain_id: event.chain_id,
+            operation: event.operation,
+            outcome: event.outcome,
+        };
+        *self.counts.lock().unwrap().entry(series).or_default() += 1;
+    }
+}
+
+struct Writer {
+    project_id: String,
+    tokens: Tokens,
+    http: reqwest::Client,
+    /// The start of every cumulative series
+    started: SystemTime,
+    clock: Arc<dyn Clock>,
+}
+
+impl Writer {
+    async fn run(self, counts: Weak<Counts>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        // the first tick completes immediately
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            let Some(counts) = counts.upgrade() else {
+                break;
+            };
+            let snapshot: Vec<_> = counts
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(series, count)| (series.clone(), *count))
+                .collect();
+            drop(counts);
+
+            for chunk in snapshot.chunks(MAX_SERIES_PER_REQUEST) {
+                let body = time_series(&self.project_id, chunk, self.started, self.clock.now());
+                if let Err(e) = self.write(body).await {
+                    warn!(
+                        "Failed to write {} time series to Cloud Monitoring: {}",
+                        chunk.len(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    async fn write(&self, body: Value) -> Result<(), String> {
+        let authorization = self
+            .tokens
+            .authorization()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let response = self
+            .http
+            .post(format!(
+                "https://monitoring.googleapis.com/v3/projects/{}/timeSeries",
+                self.project_id
+            ))
+            .header("authorization", authorization)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("HTTP {status}: {body}"));
+        }
+
+        debug!("Wrote signing counts to Cloud Monitoring");
+        Ok(())
+    }
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// The `timeSeries.create` request body for the given counts
+fn time_series(
+    project_id: &str,
+    counts: &[(Series, u64)],
+    started: SystemTime,
+    now: SystemTime,
+) -> Value {
+    let series: Vec<_> = counts
+        .iter()
+        .map(|(series, count)| {
+            json!({
+                "metric": {
+                    "type": SIGN_REQUESTS_METRIC,
+                    "labels": {
+                        "key_name": series.key_name,
+                        "key_version": series.key_version.to_string(),
+                        "chain_id": series.chain_id.map(|id| id.to_string()).unwrap_or_default(),
+                        "operation": series.operation,
+                        "outcome": series.outcome,
+                    },
+                },
+                "resource": {
+                    "type": "global",
+                    "labels": { "project_id": project_id },
+                },
+                "metricKind": "CUMULATIVE",
+                "valueType": "INT64",
+                "points": [{
+                    "interval": {
+                        "startTime": rfc3339(started),
+                        "endTime": rfc3339(now),
+                    },
+                    // int64 values are strings in the JSON mapping
+                    "value": { "int64Value": count.to_string() },
+                }],
+            })
+        })
+        .collect();
+    json!({ "timeSeries": series })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn time_series_labels() {
+        let series = Series {
+            key_name: "projects/p/locations/l/keyRings/r/cryptoKeys/k".to_string(),
+            key_version: 2,
+            chain_id: Some(1),
+            operation: AuditOperation::Transaction,
+            outcome: AuditOutcome::Failed,
+        };
+        let body = time_series(
+            "p",
+            &[(series, 5)],
+            UNIX_EPOCH,
+            UNIX_EPOCH + Duration::from_secs(60),
+        );
+
+        let series = &body["timeSeries"][0];
+        assert_eq!(series["metric"]["labels"]["chain_id"], "1");
+        assert_eq!(series["metric"]["labels"]["operation"], "transaction");
+        assert_eq!(series["metric"]["labels"]["outcome"], "failed");
+        assert_eq!(series["points"][0]["value"]["int64Value"], "5");
+        assert_eq!(
+            series["points"][0]["interval"]["endTime"],
+            "1970-01-01T00:01:00.000Z"
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use std::{fmt, sync::Arc};
+
+use async_trait::async_trait;
+use ethers::prelude::k256::ecdsa::VerifyingKey;
+
+use crate::{CKMSError, GcpKmsProvider, KeyVersion, SigningContext};
+
+/// A store of secp256k1 keys which signs prehashes, under the Ethereum
+/// logic of [`GcpKmsSigner`](crate::GcpKmsSigner): trial recovery, `v`
+/// conventions, policies, scopes, replay protection and auditing.
+/// [`GcpKmsProvider`] is the Cloud KMS backend; others, such as Vault's
+/// transit engine, a PKCS#11 token or a test's deterministic fake, are
+/// passed to the signer's constructors as a [`KeyBackend`].
+#[async_trait]
+pub trait KmsKeyBackend: fmt::Debug + Send + Sync {
+    /// Fetches a key version's public key
+    async fn get_public_key(
+        &self,
+        key_id: &str,
+        key_version: u64,
+    ) -> Result<VerifyingKey, CKMSError>;
+
+    /// Signs a 32-byte prehash with a key version, returning the DER ECDSA
+    /// signature and the version which signed it. The signature may be
+    /// high-s; the signer applies its high-s policy.
+    async fn sign_digest(
+        &self,
+        key_id: &str,
+        key_version: u64,
+        digest: [u8; 32],
+        context: &SigningContext,
+    ) -> Result<(Vec<u8>, u64), CKMSError>;
+
+    /// Resolves a [`KeyVersion`] to a version number. Backends without
+    /// symbolic versions only resolve pinned ones.
+    async fn resolve_key_version(
+        &self,
+        key_id: &str,
+        key_version: KeyVersion,
+    ) -> Result<u64, CKMSError> {
+        key_version.as_pinned().ok_or_else(|| {
+            CKMSError::UnsupportedByBackend(format!("resolving {key_version} of {key_id}"))
+        })
+    }
+
+    /// A key's name in errors and logs
+    fn key_name(&self, key_id: &str) -> String {
+        key_id.to_string()
+    }
+
+    /// A key version's name in errors and logs
+    fn key_version_name(&self, key_id: &str, key_version: u64) -> String {
+        format!("{}/{key_version}", self.key_name(key_id))
+    }
+}
+
+/// What a [`GcpKmsSigner`](crate::GcpKmsSigner) signs with: Cloud KMS,
+/// from a [`GcpKmsProvider`], or another [`KmsKeyBackend`], from an `Arc`
+#[derive(Clone, Debug)]
+pub struct KeyBackend {
+    pub(crate) backend: Arc<dyn KmsKeyBackend>,
+    /// The backend, when it is Cloud KMS, for its reports and queue stats
+    pub(crate) provider: Option<GcpKmsProvider>,
+}
+
+impl From<GcpKmsProvider> for KeyBackend {
+    fn from(provider: GcpKmsProvider) -> Self {
+        Self {
+            backend: Arc::new(provider.clone()),
+            provider: Some(provider),
+        }
+    }
+}
+
+impl From<Arc<dyn KmsKeyBackend>> for KeyBackend {
+    fn from(backend: Arc<dyn KmsKeyBackend>) -> Self {
+        Self {
+            backend,
+            provider: None,
+        }
+    }
+}
+
+impl<B: KmsKeyBackend + 'static> From<Arc<B>> for KeyBackend {
+    fn from(backend: Arc<B>) -> Self {
+        Self {
+            backend,
+            provider: None,
+        }
+    }
+}
+
+#[async_trait]
+impl KmsKeyBackend for GcpKmsProvider {
+    async fn get_public_key(
+        &self,
+        key_id: &str,
+        key_version: u64,
+    ) -> Result<VerifyingKey, CKMSError> {
+        self.get_verifying_key(key_id, key_version).await
+    }
+
+    async fn sign_digest(
+        &self,
+        key_id: &str,
+        key_version: u64,
+        digest: [u8; 32],
+        context: &SigningContext,
+    ) -> Result<(Vec<u8>, u64), CKMSError> {
+        self.sign_digest_with_context(key_id, key_version, &digest, context)
+            .await
+    }
+
+    async fn resolve_key_version(
+        &self,
+        key_id: &str,
+        key_version: KeyVersion,
+    ) -> Result<u64, CKMSError> {
+        GcpKmsProvider::resolve_key_version(self, key_id, key_version).await
+    }
+
+    fn key_name(&self, key_id: &str) -> String {
+        self.kms_key_ref.to_crypto_key_ref(key_id)
+    }
+
+    fn key_version_name(&self, key_id: &str, key_version: u64) -> String {
+        self.kms_key_ref.to_key_version_ref(key_id, key_version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audit::{AuditEvent, AuditOperation, AuditOutcome, AuditSink},
+        GcpKmsSigner,
+    };
+    use ethers::{
+        prelude::k256::ecdsa::{signature::hazmat::PrehashSigner, Signature, SigningKey},
+        signers::{LocalWallet, Signer},
+        types::{
+            transaction::eip2718::TypedTransaction, Eip1559TransactionRequest, TransactionRequest,
+            H256,
+        },
+        utils::rlp::Rlp,
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    #[derive(Debug)]
+    struct LocalBackend(SigningKey);
+
+    #[async_trait]
+    impl KmsKeyBackend for LocalBackend {
+        async fn get_public_key(&self, _: &str, _: u64) -> Result<VerifyingKey, CKMSError> {
+            Ok(*self.0.verifying_key())
+        }
+
+        async fn sign_digest(
+            &self,
+            _: &str,
+            key_version: u64,
+            digest: [u8; 32],
+            _: &SigningContext,
+        ) -> Result<(Vec<u8>, u64), CKMSError> {
+            let signature: Signature = self.0.sign_prehash(&digest)?;
+            Ok((signature.to_der().as_bytes().to_vec(), key_version))
+        }
+    }
+
+    #[tokio::test]
+    async fn signs_over_other_backends() {
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let wallet = LocalWallet::from(key.clone()).with_chain_id(5u64);
+        let backend = Arc::new(LocalBackend(key));
+        let signer = GcpKmsSigner::new(backend, "local".to_string(), 1, 5)
+            .await
+            .unwrap();
+        assert_eq!(signer.address(), wallet.address());
+        assert_eq!(signer.key_name(), "local");
+
+        let message = b"hello world";
+        assert_eq!(
+            signer.sign_message(message).await.unwrap(),
+            wallet.sign_message(message).await.unwrap()
+        );
+        let tx = TransactionRequest::new()
+            .to(wallet.address())
+            .value(1)
+            .nonce(0)
+            .gas(21_000)
+            .gas_price(1)
+            .into();
+        assert_eq!(
+            signer.sign_transaction(&tx).await.unwrap(),
+            wallet.sign_transaction(&tx).await.unwrap()
+        );
+        assert!(matches!(
+            signer.report().await,
+            Err(CKMSError::UnsupportedByBackend(_))
+        ));
+    }
+
+    #[derive(Default)]
+    struct RecordingSink(Mutex<Vec<AuditEvent>>);
+
+    impl AuditSink for RecordingSink {
+        fn record(&self, event: &AuditEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn audits_signing_with_another_key_version() {
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let signer = GcpKmsSigner::new(Arc::new(LocalBackend(key)), "local".to_string(), 1, 5)
+            .await
+            .unwrap()
+            .with_audit_sink(sink.clone());
+
+        let (_, signed_version) = signer
+            .sign_digest_with_key_version([7; 32], 3)
+            .await
+            .unwrap();
+        assert_eq!(signed_version, 3);
+
+        let events = sink.0.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].operation, AuditOperation::Digest);
+        assert_eq!(events[0].outcome, AuditOutcome::Signed);
+        assert_eq!(events[0].key_version, 3);
+        assert_eq!(events[0].digest, H256::repeat_byte(7));
+        assert!(events[0]
+            .notes
+            .contains(&"key_version_override=3".to_string()));
+    }
+
+    #[tokio::test]
+    async fn raw_transactions_carry_the_signed_chain_id() {
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let backend = Arc::new(LocalBackend(key));
+        let signer = GcpKmsSigner::new(backend, "local".to_string(), 1, 5)
+            .await
+            .unwrap();
+        let tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(signer.address())
+            .value(1)
+            .nonce(0)
+            .gas(21_000)
+            .max_fee_per_gas(2)
+            .max_priority_fee_per_gas(1)
+            .into();
+        assert_eq!(tx.chain_id(), None);
+
+        let raw = signer.sign_transaction_raw(&tx).await.unwrap();
+        let (decoded, signature) = TypedTransaction::decode_signed(&Rlp::new(&raw)).unwrap();
+        assert_eq!(decoded.chain_id(), Some(5u64.into()));
+        assert_eq!(
+            signature.recover(decoded.sighash()).unwrap(),
+            signer.address()
+        );
+    }
+
+    /// Fails its first public key fetch, as KMS might
+    #[derive(Debug)]
+    struct FlakyBackend {
+        key: LocalBackend,
+        fetches: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl KmsKeyBackend for FlakyBackend {
+        async fn get_public_key(
+            &self,
+            key_id: &str,
+            key_version: u64,
+        ) -> Result<VerifyingKey, CKMSError> {
+            if self.fetches.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(CKMSError::RequestError(tonic::Status::unavailable(
+                    "try again",
+                )));
+            }
+            self.key.get_public_key(key_id, key_version).await
+        }
+
+        async fn sign_digest(
+            &self,
+            key_id: &str,
+            key_version: u64,
+            digest: [u8; 32],
+            context: &SigningContext,
+        ) -> Result<(Vec<u8>, u64), CKMSError> {
+            self.key
+                .sign_digest(key_id, key_version, digest, context)
+                .await
+        }
+    }
+
+    #[tokio::test]
+    async fn lazy_signers_retry_and_cache_public_keys_of_fakes() {
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let backend = Arc::new(FlakyBackend {
+            key: LocalBackend(key.clone()),
+            fetches: AtomicUsize::new(0),
+        });
+        let signer = GcpKmsSigner::new_lazy(backend.clone(), "flaky".to_string(), 1, 1).unwrap();
+        assert!(matches!(
+            signer.resolve().await,
+            Err(CKMSError::RequestError(_))
+        ));
+        assert_eq!(signer.resolve().await.unwrap(), *key.verifying_key());
+        signer.sign_message(b"hello world").await.unwrap();
+        assert_eq!(backend.fetches.load(Ordering::SeqCst), 2);
+
+        assert!(matches!(
+            GcpKmsSigner::new_with_key_version(backend, "flaky".to_string(), KeyVersion::Latest, 1)
+                .await,
+            Err(CKMSError::UnsupportedByBackend(_))
+        ));
+    }
+}
@@ -0,0 +1,764 @@
+// `CKMSError` carries `tonic::Status` by value, which is larger than clippy
+// would like for an error type
+#![allow(clippy::result_large_err)]
+
+#[cfg(feature = "server")]
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use ethers::{
+    signers::Signer,
+    types::{transaction::eip2718::TypedTransaction, Address, Signature, H256},
+    utils::{hex, to_checksum},
+};
+use ethers_gcp_kms_signer::{
+    fixtures, parse_transaction, CKMSError, GcpKeyRingRef, GcpKmsProvider, GcpKmsSigner,
+    KeyProtection,
+};
+use futures::StreamExt;
+
+/// Why a command failed
+#[derive(Debug, thiserror::Error)]
+enum CliError {
+    #[error(transparent)]
+    Kms(#[from] CKMSError),
+    /// Bad input, or a failure of the CLI itself rather than of signing
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// Signing utilities for Ethereum keys held in Google Cloud KMS
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+// parsed once, so `Serve`'s size does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Writes deterministic JSON fixtures of this crate's signing
+    /// conventions, signed with a local synthetic key rather than KMS
+    Fixtures {
+        /// Hex private key of the synthetic key
+        #[arg(long, value_parser = parse_h256)]
+        private_key: Option<H256>,
+        #[arg(long, default_value_t = 1)]
+        chain_id: u64,
+        /// Writes to a file rather than stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Creates an `EC_SIGN_SECP256K1_SHA256` key, printing its checksummed
+    /// address once KMS has generated it
+    CreateKey {
+        #[command(flatten)]
+        key_ring: KeyRingArgs,
+        #[arg(long)]
+        key: String,
+        /// Keeps the key in a Cloud HSM rather than in software
+        #[arg(long)]
+        hsm: bool,
+    },
+    /// Prints a KMS key version's checksummed address
+    Address {
+        #[command(flatten)]
+        key: KeyArgs,
+        /// Fails unless the key version derives to this address, as a
+        /// check of a deployment's key configuration
+        #[arg(long)]
+        expect: Option<Address>,
+    },
+    /// Checks that an EIP-191 personal message signature is by an address,
+    /// without calling KMS
+    Verify {
+        #[arg(long)]
+        message: String,
+        /// Decodes `--message` from hex rather than taking its UTF-8 bytes
+        #[arg(long)]
+        hex: bool,
+        /// The 65-byte signature as hex
+        #[arg(long)]
+        signature: Signature,
+        #[arg(long)]
+        address: Address,
+    },
+    /// Signs an EIP-191 personal message, printing the 65-byte signature
+    /// as hex
+    SignMessage {
+        #[command(flatten)]
+        key: KeyArgs,
+        #[arg(long)]
+        message: String,
+        /// Decodes `--message` from hex rather than signing its UTF-8 bytes
+        #[arg(long)]
+        hex: bool,
+    },
+    /// Signs a JSON transaction request, as `eth_signTransaction` takes,
+    /// printing the raw transaction as hex. `from` and `chainId` default to
+    /// the key's.
+    SignTransaction {
+        #[command(flatten)]
+        key: KeyArgs,
+        /// The transaction's JSON file, or `-` for stdin
+        #[arg(long)]
+        file: PathBuf,
+    },
+    /// Serves KMS keys over the Web3Signer ETH1 API and JSON-RPC
+    /// Signs a JSON array of transaction requests concurrently, writing a
+    /// JSON array of `{"raw": ...}`, or `{"error": ...}` for a transaction
+    /// which failed, in input order. Each request may set its own
+    /// `chainId`.
+    SignBatch {
+        #[command(flatten)]
+        key: KeyArgs,
+        /// The transactions' JSON file, or `-` for stdin
+        #[arg(long)]
+        input: PathBuf,
+        /// Writes to a file rather than stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// How many KMS requests to keep in flight
+        #[arg(long, default_value_t = 8)]
+        concurrency: usize,
+    },
+    /// Signs an `eth_signTypedData_v4` EIP-712 payload, such as a Safe
+    /// transaction, printing the 65-byte signature as hex with `v` = 27/28
+    SignTypedData {
+        #[command(flatten)]
+        key: KeyArgs,
+        /// The payload's JSON file, or `-` for stdin
+        #[arg(long)]
+        file: PathBuf,
+    },
+    #[cfg(feature = "server")]
+    Serve {
+        #[command(flatten)]
+        key_ring: KeyRingArgs,
+        /// The keys to serve, all at the same version
+        #[arg(long = "key", required = true)]
+        keys: Vec<String>,
+        #[arg(long, default_value_t = 1)]
+        key_version: u64,
+        #[arg(long, default_value_t = 1)]
+        chain_id: u64,
+        #[arg(long, default_value = "127.0.0.1:9000")]
+        listen: SocketAddr,
+        /// A TOML file of further settings, such as a `[tls]` table of
+        /// `certificate`, `key` and optionally `client_ca` PEM paths
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Serves on this Unix socket instead of `--listen`
+        #[cfg(unix)]
+        #[arg(long)]
+        unix_socket: Option<PathBuf>,
+        /// Allows only processes running as these uids to use the Unix
+        /// socket
+        #[cfg(unix)]
+        #[arg(long = "allow-uid")]
+        allow_uids: Vec<u32>,
+        /// Allows only processes in these primary groups to use the Unix
+        /// socket
+        #[cfg(unix)]
+        #[arg(long = "allow-gid")]
+        allow_gids: Vec<u32>,
+        /// Also serves the gRPC signing service on this address
+        #[cfg(feature = "grpc")]
+        #[arg(long)]
+        grpc_listen: Option<SocketAddr>,
+    },
+}
+
+#[derive(Debug, Args)]
+struct KeyRingArgs {
+    #[arg(long)]
+    project: String,
+    #[arg(long)]
+    location: String,
+    #[arg(long)]
+    key_ring: String,
+}
+
+impl KeyRingArgs {
+    async fn provider(&self) -> Result<GcpKmsProvider, CKMSError> {
+        GcpKmsProvider::new(GcpKeyRingRef::new(
+            &self.project,
+            &self.location,
+            &self.key_ring,
+        ))
+        .await
+    }
+}
+
+/// The key a one-off command signs with
+#[derive(Debug, Args)]
+struct KeyArgs {
+    #[command(flatten)]
+    key_ring: KeyRingArgs,
+    #[arg(long)]
+    key: String,
+    #[arg(long, default_value_t = 1)]
+    key_version: u64,
+    #[arg(long, default_value_t = 1)]
+    chain_id: u64,
+}
+
+impl KeyArgs {
+    async fn signer(&self) -> Result<GcpKmsSigner, CKMSError> {
+        let provider = self.key_ring.provider().await?;
+        GcpKmsSigner::new(provider, self.key.clone(), self.key_version, self.chain_id).await
+    }
+}
+
+/// Runs a command's KMS calls to completion
+fn block_on<T>(
+    future: impl std::future::Future<Output = Result<T, CliError>>,
+) -> Result<T, CliError> {
+    tokio::runtime::Runtime::new()
+        .map_err(|e| CliError::Invalid(e.to_string()))?
+        .block_on(future)
+}
+
+/// Reads a file, or stdin for `-`
+fn read_input(path: &std::path::Path) -> Result<Vec<u8>, CliError> {
+    if path == std::path::Path::new("-") {
+        let mut input = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut input)
+            .map_err(|e| CliError::Invalid(format!("stdin: {e}")))?;
+        return Ok(input);
+    }
+    read(path)
+}
+
+/// `serve`'s config file
+#[cfg(feature = "server")]
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServeConfig {
+    /// Terminates TLS on the TCP listeners
+    #[cfg(feature = "tls")]
+    tls: Option<TlsFiles>,
+}
+
+#[cfg(feature = "tls")]
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TlsFiles {
+    certificate: PathBuf,
+    key: PathBuf,
+    /// Requires client certificates issued by this CA
+    client_ca: Option<PathBuf>,
+}
+
+#[cfg(feature = "tls")]
+impl TlsFiles {
+    fn load(&self) -> Result<ethers_gcp_kms_signer::server::TlsConfig, CliError> {
+        let tls = ethers_gcp_kms_signer::server::TlsConfig::from_pem(
+            &read(&self.certificate)?,
+            &read(&self.key)?,
+        )?;
+        Ok(match &self.client_ca {
+            Some(client_ca) => tls.with_client_ca(&read(client_ca)?)?,
+            None => tls,
+        })
+    }
+}
+
+fn read(path: &std::path::Path) -> Result<Vec<u8>, CliError> {
+    std::fs::read(path).map_err(|e| CliError::Invalid(format!("{}: {e}", path.display())))
+}
+
+#[cfg(feature = "server")]
+fn read_config(path: Option<PathBuf>) -> Result<ServeConfig, CliError> {
+    let Some(path) = path else {
+        return Ok(ServeConfig::default());
+    };
+    let text = String::from_utf8(read(&path)?)
+        .map_err(|e| CliError::Invalid(format!("{}: {e}", path.display())))?;
+    toml::from_str(&text).map_err(|e| CliError::Invalid(format!("{}: {e}", path.display())))
+}
+
+fn parse_h256(s: &str) -> Result<H256, String> {
+    let bytes = hex::decode(s).map_err(|e| e.to_string())?;
+    if bytes.len() != 32 {
+        return Err(format!("expected 32 bytes, got {}", bytes.len()));
+    }
+    Ok(H256::from_slice(&bytes))
+}
+
+/// `--message`'s bytes, decoded from hex with `--hex`
+fn message_bytes(message: String, hex: bool) -> Result<Vec<u8>, CliError> {
+    if hex {
+        hex::decode(&message).map_err(|e| CliError::Invalid(format!("--message: {e}")))
+    } else {
+        Ok(message.into_bytes())
+    }
+}
+
+/// Fills in a transaction's `from` and chain id from the key, refusing one
+/// from another address
+fn with_defaults(
+    mut tx: TypedTransaction,
+    signer: &GcpKmsSigner,
+) -> Result<TypedTransaction, CliError> {
+    match tx.from() {
+        Some(from) if *from != signer.address() => {
+            return Err(CliError::Invalid(format!(
+                "transaction is from {from:?}, not the key's {:?}",
+                signer.address()
+            )));
+        }
+        Some(_) => {}
+        None => {
+            tx.set_from(signer.address());
+        }
+    }
+    if tx.chain_id().is_none() {
+        tx.set_chain_id(signer.chain_id());
+    }
+    Ok(tx)
+}
+
+fn write_json(out: Option<PathBuf>, value: &impl serde::Serialize) -> Result<(), CliError> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| CliError::Invalid(e.to_string()))?;
+    match out {
+        Some(path) => std::fs::write(&path, json + "\n")
+            .map_err(|e| CliError::Invalid(format!("{}: {e}", path.display()))),
+        None => {
+            println!("{json}");
+            Ok(())
+        }
+    }
+}
+
+/// `sign-message`'s output: the 65-byte signature as hex
+async fn sign_message(signer: &GcpKmsSigner, message: Vec<u8>) -> Result<String, CliError> {
+    Ok(format!("0x{}", signer.sign_message(message).await?))
+}
+
+/// `sign-transaction`'s output: the raw signed transaction as hex
+async fn sign_transaction(signer: &GcpKmsSigner, tx: TypedTransaction) -> Result<String, CliError> {
+    let raw = signer
+        .sign_transaction_raw(&with_defaults(tx, signer)?)
+        .await?;
+    Ok(raw.to_string())
+}
+
+/// `sign-batch`'s output, in input order, and how many transactions failed
+async fn sign_batch(
+    signer: &GcpKmsSigner,
+    txs: Vec<TypedTransaction>,
+    concurrency: usize,
+) -> Result<(Vec<serde_json::Value>, usize), CliError> {
+    let txs = txs
+        .into_iter()
+        .map(|tx| with_defaults(tx, signer))
+        .collect::<Result<Vec<_>, _>>()?;
+    let results = futures::stream::iter(&txs)
+        .map(|tx| signer.sign_transaction_raw(tx))
+        .buffered(concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    let results = results
+        .into_iter()
+        .map(|result| match result {
+            Ok(raw) => serde_json::json!({ "raw": raw }),
+            Err(e) => serde_json::json!({ "error": e.to_string() }),
+        })
+        .collect();
+    Ok((results, failed))
+}
+
+/// `sign-typed-data`'s output: the 65-byte signature as hex
+async fn sign_typed_data(
+    signer: &GcpKmsSigner,
+    payload: &serde_json::Value,
+) -> Result<String, CliError> {
+    let mut signature = signer.sign_typed_data_json(payload).await?;
+    // as wallets return it, rather than `sign_typed_data`'s 0/1
+    if signature.v < 27 {
+        signature.v += 27;
+    }
+    Ok(format!("0x{signature}"))
+}
+
+/// `verify`'s check that `signature` over `message` is by `address`
+fn verify(message: &[u8], signature: Signature, address: Address) -> Result<(), CliError> {
+    let signer = signature
+        .recover(message)
+        .map_err(|e| CKMSError::InvalidSignature(e.to_string()))?;
+    if signer != address {
+        return Err(CKMSError::InvalidSignature(format!(
+            "signed by {}, not {}",
+            to_checksum(&signer, None),
+            to_checksum(&address, None)
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+fn run(cli: Cli) -> Result<(), CliError> {
+    match cli.command {
+        Command::Fixtures {
+            private_key,
+            chain_id,
+            out,
+        } => {
+            let private_key = private_key.map_or(fixtures::DEFAULT_PRIVATE_KEY, |key| key.0);
+            write_json(out, &fixtures::generate(private_key, chain_id)?)
+        }
+        Command::CreateKey { key_ring, key, hsm } => {
+            let protection = if hsm {
+                KeyProtection::Hsm
+            } else {
+                KeyProtection::Software
+            };
+            let address = block_on(async {
+                let provider = key_ring.provider().await?;
+                let key_version = provider.create_key(&key, protection).await?;
+                let public_key = provider.get_verifying_key(&key, key_version).await?;
+                Ok(ethers::utils::public_key_to_address(&public_key))
+            })?;
+            println!("{}", to_checksum(&address, None));
+            Ok(())
+        }
+        Command::Address { key, expect } => {
+            let signer = block_on(async {
+                let signer = key.signer().await?;
+                Ok(match expect {
+                    Some(address) => signer.with_expected_address(address)?,
+                    None => signer,
+                })
+            })?;
+            println!("{}", to_checksum(&signer.address(), None));
+            Ok(())
+        }
+        Command::SignMessage { key, message, hex } => {
+            let message = message_bytes(message, hex)?;
+            let signature = block_on(async { sign_message(&key.signer().await?, message).await })?;
+            println!("{signature}");
+            Ok(())
+        }
+        Command::Verify {
+            message,
+            hex,
+            signature,
+            address,
+        } => {
+            verify(&message_bytes(message, hex)?, signature, address)?;
+            println!("OK");
+            Ok(())
+        }
+        Command::SignTransaction { key, file } => {
+            let tx = serde_json::from_slice(&read_input(&file)?)
+                .and_then(parse_transaction)
+                .map_err(|e| CliError::Invalid(format!("{}: {e}", file.display())))?;
+            let raw = block_on(async { sign_transaction(&key.signer().await?, tx).await })?;
+            println!("{raw}");
+            Ok(())
+        }
+        Command::SignBatch {
+            key,
+            input,
+            out,
+            concurrency,
+        } => {
+            let txs: Vec<serde_json::Value> = serde_json::from_slice(&read_input(&input)?)
+                .map_err(|e| CliError::Invalid(format!("{}: {e}", input.display())))?;
+            let txs = txs
+                .into_iter()
+                .enumerate()
+                .map(|(i, tx)| {
+                    parse_transaction(tx).map_err(|e| {
+                        CliError::Invalid(format!("{}: transaction {i}: {e}", input.display()))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let (results, failed) =
+                block_on(async { sign_batch(&key.signer().await?, txs, concurrency).await })?;
+            write_json(out, &results)?;
+            if failed > 0 {
+                return Err(CliError::Invalid(format!(
+                    "{failed} of {} transactions failed",
+                    results.len()
+                )));
+            }
+            Ok(())
+        }
+        Command::SignTypedData { key, file } => {
+            let payload: serde_json::Value = serde_json::from_slice(&read_input(&file)?)
+                .map_err(|e| CliError::Invalid(format!("{}: {e}", file.display())))?;
+            let signature =
+                block_on(async { sign_typed_data(&key.signer().await?, &payload).await })?;
+            println!("{signature}");
+            Ok(())
+        }
+        #[cfg(feature = "server")]
+        Command::Serve {
+            key_ring,
+            keys,
+            key_version,
+            chain_id,
+            listen,
+            config,
+            #[cfg(unix)]
+            unix_socket,
+            #[cfg(unix)]
+            allow_uids,
+            #[cfg(unix)]
+            allow_gids,
+            #[cfg(feature = "grpc")]
+            grpc_listen,
+        } => {
+            #[cfg(unix)]
+            use ethers_gcp_kms_signer::server::PeerPolicy;
+            use ethers_gcp_kms_signer::server::SignerServer;
+
+            let ServeConfig {
+                #[cfg(feature = "tls")]
+                tls,
+            } = read_config(config)?;
+            #[cfg(feature = "tls")]
+            let tls = tls.map(|files| files.load()).transpose()?;
+            block_on(async {
+                let provider = key_ring.provider().await?;
+                let mut signers = Vec::new();
+                for key in keys {
+                    signers.push(
+                        GcpKmsSigner::new(provider.clone(), key, key_version, chain_id).await?,
+                    );
+                }
+                let server = SignerServer::new(signers).await?;
+                #[cfg(feature = "tls")]
+                let server = match tls {
+                    Some(tls) => server.with_tls(tls),
+                    None => server,
+                };
+                let http = {
+                    let server = server.clone();
+                    async move {
+                        #[cfg(unix)]
+                        if let Some(path) = unix_socket {
+                            let policy = allow_uids
+                                .into_iter()
+                                .fold(PeerPolicy::default(), PeerPolicy::with_uid);
+                            let policy = allow_gids.into_iter().fold(policy, PeerPolicy::with_gid);
+                            eprintln!("listening on {}", path.display());
+                            return server.serve_unix(path, policy).await;
+                        }
+                        eprintln!("listening on {listen}");
+                        server.serve(listen).await
+                    }
+                };
+                #[cfg(feature = "grpc")]
+                if let Some(grpc_listen) = grpc_listen {
+                    eprintln!("serving gRPC on {grpc_listen}");
+                    tokio::try_join!(http, server.serve_grpc(grpc_listen))?;
+                    return Ok(());
+                }
+                Ok(http.await?)
+            })
+        }
+    }
+}
+
+fn main() {
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [&str; 8] = [
+        "--project",
+        "project",
+        "--location",
+        "global",
+        "--key-ring",
+        "ring",
+        "--key",
+        "key",
+    ];
+
+    fn parse(command: &[&str]) -> Result<Command, clap::Error> {
+        let args = ["gcp-eth-signer"].iter().chain(command);
+        Cli::try_parse_from(args).map(|cli| cli.command)
+    }
+
+    #[test]
+    fn parses_key_arguments_with_defaults() {
+        let args: Vec<_> = ["sign-message", "--message", "68656c6c6f", "--hex"]
+            .into_iter()
+            .chain(KEY)
+            .collect();
+        let Command::SignMessage { key, message, hex } = parse(&args).unwrap() else {
+            panic!("not sign-message");
+        };
+        assert_eq!(message, "68656c6c6f");
+        assert!(hex);
+        assert_eq!(key.key, "key");
+        assert_eq!(key.key_ring.key_ring, "ring");
+        assert_eq!((key.key_version, key.chain_id), (1, 1));
+
+        let args: Vec<_> = ["sign-batch", "--input", "-", "--chain-id", "5"]
+            .into_iter()
+            .chain(KEY)
+            .collect();
+        let Command::SignBatch {
+            key,
+            input,
+            out,
+            concurrency,
+        } = parse(&args).unwrap()
+        else {
+            panic!("not sign-batch");
+        };
+        assert_eq!(key.chain_id, 5);
+        assert_eq!(input, PathBuf::from("-"));
+        assert_eq!((out, concurrency), (None, 8));
+    }
+
+    #[test]
+    fn rejects_incomplete_and_malformed_arguments() {
+        // no key
+        assert!(parse(&["address", "--project", "project"]).is_err());
+        assert!(parse(&["verify", "--message", "hi", "--signature", "0x01"]).is_err());
+        assert!(parse(&["fixtures", "--private-key", "0x01"]).is_err());
+        assert!(parse(&["unknown"]).is_err());
+    }
+
+    #[test]
+    fn decodes_hex_messages() {
+        assert_eq!(message_bytes("hi".into(), false).unwrap(), b"hi");
+        assert_eq!(message_bytes("6869".into(), true).unwrap(), b"hi");
+        assert!(matches!(
+            message_bytes("zz".into(), true),
+            Err(CliError::Invalid(reason)) if reason.starts_with("--message")
+        ));
+    }
+
+    #[test]
+    fn writes_pretty_json_files() {
+        let path = std::env::temp_dir().join(format!("cli-out-{}.json", std::process::id()));
+        write_json(Some(path.clone()), &serde_json::json!([{ "raw": "0x01" }])).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, "[\n  {\n    \"raw\": \"0x01\"\n  }\n]\n");
+    }
+
+    #[cfg(feature = "test-utils")]
+    mod signing {
+        use ethers::{
+            types::{Bytes, TransactionRequest},
+            utils::rlp::Rlp,
+        };
+        use ethers_gcp_kms_signer::test_utils::{MockKmsProvider, MockKmsSigner};
+
+        use super::*;
+
+        async fn signer() -> GcpKmsSigner {
+            let provider = MockKmsProvider::new(GcpKeyRingRef::new("project", "global", "ring"))
+                .await
+                .unwrap();
+            MockKmsSigner::new(provider, "key".to_string(), 1, 5)
+                .await
+                .unwrap()
+        }
+
+        #[tokio::test]
+        async fn prints_verifiable_message_signatures() {
+            let signer = signer().await;
+            let output = sign_message(&signer, b"hello".to_vec()).await.unwrap();
+            assert!(output.starts_with("0x"));
+            assert_eq!(output.len(), 2 + 130);
+
+            let signature: Signature = output.parse().unwrap();
+            verify(b"hello", signature, signer.address()).unwrap();
+            assert!(matches!(
+                verify(b"hello", signature, Address::zero()),
+                Err(CliError::Kms(CKMSError::InvalidSignature(_)))
+            ));
+        }
+
+        #[tokio::test]
+        async fn prints_raw_transactions_for_the_key() {
+            let signer = signer().await;
+            let tx: TypedTransaction = TransactionRequest::new()
+                .to(Address::repeat_byte(1))
+                .value(1)
+                .nonce(0)
+                .gas(21_000)
+                .gas_price(1)
+                .into();
+            let output = sign_transaction(&signer, tx.clone()).await.unwrap();
+            let raw: Bytes = output.parse().unwrap();
+            let (decoded, signature) = TypedTransaction::decode_signed(&Rlp::new(&raw)).unwrap();
+            assert_eq!(decoded.chain_id(), Some(5.into()));
+            assert_eq!(
+                signature.recover(decoded.sighash()).unwrap(),
+                signer.address()
+            );
+
+            let mut foreign = tx;
+            foreign.set_from(Address::repeat_byte(2));
+            assert!(matches!(
+                sign_transaction(&signer, foreign).await,
+                Err(CliError::Invalid(reason)) if reason.contains("not the key's")
+            ));
+        }
+
+        #[tokio::test]
+        async fn prints_batches_in_input_order() {
+            let signer = signer().await;
+            let txs: Vec<TypedTransaction> = [1u64, 10]
+                .into_iter()
+                .map(|chain_id| {
+                    TransactionRequest::new()
+                        .to(Address::repeat_byte(1))
+                        .nonce(0)
+                        .gas(21_000)
+                        .gas_price(1)
+                        .chain_id(chain_id)
+                        .into()
+                })
+                .collect();
+            let (results, failed) = sign_batch(&signer, txs, 2).await.unwrap();
+            assert_eq!(failed, 0);
+            let chain_ids: Vec<_> = results
+                .iter()
+                .map(|result| {
+                    let raw: Bytes = result["raw"].as_str().unwrap().parse().unwrap();
+                    let (tx, _) = TypedTransaction::decode_signed(&Rlp::new(&raw)).unwrap();
+                    tx.chain_id().unwrap().as_u64()
+                })
+                .collect();
+            assert_eq!(chain_ids, [1, 10]);
+        }
+
+        #[tokio::test]
+        async fn prints_typed_data_signatures_with_wallet_v() {
+            let signer = signer().await;
+            let payload = serde_json::json!({
+                "types": {
+                    "EIP712Domain": [{"name": "name", "type": "string"}],
+                    "Mail": [{"name": "contents", "type": "string"}]
+                },
+                "primaryType": "Mail",
+                "domain": {"name": "test"},
+                "message": {"contents": "hello"}
+            });
+            let output = sign_typed_data(&signer, &payload).await.unwrap();
+            let signature: Signature = output.parse().unwrap();
+            assert!(signature.v == 27 || signature.v == 28);
+        }
+    }
+}
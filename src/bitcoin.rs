@@ -0,0 +1,104 @@
+//! Bitcoin-style signed messages (as produced by `signmessage` in Bitcoin Core)
+//! from the same secp256k1 KMS key.
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ethers::prelude::k256::{
+    ecdsa::VerifyingKey,
+    sha2::{Digest, Sha256},
+};
+use ripemd::Ripemd160;
+
+use crate::{sig_from_digest_bytes_trial_recovery, CKMSError, GcpKmsSigner};
+
+const MESSAGE_PREFIX: &[u8] = b"\x18Bitcoin Signed Message:\n";
+
+/// Header byte offset for a recoverable signature over a compressed key
+const COMPRESSED_HEADER: u8 = 27 + 4;
+
+/// Appends a Bitcoin `CompactSize` length prefix to `out`
+fn write_varint(out: &mut Vec<u8>, len: usize) {
+    match len {
+        0..=0xfc => out.push(len as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(len as u16).to_le_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&(len as u64).to_le_bytes());
+        }
+    }
+}
+
+/// Computes the double-sha256 digest of a message with the
+/// "Bitcoin Signed Message" prefix
+pub fn message_hash(message: &[u8]) -> [u8; 32] {
+    let mut data = MESSAGE_PREFIX.to_vec();
+    write_varint(&mut data, message.len());
+    data.extend_from_slice(message);
+    Sha256::digest(Sha256::digest(&data)).into()
+}
+
+/// Returns the P2PKH address of a key's compressed public key, for mainnet or
+/// testnet
+pub fn p2pkh_address(key: &VerifyingKey, mainnet: bool) -> String {
+    let compressed = key.to_encoded_point(true);
+    let hash = Ripemd160::digest(Sha256::digest(compressed.as_bytes()));
+
+    let mut payload = vec![if mainnet { 0x00 } else { 0x6f }];
+    payload.extend_from_slice(&hash);
+    bs58::encode(payload).with_check().into_string()
+}
+
+impl GcpKmsSigner {
+    /// Signs a message in the Bitcoin signed message format, returning the
+    /// base64-encoded 65-byte recoverable signature for the compressed key
+    pub async fn sign_bitcoin_message<S: AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<String, CKMSError> {
+        let digest = message_hash(message.as_ref());
+        let sig = self.sign_digest(digest).await?;
+        // verifiers require low-s, whatever the high-s policy
+        let sig = sig.normalize_s().unwrap_or(sig);
+        let recoverable =
+            sig_from_digest_bytes_trial_recovery(&sig, digest, &self.resolve().await?)?;
+
+        let mut out = Vec::with_capacity(65);
+        out.push(COMPRESSED_HEADER + recoverable.v as u8);
+        out.extend_from_slice(&sig.to_bytes());
+        Ok(STANDARD.encode(out))
+    }
+
+    /// Returns this signer's P2PKH Bitcoin address
+    pub fn bitcoin_address(&self, mainnet: bool) -> String {
+        p2pkh_address(&self.verifying_key(), mainnet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::prelude::k256::ecdsa::SigningKey;
+
+    #[test]
+    fn varint_lengths() {
+        let mut out = Vec::new();
+        write_varint(&mut out, 0xfc);
+        write_varint(&mut out, 0xfd);
+        assert_eq!(out, vec![0xfc, 0xfd, 0xfd, 0x00]);
+    }
+
+    #[test]
+    fn address_of_generator_point() {
+        // private key 1 has the well-known compressed address below
+        let key = SigningKey::from_slice(&[[0u8; 31].as_slice(), &[1]].concat()).unwrap();
+        assert_eq!(
+            p2pkh_address(key.verifying_key(), true),
+            "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH"
+        );
+    }
+}
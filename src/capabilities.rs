@@ -0,0 +1,165 @@
+use ethers::types::Address;
+use serde::Serialize;
+
+use crate::{audit::AuditOperation, GcpKmsSigner, HighSPolicy, TxType};
+
+/// The optional features of this crate which are compiled in
+const FEATURES: &[(&str, bool)] = &[
+    ("alloy", cfg!(feature = "alloy")),
+    ("bigquery", cfg!(feature = "bigquery")),
+    ("bitcoin", cfg!(feature = "bitcoin")),
+    ("cli", cfg!(feature = "cli")),
+    ("cosmos", cfg!(feature = "cosmos")),
+    ("differential", cfg!(feature = "differential")),
+    ("fixtures", cfg!(feature = "fixtures")),
+    ("grpc", cfg!(feature = "grpc")),
+    ("monitoring", cfg!(feature = "monitoring")),
+    ("remote", cfg!(feature = "remote")),
+    ("server", cfg!(feature = "server")),
+    ("siwe", cfg!(feature = "siwe")),
+    ("test-utils", cfg!(feature = "test-utils")),
+    ("tls", cfg!(feature = "tls")),
+    ("web3", cfg!(feature = "web3")),
+];
+
+/// What a signer supports, given the crate's compiled features and the
+/// signer's configuration, for orchestrators to inspect at runtime rather
+/// than inferring it from version numbers
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct Capabilities {
+    pub crate_version: &'static str,
+    /// Optional cargo features compiled in
+    pub features: Vec<&'static str>,
+    pub key_name: String,
+    pub key_version: u64,
+    pub chain_id: u64,
+    /// Unknown for a lazy signer which has not been resolved
+    pub address: Option<Address>,
+    /// Operations the signer's scopes allow
+    pub operations: Vec<AuditOperation>,
+    /// Transaction envelopes the signer will sign
+    pub tx_types: Vec<TxType>,
+    pub typed_data_versions: Vec<&'static str>,
+    pub high_s_policy: HighSPolicy,
+    /// Other active policies, as `rule` or `rule=value`
+    pub policies: Vec<String>,
+    /// Ways this build can serve the signer to other processes
+    pub server_modes: Vec<&'static str>,
+}
+
+impl GcpKmsSigner {
+    /// Describes what this signer supports, without contacting KMS
+    pub fn capabilities(&self) -> Capabilities {
+        let snapshot = self.snapshot();
+
+        let mut operations = vec![
+            AuditOperation::Digest,
+            AuditOperation::Message,
+            AuditOperation::Transaction,
+            AuditOperation::TypedData,
+        ];
+        let mut policies = Vec::new();
+        for guard in &self.scopes {
+            let scope = guard.scope();
+            if let Some(allowed) = &scope.operations {
+                operations.retain(|operation| allowed.contains(operation));
+            }
+            if let Some(chain_ids) = &scope.chain_ids {
+                let chain_ids: Vec<_> = chain_ids.iter().map(ToString::to_string).collect();
+                policies.push(format!("scope_chain_ids={}", chain_ids.join("|")));
+            }
+            if scope.typed_data_domains.is_some() {
+                policies.push("scope_typed_data_domains".to_string());
+            }
+        }
+        if let Some(remaining) = self.remaining_signatures() {
+            policies.push(format!("remaining_signatures={remaining}"));
+        }
+
+        let tx_types = match &snapshot.allowed_tx_types {
+            Some(allowed) => {
+                policies.push("tx_type_allowlist".to_string());
+                allowed.clone()
+            }
+            None => {
+                let mut tx_types = vec![
+                    TxType::Legacy,
+                    TxType::Eip2930,
+                    TxType::Eip1559,
+                    TxType::Eip4844,
+                ];
+                if cfg!(feature = "alloy") {
+                    tx_types.push(TxType::Eip7702);
+                }
+                tx_types
+            }
+        };
+        if let Some(expected) = self.expected_address {
+            policies.push(format!("expected_address={expected:?}"));
+        }
+        if snapshot.strict_chain_id {
+            policies.push("strict_chain_id".to_string());
+        }
+        if snapshot.eip155_message_v {
+            policies.push("eip155_message_v".to_string());
+        }
+        if !snapshot.replay_protection {
+            policies.push("legacy_replay_protection=disabled".to_string());
+        }
+        if self.replay_guard.is_some() {
+            policies.push("typed_data_replay_protection".to_string());
+        }
+
+        Capabilities {
+            crate_version: env!("CARGO_PKG_VERSION"),
+            features: compiled_features(),
+            key_name: self.key_name(),
+            key_version: snapshot.key_version,
+            chain_id: snapshot.chain_id,
+            address: snapshot.address(),
+            operations,
+            tx_types,
+            typed_data_versions: vec!["eip712"],
+            high_s_policy: snapshot.high_s_policy,
+            policies,
+            server_modes: server_modes(),
+        }
+    }
+}
+
+/// The server modes compiled in
+fn server_modes() -> Vec<&'static str> {
+    let mut modes = Vec::new();
+    if cfg!(feature = "server") {
+        modes.push("web3signer");
+        modes.push("jsonrpc");
+        if cfg!(unix) {
+            modes.push("unix");
+        }
+    }
+    if cfg!(feature = "grpc") {
+        modes.push("grpc");
+    }
+    modes
+}
+
+fn compiled_features() -> Vec<&'static str> {
+    FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_compiled_features() {
+        let features = compiled_features();
+        assert_eq!(features.contains(&"bitcoin"), cfg!(feature = "bitcoin"));
+        assert_eq!(features.contains(&"fixtures"), cfg!(feature = "fixtures"));
+    }
+}
@@ -0,0 +1,78 @@
+use gcloud_sdk::{
+    google::cloud::kms::v1::{
+        key_management_service_client::KeyManagementServiceClient, AsymmetricSignRequest,
+        AsymmetricSignResponse, CreateCryptoKeyRequest, CryptoKey, CryptoKeyVersion,
+        GetCryptoKeyVersionRequest, GetPublicKeyRequest, ListCryptoKeyVersionsRequest,
+        ListCryptoKeyVersionsResponse, PublicKey,
+    },
+    GoogleAuthMiddleware,
+};
+use tonic::{Request, Response, Status};
+
+use crate::KmsClient;
+
+/// A KMS client [`GcpKmsProvider::new_with_client`](crate::GcpKmsProvider::new_with_client)
+/// can call through: a [`KmsClient`], or gcloud-sdk's own
+/// `KeyManagementServiceClient<GoogleAuthMiddleware>`
+pub trait KmsApiClient: Clone + Send + Sync + sealed::Sealed {}
+
+impl KmsApiClient for KmsClient {}
+
+impl KmsApiClient for KeyManagementServiceClient<GoogleAuthMiddleware> {}
+
+mod sealed {
+    pub trait Sealed {
+        fn into_client(self) -> super::Client;
+    }
+
+    impl Sealed for crate::KmsClient {
+        fn into_client(self) -> super::Client {
+            super::Client::Kms(self)
+        }
+    }
+
+    impl Sealed for super::KeyManagementServiceClient<super::GoogleAuthMiddleware> {
+        fn into_client(self) -> super::Client {
+            super::Client::Google(self)
+        }
+    }
+}
+
+pub(crate) fn into_client(client: impl KmsApiClient) -> Client {
+    sealed::Sealed::into_client(client)
+}
+
+/// The client a provider calls KMS through. Public only to the sealed
+/// trait; the module is private.
+#[derive(Clone)]
+pub enum Client {
+    Kms(KmsClient),
+    /// A stock gcloud-sdk client, authorized by its own middleware
+    Google(KeyManagementServiceClient<GoogleAuthMiddleware>),
+}
+
+macro_rules! rpcs {
+    ($($method:ident($request:ty) -> $response:ty;)*) => {
+        impl Client {
+            $(
+                pub(crate) async fn $method(
+                    &self,
+                    request: Request<$request>,
+                ) -> Result<Response<$response>, Status> {
+                    match self.clone() {
+                        Client::Kms(mut client) => client.$method(request).await,
+                        Client::Google(mut client) => client.$method(request).await,
+                    }
+                }
+            )*
+        }
+    };
+}
+
+rpcs! {
+    asymmetric_sign(AsymmetricSignRequest) -> AsymmetricSignResponse;
+    create_crypto_key(CreateCryptoKeyRequest) -> CryptoKey;
+    get_crypto_key_version(GetCryptoKeyVersionRequest) -> CryptoKeyVersion;
+    get_public_key(GetPublicKeyRequest) -> PublicKey;
+    list_crypto_key_versions(ListCryptoKeyVersionsRequest) -> ListCryptoKeyVersionsResponse;
+}
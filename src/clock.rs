@@ -0,0 +1,87 @@
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A source of wall-clock time for time-based policies, receipts and audit
+/// timestamps. Swap in a [`ManualClock`] to test time-dependent behaviour
+/// deterministically, or an [`OffsetClock`] to correct a skewed host clock.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    /// Milliseconds since the unix epoch
+    fn now_ms(&self) -> u64 {
+        self.now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default()
+    }
+}
+
+/// The host's system time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock which only moves when told to
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// The system time shifted by a fixed offset
+#[derive(Clone, Copy, Debug)]
+pub enum OffsetClock {
+    Ahead(Duration),
+    Behind(Duration),
+}
+
+impl Clock for OffsetClock {
+    fn now(&self) -> SystemTime {
+        match self {
+            OffsetClock::Ahead(offset) => SystemTime::now() + *offset,
+            OffsetClock::Behind(offset) => SystemTime::now() - *offset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_advances() {
+        let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(1));
+        assert_eq!(clock.now_ms(), 1_000);
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(clock.now_ms(), 1_250);
+    }
+}
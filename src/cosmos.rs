@@ -0,0 +1,64 @@
+//! Cosmos SDK `SIGN_MODE_DIRECT` signing from the same secp256k1 KMS key.
+use bech32::{ToBase32, Variant};
+use ethers::prelude::k256::{
+    ecdsa::VerifyingKey,
+    sha2::{Digest, Sha256},
+};
+use ripemd::Ripemd160;
+
+use crate::{CKMSError, GcpKmsSigner};
+
+/// Computes the digest signed for `SIGN_MODE_DIRECT`: the sha256 of the
+/// protobuf-encoded `SignDoc`
+pub fn sign_doc_digest(sign_doc: &[u8]) -> [u8; 32] {
+    Sha256::digest(sign_doc).into()
+}
+
+/// Returns the bech32 account address of a key for the given human-readable
+/// prefix, e.g. `cosmos` or `osmo`
+pub fn account_address(key: &VerifyingKey, hrp: &str) -> Result<String, CKMSError> {
+    let compressed = key.to_encoded_point(true);
+    let hash = Ripemd160::digest(Sha256::digest(compressed.as_bytes()));
+    bech32::encode(hrp, hash.to_base32(), Variant::Bech32)
+        .map_err(|e| CKMSError::CosmosError(e.to_string()))
+}
+
+impl GcpKmsSigner {
+    /// Signs the bytes of a `SignDoc`, returning the fixed-length 64-byte
+    /// `r || s` signature (with low s) expected in `TxRaw.signatures`
+    pub async fn sign_cosmos_direct(&self, sign_doc: &[u8]) -> Result<[u8; 64], CKMSError> {
+        let sig = self.sign_digest(sign_doc_digest(sign_doc)).await?;
+        // verifiers require low-s, whatever the high-s policy
+        let sig = sig.normalize_s().unwrap_or(sig);
+        Ok(sig.to_bytes().into())
+    }
+
+    /// Returns the 33-byte compressed public key, as used in
+    /// `cosmos.crypto.secp256k1.PubKey`
+    pub fn cosmos_public_key(&self) -> [u8; 33] {
+        let compressed = self.verifying_key().to_encoded_point(true);
+        let mut out = [0u8; 33];
+        out.copy_from_slice(compressed.as_bytes());
+        out
+    }
+
+    /// Returns this signer's bech32 account address for the given prefix
+    pub fn cosmos_address(&self, hrp: &str) -> Result<String, CKMSError> {
+        account_address(&self.verifying_key(), hrp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::prelude::k256::ecdsa::SigningKey;
+
+    #[test]
+    fn address_has_prefix() {
+        let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let address = account_address(key.verifying_key(), "cosmos").unwrap();
+        assert!(address.starts_with("cosmos1"));
+        assert_eq!(address.len(), 45);
+        assert!(account_address(key.verifying_key(), "Invalid Prefix").is_err());
+    }
+}
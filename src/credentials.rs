@@ -0,0 +1,298 @@
+use std::{fmt, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use gcloud_sdk::{GoogleAuthTokenGenerator, TokenSourceType, GCP_DEFAULT_SCOPES};
+use tokio::sync::Mutex;
+
+use crate::CKMSError;
+
+/// A source of Google credentials for the KMS client.
+///
+/// Sources can be combined into an ordered fallback list with
+/// [`GcpKmsProvider::new_with_credential_sources`](crate::GcpKmsProvider::new_with_credential_sources),
+/// so the same construction code works in local development, CI and GKE.
+///
+/// Service account impersonation and workload identity federation are
+/// configured through an `external_account` credentials document passed as
+/// [`CredentialSource::Json`] or [`CredentialSource::File`].
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum CredentialSource {
+    /// A credentials JSON document held in memory
+    Json(String),
+    /// A credentials JSON document read from a file
+    File(PathBuf),
+    /// Application default credentials: `GOOGLE_APPLICATION_CREDENTIALS`, the
+    /// gcloud well-known file, then the metadata server
+    ApplicationDefault,
+    /// The default service account of the GCE/GKE metadata server
+    MetadataServer,
+    /// A named service account of the GCE/GKE metadata server
+    MetadataServerWithAccount(String),
+    /// A fixed OAuth 2.0 access token, such as one printed by
+    /// `gcloud auth print-access-token`, or any token for an emulator which
+    /// does not check it. It is not refreshed.
+    AccessToken(String),
+    /// Access tokens fetched by the application, e.g. from its own STS
+    /// exchange or a secrets broker, for processes without credential files
+    /// or a metadata server
+    TokenProvider(Arc<dyn TokenProvider>),
+}
+
+impl PartialEq for CredentialSource {
+    fn eq(&self, other: &Self) -> bool {
+        use CredentialSource::*;
+        match (self, other) {
+            (Json(a), Json(b)) | (AccessToken(a), AccessToken(b)) => a == b,
+            (File(a), File(b)) => a == b,
+            (ApplicationDefault, ApplicationDefault) | (MetadataServer, MetadataServer) => true,
+            (MetadataServerWithAccount(a), MetadataServerWithAccount(b)) => a == b,
+            (TokenProvider(a), TokenProvider(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for CredentialSource {}
+
+/// Supplies OAuth 2.0 access tokens for
+/// [`CredentialSource::TokenProvider`]. Tokens are cached until 15 seconds
+/// before they expire, so `access_token` is called about once per token
+/// lifetime. An error fails the request which needed the token, and the
+/// next request asks again.
+#[async_trait]
+pub trait TokenProvider: Send + Sync {
+    async fn access_token(&self) -> Result<ProvidedToken, CKMSError>;
+}
+
+/// An access token returned by a [`TokenProvider`]
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct ProvidedToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl ProvidedToken {
+    pub fn new(token: impl Into<String>, expires_at: DateTime<Utc>) -> Self {
+        Self {
+            token: token.into(),
+            expires_at,
+        }
+    }
+}
+
+impl fmt::Debug for ProvidedToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProvidedToken")
+            .field("token", &"..")
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl CredentialSource {
+    /// The fallback order used when no sources are configured explicitly
+    pub fn default_chain() -> Vec<CredentialSource> {
+        vec![CredentialSource::ApplicationDefault]
+    }
+
+    /// Best-effort lookup of the account these credentials belong to: the
+    /// `client_email` of a service account document, or the metadata
+    /// server's service account email
+    pub async fn principal(&self) -> Option<String> {
+        match self {
+            CredentialSource::Json(json) => principal_from_json(json),
+            CredentialSource::File(path) => {
+                principal_from_json(&std::fs::read_to_string(path).ok()?)
+            }
+            CredentialSource::ApplicationDefault => {
+                match std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS") {
+                    Some(path) => principal_from_json(&std::fs::read_to_string(path).ok()?),
+                    None => gcemeta::Client::new().email(None).await.ok(),
+                }
+            }
+            CredentialSource::MetadataServer => gcemeta::Client::new().email(None).await.ok(),
+            CredentialSource::MetadataServerWithAccount(account) => {
+                gcemeta::Client::new().email(Some(account)).await.ok()
+            }
+            CredentialSource::AccessToken(_) | CredentialSource::TokenProvider(_) => None,
+        }
+    }
+}
+
+fn principal_from_json(json: &str) -> Option<String> {
+    let document: serde_json::Value = serde_json::from_str(json).ok()?;
+    document
+        .get("client_email")
+        .and_then(|email| email.as_str())
+        .map(ToString::to_string)
+}
+
+impl fmt::Debug for CredentialSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // never print the credentials themselves
+            CredentialSource::Json(_) => write!(f, "Json(..)"),
+            CredentialSource::File(path) => f.debug_tuple("File").field(path).finish(),
+            CredentialSource::ApplicationDefault => write!(f, "ApplicationDefault"),
+            CredentialSource::MetadataServer => write!(f, "MetadataServer"),
+            CredentialSource::MetadataServerWithAccount(account) => f
+                .debug_tuple("MetadataServerWithAccount")
+                .field(account)
+                .finish(),
+            CredentialSource::AccessToken(_) => write!(f, "AccessToken(..)"),
+            CredentialSource::TokenProvider(_) => write!(f, "TokenProvider(..)"),
+        }
+    }
+}
+
+/// Access tokens for a [`CredentialSource`], cached until shortly before
+/// they expire
+pub(crate) enum Tokens {
+    Google(GoogleAuthTokenGenerator),
+    Provided {
+        provider: Arc<dyn TokenProvider>,
+        cached: Mutex<Option<ProvidedToken>>,
+    },
+}
+
+impl Tokens {
+    /// Loads the credentials of `source`
+    pub(crate) async fn new(source: CredentialSource) -> Result<Self, CKMSError> {
+        let source_type = match source {
+            CredentialSource::Json(json) => TokenSourceType::Json(json),
+            CredentialSource::File(path) => TokenSourceType::File(path),
+            CredentialSource::ApplicationDefault => TokenSourceType::Default,
+            CredentialSource::MetadataServer => TokenSourceType::MetadataServer,
+            CredentialSource::MetadataServerWithAccount(account) => {
+                TokenSourceType::MetadataServerWithAccount(account)
+            }
+            CredentialSource::AccessToken(token) => {
+                return Ok(Self::provided(Arc::new(StaticToken(token))))
+            }
+            CredentialSource::TokenProvider(provider) => return Ok(Self::provided(provider)),
+        };
+        let tokens = GoogleAuthTokenGenerator::new(source_type, GCP_DEFAULT_SCOPES.clone()).await?;
+        Ok(Tokens::Google(tokens))
+    }
+
+    fn provided(provider: Arc<dyn TokenProvider>) -> Self {
+        Tokens::Provided {
+            provider,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// The `authorization` header value for a request
+    pub(crate) async fn authorization(&self) -> Result<String, CKMSError> {
+        let (provider, cached) = match self {
+            Tokens::Google(tokens) => return Ok(tokens.create_token().await?.header_value()),
+            Tokens::Provided { provider, cached } => (provider, cached),
+        };
+        let mut cached = cached.lock().await;
+        // leave time for the request to reach KMS before the token expires
+        let valid_until = Utc::now() + chrono::Duration::seconds(15);
+        match &*cached {
+            Some(token) if token.expires_at > valid_until => Ok(format!("Bearer {}", token.token)),
+            _ => {
+                let token = provider.access_token().await?;
+                let authorization = format!("Bearer {}", token.token);
+                *cached = Some(token);
+                Ok(authorization)
+            }
+        }
+    }
+}
+
+/// The token provider of [`CredentialSource::AccessToken`]
+struct StaticToken(String);
+
+#[async_trait]
+impl TokenProvider for StaticToken {
+    async fn access_token(&self) -> Result<ProvidedToken, CKMSError> {
+        Ok(ProvidedToken::new(
+            self.0.clone(),
+            Utc::now() + chrono::Duration::hours(1),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn principal_from_service_account_json() {
+        let json =
+            r#"{"type": "service_account", "client_email": "signer@p.iam.gserviceaccount.com"}"#;
+        assert_eq!(
+            principal_from_json(json).as_deref(),
+            Some("signer@p.iam.gserviceaccount.com")
+        );
+        assert_eq!(principal_from_json("{}"), None);
+    }
+
+    #[test]
+    fn access_tokens_are_not_printed() {
+        let source = CredentialSource::AccessToken("ya29.secret".to_string());
+        assert_eq!(format!("{source:?}"), "AccessToken(..)");
+    }
+
+    struct Fixed;
+
+    #[async_trait]
+    impl TokenProvider for Fixed {
+        async fn access_token(&self) -> Result<ProvidedToken, CKMSError> {
+            Ok(ProvidedToken::new("ya29.secret", Utc::now()))
+        }
+    }
+
+    #[test]
+    fn token_providers_compare_by_identity() {
+        let provider: Arc<dyn TokenProvider> = Arc::new(Fixed);
+        let source = CredentialSource::TokenProvider(provider.clone());
+        assert_eq!(source, CredentialSource::TokenProvider(provider));
+        assert_ne!(source, CredentialSource::TokenProvider(Arc::new(Fixed)));
+        assert_eq!(format!("{source:?}"), "TokenProvider(..)");
+    }
+
+    /// Hands out tokens valid for `lifetime` seconds, counting them
+    struct Counting {
+        lifetime: i64,
+        fetched: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TokenProvider for Counting {
+        async fn access_token(&self) -> Result<ProvidedToken, CKMSError> {
+            let n = self
+                .fetched
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let expires_at = Utc::now() + chrono::Duration::seconds(self.lifetime);
+            Ok(ProvidedToken::new(format!("token-{n}"), expires_at))
+        }
+    }
+
+    async fn tokens(lifetime: i64) -> Tokens {
+        let provider = Counting {
+            lifetime,
+            fetched: Default::default(),
+        };
+        Tokens::new(CredentialSource::TokenProvider(Arc::new(provider)))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn caches_provided_tokens_until_they_expire() {
+        let long_lived = tokens(3600).await;
+        assert_eq!(long_lived.authorization().await.unwrap(), "Bearer token-0");
+        assert_eq!(long_lived.authorization().await.unwrap(), "Bearer token-0");
+
+        let expiring = tokens(10).await;
+        assert_eq!(expiring.authorization().await.unwrap(), "Bearer token-0");
+        assert_eq!(expiring.authorization().await.unwrap(), "Bearer token-1");
+    }
+}
@@ -0,0 +1,416 @@
+//! A differential test harness which signs generated messages, transactions
+//! of every envelope type and EIP-712 payloads with both a reference
+//! [`LocalWallet`] and a candidate signer, and compares the results.
+//!
+//! Signatures are compared as `(r, s, y-parity)`, since the crate's `v`
+//! encoding deliberately differs from `LocalWallet`'s for typed transactions
+//! and typed data. KMS signatures are randomized, so a candidate backed by
+//! real KMS should be checked with [`Comparison::Equivalent`]; a candidate
+//! using the same deterministic (RFC 6979) key as the reference can be held
+//! to [`Comparison::Identical`]. Run it with [`check`], or combine the
+//! strategies and [`compare`] into your own property tests.
+use std::fmt;
+
+use ethers::{
+    signers::{LocalWallet, Signer},
+    types::{
+        transaction::{
+            eip2718::TypedTransaction,
+            eip2930::{AccessList, AccessListItem},
+            eip712::{Eip712, TypedData},
+        },
+        Address, Eip1559TransactionRequest, Eip2930TransactionRequest, Signature,
+        TransactionRequest, H256,
+    },
+    utils::hash_message,
+};
+use proptest::{
+    prelude::*,
+    test_runner::{Config, TestCaseError, TestError, TestRunner},
+};
+
+use crate::RecoverableSignature;
+
+/// How closely a candidate's signatures must match the reference's
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Comparison {
+    /// The same `(r, s, y-parity)` as the reference
+    Identical,
+    /// A valid signature by the reference's address over the same digest
+    Equivalent,
+}
+
+/// A payload the harness signs
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum Case {
+    Message(Vec<u8>),
+    Transaction(TypedTransaction),
+    TypedData(TypedData),
+}
+
+/// A case on which the candidate disagreed with the reference
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Mismatch {
+    pub case: Case,
+    pub reason: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} for {:?}", self.reason, self.case)
+    }
+}
+
+/// Arbitrary message bytes
+pub fn arb_message() -> impl Strategy<Value = Vec<u8>> {
+    proptest::collection::vec(any::<u8>(), 0..256)
+}
+
+fn arb_address() -> impl Strategy<Value = Address> {
+    any::<[u8; 20]>().prop_map(Address::from)
+}
+
+fn arb_access_list() -> impl Strategy<Value = AccessList> {
+    proptest::collection::vec(
+        (
+            arb_address(),
+            proptest::collection::vec(any::<[u8; 32]>().prop_map(H256::from), 0..3),
+        ),
+        0..3,
+    )
+    .prop_map(|items| {
+        AccessList(
+            items
+                .into_iter()
+                .map(|(address, storage_keys)| AccessListItem {
+                    address,
+                    storage_keys,
+                })
+                .collect(),
+        )
+    })
+}
+
+fn arb_legacy() -> impl Strategy<Value = TransactionRequest> {
+    (
+        any::<u64>(),
+        proptest::option::of(arb_address()),
+        any::<u64>(),
+        any::<u64>(),
+        proptest::collection::vec(any::<u8>(), 0..64),
+        proptest::option::of(1..=u32::MAX as u64 * 4),
+    )
+        .prop_map(|(nonce, to, value, gas_price, data, chain_id)| {
+            let mut tx = TransactionRequest::new()
+                .nonce(nonce)
+                .value(value)
+                .gas(21_000 + data.len() as u64 * 16)
+                .gas_price(gas_price)
+                .data(data);
+            if let Some(to) = to {
+                tx = tx.to(to);
+            }
+            if let Some(chain_id) = chain_id {
+                tx = tx.chain_id(chain_id);
+            }
+            tx
+        })
+}
+
+/// Legacy, EIP-2930 and EIP-1559 transactions, with and without a chain id
+pub fn arb_transaction() -> impl Strategy<Value = TypedTransaction> {
+    prop_oneof![
+        arb_legacy().prop_map(TypedTransaction::Legacy),
+        (arb_legacy(), arb_access_list()).prop_map(|(tx, access_list)| {
+            TypedTransaction::Eip2930(Eip2930TransactionRequest::new(tx, access_list))
+        }),
+        (arb_legacy(), arb_access_list(), any::<u64>(), any::<u64>()).prop_map(
+            |(tx, access_list, max_fee, priority_fee)| {
+                let mut dynamic = Eip1559TransactionRequest::new()
+                    .access_list(access_list)
+                    .max_fee_per_gas(max_fee)
+                    .max_priority_fee_per_gas(priority_fee.min(max_fee));
+                dynamic.nonce = tx.nonce;
+                dynamic.to = tx.to;
+                dynamic.value = tx.value;
+                dynamic.gas = tx.gas;
+                dynamic.data = tx.data;
+                dynamic.chain_id = tx.chain_id;
+                TypedTransaction::Eip1559(dynamic)
+            }
+        ),
+    ]
+}
+
+/// EIP-712 payloads of a fixed mail-like schema with arbitrary domains and
+/// values
+pub fn arb_typed_data() -> impl Strategy<Value = TypedData> {
+    (
+        "[a-zA-Z ]{0,16}",
+        1..=u32::MAX as u64,
+        arb_address(),
+        arb_address(),
+        any::<u64>(),
+        "\\PC{0,64}",
+    )
+        .prop_map(|(name, chain_id, contract, to, amount, contents)| {
+            serde_json::from_value(serde_json::json!({
+                "types": {
+                    "EIP712Domain": [
+                        {"name": "name", "type": "string"},
+                        {"name": "version", "type": "string"},
+                        {"name": "chainId", "type": "uint256"},
+                        {"name": "verifyingContract", "type": "address"}
+                    ],
+                    "Mail": [
+                        {"name": "to", "type": "address"},
+                        {"name": "amount", "type": "uint256"},
+                        {"name": "contents", "type": "string"}
+                    ]
+                },
+                "primaryType": "Mail",
+                "domain": {
+                    "name": name,
+                    "version": "1",
+                    "chainId": chain_id,
+                    "verifyingContract": contract
+                },
+                "message": {"to": to, "amount": amount, "contents": contents}
+            }))
+            .expect("schema is valid")
+        })
+}
+
+/// Any [`Case`]
+pub fn arb_case() -> impl Strategy<Value = Case> {
+    prop_oneof![
+        arb_message().prop_map(Case::Message),
+        arb_transaction().prop_map(Case::Transaction),
+        arb_typed_data().prop_map(Case::TypedData),
+    ]
+}
+
+/// Signs one case with both signers and compares the signatures
+pub async fn compare<S: Signer>(
+    reference: &LocalWallet,
+    candidate: &S,
+    comparison: Comparison,
+    case: &Case,
+) -> Result<(), Mismatch> {
+    let mismatch = |reason: String| Mismatch {
+        case: case.clone(),
+        reason,
+    };
+    if candidate.address() != reference.address() {
+        return Err(mismatch(format!(
+            "candidate address {:?} differs from {:?}",
+            candidate.address(),
+            reference.address()
+        )));
+    }
+
+    let (digest, expected, actual) = match case {
+        Case::Message(message) => (
+            hash_message(message),
+            reference
+                .sign_message(message)
+                .await
+                .map_err(|e| e.to_string()),
+            candidate
+                .sign_message(message)
+                .await
+                .map_err(|e| e.to_string()),
+        ),
+        Case::Transaction(tx) => {
+            let mut with_chain = tx.clone();
+            if with_chain.chain_id().is_none() {
+                with_chain.set_chain_id(reference.chain_id());
+            }
+            (
+                with_chain.sighash(),
+                reference
+                    .sign_transaction(tx)
+                    .await
+                    .map_err(|e| e.to_string()),
+                candidate
+                    .sign_transaction(tx)
+                    .await
+                    .map_err(|e| e.to_string()),
+            )
+        }
+        Case::TypedData(data) => (
+            data.encode_eip712()
+                .map_err(|e| mismatch(e.to_string()))?
+                .into(),
+            reference
+                .sign_typed_data(data)
+                .await
+                .map_err(|e| e.to_string()),
+            candidate
+                .sign_typed_data(data)
+                .await
+                .map_err(|e| e.to_string()),
+        ),
+    };
+    let expected = expected.map_err(|e| mismatch(format!("reference failed: {e}")))?;
+    let actual = actual.map_err(|e| mismatch(format!("candidate failed: {e}")))?;
+    check_signature(reference.address(), digest, &expected, &actual, comparison).map_err(mismatch)
+}
+
+fn check_signature(
+    address: Address,
+    digest: H256,
+    expected: &Signature,
+    actual: &Signature,
+    comparison: Comparison,
+) -> Result<(), String> {
+    let canonical =
+        |sig: &Signature| RecoverableSignature::from_ethers(sig).map_err(|e| e.to_string());
+    let (expected_parts, actual_parts) = (canonical(expected)?, canonical(actual)?);
+
+    match comparison {
+        Comparison::Identical if expected_parts != actual_parts => Err(format!(
+            "candidate signature {actual:?} differs from {expected:?}"
+        )),
+        _ => actual
+            .verify(digest, address)
+            .map_err(|e| format!("candidate signature {actual:?} does not verify: {e}")),
+    }
+}
+
+/// Runs `cases` generated cases against the candidate on a new current-thread
+/// runtime, returning the first (shrunk) mismatch
+pub fn check<S: Signer>(
+    reference: &LocalWallet,
+    candidate: &S,
+    comparison: Comparison,
+    cases: u32,
+) -> Result<(), TestError<Case>> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("tokio runtime");
+    let mut runner = TestRunner::new(Config::with_cases(cases));
+    runner.run(&arb_case(), |case| {
+        runtime
+            .block_on(compare(reference, candidate, comparison, &case))
+            .map_err(|mismatch| TestCaseError::fail(mismatch.reason))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{apply_transaction_v, find_recovery_id, sig_from_digest_bytes_trial_recovery};
+    use async_trait::async_trait;
+    use ethers::prelude::k256::ecdsa::{Signature as KSig, SigningKey};
+
+    /// Signs like `GcpKmsSigner`, but with a local key and RFC 6979 nonces
+    #[derive(Debug)]
+    struct LocalPipeline {
+        key: SigningKey,
+        address: Address,
+        chain_id: u64,
+    }
+
+    impl LocalPipeline {
+        fn sign(&self, digest: H256) -> Signature {
+            let (sig, _): (KSig, _) = self
+                .key
+                .sign_prehash_recoverable(digest.as_bytes())
+                .unwrap();
+            let sig = sig.normalize_s().unwrap_or(sig);
+            assert!(find_recovery_id(&sig, digest.into(), self.key.verifying_key()).is_some());
+            sig_from_digest_bytes_trial_recovery(&sig, digest.into(), self.key.verifying_key())
+                .unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl Signer for LocalPipeline {
+        type Error = crate::CKMSError;
+
+        async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+            &self,
+            message: S,
+        ) -> Result<Signature, Self::Error> {
+            let mut sig = self.sign(hash_message(message));
+            sig.v += 27;
+            Ok(sig)
+        }
+
+        async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+            let mut with_chain = tx.clone();
+            let chain_id = tx.chain_id().map(|id| id.as_u64()).unwrap_or(self.chain_id);
+            with_chain.set_chain_id(chain_id);
+            let mut sig = self.sign(with_chain.sighash());
+            apply_transaction_v(&mut sig, tx, chain_id)?;
+            Ok(sig)
+        }
+
+        async fn sign_typed_data<T: Eip712 + Send + Sync>(
+            &self,
+            payload: &T,
+        ) -> Result<Signature, Self::Error> {
+            let digest = payload
+                .encode_eip712()
+                .map_err(|e| crate::CKMSError::Eip712Error(e.to_string()))?;
+            Ok(self.sign(digest.into()))
+        }
+
+        fn address(&self) -> Address {
+            self.address
+        }
+
+        fn chain_id(&self) -> u64 {
+            self.chain_id
+        }
+
+        fn with_chain_id<T: Into<u64>>(mut self, chain_id: T) -> Self {
+            self.chain_id = chain_id.into();
+            self
+        }
+    }
+
+    #[test]
+    fn local_pipeline_matches_wallet() {
+        let reference = LocalWallet::from_bytes(&[7u8; 32])
+            .unwrap()
+            .with_chain_id(5u64);
+        let candidate = LocalPipeline {
+            key: SigningKey::from_slice(&[7u8; 32]).unwrap(),
+            address: reference.address(),
+            chain_id: 5,
+        };
+        check(&reference, &candidate, Comparison::Identical, 64).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_foreign_signatures() {
+        let reference = LocalWallet::from_bytes(&[7u8; 32]).unwrap();
+        let other = LocalWallet::from_bytes(&[8u8; 32]).unwrap();
+        let expected = reference.sign_hash(H256::zero()).unwrap();
+        let actual = other.sign_hash(H256::zero()).unwrap();
+
+        assert!(check_signature(
+            reference.address(),
+            H256::zero(),
+            &expected,
+            &actual,
+            Comparison::Equivalent
+        )
+        .is_err());
+        assert!(compare(
+            &reference,
+            &other,
+            Comparison::Equivalent,
+            &Case::Message(vec![])
+        )
+        .await
+        .is_err());
+    }
+}
@@ -0,0 +1,330 @@
+use std::convert::Infallible;
+
+use ethers::{
+    abi::{self, Token},
+    signers::Signer,
+    types::{
+        transaction::eip712::{EIP712Domain, Eip712},
+        Address, Bytes, H256, U256,
+    },
+    utils::keccak256,
+};
+use serde::Serialize;
+
+use crate::{CKMSError, GcpKmsSigner};
+
+const ATTEST_TYPE: &str = "Attest(address attester,bytes32 schema,address recipient,uint64 expirationTime,bool revocable,bytes32 refUID,bytes data,uint256 value,uint256 nonce,uint64 deadline)";
+
+const REVOKE_TYPE: &str =
+    "Revoke(address revoker,bytes32 schema,bytes32 uid,uint256 value,uint256 nonce,uint64 deadline)";
+
+/// The domain of the Ethereum Attestation Service contract at `eas`, whose
+/// `version()` is e.g. `1.3.0`
+pub fn eas_domain(version: &str, chain_id: u64, eas: Address) -> EIP712Domain {
+    EIP712Domain {
+        name: Some("EAS".to_string()),
+        version: Some(version.to_string()),
+        chain_id: Some(chain_id.into()),
+        verifying_contract: Some(eas),
+        salt: None,
+    }
+}
+
+/// An attestation which a relayer submits with `attestByDelegation` on
+/// behalf of the signer, its attester
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DelegatedAttestation {
+    pub schema: H256,
+    pub recipient: Address,
+    /// Unix seconds, or 0 for none
+    pub expiration_time: u64,
+    pub revocable: bool,
+    pub ref_uid: H256,
+    pub data: Bytes,
+    pub value: U256,
+    /// The attester's nonce, as the contract's `getNonce` returns it
+    pub nonce: U256,
+    /// Unix seconds after which the signature is refused, or 0 for none
+    pub deadline: u64,
+}
+
+/// A revocation which a relayer submits with `revokeByDelegation` on behalf
+/// of the signer, the attestation's attester
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DelegatedRevocation {
+    pub schema: H256,
+    pub uid: H256,
+    pub value: U256,
+    pub nonce: U256,
+    pub deadline: u64,
+}
+
+/// A signature as the EAS contracts take it, with `v` = 27/28
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct EasSignature {
+    pub v: u8,
+    pub r: H256,
+    pub s: H256,
+}
+
+impl EasSignature {
+    /// The signature as the ABI tuple `(uint8 v, bytes32 r, bytes32 s)`
+    pub fn into_token(self) -> Token {
+        Token::Tuple(vec![
+            Token::Uint(self.v.into()),
+            Token::FixedBytes(self.r.as_bytes().to_vec()),
+            Token::FixedBytes(self.s.as_bytes().to_vec()),
+        ])
+    }
+}
+
+impl From<ethers::types::Signature> for EasSignature {
+    fn from(signature: ethers::types::Signature) -> Self {
+        let mut r = [0u8; 32];
+        let mut s = [0u8; 32];
+        signature.r.to_big_endian(&mut r);
+        signature.s.to_big_endian(&mut s);
+        Self {
+            v: match signature.v {
+                0 | 1 => signature.v as u8 + 27,
+                v => v as u8,
+            },
+            r: r.into(),
+            s: s.into(),
+        }
+    }
+}
+
+/// An EAS request in the contract's domain, signed by `signer`
+struct EasTypedData<'a, T> {
+    domain: &'a EIP712Domain,
+    signer: Address,
+    request: &'a T,
+}
+
+impl Eip712 for EasTypedData<'_, DelegatedAttestation> {
+    type Error = Infallible;
+
+    fn domain(&self) -> Result<EIP712Domain, Infallible> {
+        Ok(self.domain.clone())
+    }
+
+    fn type_hash() -> Result<[u8; 32], Infallible> {
+        Ok(keccak256(ATTEST_TYPE))
+    }
+
+    fn struct_hash(&self) -> Result<[u8; 32], Infallible> {
+        let request = self.request;
+        Ok(keccak256(abi::encode(&[
+            Token::FixedBytes(Self::type_hash()?.to_vec()),
+            Token::Address(self.signer),
+            Token::FixedBytes(request.schema.as_bytes().to_vec()),
+            Token::Address(request.recipient),
+            Token::Uint(request.expiration_time.into()),
+            Token::Bool(request.revocable),
+            Token::FixedBytes(request.ref_uid.as_bytes().to_vec()),
+            Token::FixedBytes(keccak256(&request.data).to_vec()),
+            Token::Uint(request.value),
+            Token::Uint(request.nonce),
+            Token::Uint(request.deadline.into()),
+        ])))
+    }
+}
+
+impl Eip712 for EasTypedData<'_, DelegatedRevocation> {
+    type Error = Infallible;
+
+    fn domain(&self) -> Result<EIP712Domain, Infallible> {
+        Ok(self.domain.clone())
+    }
+
+    fn type_hash() -> Result<[u8; 32], Infallible> {
+        Ok(keccak256(REVOKE_TYPE))
+    }
+
+    fn struct_hash(&self) -> Result<[u8; 32], Infallible> {
+        let request = self.request;
+        Ok(keccak256(abi::encode(&[
+            Token::FixedBytes(Self::type_hash()?.to_vec()),
+            Token::Address(self.signer),
+            Token::FixedBytes(request.schema.as_bytes().to_vec()),
+            Token::FixedBytes(request.uid.as_bytes().to_vec()),
+            Token::Uint(request.value),
+            Token::Uint(request.nonce),
+            Token::Uint(request.deadline.into()),
+        ])))
+    }
+}
+
+impl GcpKmsSigner {
+    /// Signs an attestation for a relayer to submit with `attestByDelegation`
+    /// to the EAS contract whose domain is `domain`, with this signer as
+    /// the attester
+    pub async fn sign_delegated_attestation(
+        &self,
+        attestation: &DelegatedAttestation,
+        domain: &EIP712Domain,
+    ) -> Result<EasSignature, CKMSError> {
+        let typed = EasTypedData {
+            domain,
+            signer: self.resolve_address().await?,
+            request: attestation,
+        };
+        self.sign_typed_data(&typed).await.map(EasSignature::from)
+    }
+
+    /// Signs a revocation for a relayer to submit with `revokeByDelegation`
+    /// to the EAS contract whose domain is `domain`, with this signer as
+    /// the revoker
+    pub async fn sign_delegated_revocation(
+        &self,
+        revocation: &DelegatedRevocation,
+        domain: &EIP712Domain,
+    ) -> Result<EasSignature, CKMSError> {
+        let typed = EasTypedData {
+            domain,
+            signer: self.resolve_address().await?,
+            request: revocation,
+        };
+        self.sign_typed_data(&typed).await.map(EasSignature::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::transaction::eip712::TypedData;
+    use serde_json::json;
+
+    fn typed_data(
+        primary_type: &str,
+        fields: serde_json::Value,
+        message: serde_json::Value,
+    ) -> TypedData {
+        serde_json::from_value(json!({
+            "types": {
+                "EIP712Domain": [
+                    {"name": "name", "type": "string"},
+                    {"name": "version", "type": "string"},
+                    {"name": "chainId", "type": "uint256"},
+                    {"name": "verifyingContract", "type": "address"}
+                ],
+                primary_type: fields
+            },
+            "primaryType": primary_type,
+            "domain": {
+                "name": "EAS",
+                "version": "1.3.0",
+                "chainId": 11155111,
+                "verifyingContract": Address::repeat_byte(0xea)
+            },
+            "message": message
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn attestation_digest_matches_typed_data() {
+        let attester = Address::repeat_byte(1);
+        let attestation = DelegatedAttestation {
+            schema: H256::repeat_byte(0x5c),
+            recipient: Address::repeat_byte(2),
+            revocable: true,
+            data: vec![1, 2, 3].into(),
+            nonce: 7.into(),
+            deadline: 1_700_000_000,
+            ..Default::default()
+        };
+        let expected = typed_data(
+            "Attest",
+            json!([
+                {"name": "attester", "type": "address"},
+                {"name": "schema", "type": "bytes32"},
+                {"name": "recipient", "type": "address"},
+                {"name": "expirationTime", "type": "uint64"},
+                {"name": "revocable", "type": "bool"},
+                {"name": "refUID", "type": "bytes32"},
+                {"name": "data", "type": "bytes"},
+                {"name": "value", "type": "uint256"},
+                {"name": "nonce", "type": "uint256"},
+                {"name": "deadline", "type": "uint64"}
+            ]),
+            json!({
+                "attester": attester,
+                "schema": attestation.schema,
+                "recipient": attestation.recipient,
+                "expirationTime": 0,
+                "revocable": true,
+                "refUID": H256::zero(),
+                "data": "0x010203",
+                "value": 0,
+                "nonce": 7,
+                "deadline": 1_700_000_000u64
+            }),
+        );
+
+        let domain = eas_domain("1.3.0", 11155111, Address::repeat_byte(0xea));
+        let typed = EasTypedData {
+            domain: &domain,
+            signer: attester,
+            request: &attestation,
+        };
+        assert_eq!(
+            typed.encode_eip712().unwrap(),
+            expected.encode_eip712().unwrap()
+        );
+    }
+
+    #[test]
+    fn revocation_digest_matches_typed_data() {
+        let revoker = Address::repeat_byte(1);
+        let revocation = DelegatedRevocation {
+            schema: H256::repeat_byte(0x5c),
+            uid: H256::repeat_byte(0x1d),
+            nonce: 8.into(),
+            ..Default::default()
+        };
+        let expected = typed_data(
+            "Revoke",
+            json!([
+                {"name": "revoker", "type": "address"},
+                {"name": "schema", "type": "bytes32"},
+                {"name": "uid", "type": "bytes32"},
+                {"name": "value", "type": "uint256"},
+                {"name": "nonce", "type": "uint256"},
+                {"name": "deadline", "type": "uint64"}
+            ]),
+            json!({
+                "revoker": revoker,
+                "schema": revocation.schema,
+                "uid": revocation.uid,
+                "value": 0,
+                "nonce": 8,
+                "deadline": 0
+            }),
+        );
+
+        let domain = eas_domain("1.3.0", 11155111, Address::repeat_byte(0xea));
+        let typed = EasTypedData {
+            domain: &domain,
+            signer: revoker,
+            request: &revocation,
+        };
+        assert_eq!(
+            typed.encode_eip712().unwrap(),
+            expected.encode_eip712().unwrap()
+        );
+    }
+
+    #[test]
+    fn signature_uses_27_28_v() {
+        let signature = ethers::types::Signature {
+            r: 1.into(),
+            s: 2.into(),
+            v: 1,
+        };
+        let eas = EasSignature::from(signature);
+        assert_eq!(eas.v, 28);
+        assert_eq!(eas.r, H256::from_low_u64_be(1));
+    }
+}
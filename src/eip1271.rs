@@ -0,0 +1,93 @@
+use ethers::{
+    abi::{self, Token},
+    providers::{Middleware, MiddlewareError},
+    types::{Address, Bytes, TransactionRequest, H256},
+};
+
+use crate::Eip6492Signature;
+
+/// The selector of `isValidSignature(bytes32,bytes)`, which a contract
+/// wallet returns for a valid signature
+pub const EIP1271_MAGIC_VALUE: [u8; 4] = [0x16, 0x26, 0xba, 0x7e];
+
+/// Checks a signature over `hash` with the contract wallet at `wallet`,
+/// such as a Safe, by calling its `isValidSignature`.
+///
+/// A revert or a result other than the magic value means the signature is
+/// invalid, which includes `wallet` having no code. Only failures to reach
+/// the node are errors. An EIP-6492 signature is checked by its inner
+/// signature, so the account must already be deployed.
+pub async fn verify_eip1271<M: Middleware>(
+    provider: &M,
+    wallet: Address,
+    hash: H256,
+    signature: &[u8],
+) -> Result<bool, M::Error> {
+    let signature = match Eip6492Signature::decode(signature) {
+        Ok(Some(wrapped)) => wrapped.signature,
+        Ok(None) => Bytes::from(signature.to_vec()),
+        Err(_) => return Ok(false),
+    };
+    let mut data = EIP1271_MAGIC_VALUE.to_vec();
+    data.extend(abi::encode(&[
+        Token::FixedBytes(hash.as_bytes().to_vec()),
+        Token::Bytes(signature.to_vec()),
+    ]));
+    let call = TransactionRequest::new().to(wallet).data(data);
+
+    match provider.call(&call.into(), None).await {
+        Ok(result) => Ok(result.len() >= 4 && result[..4] == EIP1271_MAGIC_VALUE),
+        Err(e) if e.as_error_response().is_some() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::{JsonRpcError, MockResponse, Provider};
+
+    fn returned(value: [u8; 4]) -> Bytes {
+        let mut word = value.to_vec();
+        word.resize(32, 0);
+        word.into()
+    }
+
+    #[tokio::test]
+    async fn checks_the_magic_value() {
+        let (provider, mock) = Provider::mocked();
+        let wallet = Address::repeat_byte(0x5a);
+        let hash = H256::repeat_byte(1);
+
+        mock.push::<Bytes, _>(returned(EIP1271_MAGIC_VALUE))
+            .unwrap();
+        assert!(verify_eip1271(&provider, wallet, hash, &[0; 65])
+            .await
+            .unwrap());
+
+        mock.push::<Bytes, _>(returned([0xff; 4])).unwrap();
+        assert!(!verify_eip1271(&provider, wallet, hash, &[0; 65])
+            .await
+            .unwrap());
+
+        // no code at the address
+        mock.push::<Bytes, _>(Bytes::new()).unwrap();
+        assert!(!verify_eip1271(&provider, wallet, hash, &[0; 65])
+            .await
+            .unwrap());
+
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: 3,
+            message: "execution reverted: GS026".to_string(),
+            data: None,
+        }));
+        assert!(!verify_eip1271(&provider, wallet, hash, &[0; 65])
+            .await
+            .unwrap());
+
+        // no response at all is a transport failure
+        assert!(verify_eip1271(&provider, wallet, hash, &[0; 65])
+            .await
+            .is_err());
+    }
+}
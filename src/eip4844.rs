@@ -0,0 +1,334 @@
+//! EIP-4844 blob transactions, which ethers' `TypedTransaction` does not
+//! model. The signer signs the transaction payload; the blobs travel beside
+//! it in a sidecar which the signature does not cover.
+
+use ethers::{
+    prelude::k256::sha2::{Digest, Sha256},
+    types::{transaction::eip2930::AccessList, Address, Bytes, Signature, H256, U256, U64},
+    utils::{keccak256, rlp::RlpStream},
+};
+
+use crate::{y_parity_from_v, CKMSError, GcpKmsSigner, TxType};
+
+/// The EIP-2718 type byte of blob transactions
+const BLOB_TX_TYPE: u8 = 0x03;
+
+/// The version byte of a versioned hash of a KZG commitment
+const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+
+/// The versioned hash of a blob's KZG commitment:
+/// `0x01 || sha256(commitment)[1..]`
+pub fn kzg_to_versioned_hash(commitment: &[u8]) -> H256 {
+    let mut hash: [u8; 32] = Sha256::digest(commitment).into();
+    hash[0] = VERSIONED_HASH_VERSION_KZG;
+    hash.into()
+}
+
+/// The payload of a type 0x03 blob transaction, without its sidecar.
+///
+/// Blob transactions cannot create contracts, so `to` is required, as are
+/// `max_fee_per_blob_gas` and at least one blob versioned hash. Other fields
+/// left unset are encoded as zero or empty, as ethers does for its requests.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Eip4844TransactionRequest {
+    /// Defaults to the signer's chain id when signing
+    pub chain_id: Option<U64>,
+    pub nonce: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub max_fee_per_gas: Option<U256>,
+    pub gas: Option<U256>,
+    pub to: Option<Address>,
+    pub value: Option<U256>,
+    pub data: Option<Bytes>,
+    pub access_list: AccessList,
+    pub max_fee_per_blob_gas: Option<U256>,
+    pub blob_versioned_hashes: Vec<H256>,
+}
+
+fn invalid(reason: impl Into<String>) -> CKMSError {
+    CKMSError::InvalidBlobTransaction(reason.into())
+}
+
+fn append_opt<T: ethers::utils::rlp::Encodable>(rlp: &mut RlpStream, value: Option<&T>) {
+    match value {
+        Some(value) => rlp.append(value),
+        None => rlp.append(&""),
+    };
+}
+
+impl Eip4844TransactionRequest {
+    /// Checks the fields which blob transactions require
+    pub fn validate(&self) -> Result<(), CKMSError> {
+        if self.to.is_none() {
+            return Err(invalid(
+                "missing `to`, blob transactions cannot create contracts",
+            ));
+        }
+        if self.max_fee_per_blob_gas.is_none() {
+            return Err(invalid("missing `max_fee_per_blob_gas`"));
+        }
+        if self.blob_versioned_hashes.is_empty() {
+            return Err(invalid("missing `blob_versioned_hashes`"));
+        }
+        for (index, hash) in self.blob_versioned_hashes.iter().enumerate() {
+            if hash[0] != VERSIONED_HASH_VERSION_KZG {
+                return Err(invalid(format!(
+                    "blob versioned hash {index} has version {:#04x}, expected {VERSIONED_HASH_VERSION_KZG:#04x}",
+                    hash[0]
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn rlp_append_fields(&self, rlp: &mut RlpStream) {
+        append_opt(rlp, self.chain_id.as_ref());
+        append_opt(rlp, self.nonce.as_ref());
+        append_opt(rlp, self.max_priority_fee_per_gas.as_ref());
+        append_opt(rlp, self.max_fee_per_gas.as_ref());
+        append_opt(rlp, self.gas.as_ref());
+        append_opt(rlp, self.to.as_ref());
+        append_opt(rlp, self.value.as_ref());
+        rlp.append(&self.data.as_deref().unwrap_or_default());
+        rlp.append(&self.access_list);
+        append_opt(rlp, self.max_fee_per_blob_gas.as_ref());
+        rlp.append_list(&self.blob_versioned_hashes);
+    }
+
+    fn rlp_append_signed(
+        &self,
+        rlp: &mut RlpStream,
+        signature: &Signature,
+    ) -> Result<(), CKMSError> {
+        let y_parity = y_parity_from_v(signature.v)?;
+        rlp.begin_list(14);
+        self.rlp_append_fields(rlp);
+        rlp.append(&u8::from(y_parity));
+        rlp.append(&signature.r);
+        rlp.append(&signature.s);
+        Ok(())
+    }
+
+    /// The hash signed for the transaction:
+    /// `keccak256(0x03 || rlp([chain_id, ..., blob_versioned_hashes]))`
+    pub fn sighash(&self) -> H256 {
+        let mut rlp = RlpStream::new();
+        rlp.begin_list(11);
+        self.rlp_append_fields(&mut rlp);
+        keccak256([&[BLOB_TX_TYPE], rlp.as_raw()].concat()).into()
+    }
+
+    /// The signed transaction without its sidecar, as blocks contain it and
+    /// from which its hash is computed
+    pub fn rlp_signed(&self, signature: &Signature) -> Result<Bytes, CKMSError> {
+        let mut rlp = RlpStream::new();
+        self.rlp_append_signed(&mut rlp, signature)?;
+        Ok([&[BLOB_TX_TYPE], rlp.as_raw()].concat().into())
+    }
+
+    /// The signed transaction with its sidecar, as `eth_sendRawTransaction`
+    /// takes it. The sidecar's commitments must match the transaction's blob
+    /// versioned hashes.
+    pub fn rlp_signed_with_sidecar(
+        &self,
+        signature: &Signature,
+        sidecar: &BlobSidecar,
+    ) -> Result<Bytes, CKMSError> {
+        let lengths = [
+            sidecar.blobs.len(),
+            sidecar.commitments.len(),
+            sidecar.proofs.len(),
+        ];
+        if lengths
+            .iter()
+            .any(|len| *len != self.blob_versioned_hashes.len())
+        {
+            return Err(invalid(format!(
+                "sidecar has {lengths:?} blobs, commitments and proofs for {} blob versioned hashes",
+                self.blob_versioned_hashes.len()
+            )));
+        }
+        if sidecar.versioned_hashes() != self.blob_versioned_hashes {
+            return Err(invalid(
+                "sidecar commitments do not match the blob versioned hashes",
+            ));
+        }
+
+        let mut rlp = RlpStream::new();
+        rlp.begin_list(4);
+        self.rlp_append_signed(&mut rlp, signature)?;
+        for items in [&sidecar.blobs, &sidecar.commitments, &sidecar.proofs] {
+            rlp.begin_list(items.len());
+            for item in items {
+                rlp.append(&item.as_ref());
+            }
+        }
+        Ok([&[BLOB_TX_TYPE], rlp.as_raw()].concat().into())
+    }
+}
+
+/// The blobs of a blob transaction, with their KZG commitments and proofs
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlobSidecar {
+    pub blobs: Vec<Bytes>,
+    pub commitments: Vec<Bytes>,
+    pub proofs: Vec<Bytes>,
+}
+
+impl BlobSidecar {
+    /// The versioned hashes of the commitments, for the transaction's
+    /// `blob_versioned_hashes`
+    pub fn versioned_hashes(&self) -> Vec<H256> {
+        self.commitments
+            .iter()
+            .map(|commitment| kzg_to_versioned_hash(commitment))
+            .collect()
+    }
+}
+
+impl GcpKmsSigner {
+    /// Signs a blob transaction, with `v` = the bare y-parity. The signature
+    /// is subject to the signer's transaction policies, with
+    /// [`TxType::Eip4844`] as the envelope type, and a transaction missing
+    /// blob fields is refused with [`CKMSError::InvalidBlobTransaction`].
+    pub async fn sign_blob_transaction(
+        &self,
+        tx: &Eip4844TransactionRequest,
+    ) -> Result<Signature, CKMSError> {
+        let snapshot = self.snapshot();
+        let chain_id = tx.chain_id.map_or(snapshot.chain_id, |id| id.as_u64());
+        let tx = Eip4844TransactionRequest {
+            chain_id: Some(chain_id.into()),
+            ..tx.clone()
+        };
+        self.sign_envelope_sighash(
+            &snapshot,
+            tx.sighash(),
+            TxType::Eip4844,
+            Some(chain_id),
+            tx.validate(),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        utils::rlp::Rlp,
+    };
+
+    fn commitment() -> Bytes {
+        vec![0xc0; 48].into()
+    }
+
+    fn blob_tx() -> Eip4844TransactionRequest {
+        Eip4844TransactionRequest {
+            chain_id: Some(1.into()),
+            nonce: Some(3.into()),
+            max_fee_per_gas: Some(100.into()),
+            gas: Some(21_000.into()),
+            to: Some(Address::repeat_byte(0xbb)),
+            max_fee_per_blob_gas: Some(10.into()),
+            blob_versioned_hashes: vec![kzg_to_versioned_hash(&commitment())],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rejects_missing_blob_fields() {
+        assert!(blob_tx().validate().is_ok());
+        let cases = [
+            Eip4844TransactionRequest {
+                to: None,
+                ..blob_tx()
+            },
+            Eip4844TransactionRequest {
+                max_fee_per_blob_gas: None,
+                ..blob_tx()
+            },
+            Eip4844TransactionRequest {
+                blob_versioned_hashes: Vec::new(),
+                ..blob_tx()
+            },
+            Eip4844TransactionRequest {
+                blob_versioned_hashes: vec![H256::repeat_byte(2)],
+                ..blob_tx()
+            },
+        ];
+        for tx in cases {
+            assert!(matches!(
+                tx.validate(),
+                Err(CKMSError::InvalidBlobTransaction(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn sighash_commits_to_blob_fields() {
+        let sighash = blob_tx().sighash();
+        let cheaper = Eip4844TransactionRequest {
+            max_fee_per_blob_gas: Some(9.into()),
+            ..blob_tx()
+        };
+        assert_ne!(cheaper.sighash(), sighash);
+        let other_chain = Eip4844TransactionRequest {
+            chain_id: Some(5.into()),
+            ..blob_tx()
+        };
+        assert_ne!(other_chain.sighash(), sighash);
+    }
+
+    #[test]
+    fn signed_encoding_recovers() {
+        let wallet = LocalWallet::from_bytes(&[7u8; 32]).unwrap();
+        let tx = blob_tx();
+        let signature = wallet.sign_hash(tx.sighash()).unwrap();
+
+        let raw = tx.rlp_signed(&signature).unwrap();
+        assert_eq!(raw[0], BLOB_TX_TYPE);
+        let rlp = Rlp::new(&raw[1..]);
+        assert_eq!(rlp.item_count().unwrap(), 14);
+        let y_parity: u8 = rlp.val_at(11).unwrap();
+        assert_eq!(u64::from(y_parity), signature.v - 27);
+        let decoded = Signature {
+            r: rlp.val_at(12).unwrap(),
+            s: rlp.val_at(13).unwrap(),
+            v: signature.v,
+        };
+        assert_eq!(decoded.recover(tx.sighash()).unwrap(), wallet.address());
+    }
+
+    #[test]
+    fn sidecar_must_match_versioned_hashes() {
+        let wallet = LocalWallet::from_bytes(&[7u8; 32]).unwrap();
+        let tx = blob_tx();
+        let signature = wallet.sign_hash(tx.sighash()).unwrap();
+        let sidecar = BlobSidecar {
+            blobs: vec![vec![0; 8].into()],
+            commitments: vec![commitment()],
+            proofs: vec![vec![0xaa; 48].into()],
+        };
+
+        let raw = tx.rlp_signed_with_sidecar(&signature, &sidecar).unwrap();
+        let rlp = Rlp::new(&raw[1..]);
+        assert_eq!(rlp.item_count().unwrap(), 4);
+        assert_eq!(
+            rlp.at(0).unwrap().as_raw(),
+            &tx.rlp_signed(&signature).unwrap()[1..]
+        );
+
+        let mismatched = BlobSidecar {
+            commitments: vec![vec![0xc1; 48].into()],
+            ..sidecar.clone()
+        };
+        assert!(tx.rlp_signed_with_sidecar(&signature, &mismatched).is_err());
+        let short = BlobSidecar {
+            proofs: Vec::new(),
+            ..sidecar
+        };
+        assert!(tx.rlp_signed_with_sidecar(&signature, &short).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use ethers::{
+    abi::{self, ParamType, Token},
+    signers::Signer,
+    types::{Address, Bytes},
+};
+
+use crate::{CKMSError, GcpKmsSigner};
+
+/// The suffix which marks an EIP-6492 signature
+pub const EIP6492_MAGIC_SUFFIX: [u8; 32] = [
+    0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92,
+    0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92,
+];
+
+/// A signature for a smart account which may not be deployed yet, with the
+/// factory call which deploys it, so that verifiers can simulate the
+/// deployment before calling its `isValidSignature`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Eip6492Signature {
+    pub factory: Address,
+    pub factory_calldata: Bytes,
+    /// The signature the deployed account validates
+    pub signature: Bytes,
+}
+
+impl Eip6492Signature {
+    /// `abi.encode(factory, factoryCalldata, signature) || magicSuffix`
+    pub fn encode(&self) -> Bytes {
+        let mut encoded = abi::encode(&[
+            Token::Address(self.factory),
+            Token::Bytes(self.factory_calldata.to_vec()),
+            Token::Bytes(self.signature.to_vec()),
+        ]);
+        encoded.extend_from_slice(&EIP6492_MAGIC_SUFFIX);
+        encoded.into()
+    }
+
+    /// Unwraps an EIP-6492 signature, or returns `None` for a signature
+    /// without the magic suffix, which verifiers check as is
+    pub fn decode(signature: &[u8]) -> Result<Option<Self>, CKMSError> {
+        let Some(wrapped) = signature.strip_suffix(&EIP6492_MAGIC_SUFFIX) else {
+            return Ok(None);
+        };
+        let tokens = abi::decode(
+            &[ParamType::Address, ParamType::Bytes, ParamType::Bytes],
+            wrapped,
+        )
+        .map_err(|e| CKMSError::InvalidSignature(format!("malformed EIP-6492 signature: {e}")))?;
+        match tokens.as_slice() {
+            [Token::Address(factory), Token::Bytes(factory_calldata), Token::Bytes(signature)] => {
+                Ok(Some(Self {
+                    factory: *factory,
+                    factory_calldata: factory_calldata.clone().into(),
+                    signature: signature.clone().into(),
+                }))
+            }
+            _ => unreachable!("decoded tokens match the requested types"),
+        }
+    }
+}
+
+impl GcpKmsSigner {
+    /// Signs a message as the owner of a smart account which `factory`
+    /// deploys when called with `factory_calldata`, returning the EIP-6492
+    /// envelope around the EIP-191 personal signature. Use
+    /// [`Eip6492Signature`] to wrap other signatures, such as typed data.
+    pub async fn sign_message_eip6492(
+        &self,
+        message: impl AsRef<[u8]> + Send + Sync,
+        factory: Address,
+        factory_calldata: Bytes,
+    ) -> Result<Bytes, CKMSError> {
+        let signature = self.sign_message(message).await?;
+        Ok(Eip6492Signature {
+            factory,
+            factory_calldata,
+            signature: signature.to_vec().into(),
+        }
+        .encode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_passes_plain_signatures_through() {
+        let wrapped = Eip6492Signature {
+            factory: Address::repeat_byte(0xfa),
+            factory_calldata: vec![0x5f, 0xbf, 0xb9, 0xcf, 1, 2, 3].into(),
+            signature: vec![0x11; 65].into(),
+        };
+        let encoded = wrapped.encode();
+        assert!(encoded.ends_with(&EIP6492_MAGIC_SUFFIX));
+        assert_eq!(&encoded[12..32], wrapped.factory.as_bytes());
+        assert_eq!(Eip6492Signature::decode(&encoded).unwrap(), Some(wrapped));
+
+        assert_eq!(Eip6492Signature::decode(&[0x11; 65]).unwrap(), None);
+        assert!(Eip6492Signature::decode(&EIP6492_MAGIC_SUFFIX).is_err());
+    }
+}
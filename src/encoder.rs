@@ -0,0 +1,90 @@
+use std::fmt;
+
+use ethers::types::{transaction::eip2718::TypedTransaction, Bytes, Signature};
+use serde_json::{json, Value};
+
+use crate::CKMSError;
+
+/// Serializes signed transactions for
+/// [`GcpKmsSigner::sign_transaction_raw`](crate::GcpKmsSigner::sign_transaction_raw).
+///
+/// Networks whose signed transaction encoding differs from mainnet's (extra
+/// RLP fields, other type bytes) can supply their own encoder, while the
+/// signer still computes the sighash and signature.
+pub trait TransactionEncoder: fmt::Debug + Send + Sync {
+    fn encode(&self, tx: &TypedTransaction, signature: &Signature) -> Result<Bytes, CKMSError>;
+}
+
+/// The standard EIP-2718 encoding, as produced by
+/// [`TypedTransaction::rlp_signed`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StandardEncoder;
+
+impl TransactionEncoder for StandardEncoder {
+    fn encode(&self, tx: &TypedTransaction, signature: &Signature) -> Result<Bytes, CKMSError> {
+        Ok(tx.rlp_signed(signature))
+    }
+}
+
+/// Parses a transaction object, which, as wallets send it, usually has no
+/// `type`; its type is then inferred from its fee fields
+pub fn parse_transaction(mut tx: Value) -> Result<TypedTransaction, serde_json::Error> {
+    if let Some(fields) = tx.as_object_mut() {
+        if !fields.contains_key("type") {
+            let tx_type = if fields.contains_key("maxFeePerGas")
+                || fields.contains_key("maxPriorityFeePerGas")
+            {
+                "0x02"
+            } else if fields.contains_key("accessList") {
+                "0x01"
+            } else {
+                "0x00"
+            };
+            fields.insert("type".to_string(), json!(tx_type));
+        }
+    }
+    serde_json::from_value(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::{Address, Eip1559TransactionRequest},
+        utils::rlp::Rlp,
+    };
+
+    #[tokio::test]
+    async fn standard_encoding_decodes() {
+        let wallet = LocalWallet::from_bytes(&[7u8; 32]).unwrap();
+        let tx: TypedTransaction = Eip1559TransactionRequest::new().nonce(3).chain_id(1).into();
+        let signature = wallet.sign_transaction(&tx).await.unwrap();
+
+        let raw = StandardEncoder.encode(&tx, &signature).unwrap();
+        let (decoded, decoded_sig) = TypedTransaction::decode_signed(&Rlp::new(&raw)).unwrap();
+        assert_eq!(decoded.sighash(), tx.sighash());
+        assert_eq!((decoded_sig.r, decoded_sig.s), (signature.r, signature.s));
+        assert_eq!(
+            decoded_sig.recover(decoded.sighash()).unwrap(),
+            wallet.address()
+        );
+    }
+
+    #[test]
+    fn infers_transaction_types() {
+        let from = format!("{:?}", Address::repeat_byte(1));
+        let legacy = parse_transaction(json!({"from": from, "gasPrice": "0x1"})).unwrap();
+        assert!(matches!(legacy, TypedTransaction::Legacy(_)));
+        let eip2930 = parse_transaction(json!({"from": from, "accessList": []})).unwrap();
+        assert!(matches!(eip2930, TypedTransaction::Eip2930(_)));
+        let eip1559 = parse_transaction(json!({
+            "from": from,
+            "maxFeePerGas": "0x2",
+            "maxPriorityFeePerGas": "0x1",
+        }))
+        .unwrap();
+        assert!(matches!(eip1559, TypedTransaction::Eip1559(_)));
+        assert_eq!(eip1559.from(), Some(&Address::repeat_byte(1)));
+    }
+}
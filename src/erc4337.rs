@@ -0,0 +1,411 @@
+//! ERC-4337 user operations, signed by a KMS key which owns a smart account.
+use ethers::{
+    abi::{self, Token},
+    types::{Address, Bytes, Signature, H256, U256},
+    utils::{hash_message, keccak256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{CKMSError, GcpKmsSigner};
+
+mod account;
+pub use account::{AccountOperation, ManagedAccount};
+
+/// A user operation for the v0.6 entry point, as bundlers' JSON-RPC APIs
+/// serialize it
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperation {
+    pub sender: Address,
+    pub nonce: U256,
+    pub init_code: Bytes,
+    pub call_data: Bytes,
+    pub call_gas_limit: U256,
+    pub verification_gas_limit: U256,
+    pub pre_verification_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub paymaster_and_data: Bytes,
+    pub signature: Bytes,
+}
+
+/// A user operation for the v0.7 entry point, with its gas limits and fees
+/// packed in pairs of 16-byte values
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackedUserOperation {
+    pub sender: Address,
+    pub nonce: U256,
+    pub init_code: Bytes,
+    pub call_data: Bytes,
+    /// `verificationGasLimit || callGasLimit`
+    pub account_gas_limits: H256,
+    pub pre_verification_gas: U256,
+    /// `maxPriorityFeePerGas || maxFeePerGas`
+    pub gas_fees: H256,
+    pub paymaster_and_data: Bytes,
+    pub signature: Bytes,
+}
+
+/// A user operation whose `userOpHash` an entry point can compute
+pub trait UserOperationHash {
+    /// The hash of the operation's fields other than its signature, as
+    /// `EntryPoint.getUserOpHash` computes it
+    fn user_op_hash(&self, entry_point: Address, chain_id: u64) -> H256;
+}
+
+/// Binds the hash of a packed operation to an entry point and chain
+fn user_op_hash(packed: Vec<Token>, entry_point: Address, chain_id: u64) -> H256 {
+    let inner = keccak256(abi::encode(&packed));
+    keccak256(abi::encode(&[
+        Token::FixedBytes(inner.to_vec()),
+        Token::Address(entry_point),
+        Token::Uint(chain_id.into()),
+    ]))
+    .into()
+}
+
+fn hashed(bytes: &Bytes) -> Token {
+    Token::FixedBytes(keccak256(bytes).to_vec())
+}
+
+impl UserOperationHash for UserOperation {
+    fn user_op_hash(&self, entry_point: Address, chain_id: u64) -> H256 {
+        let packed = vec![
+            Token::Address(self.sender),
+            Token::Uint(self.nonce),
+            hashed(&self.init_code),
+            hashed(&self.call_data),
+            Token::Uint(self.call_gas_limit),
+            Token::Uint(self.verification_gas_limit),
+            Token::Uint(self.pre_verification_gas),
+            Token::Uint(self.max_fee_per_gas),
+            Token::Uint(self.max_priority_fee_per_gas),
+            hashed(&self.paymaster_and_data),
+        ];
+        user_op_hash(packed, entry_point, chain_id)
+    }
+}
+
+impl UserOperationHash for PackedUserOperation {
+    fn user_op_hash(&self, entry_point: Address, chain_id: u64) -> H256 {
+        let packed = vec![
+            Token::Address(self.sender),
+            Token::Uint(self.nonce),
+            hashed(&self.init_code),
+            hashed(&self.call_data),
+            Token::FixedBytes(self.account_gas_limits.as_bytes().to_vec()),
+            Token::Uint(self.pre_verification_gas),
+            Token::FixedBytes(self.gas_fees.as_bytes().to_vec()),
+            hashed(&self.paymaster_and_data),
+        ];
+        user_op_hash(packed, entry_point, chain_id)
+    }
+}
+
+/// The largest timestamp a paymaster's `uint48` validity fields hold
+const MAX_UINT48: u64 = (1 << 48) - 1;
+
+/// When a paymaster's sponsorship is valid, in unix seconds. A `valid_until`
+/// of 0 means indefinitely.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ValidityWindow {
+    pub valid_until: u64,
+    pub valid_after: u64,
+}
+
+impl ValidityWindow {
+    /// `abi.encode(uint48 validUntil, uint48 validAfter)`
+    fn encode(&self) -> Result<Vec<u8>, CKMSError> {
+        for (name, value) in [
+            ("valid_until", self.valid_until),
+            ("valid_after", self.valid_after),
+        ] {
+            if value > MAX_UINT48 {
+                return Err(CKMSError::UserOperationError(format!(
+                    "{name} {value} does not fit in a uint48"
+                )));
+            }
+        }
+        Ok(abi::encode(&self.tokens()))
+    }
+
+    fn tokens(&self) -> [Token; 2] {
+        [
+            Token::Uint(self.valid_until.into()),
+            Token::Uint(self.valid_after.into()),
+        ]
+    }
+}
+
+/// The paymaster's own gas limits, which v0.7 user operations carry at the
+/// start of `paymasterAndData`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PaymasterGasLimits {
+    pub verification_gas_limit: u128,
+    pub post_op_gas_limit: u128,
+}
+
+impl PaymasterGasLimits {
+    /// `paymasterVerificationGasLimit || paymasterPostOpGasLimit`
+    fn packed(&self) -> [u8; 32] {
+        let mut packed = [0u8; 32];
+        packed[..16].copy_from_slice(&self.verification_gas_limit.to_be_bytes());
+        packed[16..].copy_from_slice(&self.post_op_gas_limit.to_be_bytes());
+        packed
+    }
+}
+
+/// A user operation which a verifying paymaster can sponsor
+pub trait PaymasterHash {
+    /// What the paymaster hash covers besides the operation: for v0.6, the
+    /// paymaster's `senderNonce` for the sender; for v0.7, the
+    /// [`PaymasterGasLimits`] which `paymasterAndData` holds between the
+    /// paymaster's address and its validity window
+    type Params: Copy;
+
+    /// The hash the eth-infinitism `VerifyingPaymaster` signs, as its
+    /// `getHash` computes it
+    fn paymaster_hash(
+        &self,
+        paymaster: Address,
+        params: Self::Params,
+        chain_id: u64,
+        validity: ValidityWindow,
+    ) -> H256;
+
+    /// The `paymasterAndData` prefix which precedes the validity window
+    fn paymaster_prefix(paymaster: Address, params: Self::Params) -> Vec<u8>;
+}
+
+impl PaymasterHash for UserOperation {
+    type Params = U256;
+
+    fn paymaster_hash(
+        &self,
+        paymaster: Address,
+        sender_nonce: U256,
+        chain_id: u64,
+        validity: ValidityWindow,
+    ) -> H256 {
+        let mut tokens = vec![
+            Token::Address(self.sender),
+            Token::Uint(self.nonce),
+            hashed(&self.init_code),
+            hashed(&self.call_data),
+            Token::Uint(self.call_gas_limit),
+            Token::Uint(self.verification_gas_limit),
+            Token::Uint(self.pre_verification_gas),
+            Token::Uint(self.max_fee_per_gas),
+            Token::Uint(self.max_priority_fee_per_gas),
+            Token::Uint(chain_id.into()),
+            Token::Address(paymaster),
+            Token::Uint(sender_nonce),
+        ];
+        tokens.extend(validity.tokens());
+        keccak256(abi::encode(&tokens)).into()
+    }
+
+    fn paymaster_prefix(paymaster: Address, _: U256) -> Vec<u8> {
+        paymaster.as_bytes().to_vec()
+    }
+}
+
+impl PaymasterHash for PackedUserOperation {
+    type Params = PaymasterGasLimits;
+
+    fn paymaster_hash(
+        &self,
+        paymaster: Address,
+        gas_limits: PaymasterGasLimits,
+        chain_id: u64,
+        validity: ValidityWindow,
+    ) -> H256 {
+        let mut tokens = vec![
+            Token::Address(self.sender),
+            Token::Uint(self.nonce),
+            hashed(&self.init_code),
+            hashed(&self.call_data),
+            Token::FixedBytes(self.account_gas_limits.as_bytes().to_vec()),
+            Token::Uint(U256::from_big_endian(&gas_limits.packed())),
+            Token::Uint(self.pre_verification_gas),
+            Token::FixedBytes(self.gas_fees.as_bytes().to_vec()),
+            Token::Uint(chain_id.into()),
+            Token::Address(paymaster),
+        ];
+        tokens.extend(validity.tokens());
+        keccak256(abi::encode(&tokens)).into()
+    }
+
+    fn paymaster_prefix(paymaster: Address, gas_limits: PaymasterGasLimits) -> Vec<u8> {
+        let mut prefix = paymaster.as_bytes().to_vec();
+        prefix.extend_from_slice(&gas_limits.packed());
+        prefix
+    }
+}
+
+impl GcpKmsSigner {
+    /// Signs a user operation for `entry_point` on the signer's chain the way
+    /// `SimpleAccount` and most owner-validated accounts expect: an EIP-191
+    /// personal signature over the `userOpHash`, with `v` = 27/28. Put the
+    /// result in the operation's `signature` field.
+    pub async fn sign_user_operation(
+        &self,
+        user_op: &impl UserOperationHash,
+        entry_point: Address,
+    ) -> Result<Signature, CKMSError> {
+        let chain_id = self.snapshot().chain_id;
+        let user_op_hash = user_op.user_op_hash(entry_point, chain_id);
+        let notes = vec![
+            format!("erc4337_entry_point={entry_point:?}"),
+            format!("erc4337_user_op_hash={user_op_hash:?}"),
+        ];
+        self.sign_message_digest(hash_message(user_op_hash), notes)
+            .await
+    }
+
+    /// Sponsors a user operation as the verifying paymaster at `paymaster`
+    /// on the signer's chain, returning the operation's `paymasterAndData`:
+    /// the paymaster's address, its gas limits for v0.7 operations, the
+    /// ABI-encoded validity window, and an EIP-191 personal signature over
+    /// the paymaster hash with `v` = 27/28.
+    ///
+    /// Sign the operation with its other fields final, as the hash commits to
+    /// them; the account signs it after, as its own hash commits to
+    /// `paymasterAndData`.
+    pub async fn sign_paymaster_and_data<O: PaymasterHash>(
+        &self,
+        user_op: &O,
+        paymaster: Address,
+        params: O::Params,
+        validity: ValidityWindow,
+    ) -> Result<Bytes, CKMSError> {
+        let mut paymaster_and_data = O::paymaster_prefix(paymaster, params);
+        paymaster_and_data.extend(validity.encode()?);
+
+        let chain_id = self.snapshot().chain_id;
+        let hash = user_op.paymaster_hash(paymaster, params, chain_id, validity);
+        let notes = vec![
+            format!("erc4337_paymaster={paymaster:?}"),
+            format!("erc4337_paymaster_hash={hash:?}"),
+        ];
+        let signature = self.sign_message_digest(hash_message(hash), notes).await?;
+        paymaster_and_data.extend(signature.to_vec());
+        Ok(paymaster_and_data.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_commits_to_everything_but_the_signature() {
+        let entry_point = Address::repeat_byte(0xee);
+        let user_op = UserOperation {
+            sender: Address::repeat_byte(1),
+            nonce: 7.into(),
+            call_data: vec![0xb6, 0x1d, 0x27, 0xf6].into(),
+            call_gas_limit: 100_000.into(),
+            ..Default::default()
+        };
+        let hash = user_op.user_op_hash(entry_point, 1);
+
+        let signed = UserOperation {
+            signature: vec![1; 65].into(),
+            ..user_op.clone()
+        };
+        assert_eq!(signed.user_op_hash(entry_point, 1), hash);
+        assert_ne!(user_op.user_op_hash(entry_point, 5), hash);
+        assert_ne!(user_op.user_op_hash(Address::zero(), 1), hash);
+        let bumped = UserOperation {
+            nonce: 8.into(),
+            ..user_op
+        };
+        assert_ne!(bumped.user_op_hash(entry_point, 1), hash);
+    }
+
+    #[test]
+    fn paymaster_prefix_layout() {
+        let paymaster = Address::repeat_byte(0xaa);
+        assert_eq!(
+            UserOperation::paymaster_prefix(paymaster, U256::zero()),
+            paymaster.as_bytes()
+        );
+
+        let gas_limits = PaymasterGasLimits {
+            verification_gas_limit: 0x1234,
+            post_op_gas_limit: 0x56,
+        };
+        let prefix = PackedUserOperation::paymaster_prefix(paymaster, gas_limits);
+        assert_eq!(prefix.len(), 52);
+        assert_eq!(&prefix[34..36], &[0x12, 0x34]);
+        assert_eq!(prefix[51], 0x56);
+
+        let window = ValidityWindow {
+            valid_until: 1_700_000_000,
+            valid_after: 0,
+        };
+        let encoded = window.encode().unwrap();
+        assert_eq!(encoded.len(), 64);
+        assert_eq!(U256::from_big_endian(&encoded[..32]), 1_700_000_000.into());
+        assert!(matches!(
+            ValidityWindow {
+                valid_until: 1 << 48,
+                valid_after: 0
+            }
+            .encode(),
+            Err(CKMSError::UserOperationError(_))
+        ));
+    }
+
+    #[test]
+    fn paymaster_hash_commits_to_window_and_paymaster() {
+        let user_op = UserOperation {
+            sender: Address::repeat_byte(1),
+            ..Default::default()
+        };
+        let paymaster = Address::repeat_byte(0xaa);
+        let window = ValidityWindow {
+            valid_until: 100,
+            valid_after: 10,
+        };
+        let hash = user_op.paymaster_hash(paymaster, 0.into(), 1, window);
+        assert_ne!(
+            user_op.paymaster_hash(
+                paymaster,
+                0.into(),
+                1,
+                ValidityWindow {
+                    valid_until: 101,
+                    ..window
+                }
+            ),
+            hash
+        );
+        assert_ne!(
+            user_op.paymaster_hash(Address::zero(), 0.into(), 1, window),
+            hash
+        );
+        assert_ne!(user_op.paymaster_hash(paymaster, 1.into(), 1, window), hash);
+        // the account's signature and the paymaster's own data are excluded
+        let signed = UserOperation {
+            signature: vec![1; 65].into(),
+            paymaster_and_data: paymaster.as_bytes().to_vec().into(),
+            ..user_op
+        };
+        assert_eq!(signed.paymaster_hash(paymaster, 0.into(), 1, window), hash);
+    }
+
+    #[test]
+    fn serializes_like_bundlers() {
+        let user_op = PackedUserOperation {
+            nonce: 1.into(),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&user_op).unwrap();
+        assert_eq!(json["nonce"], "0x1");
+        assert!(json.get("accountGasLimits").is_some());
+        assert!(json.get("paymasterAndData").is_some());
+    }
+}
@@ -0,0 +1,335 @@
+//! A smart account owned by a KMS key, which the signer deploys and sends
+//! user operations from through an entry point.
+use ethers::{
+    abi::{self, ParamType, Token},
+    providers::Middleware,
+    signers::Signer,
+    types::{transaction::eip2718::TypedTransaction, Address, Bytes, TransactionRequest, U256},
+    utils::id,
+};
+use tokio::sync::Mutex;
+
+use super::{PackedUserOperation, UserOperation, UserOperationHash};
+use crate::{CKMSError, GcpKmsSigner};
+
+/// A user operation whose sender, nonce, init code and signature a
+/// [`ManagedAccount`] fills in
+pub trait AccountOperation: UserOperationHash {
+    fn set_sender(&mut self, sender: Address);
+    fn set_nonce(&mut self, nonce: U256);
+    fn set_init_code(&mut self, init_code: Bytes);
+    fn set_signature(&mut self, signature: Bytes);
+}
+
+macro_rules! account_operation {
+    ($op:ty) => {
+        impl AccountOperation for $op {
+            fn set_sender(&mut self, sender: Address) {
+                self.sender = sender;
+            }
+
+            fn set_nonce(&mut self, nonce: U256) {
+                self.nonce = nonce;
+            }
+
+            fn set_init_code(&mut self, init_code: Bytes) {
+                self.init_code = init_code;
+            }
+
+            fn set_signature(&mut self, signature: Bytes) {
+                self.signature = signature;
+            }
+        }
+    };
+}
+
+account_operation!(UserOperation);
+account_operation!(PackedUserOperation);
+
+/// A `SimpleAccount`-style smart account owned by a [`GcpKmsSigner`]: its
+/// address is the factory's counterfactual address for the owner and salt,
+/// the account is deployed by the first user operation's `initCode`, and
+/// operations are signed with [`GcpKmsSigner::sign_user_operation`].
+///
+/// Nonces come from the entry point's `getNonce` for the account's nonce
+/// key, and are assigned locally past it, so several operations can be
+/// signed before the first is included. Call [`ManagedAccount::resync`]
+/// after an operation is dropped by the bundler.
+#[derive(Debug)]
+pub struct ManagedAccount<M> {
+    client: M,
+    signer: GcpKmsSigner,
+    entry_point: Address,
+    factory: Address,
+    salt: U256,
+    nonce_key: U256,
+    address: Address,
+    /// The lowest nonce not yet handed out, once read from the entry point
+    next_nonce: Mutex<Option<U256>>,
+}
+
+impl<M: Middleware> ManagedAccount<M> {
+    /// Looks up the account of `signer` at `factory` with `salt`, which
+    /// need not be deployed yet. The factory must implement
+    /// `getAddress(address owner, uint256 salt)` and
+    /// `createAccount(address owner, uint256 salt)`, as eth-infinitism's
+    /// `SimpleAccountFactory` does.
+    pub async fn new(
+        client: M,
+        signer: GcpKmsSigner,
+        entry_point: Address,
+        factory: Address,
+        salt: U256,
+    ) -> Result<Self, CKMSError> {
+        let owner_and_salt = [Token::Address(signer.address()), Token::Uint(salt)];
+        let data = call_data("getAddress(address,uint256)", &owner_and_salt);
+        let returned = call(&client, factory, data, ParamType::Address).await?;
+        let address = returned
+            .into_address()
+            .ok_or_else(|| CKMSError::UserOperationError("getAddress: not an address".into()))?;
+        Ok(Self {
+            client,
+            signer,
+            entry_point,
+            factory,
+            salt,
+            nonce_key: U256::zero(),
+            address,
+            next_nonce: Mutex::new(None),
+        })
+    }
+
+    /// Uses the entry point's parallel nonce sequence `key`, a `uint192`,
+    /// rather than sequence 0
+    pub fn with_nonce_key(mut self, key: U256) -> Result<Self, CKMSError> {
+        if key.bits() > 192 {
+            return Err(CKMSError::UserOperationError(format!(
+                "nonce key {key} does not fit in a uint192"
+            )));
+        }
+        self.nonce_key = key;
+        Ok(self)
+    }
+
+    /// The account's address
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// The account's owner, the signer's address
+    pub fn owner(&self) -> Address {
+        self.signer.address()
+    }
+
+    pub fn signer(&self) -> &GcpKmsSigner {
+        &self.signer
+    }
+
+    /// The `initCode` which deploys the account: the factory's address and
+    /// its `createAccount` call
+    pub fn init_code(&self) -> Bytes {
+        let owner_and_salt = [Token::Address(self.owner()), Token::Uint(self.salt)];
+        let mut init_code = self.factory.as_bytes().to_vec();
+        init_code.extend(call_data("createAccount(address,uint256)", &owner_and_salt));
+        init_code.into()
+    }
+
+    /// Whether the account has been deployed
+    pub async fn is_deployed(&self) -> Result<bool, CKMSError> {
+        let code = self
+            .client
+            .get_code(self.address, None)
+            .await
+            .map_err(|e| CKMSError::UserOperationError(format!("eth_getCode: {e}")))?;
+        Ok(!code.is_empty())
+    }
+
+    /// The entry point's next nonce for the account and its nonce key
+    pub async fn entry_point_nonce(&self) -> Result<U256, CKMSError> {
+        let sender_and_key = [Token::Address(self.address), Token::Uint(self.nonce_key)];
+        let data = call_data("getNonce(address,uint192)", &sender_and_key);
+        let returned = call(&self.client, self.entry_point, data, ParamType::Uint(256)).await?;
+        returned
+            .into_uint()
+            .ok_or_else(|| CKMSError::UserOperationError("getNonce: not a uint256".into()))
+    }
+
+    /// Re-reads the entry point's nonce and continues from it
+    pub async fn resync(&self) -> Result<U256, CKMSError> {
+        let mut next = self.next_nonce.lock().await;
+        let nonce = self.entry_point_nonce().await?;
+        *next = Some(nonce);
+        Ok(nonce)
+    }
+
+    /// Hands out the next nonce, reading the entry point's the first time
+    async fn allocate_nonce(&self) -> Result<U256, CKMSError> {
+        let mut next = self.next_nonce.lock().await;
+        let nonce = match *next {
+            Some(nonce) => nonce,
+            None => self.entry_point_nonce().await?,
+        };
+        *next = Some(nonce + 1);
+        Ok(nonce)
+    }
+
+    /// Fills in the operation's sender, nonce and, until the account is
+    /// deployed, `initCode`, then signs it. The operation's gas fields and
+    /// `paymasterAndData` must already be final.
+    pub async fn sign<O: AccountOperation + Sync>(&self, mut user_op: O) -> Result<O, CKMSError> {
+        user_op.set_sender(self.address);
+        let init_code = match self.is_deployed().await? {
+            true => Bytes::new(),
+            false => self.init_code(),
+        };
+        user_op.set_init_code(init_code);
+        user_op.set_nonce(self.allocate_nonce().await?);
+
+        let signature = self
+            .signer
+            .sign_user_operation(&user_op, self.entry_point)
+            .await?;
+        user_op.set_signature(signature.to_vec().into());
+        Ok(user_op)
+    }
+}
+
+/// A call of `signature` with `args`
+fn call_data(signature: &str, args: &[Token]) -> Vec<u8> {
+    let mut data = id(signature).to_vec();
+    data.extend(abi::encode(args));
+    data
+}
+
+/// Calls a view function returning one value of type `returns`
+async fn call<M: Middleware>(
+    client: &M,
+    to: Address,
+    data: Vec<u8>,
+    returns: ParamType,
+) -> Result<Token, CKMSError> {
+    let tx: TypedTransaction = TransactionRequest::new().to(to).data(data).into();
+    let returned = client
+        .call(&tx, None)
+        .await
+        .map_err(|e| CKMSError::UserOperationError(format!("eth_call to {to:?}: {e}")))?;
+    abi::decode(&[returns], &returned)
+        .map_err(|e| CKMSError::UserOperationError(format!("eth_call to {to:?}: {e}")))?
+        .pop()
+        .ok_or_else(|| CKMSError::UserOperationError(format!("eth_call to {to:?}: no value")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use ethers::{
+        prelude::k256::ecdsa::{signature::hazmat::PrehashSigner, SigningKey, VerifyingKey},
+        providers::{MockProvider, Provider},
+        types::Signature,
+        utils::hash_message,
+    };
+
+    use super::*;
+    use crate::{KmsKeyBackend, SigningContext};
+
+    #[derive(Debug)]
+    struct LocalBackend(SigningKey);
+
+    #[async_trait]
+    impl KmsKeyBackend for LocalBackend {
+        async fn get_public_key(&self, _: &str, _: u64) -> Result<VerifyingKey, CKMSError> {
+            Ok(*self.0.verifying_key())
+        }
+
+        async fn sign_digest(
+            &self,
+            _: &str,
+            key_version: u64,
+            digest: [u8; 32],
+            _: &SigningContext,
+        ) -> Result<(Vec<u8>, u64), CKMSError> {
+            let signature: ethers::prelude::k256::ecdsa::Signature =
+                self.0.sign_prehash(&digest)?;
+            Ok((signature.to_der().as_bytes().to_vec(), key_version))
+        }
+    }
+
+    const ENTRY_POINT: Address = Address::repeat_byte(0xee);
+    const FACTORY: Address = Address::repeat_byte(0xfa);
+    const ACCOUNT: Address = Address::repeat_byte(0xac);
+
+    fn encoded(token: Token) -> Bytes {
+        abi::encode(&[token]).into()
+    }
+
+    async fn account() -> (ManagedAccount<Provider<MockProvider>>, MockProvider) {
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let signer = GcpKmsSigner::new(Arc::new(LocalBackend(key)), "local".to_string(), 1, 5)
+            .await
+            .unwrap();
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Bytes, _>(encoded(Token::Address(ACCOUNT)))
+            .unwrap();
+        let account = ManagedAccount::new(provider, signer, ENTRY_POINT, FACTORY, 3.into())
+            .await
+            .unwrap();
+        (account, mock)
+    }
+
+    #[tokio::test]
+    async fn first_operation_deploys_the_account() {
+        let (account, mock) = account().await;
+        assert_eq!(account.address(), ACCOUNT);
+
+        // responses are served last pushed first
+        mock.push::<Bytes, _>(encoded(Token::Uint(0.into())))
+            .unwrap();
+        mock.push::<Bytes, _>(Bytes::new()).unwrap();
+        let user_op = account.sign(UserOperation::default()).await.unwrap();
+        assert_eq!(user_op.sender, ACCOUNT);
+        assert_eq!(user_op.nonce, 0.into());
+        assert_eq!(&user_op.init_code[..20], FACTORY.as_bytes());
+        assert_eq!(
+            &user_op.init_code[20..24],
+            &id("createAccount(address,uint256)")
+        );
+
+        let signature = Signature::try_from(user_op.signature.as_ref()).unwrap();
+        let hash = user_op.user_op_hash(ENTRY_POINT, 5);
+        assert_eq!(
+            signature.recover(hash_message(hash)).unwrap(),
+            account.owner()
+        );
+    }
+
+    #[tokio::test]
+    async fn assigns_nonces_past_the_entry_points() {
+        let (account, mock) = account().await;
+        mock.push::<Bytes, _>(encoded(Token::Uint(7.into())))
+            .unwrap();
+        mock.push::<Bytes, _>(Bytes::from(vec![0x60])).unwrap();
+        let first = account.sign(PackedUserOperation::default()).await.unwrap();
+        assert_eq!(first.nonce, 7.into());
+        assert!(first.init_code.is_empty());
+
+        mock.push::<Bytes, _>(Bytes::from(vec![0x60])).unwrap();
+        let second = account.sign(PackedUserOperation::default()).await.unwrap();
+        assert_eq!(second.nonce, 8.into());
+
+        mock.push::<Bytes, _>(encoded(Token::Uint(7.into())))
+            .unwrap();
+        assert_eq!(account.resync().await.unwrap(), 7.into());
+    }
+
+    #[tokio::test]
+    async fn nonce_keys_are_uint192() {
+        let (account, _) = account().await;
+        let account = account.with_nonce_key((U256::one() << 192) - 1).unwrap();
+        assert!(matches!(
+            account.with_nonce_key(U256::one() << 192),
+            Err(CKMSError::UserOperationError(_))
+        ));
+    }
+}
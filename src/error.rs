@@ -2,13 +2,18 @@ use ethers::{
     prelude::k256::{self, pkcs8},
     types::SignatureError,
 };
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum CKMSError {
     #[error("GCloud sdk error: {0}")]
     GoogleKmsError(#[from] gcloud_sdk::error::Error),
 
+    #[error("Metadata server error: {0}")]
+    MetadataError(#[from] gcemeta::Error),
+
     #[error("Request error: {0}")]
     RequestError(#[from] tonic::Status),
 
@@ -23,6 +28,171 @@ pub enum CKMSError {
     #[error("Signature error: {0}")]
     SignatureError(#[from] SignatureError),
 
+    #[error("Signing denied: {0}")]
+    SigningDenied(SigningDenied),
+
+    #[error("Authentication error: {0}")]
+    AuthError(String),
+
+    #[error("Backpressure: {0}")]
+    Backpressure(String),
+
+    #[error("Recovery error: signature does not recover to the KMS public key")]
+    RecoveryError,
+
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("Chain id {0} is too large for an EIP-155 v value")]
+    UnsupportedChainId(u64),
+
+    #[error("EIP-155 v value {v} is not for chain id {expected}")]
+    ChainIdMismatch { v: u64, expected: u64 },
+
+    #[error("Store error: {0}")]
+    StoreError(String),
+
     #[error("EIP712 error: {0}")]
     Eip712Error(String),
+
+    #[error("No credential source succeeded: {0}")]
+    CredentialsError(String),
+
+    #[error("Cosmos error: {0}")]
+    CosmosError(String),
+
+    #[error("Invalid blob transaction: {0}")]
+    InvalidBlobTransaction(String),
+
+    #[error(
+        "Quorum not reached: {signed} of {required} signatures ({})",
+        failures.join("; ")
+    )]
+    QuorumNotReached {
+        required: usize,
+        signed: usize,
+        failures: Vec<String>,
+    },
+
+    #[error("Remote signer error: {0}")]
+    RemoteSignerError(String),
+
+    #[error("Server error: {0}")]
+    ServerError(String),
+
+    #[error("Connection error: {0}")]
+    ConnectionError(String),
+
+    #[error("TLS error: {0}")]
+    TlsError(String),
+
+    #[error("SIWE error: {0}")]
+    SiweError(String),
+
+    #[error("User operation error: {0}")]
+    UserOperationError(String),
+
+    #[error("Invalid key version: {0}")]
+    InvalidKeyVersion(String),
+
+    #[error("No matching key version found for {0}")]
+    KeyVersionNotFound(String),
+
+    #[error("Key creation error: {0}")]
+    KeyCreationError(String),
+
+    #[error("Not supported by the signer's key backend: {0}")]
+    UnsupportedByBackend(String),
+
+    #[error("No recorded KMS response for {0}")]
+    NoRecordedResponse(String),
+
+    #[error("Test vector failed: {0}")]
+    TestVectorFailed(String),
+
+    #[error("Location {0} has no regional KMS endpoint")]
+    NoRegionalEndpoint(String),
+
+    #[error("Key {key_name} has address {actual:?}, expected {expected:?}")]
+    AddressMismatch {
+        key_name: String,
+        expected: ethers::types::Address,
+        actual: ethers::types::Address,
+    },
+}
+
+/// Details of a signing request refused by a local policy. Every policy
+/// reports denials with this type, so callers can route them all through a
+/// single `CKMSError::SigningDenied` match arm.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SigningDenied {
+    /// The rule which refused the request, e.g. `tx_type_allowlist`
+    pub rule: &'static str,
+    /// The values the rule evaluated, as `(name, value)` pairs
+    pub evaluated: Vec<(String, String)>,
+    /// A hint on how the denial can be resolved
+    pub remediation: Option<String>,
+}
+
+impl SigningDenied {
+    pub fn new(rule: &'static str) -> Self {
+        Self {
+            rule,
+            evaluated: Vec::new(),
+            remediation: None,
+        }
+    }
+
+    /// Records a value evaluated by the rule
+    pub fn with_value(mut self, name: impl Into<String>, value: impl fmt::Display) -> Self {
+        self.evaluated.push((name.into(), value.to_string()));
+        self
+    }
+
+    /// Sets the remediation hint
+    pub fn with_remediation(mut self, remediation: impl Into<String>) -> Self {
+        self.remediation = Some(remediation.into());
+        self
+    }
+}
+
+impl fmt::Display for SigningDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rule `{}`", self.rule)?;
+        if !self.evaluated.is_empty() {
+            let values: Vec<_> = self
+                .evaluated
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect();
+            write!(f, " ({})", values.join(", "))?;
+        }
+        if let Some(remediation) = &self.remediation {
+            write!(f, ": {remediation}")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<SigningDenied> for CKMSError {
+    fn from(denied: SigningDenied) -> Self {
+        CKMSError::SigningDenied(denied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signing_denied_display() {
+        let denied = SigningDenied::new("tx_type_allowlist")
+            .with_value("tx_type", "legacy")
+            .with_remediation("send an EIP-1559 transaction");
+        assert_eq!(
+            CKMSError::from(denied).to_string(),
+            "Signing denied: rule `tx_type_allowlist` (tx_type=legacy): send an EIP-1559 transaction"
+        );
+    }
 }
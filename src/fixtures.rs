@@ -0,0 +1,269 @@
+//! Deterministic fixtures of this crate's signing conventions, for checking
+//! interop from other languages' test suites. Fixtures are signed with a
+//! local synthetic key; KMS signatures are randomized, so only a local key
+//! gives repeatable output. The `gcp-eth-signer fixtures` command writes them
+//! as JSON.
+
+use ethers::{
+    prelude::k256::ecdsa::SigningKey,
+    types::{
+        transaction::{
+            eip2718::TypedTransaction,
+            eip712::{Eip712, TypedData},
+        },
+        Address, Bytes, Eip1559TransactionRequest, Eip2930TransactionRequest, Signature,
+        TransactionRequest, H256, U256,
+    },
+    utils::{hash_message, keccak256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apply_eip155, apply_transaction_v, sig_from_digest_bytes_trial_recovery,
+    verifying_key_to_address, CKMSError,
+};
+
+/// The key fixtures are signed with unless another is given
+pub const DEFAULT_PRIVATE_KEY: [u8; 32] = [0x42; 32];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixtureSet {
+    /// The chain every fixture is signed for. Transactions serialize without
+    /// a chain id, so it is only recorded here.
+    pub chain_id: u64,
+    pub key: KeyFixture,
+    pub messages: Vec<MessageFixture>,
+    pub digests: Vec<DigestFixture>,
+    pub transactions: Vec<TransactionFixture>,
+    pub typed_data: Vec<TypedDataFixture>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyFixture {
+    pub private_key: H256,
+    pub address: Address,
+    /// Uncompressed SEC1 public key
+    pub public_key: Bytes,
+}
+
+/// An EIP-191 personal message
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageFixture {
+    pub message: Bytes,
+    pub hash: H256,
+    /// As returned by `sign_message`, with `v` = 27/28
+    pub signature: Signature,
+    /// As returned with EIP-155 message `v` enabled
+    pub signature_eip155: Signature,
+}
+
+/// A pre-computed digest, as signed by `sign_hash`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigestFixture {
+    pub digest: H256,
+    /// With `v` = 27/28
+    pub signature: Signature,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionFixture {
+    pub transaction: TypedTransaction,
+    pub sighash: H256,
+    /// With an EIP-155 `v` for legacy transactions and y-parity for others
+    pub signature: Signature,
+    /// The signed transaction, as for `eth_sendRawTransaction`
+    pub raw: Bytes,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypedDataFixture {
+    /// An `eth_signTypedData_v4` document
+    pub typed_data: TypedData,
+    pub digest: H256,
+    /// With `v` = 0/1
+    pub signature: Signature,
+}
+
+/// Signs a digest with the local key, with a 0/1 recovery id as `v`, through
+/// the same recovery path as KMS signatures
+fn sign(key: &SigningKey, digest: [u8; 32]) -> Result<Signature, CKMSError> {
+    let (sig, _) = key.sign_prehash_recoverable(&digest)?;
+    sig_from_digest_bytes_trial_recovery(&sig, digest, key.verifying_key())
+}
+
+fn with_27_28_v(mut sig: Signature) -> Signature {
+    sig.v += 27;
+    sig
+}
+
+fn transactions(chain_id: u64) -> Vec<TypedTransaction> {
+    let to: Address = "0x000000000000000000000000000000000000dEaD"
+        .parse()
+        .unwrap();
+    let legacy = TransactionRequest::new()
+        .to(to)
+        .value(U256::exp10(18))
+        .nonce(0)
+        .gas(21_000)
+        .gas_price(U256::exp10(9))
+        .chain_id(chain_id);
+    vec![
+        legacy.clone().into(),
+        Eip2930TransactionRequest::new(legacy.nonce(1), Default::default()).into(),
+        Eip1559TransactionRequest::new()
+            .to(to)
+            .value(U256::exp10(18))
+            .nonce(2)
+            .gas(21_000)
+            .max_fee_per_gas(U256::exp10(10))
+            .max_priority_fee_per_gas(U256::exp10(9))
+            .data(vec![0xde, 0xad, 0xbe, 0xef])
+            .chain_id(chain_id)
+            .into(),
+    ]
+}
+
+/// The example from EIP-712, for the given chain
+fn typed_data(chain_id: u64) -> TypedData {
+    serde_json::from_value(serde_json::json!({
+        "types": {
+            "EIP712Domain": [
+                { "name": "name", "type": "string" },
+                { "name": "version", "type": "string" },
+                { "name": "chainId", "type": "uint256" },
+                { "name": "verifyingContract", "type": "address" }
+            ],
+            "Person": [
+                { "name": "name", "type": "string" },
+                { "name": "wallet", "type": "address" }
+            ],
+            "Mail": [
+                { "name": "from", "type": "Person" },
+                { "name": "to", "type": "Person" },
+                { "name": "contents", "type": "string" }
+            ]
+        },
+        "primaryType": "Mail",
+        "domain": {
+            "name": "Ether Mail",
+            "version": "1",
+            "chainId": chain_id,
+            "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+        },
+        "message": {
+            "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+            "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+            "contents": "Hello, Bob!"
+        }
+    }))
+    .expect("the EIP-712 example is valid typed data")
+}
+
+/// Generates the fixture set for a private key and chain id
+pub fn generate(private_key: [u8; 32], chain_id: u64) -> Result<FixtureSet, CKMSError> {
+    let key = SigningKey::from_bytes(&private_key.into())
+        .map_err(|e| CKMSError::InvalidSignature(format!("invalid private key: {e}")))?;
+
+    let mut messages = Vec::new();
+    for message in [&b""[..], b"hello world", &[0x00, 0xff, 0x10, 0x80]] {
+        let hash = hash_message(message);
+        let sig = sign(&key, hash.into())?;
+        let mut signature_eip155 = sig;
+        apply_eip155(&mut signature_eip155, chain_id)?;
+        messages.push(MessageFixture {
+            message: message.to_vec().into(),
+            hash,
+            signature: with_27_28_v(sig),
+            signature_eip155,
+        });
+    }
+
+    let mut digests = Vec::new();
+    for seed in 0u8..3 {
+        let digest = keccak256([seed]);
+        digests.push(DigestFixture {
+            digest: digest.into(),
+            signature: with_27_28_v(sign(&key, digest)?),
+        });
+    }
+
+    let mut transactions_out = Vec::new();
+    for transaction in transactions(chain_id) {
+        let sighash = transaction.sighash();
+        let mut signature = sign(&key, sighash.into())?;
+        apply_transaction_v(&mut signature, &transaction, chain_id)?;
+        transactions_out.push(TransactionFixture {
+            raw: transaction.rlp_signed(&signature),
+            transaction,
+            sighash,
+            signature,
+        });
+    }
+
+    let typed_data = typed_data(chain_id);
+    let digest = typed_data
+        .encode_eip712()
+        .map_err(|e| CKMSError::Eip712Error(e.to_string()))?;
+    let typed_data = vec![TypedDataFixture {
+        signature: sign(&key, digest)?,
+        typed_data,
+        digest: digest.into(),
+    }];
+
+    let verifying_key = key.verifying_key();
+    Ok(FixtureSet {
+        chain_id,
+        key: KeyFixture {
+            private_key: private_key.into(),
+            address: verifying_key_to_address(verifying_key),
+            public_key: verifying_key
+                .to_encoded_point(false)
+                .as_bytes()
+                .to_vec()
+                .into(),
+        },
+        messages,
+        digests,
+        transactions: transactions_out,
+        typed_data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+
+    #[tokio::test]
+    async fn fixtures_match_local_wallet() {
+        let fixtures = generate(DEFAULT_PRIVATE_KEY, 10).unwrap();
+        assert_eq!(fixtures, generate(DEFAULT_PRIVATE_KEY, 10).unwrap());
+
+        let wallet = LocalWallet::from_bytes(&DEFAULT_PRIVATE_KEY)
+            .unwrap()
+            .with_chain_id(10u64);
+        assert_eq!(fixtures.key.address, wallet.address());
+        for fixture in &fixtures.messages {
+            let expected = wallet.sign_message(&fixture.message).await.unwrap();
+            assert_eq!(fixture.signature, expected);
+        }
+        for fixture in &fixtures.transactions {
+            assert_eq!(
+                fixture.signature.recover(fixture.sighash).unwrap(),
+                wallet.address()
+            );
+        }
+        let fixture = &fixtures.typed_data[0];
+        let expected = wallet.sign_typed_data(&fixture.typed_data).await.unwrap();
+        assert_eq!(
+            (fixture.signature.r, fixture.signature.s),
+            (expected.r, expected.s)
+        );
+
+        // survives a round trip through JSON. Transactions serialize without
+        // their chain id, which is the fixture set's
+        let json = serde_json::to_string(&fixtures).unwrap();
+        let decoded: FixtureSet = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+    }
+}
@@ -0,0 +1,316 @@
+use ethers::{
+    types::{transaction::eip712::TypedData, Address, Signature, SignatureError},
+    utils::to_checksum,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{CKMSError, GcpKmsSigner};
+
+/// The EIP-712 domain Snapshot signs all messages in, which is not bound to
+/// a chain or contract
+fn snapshot_domain() -> Value {
+    json!({"name": "snapshot", "version": "0.1.4"})
+}
+
+/// The choice of a Snapshot vote, whose type depends on the voting system
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VoteChoice {
+    /// For single choice and basic voting, a 1-based index
+    Single(u32),
+    /// For approval and ranked choice voting, 1-based indices
+    Multiple(Vec<u32>),
+    /// For weighted and quadratic voting, a JSON object of weights by 1-based
+    /// index, e.g. `{"1":2,"3":1}`
+    Weighted(String),
+}
+
+impl VoteChoice {
+    fn field(&self) -> (&'static str, Value) {
+        match self {
+            VoteChoice::Single(choice) => ("uint32", json!(choice)),
+            VoteChoice::Multiple(choices) => ("uint32[]", json!(choices)),
+            VoteChoice::Weighted(weights) => ("string", json!(weights)),
+        }
+    }
+}
+
+/// A vote on a Snapshot proposal
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotVote {
+    /// The space's ENS name, e.g. `aave.eth`
+    pub space: String,
+    /// The proposal id: a `0x`-prefixed hash, or an id string for spaces
+    /// which use them
+    pub proposal: String,
+    pub choice: VoteChoice,
+    pub reason: String,
+    pub app: String,
+    pub metadata: String,
+    /// Unix seconds; the signer's clock when `None`
+    pub timestamp: Option<u64>,
+}
+
+/// A Snapshot proposal
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SnapshotProposal {
+    pub space: String,
+    /// The voting system, e.g. `single-choice`
+    pub voting_type: String,
+    pub title: String,
+    pub body: String,
+    pub discussion: String,
+    pub choices: Vec<String>,
+    pub labels: Vec<String>,
+    /// Unix seconds at which voting starts and ends
+    pub start: u64,
+    pub end: u64,
+    /// The block number voting power is taken at
+    pub snapshot: u64,
+    /// The plugins' JSON configuration, usually `{}`
+    pub plugins: String,
+    pub privacy: String,
+    pub app: String,
+    /// Unix seconds; the signer's clock when `None`
+    pub timestamp: Option<u64>,
+}
+
+/// A signed Snapshot message, which serializes to the body the Snapshot hub
+/// takes: `{"address", "sig", "data": {"domain", "types", "message"}}`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SnapshotEnvelope {
+    pub address: String,
+    /// The signature, with `v` = 27/28 as Snapshot's tooling produces it
+    pub sig: String,
+    pub data: Value,
+}
+
+fn is_bytes32(id: &str) -> bool {
+    id.strip_prefix("0x")
+        .is_some_and(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn fields(fields: &[(&str, &str)]) -> Value {
+    fields
+        .iter()
+        .map(|(name, kind)| json!({"name": name, "type": kind}))
+        .collect()
+}
+
+impl GcpKmsSigner {
+    /// Signs a Snapshot vote as this signer's address, ready to submit to
+    /// the Snapshot hub
+    pub async fn sign_snapshot_vote(
+        &self,
+        vote: &SnapshotVote,
+    ) -> Result<SnapshotEnvelope, CKMSError> {
+        let proposal_type = match is_bytes32(&vote.proposal) {
+            true => "bytes32",
+            false => "string",
+        };
+        let (choice_type, choice) = vote.choice.field();
+        let types = fields(&[
+            ("from", "address"),
+            ("space", "string"),
+            ("timestamp", "uint64"),
+            ("proposal", proposal_type),
+            ("choice", choice_type),
+            ("reason", "string"),
+            ("app", "string"),
+            ("metadata", "string"),
+        ]);
+        let message = json!({
+            "space": vote.space,
+            "timestamp": vote.timestamp.unwrap_or_else(|| self.clock.now_ms() / 1000),
+            "proposal": vote.proposal,
+            "choice": choice,
+            "reason": vote.reason,
+            "app": vote.app,
+            "metadata": vote.metadata,
+        });
+        self.sign_snapshot_message("Vote", types, message).await
+    }
+
+    /// Signs a Snapshot proposal as this signer's address, ready to submit
+    /// to the Snapshot hub
+    pub async fn sign_snapshot_proposal(
+        &self,
+        proposal: &SnapshotProposal,
+    ) -> Result<SnapshotEnvelope, CKMSError> {
+        let types = fields(&[
+            ("from", "address"),
+            ("space", "string"),
+            ("timestamp", "uint64"),
+            ("type", "string"),
+            ("title", "string"),
+            ("body", "string"),
+            ("discussion", "string"),
+            ("choices", "string[]"),
+            ("labels", "string[]"),
+            ("start", "uint64"),
+            ("end", "uint64"),
+            ("snapshot", "uint64"),
+            ("plugins", "string"),
+            ("privacy", "string"),
+            ("app", "string"),
+        ]);
+        let message = json!({
+            "space": proposal.space,
+            "timestamp": proposal.timestamp.unwrap_or_else(|| self.clock.now_ms() / 1000),
+            "type": proposal.voting_type,
+            "title": proposal.title,
+            "body": proposal.body,
+            "discussion": proposal.discussion,
+            "choices": proposal.choices,
+            "labels": proposal.labels,
+            "start": proposal.start,
+            "end": proposal.end,
+            "snapshot": proposal.snapshot,
+            "plugins": proposal.plugins,
+            "privacy": proposal.privacy,
+            "app": proposal.app,
+        });
+        self.sign_snapshot_message("Proposal", types, message).await
+    }
+
+    /// Signs any Snapshot message, such as a follow or an alias, given its
+    /// primary type's fields as `[{"name", "type"}]` and its message without
+    /// `from`, which is set to this signer's address
+    pub async fn sign_snapshot_message(
+        &self,
+        primary_type: &str,
+        primary_fields: Value,
+        mut message: Value,
+    ) -> Result<SnapshotEnvelope, CKMSError> {
+        let address = to_checksum(&self.resolve_address().await?, None);
+        if let Some(message) = message.as_object_mut() {
+            message.insert("from".to_string(), json!(address));
+        }
+        let data = json!({
+            "domain": snapshot_domain(),
+            "types": {primary_type: primary_fields},
+            "message": message,
+        });
+        let signature = self.sign_typed_data_json(&typed_data(&data)?).await?;
+
+        Ok(SnapshotEnvelope {
+            address,
+            sig: snapshot_sig(signature),
+            data,
+        })
+    }
+}
+
+/// Formats a signature as `0x`-prefixed hex with `v` = 27/28
+fn snapshot_sig(mut signature: Signature) -> String {
+    if signature.v < 27 {
+        signature.v += 27;
+    }
+    format!("0x{signature}")
+}
+
+/// The `eth_signTypedData_v4` payload of a Snapshot envelope's data, which
+/// leaves out the domain's type and the primary type
+fn typed_data(data: &Value) -> Result<Value, CKMSError> {
+    let primary_type = data["types"]
+        .as_object()
+        .and_then(|types| types.keys().next())
+        .ok_or_else(|| CKMSError::Eip712Error("Snapshot message has no types".to_string()))?;
+    let mut typed_data = data.clone();
+    typed_data["primaryType"] = json!(primary_type);
+    typed_data["types"]["EIP712Domain"] = json!([
+        {"name": "name", "type": "string"},
+        {"name": "version", "type": "string"}
+    ]);
+    Ok(typed_data)
+}
+
+/// The address which signed a Snapshot envelope, for checking one received
+/// from elsewhere
+pub fn recover_snapshot_signer(envelope: &SnapshotEnvelope) -> Result<Address, CKMSError> {
+    let typed_data: TypedData = serde_json::from_value(typed_data(&envelope.data)?)
+        .map_err(|e| CKMSError::Eip712Error(e.to_string()))?;
+    let signature: Signature = envelope
+        .sig
+        .parse()
+        .map_err(|e: SignatureError| CKMSError::InvalidSignature(e.to_string()))?;
+    signature
+        .recover_typed_data(&typed_data)
+        .map_err(|e| CKMSError::InvalidSignature(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+
+    #[test]
+    fn vote_types_follow_the_proposal_and_choice() {
+        assert!(is_bytes32(
+            "0x8b65f5c841816e9fbf54e16b76fb0b6b2a2b3d4a6c4d46b0b3eaf9e4a7c7b2a1"
+        ));
+        assert!(!is_bytes32(
+            "QmPvbwguLfcVryzBRrbY4Pb9bCtxURagdv1XjhtFLf3wHj"
+        ));
+        assert_eq!(VoteChoice::Single(2).field().0, "uint32");
+        assert_eq!(VoteChoice::Multiple(vec![1, 3]).field().0, "uint32[]");
+        assert_eq!(
+            VoteChoice::Weighted(r#"{"1":2}"#.to_string()).field(),
+            ("string", json!(r#"{"1":2}"#))
+        );
+    }
+
+    #[tokio::test]
+    async fn envelopes_recover_to_the_voter() {
+        let wallet = LocalWallet::from_bytes(&[7u8; 32]).unwrap();
+        let data = json!({
+            "domain": snapshot_domain(),
+            "types": {"Vote": fields(&[
+                ("from", "address"),
+                ("space", "string"),
+                ("timestamp", "uint64"),
+                ("proposal", "bytes32"),
+                ("choice", "uint32"),
+                ("reason", "string"),
+                ("app", "string"),
+                ("metadata", "string"),
+            ])},
+            "message": {
+                "from": to_checksum(&wallet.address(), None),
+                "space": "example.eth",
+                "timestamp": 1_700_000_000u64,
+                "proposal": format!("{:?}", ethers::types::H256::repeat_byte(0xab)),
+                "choice": 1,
+                "reason": "",
+                "app": "",
+                "metadata": "{}",
+            },
+        });
+        let parsed: TypedData = serde_json::from_value(typed_data(&data).unwrap()).unwrap();
+        let signature = wallet.sign_typed_data(&parsed).await.unwrap();
+
+        let envelope = SnapshotEnvelope {
+            address: to_checksum(&wallet.address(), None),
+            sig: snapshot_sig(signature),
+            data,
+        };
+        assert_eq!(
+            recover_snapshot_signer(&envelope).unwrap(),
+            wallet.address()
+        );
+        let body = serde_json::to_value(&envelope).unwrap();
+        assert!(body["data"]["types"].get("EIP712Domain").is_none());
+    }
+
+    #[test]
+    fn formats_sig_with_27_28_v() {
+        let signature = Signature {
+            r: 1.into(),
+            s: 2.into(),
+            v: 1,
+        };
+        let sig = snapshot_sig(signature);
+        assert_eq!(sig.len(), 2 + 130);
+        assert!(sig.ends_with("1c"));
+    }
+}
@@ -0,0 +1,217 @@
+use std::{future::Future, time::Duration};
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::CKMSError;
+
+/// Hedging for idempotent KMS calls: if an attempt has not completed after
+/// `delay`, another is issued (up to `max_attempts` in total) and the first
+/// success is used. Hedges are skipped once the request's deadline is less
+/// than `delay` away, and when the concurrency limit has no free slot for
+/// them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HedgingConfig {
+    pub delay: Duration,
+    pub max_attempts: usize,
+}
+
+impl HedgingConfig {
+    pub fn new(delay: Duration, max_attempts: usize) -> Self {
+        Self {
+            delay,
+            max_attempts: max_attempts.max(1),
+        }
+    }
+}
+
+/// Runs `attempt` with hedging, returning the first success or, once every
+/// issued attempt has failed, the last error. Each hedge must first obtain a
+/// slot from `hedge_permit`, held until that attempt completes; the first
+/// attempt runs under the caller's.
+pub(crate) async fn hedged<F, Fut, T, P>(
+    config: HedgingConfig,
+    deadline: Option<Instant>,
+    mut hedge_permit: impl FnMut() -> Option<P>,
+    mut attempt: F,
+) -> Result<T, CKMSError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, CKMSError>>,
+{
+    let run = |attempt: Fut, permit: Option<P>| async move {
+        let _permit = permit;
+        attempt.await
+    };
+    let mut in_flight = FuturesUnordered::new();
+    in_flight.push(run(attempt(), None));
+    let mut issued = 1;
+    let mut saturated = false;
+
+    loop {
+        // a hedge issued after the deadline could never be used
+        let out_of_time = deadline.is_some_and(|deadline| {
+            deadline.saturating_duration_since(Instant::now()) < config.delay
+        });
+        let can_hedge = issued < config.max_attempts && !saturated && !out_of_time;
+        tokio::select! {
+            Some(result) = in_flight.next() => match result {
+                Ok(value) => return Ok(value),
+                Err(e) if in_flight.is_empty() => return Err(e),
+                Err(e) => debug!("Hedged attempt failed, waiting on others: {}", e),
+            },
+            _ = tokio::time::sleep(config.delay), if can_hedge => match hedge_permit() {
+                Some(permit) => {
+                    debug!("Issuing hedged attempt {}", issued + 1);
+                    in_flight.push(run(attempt(), Some(permit)));
+                    issued += 1;
+                }
+                None => {
+                    debug!("No capacity for a hedged attempt");
+                    saturated = true;
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{limiter::Limiter, BackpressurePolicy, ConcurrencyLimit, Priority};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn slow_attempt_is_hedged() {
+        let attempts = AtomicUsize::new(0);
+        let config = HedgingConfig::new(Duration::from_millis(10), 2);
+
+        let result = hedged(
+            config,
+            None,
+            || Some(()),
+            || {
+                let n = attempts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if n == 0 {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                    Ok::<_, CKMSError>(n)
+                }
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result, 1);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn fast_failure_is_not_retried() {
+        let attempts = AtomicUsize::new(0);
+        let config = HedgingConfig::new(Duration::from_millis(10), 3);
+
+        let result: Result<(), _> = hedged(
+            config,
+            None,
+            || Some(()),
+            || {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err(CKMSError::RecoveryError) }
+            },
+        )
+        .await;
+
+        assert!(matches!(result, Err(CKMSError::RecoveryError)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    async fn slow_first_attempt(attempts: &AtomicUsize) -> Result<usize, CKMSError> {
+        let n = attempts.fetch_add(1, Ordering::SeqCst);
+        if n == 0 {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+        }
+        Ok(n)
+    }
+
+    #[tokio::test]
+    async fn no_hedge_when_deadline_is_nearer_than_delay() {
+        let attempts = AtomicUsize::new(0);
+        let config = HedgingConfig::new(Duration::from_millis(20), 3);
+        let deadline = Instant::now() + Duration::from_millis(10);
+
+        let result = hedged(
+            config,
+            Some(deadline),
+            || Some(()),
+            || slow_first_attempt(&attempts),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result, 0);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // with room for one hedge but not a second
+        let attempts = AtomicUsize::new(0);
+        let deadline = Instant::now() + Duration::from_millis(30);
+        let result = hedged(
+            config,
+            Some(deadline),
+            || Some(()),
+            || async {
+                let n = attempts.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(if n == 0 { 300 } else { 100 })).await;
+                Ok::<_, CKMSError>(n)
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result, 1);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn hedges_take_a_concurrency_slot() {
+        let limiter = Limiter::new(ConcurrencyLimit::new(2, BackpressurePolicy::FailFast));
+        let _caller = limiter.acquire(Priority::Normal).await.unwrap();
+        let config = HedgingConfig::new(Duration::from_millis(10), 3);
+
+        let attempts = AtomicUsize::new(0);
+        let in_flight = std::sync::Mutex::new(Vec::new());
+        let result = hedged(
+            config,
+            None,
+            || limiter.try_acquire(),
+            || {
+                in_flight.lock().unwrap().push(limiter.stats().in_flight);
+                slow_first_attempt(&attempts)
+            },
+        )
+        .await
+        .unwrap();
+
+        // the hedge held the limiter's second slot until it completed
+        assert_eq!(result, 1);
+        assert_eq!(*in_flight.lock().unwrap(), [1, 2]);
+        assert_eq!(limiter.stats().in_flight, 1);
+
+        let _other = limiter.acquire(Priority::Normal).await.unwrap();
+        let attempts = AtomicUsize::new(0);
+        let result = hedged(
+            config,
+            None,
+            || limiter.try_acquire(),
+            || slow_first_attempt(&attempts),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result, 0);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
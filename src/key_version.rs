@@ -0,0 +1,115 @@
+use std::{fmt, str::FromStr};
+
+use crate::CKMSError;
+
+/// A reference to a crypto key version: either a concrete version number, or a
+/// symbolic alias which is resolved against KMS when the signer is constructed.
+///
+/// Parses from strings such as `"3"`, `"pinned:3"`, `"latest"` and
+/// `"latest-enabled"`, so it can be used directly in declarative config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum KeyVersion {
+    /// The highest-numbered version of the key, regardless of its state
+    Latest,
+    /// The highest-numbered version of the key which is currently `ENABLED`
+    LatestEnabled,
+    /// A specific version number
+    Pinned(u64),
+}
+
+impl KeyVersion {
+    /// Returns the version number if no resolution against KMS is required
+    pub fn as_pinned(&self) -> Option<u64> {
+        match self {
+            KeyVersion::Pinned(version) => Some(*version),
+            _ => None,
+        }
+    }
+}
+
+impl From<u64> for KeyVersion {
+    fn from(version: u64) -> Self {
+        KeyVersion::Pinned(version)
+    }
+}
+
+impl FromStr for KeyVersion {
+    type Err = CKMSError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CKMSError::InvalidKeyVersion(s.to_string());
+        match s.trim() {
+            "latest" => Ok(KeyVersion::Latest),
+            "latest-enabled" => Ok(KeyVersion::LatestEnabled),
+            other => {
+                let number = other.strip_prefix("pinned:").unwrap_or(other);
+                match number.parse::<u64>() {
+                    // KMS numbers versions from 1
+                    Ok(0) | Err(_) => Err(invalid()),
+                    Ok(version) => Ok(KeyVersion::Pinned(version)),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for KeyVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyVersion::Latest => write!(f, "latest"),
+            KeyVersion::LatestEnabled => write!(f, "latest-enabled"),
+            KeyVersion::Pinned(version) => write!(f, "pinned:{version}"),
+        }
+    }
+}
+
+/// Extracts the version number from a `.../cryptoKeyVersions/<n>` resource name
+pub(crate) fn version_from_resource_name(name: &str) -> Option<u64> {
+    name.rsplit_once("/cryptoKeyVersions/")
+        .and_then(|(_, version)| version.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_aliases() {
+        assert_eq!("latest".parse::<KeyVersion>().unwrap(), KeyVersion::Latest);
+        assert_eq!(
+            "latest-enabled".parse::<KeyVersion>().unwrap(),
+            KeyVersion::LatestEnabled
+        );
+        assert_eq!(
+            "pinned:3".parse::<KeyVersion>().unwrap(),
+            KeyVersion::Pinned(3)
+        );
+        assert_eq!("7".parse::<KeyVersion>().unwrap(), KeyVersion::Pinned(7));
+        assert!("pinned:".parse::<KeyVersion>().is_err());
+        assert!("0".parse::<KeyVersion>().is_err());
+        assert!("newest".parse::<KeyVersion>().is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for version in [
+            KeyVersion::Latest,
+            KeyVersion::LatestEnabled,
+            KeyVersion::Pinned(12),
+        ] {
+            assert_eq!(version.to_string().parse::<KeyVersion>().unwrap(), version);
+        }
+    }
+
+    #[test]
+    fn extracts_version_from_resource_name() {
+        assert_eq!(
+            version_from_resource_name(
+                "projects/p/locations/l/keyRings/r/cryptoKeys/k/cryptoKeyVersions/4"
+            ),
+            Some(4)
+        );
+        assert_eq!(version_from_resource_name("projects/p"), None);
+    }
+}
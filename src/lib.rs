@@ -1,5 +1,24 @@
+//! An ethers [`Signer`] backed by secp256k1 keys in Google Cloud KMS.
+//!
+//! # Stability
+//!
+//! - [`CKMSError`], the public enums and the config and report structs are
+//!   `#[non_exhaustive]`, so variants and fields can be added in minor
+//!   releases. Match them with a wildcard arm, and build configs with their
+//!   constructors, `Default` and `with_*` methods rather than struct literals.
+//! - Traits meant to be implemented downstream, such as [`Store`] and
+//!   [`audit::AuditSink`], only gain provided methods in minor releases.
+//!   Extension traits like [`SignatureExt`] are sealed.
+//! - [`RecoverableSignature`], [`SigningReceipt`] and the payloads of
+//!   protocols, such as [`ForwardRequest`] and the [`erc4337`] user
+//!   operations, stay exhaustive, as their fields are a fixed encoding.
+
+// `CKMSError` carries `tonic::Status` by value, which is larger than clippy
+// would like for an error type
+#![allow(clippy::result_large_err)]
+
 use async_trait::async_trait;
-use ethers::prelude::k256::pkcs8::DecodePublicKey;
+use ethers::prelude::k256::pkcs8::{DecodePublicKey, EncodePublicKey, LineEnding};
 use ethers::types::transaction::eip2718::TypedTransaction;
 use ethers::types::transaction::eip712::Eip712;
 use ethers::{
@@ -8,25 +27,204 @@ use ethers::{
         FieldBytes,
     },
     signers::Signer,
-    types::{Address, Signature, H256, U256},
+    types::{Address, Bytes, Signature, H256, U256},
     utils::{hash_message, keccak256},
 };
+use futures::StreamExt;
 use gcloud_sdk::{
     google::cloud::kms::{
         self,
         v1::{
+            crypto_key_version::CryptoKeyVersionState,
             key_management_service_client::KeyManagementServiceClient, AsymmetricSignRequest,
-            GetPublicKeyRequest,
+            CryptoKeyVersion, GetCryptoKeyVersionRequest, GetPublicKeyRequest,
+            ListCryptoKeyVersionsRequest,
         },
     },
-    GoogleApi, GoogleAuthMiddleware,
+    GoogleApi,
 };
-use std::fmt::Debug;
-use tonic::Request;
-use tracing::{debug, instrument};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+};
+use tokio::sync::OnceCell;
+use tonic::{service::Interceptor, Request};
+use tracing::{debug, info, instrument};
+
+mod eas;
+pub use eas::{eas_domain, DelegatedAttestation, DelegatedRevocation, EasSignature};
+
+mod eip1271;
+pub use eip1271::{verify_eip1271, EIP1271_MAGIC_VALUE};
+
+mod eip4844;
+pub use eip4844::{kzg_to_versioned_hash, BlobSidecar, Eip4844TransactionRequest};
+
+mod eip6492;
+pub use eip6492::{Eip6492Signature, EIP6492_MAGIC_SUFFIX};
+
+mod encoder;
+pub use encoder::{parse_transaction, StandardEncoder, TransactionEncoder};
 
 mod error;
-pub use error::CKMSError;
+pub use error::{CKMSError, SigningDenied};
+
+#[cfg(feature = "alloy")]
+mod alloy;
+
+#[cfg(feature = "bitcoin")]
+pub mod bitcoin;
+
+#[cfg(feature = "cosmos")]
+pub mod cosmos;
+
+#[cfg(feature = "async-signature")]
+mod rustcrypto;
+
+#[cfg(feature = "differential")]
+pub mod differential;
+
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+
+#[cfg(feature = "remote")]
+mod remote;
+#[cfg(feature = "remote")]
+pub use remote::RemoteSigner;
+
+#[cfg(feature = "server")]
+pub mod server;
+
+#[cfg(feature = "siwe")]
+pub mod siwe;
+
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+
+#[cfg(feature = "web3")]
+mod web3_key;
+#[cfg(feature = "web3")]
+pub use web3_key::Web3Key;
+
+pub mod audit;
+use audit::{AuditEvent, AuditOperation, AuditOutcome, AuditSink};
+
+pub mod erc4337;
+
+pub mod vectors;
+
+mod backend;
+pub use backend::{KeyBackend, KmsKeyBackend};
+
+mod capabilities;
+pub use capabilities::Capabilities;
+
+mod client;
+pub use client::KmsApiClient;
+
+mod clock;
+pub use clock::{Clock, ManualClock, OffsetClock, SystemClock};
+
+mod cow;
+pub use cow::{gpv2_domain, Gpv2Order, OrderKind, SignedGpv2Order, TokenBalance, GPV2_SETTLEMENT};
+
+mod connection;
+pub use connection::{
+    regional_endpoint, ChannelOptions, ChannelTls, ConnectionConfig, KmsChannel, ProxyConfig,
+    DEFAULT_ENDPOINT,
+};
+
+mod credentials;
+pub use credentials::{CredentialSource, ProvidedToken, TokenProvider};
+
+mod forwarder;
+pub use forwarder::{minimal_forwarder_domain, ForwardRequest};
+
+mod governance;
+pub use governance::{
+    recover_snapshot_signer, SnapshotEnvelope, SnapshotProposal, SnapshotVote, VoteChoice,
+};
+
+mod hedging;
+pub use hedging::HedgingConfig;
+
+mod key_version;
+pub use key_version::KeyVersion;
+
+mod limiter;
+pub use limiter::{BackpressurePolicy, ConcurrencyLimit, LimiterStats, Priority, SigningContext};
+
+mod message;
+pub use message::intended_validator_hash;
+
+mod nonce;
+pub use nonce::NonceManagedSigner;
+
+mod outage;
+pub use outage::OutageQueue;
+
+mod policy;
+pub use policy::{HighSPolicy, TxType};
+
+mod pool;
+pub use pool::{HealthConfig, HealthEvent, HealthState, KeyHealth, SignerPool};
+
+mod provision;
+pub use provision::KeyProtection;
+
+mod quorum;
+pub use quorum::{safe_domain, QuorumSignatures, QuorumSigner, SafeOperation, SafeTransaction};
+
+mod receipt;
+pub use receipt::SigningReceipt;
+
+mod replay;
+pub use replay::{ReplayAction, ReplayProtection};
+
+mod report;
+pub use report::{SignerReport, Validation};
+
+mod scope;
+pub use scope::Scope;
+
+mod signature;
+pub use signature::{y_parity_from_v, RecoverableSignature, SignatureExt};
+
+mod snapshot;
+use snapshot::{Snapshot, SnapshotCell};
+
+mod store;
+pub use store::{FileStore, MemoryStore, Store};
+
+mod typed_data;
+pub use typed_data::typed_data_digest;
+
+/// The scope request for signing typed data, bound to the domain's chain id
+/// if it has one
+fn typed_data_scope_request<T: Eip712>(payload: &T) -> scope::ScopeRequest {
+    let domain = payload.domain().ok();
+    let chain_id = domain
+        .and_then(|domain| domain.chain_id)
+        .filter(|chain_id| *chain_id <= U256::from(u64::MAX))
+        .map(|chain_id| chain_id.as_u64());
+    scope::ScopeRequest {
+        operation: AuditOperation::TypedData,
+        chain_id,
+        domain_separator: payload.domain_separator().ok().map(H256::from),
+    }
+}
+
+/// Parses a PEM or DER SubjectPublicKeyInfo
+fn parse_public_key(public_key: &[u8]) -> Result<VerifyingKey, CKMSError> {
+    match std::str::from_utf8(public_key) {
+        Ok(pem) if pem.trim_start().starts_with("-----BEGIN") => {
+            Ok(VerifyingKey::from_public_key_pem(pem.trim())?)
+        }
+        _ => Ok(VerifyingKey::from_public_key_der(public_key)?),
+    }
+}
 
 /// Convert a verifying key to an ethereum address
 fn verifying_key_to_address(key: &VerifyingKey) -> Address {
@@ -38,9 +236,106 @@ fn verifying_key_to_address(key: &VerifyingKey) -> Address {
     Address::from_slice(&hash[12..])
 }
 
-pub fn apply_eip155(sig: &mut Signature, chain_id: u64) {
-    let v = (chain_id * 2 + 35) + sig.v;
-    sig.v = v;
+/// The largest chain id whose EIP-155 `v` value (`chain_id * 2 + 36`) fits in
+/// a u64
+pub const MAX_EIP155_CHAIN_ID: u64 = (u64::MAX - 36) / 2;
+
+/// Replaces a 0/1 recovery id in `sig.v` with the EIP-155 `v` value for
+/// `chain_id`. Fails for chain ids above [`MAX_EIP155_CHAIN_ID`], whose `v`
+/// would overflow.
+pub fn apply_eip155(sig: &mut Signature, chain_id: u64) -> Result<(), CKMSError> {
+    sig.v = chain_id
+        .checked_mul(2)
+        .and_then(|v| v.checked_add(35))
+        .and_then(|v| v.checked_add(sig.v))
+        .ok_or(CKMSError::UnsupportedChainId(chain_id))?;
+    Ok(())
+}
+
+/// Checks that `chain_id` has an EIP-155 `v` value
+pub fn validate_chain_id(chain_id: u64) -> Result<u64, CKMSError> {
+    if chain_id > MAX_EIP155_CHAIN_ID {
+        return Err(CKMSError::UnsupportedChainId(chain_id));
+    }
+    Ok(chain_id)
+}
+
+/// Recovers the 0/1 recovery id from an EIP-155 `v` value, checking that it
+/// was made for `chain_id`
+pub fn recovery_id_from_eip155(v: u64, chain_id: u64) -> Result<u8, CKMSError> {
+    let base = chain_id
+        .checked_mul(2)
+        .and_then(|v| v.checked_add(35))
+        .ok_or(CKMSError::UnsupportedChainId(chain_id))?;
+    match v.checked_sub(base) {
+        Some(recovery_id @ (0 | 1)) => Ok(recovery_id as u8),
+        _ => Err(CKMSError::ChainIdMismatch {
+            v,
+            expected: chain_id,
+        }),
+    }
+}
+
+/// Replaces a 0/1 recovery id in `sig.v` with the `v` a transaction's envelope
+/// expects: the EIP-155 value for legacy transactions, and the bare y-parity
+/// for typed (EIP-2718) transactions, whose payload already commits to the
+/// chain id
+pub fn apply_transaction_v(
+    sig: &mut Signature,
+    tx: &TypedTransaction,
+    chain_id: u64,
+) -> Result<(), CKMSError> {
+    match TxType::of(tx) {
+        TxType::Legacy => apply_eip155(sig, chain_id),
+        TxType::Eip2930 | TxType::Eip1559 | TxType::Eip4844 | TxType::Eip7702 | TxType::Other => {
+            Ok(())
+        }
+    }
+}
+
+fn transaction_sighash(
+    tx: &TypedTransaction,
+    default_chain_id: u64,
+    replay_protection: bool,
+) -> (H256, Option<u64>) {
+    let tx = signed_transaction(tx, default_chain_id, replay_protection);
+    (tx.sighash(), tx.chain_id().map(|id| id.as_u64()))
+}
+
+/// The transaction as it is signed: with `default_chain_id` if it has no
+/// chain id, or without one if it is a legacy transaction and replay
+/// protection is off
+fn signed_transaction(
+    tx: &TypedTransaction,
+    default_chain_id: u64,
+    replay_protection: bool,
+) -> TypedTransaction {
+    let mut tx = tx.clone();
+    match &mut tx {
+        TypedTransaction::Legacy(legacy) if !replay_protection => legacy.chain_id = None,
+        _ => {
+            let chain_id = tx
+                .chain_id()
+                .map(|id| id.as_u64())
+                .unwrap_or(default_chain_id);
+            tx.set_chain_id(chain_id);
+        }
+    }
+    tx
+}
+
+/// Refuses a transaction chain id other than `signer_chain_id`, for
+/// [`GcpKmsSigner::with_strict_chain_id`]. Transactions without one pass.
+fn check_tx_chain_id(tx_chain_id: Option<u64>, signer_chain_id: u64) -> Result<(), SigningDenied> {
+    match tx_chain_id {
+        Some(tx_chain_id) if tx_chain_id != signer_chain_id => {
+            Err(SigningDenied::new("strict_chain_id")
+                .with_value("tx_chain_id", tx_chain_id)
+                .with_value("chain_id", signer_chain_id)
+                .with_remediation("clear the transaction's chain id or use a signer for its chain"))
+        }
+        _ => Ok(()),
+    }
 }
 
 /// Makes a trial recovery to check whether an RSig corresponds to a known
@@ -56,23 +351,42 @@ fn check_candidate(
         .unwrap_or(false)
 }
 
+/// Finds the recovery id which recovers `vk` from a (low-s) signature over
+/// `digest`, or `None` if the signature was not made by `vk`
+pub fn find_recovery_id(sig: &KSig, digest: [u8; 32], vk: &VerifyingKey) -> Option<RecoveryId> {
+    [0, 1]
+        .into_iter()
+        .filter_map(RecoveryId::from_byte)
+        .find(|recovery_id| check_candidate(sig, *recovery_id, digest, vk))
+}
+
+/// Converts a KMS signature to an ethers signature with a 0/1 recovery id as
+/// `v`, failing with [`CKMSError::RecoveryError`] if it does not recover to
+/// `vk`
 pub fn sig_from_digest_bytes_trial_recovery(
     sig: &KSig,
     digest: [u8; 32],
     vk: &VerifyingKey,
-) -> Signature {
+) -> Result<Signature, CKMSError> {
     let r_bytes: FieldBytes = sig.r().into();
     let s_bytes: FieldBytes = sig.s().into();
     let r = U256::from_big_endian(r_bytes.as_slice());
     let s = U256::from_big_endian(s_bytes.as_slice());
 
-    if check_candidate(sig, RecoveryId::from_byte(0).unwrap(), digest, vk) {
-        Signature { r, s, v: 0 }
-    } else if check_candidate(sig, RecoveryId::from_byte(1).unwrap(), digest, vk) {
-        Signature { r, s, v: 1 }
-    } else {
-        panic!("bad sig");
-    }
+    let recovery_id = find_recovery_id(sig, digest, vk).ok_or(CKMSError::RecoveryError)?;
+    Ok(Signature {
+        r,
+        s,
+        v: recovery_id.to_byte() as u64,
+    })
+}
+
+/// Strips the zone suffix from a GCE zone name, e.g. `europe-west3-b` becomes
+/// `europe-west3`
+fn region_from_zone(zone: &str) -> &str {
+    zone.rsplit_once('-')
+        .map(|(region, _)| region)
+        .unwrap_or(zone)
 }
 
 #[derive(Clone, Debug)]
@@ -91,6 +405,31 @@ impl GcpKeyRingRef {
         }
     }
 
+    /// Builds a key ring reference using the GCE/GKE metadata server to detect
+    /// the project id. If no location is given, the region of the instance's
+    /// zone is used (e.g. `us-central1` for `us-central1-a`).
+    pub async fn from_metadata_server(
+        key_ring: &str,
+        location: Option<&str>,
+    ) -> Result<Self, CKMSError> {
+        let metadata = gcemeta::Client::new();
+        let google_project_id = metadata.project_id().await?;
+        let location = match location {
+            Some(location) => location.to_string(),
+            None => region_from_zone(&metadata.zone().await?).to_string(),
+        };
+        debug!(
+            "Detected project {} and location {} from metadata server",
+            google_project_id, location
+        );
+
+        Ok(Self {
+            google_project_id,
+            location,
+            key_ring: key_ring.to_string(),
+        })
+    }
+
     fn to_google_ref(&self) -> String {
         format!(
             "projects/{}/locations/{}/keyRings/{}",
@@ -98,48 +437,317 @@ impl GcpKeyRingRef {
         )
     }
 
+    fn to_crypto_key_ref(&self, key_id: &str) -> String {
+        format!("{}/cryptoKeys/{}", self.to_google_ref(), key_id)
+    }
+
     fn to_key_version_ref(&self, key_id: &str, key_version: u64) -> String {
         format!(
-            "{}/cryptoKeys/{}/cryptoKeyVersions/{}",
-            self.to_google_ref(),
-            key_id,
+            "{}/cryptoKeyVersions/{}",
+            self.to_crypto_key_ref(key_id),
             key_version,
         )
     }
 }
 
+/// The authenticated KMS client used by [`GcpKmsProvider`]. Clones share
+/// its channel.
+pub type KmsClient = KeyManagementServiceClient<KmsChannel>;
+
 #[derive(Clone)]
 pub struct GcpKmsProvider {
-    client: GoogleApi<KeyManagementServiceClient<GoogleAuthMiddleware>>,
+    client: client::Client,
     kms_key_ref: GcpKeyRingRef,
+    /// `None` for a provider over a caller's client
+    credential_source: Option<CredentialSource>,
+    endpoint: Option<String>,
+    hedging: Option<HedgingConfig>,
+    limiter: Option<Arc<limiter::Limiter>>,
+    tenant_limiters: Arc<HashMap<String, Arc<limiter::Limiter>>>,
+    outage_queue: Option<Arc<outage::Parking>>,
+    interceptors: Vec<Arc<Mutex<dyn Interceptor + Send>>>,
 }
 
 impl Debug for GcpKmsProvider {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("GcpKmsProvider")
             .field("kms_key_ref", &self.kms_key_ref)
+            .field("credential_source", &self.credential_source)
+            .field("endpoint", &self.endpoint)
+            .field("hedging", &self.hedging)
+            .field(
+                "concurrency_limit",
+                &self.limiter.as_ref().map(|limiter| limiter.limit()),
+            )
+            .field(
+                "tenant_limits",
+                &self
+                    .tenant_limiters
+                    .iter()
+                    .map(|(tenant, limiter)| (tenant, limiter.limit()))
+                    .collect::<HashMap<_, _>>(),
+            )
+            .field(
+                "outage_queue",
+                &self.outage_queue.as_ref().map(|parking| parking.config()),
+            )
+            .field("interceptors", &self.interceptors.len())
             .finish()
     }
 }
 
 impl GcpKmsProvider {
     pub async fn new(kms_key_ref: GcpKeyRingRef) -> Result<Self, CKMSError> {
+        Self::new_with_credential_sources(kms_key_ref, CredentialSource::default_chain()).await
+    }
+
+    /// Creates a provider which tries each credential source in order and
+    /// uses the first one that can be loaded. The source which succeeded is
+    /// available from [`GcpKmsProvider::credential_source`].
+    pub async fn new_with_credential_sources(
+        kms_key_ref: GcpKeyRingRef,
+        credential_sources: Vec<CredentialSource>,
+    ) -> Result<Self, CKMSError> {
+        let config = ConnectionConfig::default().with_credential_sources(credential_sources);
+        Self::new_with_config(kms_key_ref, config).await
+    }
+
+    /// Creates a provider for the KMS API at `endpoint` rather than
+    /// [`DEFAULT_ENDPOINT`], e.g. `https://private.googleapis.com`
+    pub async fn new_with_endpoint(
+        kms_key_ref: GcpKeyRingRef,
+        endpoint: impl Into<String>,
+    ) -> Result<Self, CKMSError> {
+        let config = ConnectionConfig::default().with_endpoint(endpoint);
+        Self::new_with_config(kms_key_ref, config).await
+    }
+
+    /// Creates a provider connected as `config` describes
+    pub async fn new_with_config(
+        kms_key_ref: GcpKeyRingRef,
+        config: ConnectionConfig,
+    ) -> Result<Self, CKMSError> {
+        let endpoint = config.endpoint_for(&kms_key_ref.location)?;
         debug!(
-            "Initialising Google KMS envelope encryption for {}",
-            kms_key_ref.to_google_ref()
+            "Initialising Google KMS envelope encryption for {} at {}",
+            kms_key_ref.to_google_ref(),
+            endpoint
         );
 
-        let client = GoogleApi::from_function(
-            KeyManagementServiceClient::new,
-            "https://cloudkms.googleapis.com",
-            None,
-        )
-        .await?;
+        let channel = connection::connect(&endpoint, &config).await?;
+        let mut failures = Vec::new();
+        for credential_source in config.credential_sources {
+            match credentials::Tokens::new(credential_source.clone()).await {
+                Ok(tokens) => {
+                    info!(?credential_source, "Loaded Google credentials");
+                    let client = KeyManagementServiceClient::new(KmsChannel::new(channel, tokens));
+                    return Ok(Self {
+                        kms_key_ref,
+                        client: client::Client::Kms(client),
+                        credential_source: Some(credential_source),
+                        endpoint: Some(endpoint),
+                        hedging: None,
+                        limiter: None,
+                        tenant_limiters: Arc::default(),
+                        outage_queue: None,
+                        interceptors: Vec::new(),
+                    });
+                }
+                Err(e) => {
+                    debug!(?credential_source, "Credential source failed: {}", e);
+                    failures.push(format!("{credential_source:?}: {e}"));
+                }
+            }
+        }
 
-        Ok(Self {
+        if failures.is_empty() {
+            failures.push("no credential sources configured".to_string());
+        }
+        Err(CKMSError::CredentialsError(failures.join("; ")))
+    }
+
+    /// Creates a provider over an already configured client, e.g. one with
+    /// custom auth or sharing a channel with the rest of the application:
+    /// either gcloud-sdk's stock `KeyManagementServiceClient`, or a
+    /// [`KmsClient`] over the client's channel. The client's credentials and
+    /// endpoint are not known to the provider, so
+    /// [`GcpKmsProvider::credential_source`] and [`GcpKmsProvider::endpoint`]
+    /// return `None`.
+    ///
+    /// ```no_run
+    /// # async fn example() -> Result<(), ethers_gcp_kms_signer::CKMSError> {
+    /// use ethers_gcp_kms_signer::{GcpKeyRingRef, GcpKmsProvider};
+    /// use gcloud_sdk::{
+    ///     google::cloud::kms::v1::key_management_service_client::KeyManagementServiceClient,
+    ///     GoogleApi,
+    /// };
+    ///
+    /// let client = GoogleApi::from_function(
+    ///     KeyManagementServiceClient::new,
+    ///     "https://cloudkms.googleapis.com",
+    ///     None,
+    /// )
+    /// .await?;
+    /// let key_ring = GcpKeyRingRef::new("project", "global", "ring");
+    /// let provider = GcpKmsProvider::new_with_client(client, key_ring);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_with_client<C: KmsApiClient>(
+        client: GoogleApi<C>,
+        kms_key_ref: GcpKeyRingRef,
+    ) -> Self {
+        debug!(
+            "Initialising Google KMS envelope encryption for {} with a pre-built client",
+            kms_key_ref.to_google_ref(),
+        );
+        Self {
             kms_key_ref,
-            client,
-        })
+            client: client::into_client(client.get()),
+            credential_source: None,
+            endpoint: None,
+            hedging: None,
+            limiter: None,
+            tenant_limiters: Arc::default(),
+            outage_queue: None,
+            interceptors: Vec::new(),
+        }
+    }
+
+    /// Returns the underlying KMS client, for calling RPCs which this crate
+    /// does not wrap. Requests made through it share this provider's channel
+    /// and credentials. `None` for a provider over a stock gcloud-sdk client,
+    /// which the application already holds.
+    pub fn client(&self) -> Option<&KmsClient> {
+        match &self.client {
+            client::Client::Kms(client) => Some(client),
+            client::Client::Google(_) => None,
+        }
+    }
+
+    /// Returns the key ring this provider operates on
+    pub fn key_ring_ref(&self) -> &GcpKeyRingRef {
+        &self.kms_key_ref
+    }
+
+    /// Returns the credential source this provider authenticated with, or
+    /// `None` if it was created with [`GcpKmsProvider::new_with_client`]
+    pub fn credential_source(&self) -> Option<&CredentialSource> {
+        self.credential_source.as_ref()
+    }
+
+    /// Enables hedging of `AsymmetricSign` calls to cut tail latency: a sign
+    /// call which has not completed within the configured delay is issued
+    /// again, and the first success is used
+    pub fn with_hedging(mut self, config: HedgingConfig) -> Self {
+        self.hedging = Some(config);
+        self
+    }
+
+    /// Bounds the number of concurrent sign requests made by this provider
+    /// (and its clones), applying `limit.policy` once saturated
+    pub fn with_concurrency_limit(mut self, limit: ConcurrencyLimit) -> Self {
+        self.limiter = Some(limiter::Limiter::new(limit));
+        self
+    }
+
+    /// Partitions the provider's sign budget between named tenants. A request
+    /// whose [`SigningContext::tenant`] is configured here must obtain a slot
+    /// from its tenant's limit, as well as from the provider-wide limit if
+    /// one is set; requests from other tenants only count against the latter.
+    pub fn with_tenant_limits(
+        mut self,
+        limits: impl IntoIterator<Item = (String, ConcurrencyLimit)>,
+    ) -> Self {
+        self.tenant_limiters = Arc::new(
+            limits
+                .into_iter()
+                .map(|(tenant, limit)| (tenant, limiter::Limiter::new(limit)))
+                .collect(),
+        );
+        self
+    }
+
+    /// Parks sign calls while KMS is unavailable, retrying them until the
+    /// queue's deadline rather than failing at once. Parked calls keep their
+    /// concurrency slots.
+    pub fn with_outage_queue(mut self, queue: OutageQueue) -> Self {
+        self.outage_queue = Some(Arc::new(outage::Parking::new(queue)));
+        self
+    }
+
+    /// Runs every request through `interceptor`, after the interceptors
+    /// added before it, to add headers such as request ids or to audit
+    /// calls. An interceptor's error fails the call with
+    /// [`CKMSError::RequestError`] before it is sent.
+    pub fn with_interceptor(mut self, interceptor: impl Interceptor + Send + 'static) -> Self {
+        self.interceptors.push(Arc::new(Mutex::new(interceptor)));
+        self
+    }
+
+    /// Runs a request's metadata through the interceptors
+    pub(crate) fn intercept<T>(&self, mut request: Request<T>) -> Result<Request<T>, CKMSError> {
+        if self.interceptors.is_empty() {
+            return Ok(request);
+        }
+        let mut metadata = Request::new(());
+        *metadata.metadata_mut() = std::mem::take(request.metadata_mut());
+        for interceptor in &self.interceptors {
+            // an interceptor which panicked holds no state we rely on
+            let mut interceptor = interceptor.lock().unwrap_or_else(PoisonError::into_inner);
+            metadata = interceptor.call(metadata)?;
+        }
+        *request.metadata_mut() = std::mem::take(metadata.metadata_mut());
+        Ok(request)
+    }
+
+    /// Returns the number of sign calls parked waiting for KMS
+    pub fn parked_requests(&self) -> usize {
+        self.outage_queue
+            .as_ref()
+            .map_or(0, |parking| parking.parked())
+    }
+
+    /// Returns usage of the provider-wide concurrency limit, if one is set
+    pub fn concurrency_stats(&self) -> Option<LimiterStats> {
+        self.limiter.as_ref().map(|limiter| limiter.stats())
+    }
+
+    /// Returns usage of each tenant's concurrency limit
+    pub fn tenant_stats(&self) -> HashMap<String, LimiterStats> {
+        self.tenant_limiters
+            .iter()
+            .map(|(tenant, limiter)| (tenant.clone(), limiter.stats()))
+            .collect()
+    }
+
+    /// Returns the KMS endpoint this provider is connected to, or `None` if
+    /// it was created with [`GcpKmsProvider::new_with_client`]
+    pub fn endpoint(&self) -> Option<&str> {
+        self.endpoint.as_deref()
+    }
+
+    /// Fetches the metadata (state, algorithm, protection level) of a key version
+    pub async fn get_crypto_key_version(
+        &self,
+        key_id: &str,
+        key_version: u64,
+    ) -> Result<CryptoKeyVersion, CKMSError> {
+        let kms_key_name = self.kms_key_ref.to_key_version_ref(key_id, key_version);
+
+        let mut request = Request::new(GetCryptoKeyVersionRequest {
+            name: kms_key_name.clone(),
+        });
+
+        // Add metadata for request routing: https://cloud.google.com/kms/docs/grpc
+        request.metadata_mut().insert(
+            "x-goog-request-params",
+            format!("name={}", kms_key_name.clone()).parse().unwrap(),
+        );
+
+        let request = self.intercept(request)?;
+        let response = self.client.get_crypto_key_version(request).await?;
+        Ok(response.into_inner())
     }
 
     pub async fn get_verifying_key(
@@ -147,6 +755,17 @@ impl GcpKmsProvider {
         key_id: &str,
         key_version: u64,
     ) -> Result<VerifyingKey, CKMSError> {
+        let pem = self.get_public_key_pem(key_id, key_version).await?;
+        let public_key = VerifyingKey::from_public_key_pem(&pem)?;
+        Ok(public_key)
+    }
+
+    /// Fetches a key version's public key as KMS returns it, PEM encoded
+    pub(crate) async fn get_public_key_pem(
+        &self,
+        key_id: &str,
+        key_version: u64,
+    ) -> Result<String, CKMSError> {
         let kms_key_name = self.kms_key_ref.to_key_version_ref(key_id, key_version);
 
         let mut request = tonic::Request::new(GetPublicKeyRequest {
@@ -159,10 +778,71 @@ impl GcpKmsProvider {
             format!("name={}", kms_key_name.clone()).parse().unwrap(),
         );
 
-        let response = self.client.get().get_public_key(request).await?;
-        let pem = response.into_inner().pem;
-        let public_key = VerifyingKey::from_public_key_pem(&pem)?;
-        Ok(public_key)
+        let request = self.intercept(request)?;
+        let response = self.client.get_public_key(request).await?;
+        Ok(response.into_inner().pem)
+    }
+
+    /// Resolves a [`KeyVersion`] to a concrete version number. Symbolic aliases
+    /// are resolved by listing the versions of the key.
+    pub async fn resolve_key_version(
+        &self,
+        key_id: &str,
+        key_version: KeyVersion,
+    ) -> Result<u64, CKMSError> {
+        if let Some(version) = key_version.as_pinned() {
+            return Ok(version);
+        }
+
+        let kms_key_name = self.kms_key_ref.to_crypto_key_ref(key_id);
+        let filter = match key_version {
+            KeyVersion::LatestEnabled => "state=ENABLED".to_string(),
+            _ => String::new(),
+        };
+
+        let mut latest = None;
+        let mut page_token = String::new();
+        loop {
+            let mut request = Request::new(ListCryptoKeyVersionsRequest {
+                parent: kms_key_name.clone(),
+                filter: filter.clone(),
+                page_token,
+                ..Default::default()
+            });
+
+            // Add metadata for request routing: https://cloud.google.com/kms/docs/grpc
+            request.metadata_mut().insert(
+                "x-goog-request-params",
+                format!("parent={}", kms_key_name.clone()).parse().unwrap(),
+            );
+
+            let request = self.intercept(request)?;
+            let response = self
+                .client
+                .list_crypto_key_versions(request)
+                .await?
+                .into_inner();
+
+            latest = response
+                .crypto_key_versions
+                .iter()
+                .filter(|version| {
+                    key_version != KeyVersion::LatestEnabled
+                        || version.state == CryptoKeyVersionState::Enabled as i32
+                })
+                .filter_map(|version| key_version::version_from_resource_name(&version.name))
+                .chain(latest)
+                .max();
+
+            if response.next_page_token.is_empty() {
+                break;
+            }
+            page_token = response.next_page_token;
+        }
+
+        latest.ok_or_else(|| {
+            CKMSError::KeyVersionNotFound(format!("{key_version} of {kms_key_name}"))
+        })
     }
 
     pub async fn sign_digest(
@@ -171,79 +851,1049 @@ impl GcpKmsProvider {
         key_version: u64,
         digest: &[u8],
     ) -> Result<Vec<u8>, CKMSError> {
+        let (signature, _) = self
+            .sign_digest_reporting_version(key_id, key_version, digest)
+            .await?;
+        Ok(signature)
+    }
+
+    /// Signs a digest with an explicit key version, returning the DER
+    /// signature together with the version KMS reports having signed with
+    pub async fn sign_digest_reporting_version(
+        &self,
+        key_id: &str,
+        key_version: u64,
+        digest: &[u8],
+    ) -> Result<(Vec<u8>, u64), CKMSError> {
+        self.sign_digest_with_context(key_id, key_version, digest, &SigningContext::default())
+            .await
+    }
+
+    /// Like [`GcpKmsProvider::sign_digest_reporting_version`], with per-call
+    /// options such as the request's priority under backpressure
+    pub async fn sign_digest_with_context(
+        &self,
+        key_id: &str,
+        key_version: u64,
+        digest: &[u8],
+        context: &SigningContext,
+    ) -> Result<(Vec<u8>, u64), CKMSError> {
+        let tenant_limiter = context
+            .tenant
+            .as_ref()
+            .and_then(|tenant| self.tenant_limiters.get(tenant));
+        let _tenant_permit = match tenant_limiter {
+            Some(limiter) => Some(limiter.acquire(context.priority).await?),
+            None => None,
+        };
+        let _permit = match &self.limiter {
+            Some(limiter) => Some(limiter.acquire(context.priority).await?),
+            None => None,
+        };
+
         let kms_key_name = self.kms_key_ref.to_key_version_ref(key_id, key_version);
 
-        let mut request = Request::new(AsymmetricSignRequest {
+        let message = AsymmetricSignRequest {
             name: kms_key_name.clone(),
             digest: Some(kms::v1::Digest {
                 digest: Some(kms::v1::digest::Digest::Sha256(digest.to_vec())),
             }),
             ..Default::default()
-        });
+        };
 
-        // Add metadata for request routing: https://cloud.google.com/kms/docs/grpc
-        request.metadata_mut().insert(
-            "x-goog-request-params",
-            format!("name={}", kms_key_name.clone()).parse().unwrap(),
-        );
+        let attempt = || {
+            let mut request = Request::new(message.clone());
 
-        let response = self.client.get().asymmetric_sign(request).await?;
-        let signature = response.into_inner().signature;
-        Ok(signature)
+            // Add metadata for request routing: https://cloud.google.com/kms/docs/grpc
+            request.metadata_mut().insert(
+                "x-goog-request-params",
+                format!("name={}", kms_key_name.clone()).parse().unwrap(),
+            );
+
+            if let Some(deadline) = context.deadline {
+                request
+                    .set_timeout(deadline.saturating_duration_since(tokio::time::Instant::now()));
+            }
+
+            let request = self.intercept(request);
+            let client = self.client.clone();
+            async move { Ok(client.asymmetric_sign(request?).await?.into_inner()) }
+        };
+
+        // a hedge needs a free slot in every limit the request counts against
+        let hedge_permit = || {
+            let tenant = match tenant_limiter {
+                Some(limiter) => Some(limiter.try_acquire()?),
+                None => None,
+            };
+            let provider = match &self.limiter {
+                Some(limiter) => Some(limiter.try_acquire()?),
+                None => None,
+            };
+            Some((tenant, provider))
+        };
+        let hedged_attempt = || async {
+            match self.hedging {
+                Some(config) => {
+                    hedging::hedged(config, context.deadline, hedge_permit, attempt).await
+                }
+                None => attempt().await,
+            }
+        };
+        let response = match &self.outage_queue {
+            Some(parking) => parking.run(hedged_attempt).await?,
+            None => hedged_attempt().await?,
+        };
+        let signed_version =
+            key_version::version_from_resource_name(&response.name).unwrap_or(key_version);
+        Ok((response.signature, signed_version))
+    }
+
+    /// Signs an arbitrary 32-byte prehash and returns the low-s signature
+    /// together with its recovery id, without involving any ethers types.
+    /// `verifying_key` must be the public key of the given key version.
+    pub async fn sign_prehash_recoverable(
+        &self,
+        key_id: &str,
+        key_version: u64,
+        verifying_key: &VerifyingKey,
+        prehash: [u8; 32],
+    ) -> Result<(KSig, RecoveryId), CKMSError> {
+        let signature = self.sign_digest(key_id, key_version, &prehash).await?;
+        let sig = KSig::from_der(&signature)?;
+        let sig = sig.normalize_s().unwrap_or(sig);
+        let recovery_id =
+            find_recovery_id(&sig, prehash, verifying_key).ok_or(CKMSError::RecoveryError)?;
+        Ok((sig, recovery_id))
+    }
+}
+
+/// A signature as returned by KMS, and its low-s form
+#[derive(Clone, Copy, Debug)]
+struct KmsSignature {
+    raw: KSig,
+    normalized: KSig,
+}
+
+impl KmsSignature {
+    fn from_der(der: &[u8]) -> Result<Self, CKMSError> {
+        let raw = KSig::from_der(der)?;
+        Ok(Self {
+            raw,
+            normalized: raw.normalize_s().unwrap_or(raw),
+        })
+    }
+
+    fn is_high_s(&self) -> bool {
+        self.raw != self.normalized
+    }
+
+    /// Converts the recoverable form of the normalized signature to that of
+    /// the raw one. (r, n - s) recovers the same key with the opposite parity.
+    fn raw_recoverable(&self, mut normalized: Signature) -> Signature {
+        if self.is_high_s() {
+            let s_bytes: FieldBytes = self.raw.s().into();
+            normalized.s = U256::from_big_endian(s_bytes.as_slice());
+            normalized.v ^= 1;
+        }
+        normalized
+    }
+
+    /// The k256 signature to return under the high-s policy, with its
+    /// recovery id given the normalized signature's
+    fn recoverable_output(
+        &self,
+        policy: HighSPolicy,
+        normalized_id: RecoveryId,
+    ) -> (KSig, RecoveryId) {
+        if self.is_high_s() && policy == HighSPolicy::PassThrough {
+            let raw_id = RecoveryId::new(!normalized_id.is_y_odd(), normalized_id.is_x_reduced());
+            (self.raw, raw_id)
+        } else {
+            (self.normalized, normalized_id)
+        }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct GcpKmsSigner {
-    provider: GcpKmsProvider,
+    backend: Arc<dyn KmsKeyBackend>,
+    /// The backend, when it is Cloud KMS, for its reports and queue stats
+    provider: Option<GcpKmsProvider>,
     key_id: String,
-    key_version: u64,
-    chain_id: u64,
-    verifying_key: VerifyingKey,
+    /// Shared between clones, so a change made with
+    /// [`GcpKmsSigner::set_chain_id`] applies to all of them
+    snapshot: Arc<SnapshotCell>,
+    expected_address: Option<Address>,
+    signing_context: SigningContext,
+    audit_sinks: audit::AuditSinks,
+    replay_guard: Option<Arc<replay::ReplayGuard>>,
+    clock: Arc<dyn Clock>,
+    encoder: Arc<dyn TransactionEncoder>,
+    scopes: Vec<Arc<scope::ScopeGuard>>,
 }
 
 impl GcpKmsSigner {
+    /// Creates a signer for a key version of a [`GcpKmsProvider`], or of
+    /// another [`KmsKeyBackend`] such as a test's fake. Fails with
+    /// [`CKMSError::UnsupportedChainId`] for chain ids above
+    /// [`MAX_EIP155_CHAIN_ID`].
     pub async fn new(
-        provider: GcpKmsProvider,
+        provider: impl Into<KeyBackend>,
         key_id: String,
         key_version: u64,
         chain_id: u64,
     ) -> Result<Self, CKMSError> {
-        let verifying_key = provider.get_verifying_key(&key_id, key_version).await?;
-        Ok(Self {
+        validate_chain_id(chain_id)?;
+        let provider = provider.into();
+        let verifying_key = provider
+            .backend
+            .get_public_key(&key_id, key_version)
+            .await?;
+        Self::with_verifying_key(
             provider,
             key_id,
             key_version,
             chain_id,
-            verifying_key,
-        })
-    }
-
-    /// Sign a digest with this signer's key
-    pub async fn sign_digest(&self, digest: [u8; 32]) -> Result<KSig, CKMSError> {
-        let signature = self
-            .provider
-            .sign_digest(self.key_id.as_ref(), self.key_version, digest.as_ref())
-            .await?;
-        let sig = KSig::from_der(&signature)?;
-        let sig = sig.normalize_s().unwrap_or(sig);
-        Ok(sig)
+            OnceCell::new_with(Some(verifying_key)),
+        )
     }
 
-    /// Sign a digest with this signer's key and add the eip155 `v` value
-    /// corresponding to the input chain_id
-    #[instrument(err, skip(digest))]
-    async fn sign_digest_with_eip155(
-        &self,
-        digest: H256,
+    /// Creates a signer without contacting KMS. The public key is fetched
+    /// and cached on first use: by any signing call, or explicitly with
+    /// [`GcpKmsSigner::resolve`].
+    ///
+    /// Until then the synchronous accessors which need the key, including
+    /// [`Signer::address`], panic; resolve the signer before using them.
+    pub fn new_lazy(
+        provider: impl Into<KeyBackend>,
+        key_id: String,
+        key_version: u64,
+        chain_id: u64,
+    ) -> Result<Self, CKMSError> {
+        validate_chain_id(chain_id)?;
+        Self::with_verifying_key(
+            provider.into(),
+            key_id,
+            key_version,
+            chain_id,
+            OnceCell::new(),
+        )
+    }
+
+    /// Creates a signer from a public key pinned in configuration, as a
+    /// PEM or DER SubjectPublicKeyInfo, without contacting KMS. The key is
+    /// trusted as given: a signature which does not recover to it fails with
+    /// [`CKMSError::RecoveryError`] at signing time.
+    pub fn new_with_public_key(
+        provider: impl Into<KeyBackend>,
+        key_id: String,
+        key_version: u64,
+        chain_id: u64,
+        public_key: impl AsRef<[u8]>,
+    ) -> Result<Self, CKMSError> {
+        validate_chain_id(chain_id)?;
+        let verifying_key = parse_public_key(public_key.as_ref())?;
+        Self::with_verifying_key(
+            provider.into(),
+            key_id,
+            key_version,
+            chain_id,
+            OnceCell::new_with(Some(verifying_key)),
+        )
+    }
+
+    fn with_verifying_key(
+        KeyBackend { backend, provider }: KeyBackend,
+        key_id: String,
+        key_version: u64,
+        chain_id: u64,
+        verifying_key: OnceCell<VerifyingKey>,
+    ) -> Result<Self, CKMSError> {
+        Ok(Self {
+            backend,
+            provider,
+            key_id,
+            snapshot: Arc::new(SnapshotCell::new(Snapshot {
+                key_version,
+                verifying_key: Arc::new(verifying_key),
+                chain_id,
+                allowed_tx_types: None,
+                eip155_message_v: false,
+                high_s_policy: HighSPolicy::default(),
+                replay_protection: true,
+                strict_chain_id: false,
+            })),
+            expected_address: None,
+            signing_context: SigningContext::default(),
+            audit_sinks: audit::AuditSinks::default(),
+            replay_guard: None,
+            clock: Arc::new(SystemClock),
+            encoder: Arc::new(StandardEncoder),
+            scopes: Vec::new(),
+        })
+    }
+
+    /// Creates a signer for a [`KeyVersion`], which may be a symbolic alias
+    /// such as `latest-enabled`. The alias is resolved once, at construction;
+    /// the resolved version is available from [`GcpKmsSigner::key_version`].
+    /// Backends other than Cloud KMS may only resolve pinned versions.
+    pub async fn new_with_key_version(
+        provider: impl Into<KeyBackend>,
+        key_id: String,
+        key_version: KeyVersion,
+        chain_id: u64,
+    ) -> Result<Self, CKMSError> {
+        validate_chain_id(chain_id)?;
+        let provider = provider.into();
+        let resolved = provider
+            .backend
+            .resolve_key_version(&key_id, key_version)
+            .await?;
+        info!(
+            key_id = key_id.as_str(),
+            requested = %key_version,
+            resolved,
+            "Resolved KMS key version"
+        );
+        Self::new(provider, key_id, resolved, chain_id).await
+    }
+
+    /// Returns the id of this signer's crypto key
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Returns the full resource name of this signer's crypto key
+    pub fn key_name(&self) -> String {
+        self.backend.key_name(&self.key_id)
+    }
+
+    /// Fetches and caches the public key of a lazy signer, returning it. For
+    /// other signers, or once resolved, this returns immediately.
+    pub async fn resolve(&self) -> Result<VerifyingKey, CKMSError> {
+        self.resolve_snapshot(&self.snapshot()).await
+    }
+
+    async fn resolve_snapshot(&self, snapshot: &Snapshot) -> Result<VerifyingKey, CKMSError> {
+        snapshot
+            .verifying_key
+            .get_or_try_init(|| async {
+                let verifying_key = self
+                    .backend
+                    .get_public_key(&self.key_id, snapshot.key_version)
+                    .await?;
+                self.check_expected_address(snapshot.key_version, &verifying_key)?;
+                Ok(verifying_key)
+            })
+            .await
+            .copied()
+    }
+
+    /// The key and policies an operation should use throughout
+    fn snapshot(&self) -> Arc<Snapshot> {
+        self.snapshot.load()
+    }
+
+    /// Changes a copy of the snapshot for a signer built by a `with_*`
+    /// method, leaving clones of the original untouched
+    fn reconfigured(mut self, f: impl FnOnce(&mut Snapshot)) -> Self {
+        let mut snapshot = Snapshot::clone(&self.snapshot());
+        f(&mut snapshot);
+        self.snapshot = Arc::new(SnapshotCell::new(snapshot));
+        self
+    }
+
+    /// Changes the chain id of this signer and of every clone of it, unlike
+    /// [`Signer::with_chain_id`]. Operations already in flight finish with
+    /// the chain id they started with.
+    pub fn set_chain_id(&self, chain_id: u64) -> Result<(), CKMSError> {
+        validate_chain_id(chain_id)?;
+        self.snapshot
+            .update(|snapshot| snapshot.chain_id = chain_id);
+        Ok(())
+    }
+
+    /// Fails with [`CKMSError::AddressMismatch`] unless the key derives to
+    /// `address`, guarding against a misconfigured key id or version, e.g.
+    /// `GcpKmsSigner::new(..).await?.with_expected_address(address)?`. A lazy
+    /// signer is checked when it is resolved, and never caches a mismatched
+    /// key.
+    pub fn with_expected_address(mut self, address: Address) -> Result<Self, CKMSError> {
+        self.expected_address = Some(address);
+        let snapshot = self.snapshot();
+        if let Some(verifying_key) = snapshot.verifying_key.get() {
+            self.check_expected_address(snapshot.key_version, verifying_key)?;
+        }
+        Ok(self)
+    }
+
+    fn check_expected_address(
+        &self,
+        key_version: u64,
+        verifying_key: &VerifyingKey,
+    ) -> Result<(), CKMSError> {
+        let actual = verifying_key_to_address(verifying_key);
+        match self.expected_address {
+            Some(expected) if expected != actual => Err(CKMSError::AddressMismatch {
+                key_name: self.backend.key_version_name(&self.key_id, key_version),
+                expected,
+                actual,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the signer's address, fetching the public key first if this
+    /// is a lazy signer
+    pub async fn resolve_address(&self) -> Result<Address, CKMSError> {
+        self.resolve()
+            .await
+            .map(|verifying_key| verifying_key_to_address(&verifying_key))
+    }
+
+    /// Fetches the public key from KMS again, bypassing the cache, to check
+    /// that KMS is reachable and still lets this signer read the key. Fails
+    /// with [`CKMSError::AddressMismatch`] if the key is no longer the one
+    /// cached.
+    pub async fn check_access(&self) -> Result<(), CKMSError> {
+        let snapshot = self.snapshot();
+        let Some(cached) = snapshot.verifying_key.get().copied() else {
+            return self.resolve_snapshot(&snapshot).await.map(|_| ());
+        };
+        let fetched = self
+            .backend
+            .get_public_key(&self.key_id, snapshot.key_version)
+            .await?;
+        if fetched != cached {
+            return Err(CKMSError::AddressMismatch {
+                key_name: self
+                    .backend
+                    .key_version_name(&self.key_id, snapshot.key_version),
+                expected: verifying_key_to_address(&cached),
+                actual: verifying_key_to_address(&fetched),
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the public key is known, i.e. the signer was not created with
+    /// [`GcpKmsSigner::new_lazy`] or has since been resolved
+    pub fn is_resolved(&self) -> bool {
+        self.snapshot().verifying_key.initialized()
+    }
+
+    /// Returns the signer's address if its public key is known
+    pub(crate) fn cached_address(&self) -> Option<Address> {
+        self.snapshot().address()
+    }
+
+    /// Returns the public key of this signer's key version
+    ///
+    /// # Panics
+    ///
+    /// If the signer is lazy and has not been resolved
+    pub fn verifying_key(&self) -> VerifyingKey {
+        *self.snapshot().verifying_key.get().expect(
+            "lazy GcpKmsSigner used before its public key was resolved; \
+             call GcpKmsSigner::resolve first",
+        )
+    }
+
+    /// Returns the public key as a PEM-encoded SubjectPublicKeyInfo, as
+    /// KMS serves it
+    pub fn public_key_pem(&self) -> Result<String, CKMSError> {
+        Ok(self.verifying_key().to_public_key_pem(LineEnding::LF)?)
+    }
+
+    /// Returns the public key as a DER-encoded SubjectPublicKeyInfo
+    pub fn public_key_der(&self) -> Result<Vec<u8>, CKMSError> {
+        Ok(self.verifying_key().to_public_key_der()?.into_vec())
+    }
+
+    /// Returns the SEC1 encoding of the public key: 33 bytes compressed, or
+    /// 65 bytes uncompressed
+    pub fn public_key_bytes(&self, compressed: bool) -> Vec<u8> {
+        self.verifying_key()
+            .to_encoded_point(compressed)
+            .as_bytes()
+            .to_vec()
+    }
+
+    /// Returns the concrete version of the crypto key used by this signer
+    pub fn key_version(&self) -> u64 {
+        self.snapshot().key_version
+    }
+
+    /// Restricts the transaction envelopes this signer will sign. Other
+    /// transactions are refused with [`CKMSError::SigningDenied`] before
+    /// KMS is called.
+    pub fn with_allowed_tx_types(self, tx_types: impl IntoIterator<Item = TxType>) -> Self {
+        let tx_types = tx_types.into_iter().collect();
+        self.reconfigured(|snapshot| snapshot.allowed_tx_types = Some(tx_types))
+    }
+
+    /// Sets the priority of this signer's requests. Signers for different
+    /// classes of work can be cloned from one signer, e.g.
+    /// `signer.clone().with_priority(Priority::Critical)`, and share the
+    /// provider's concurrency limit.
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.signing_context.priority = priority;
+        self
+    }
+
+    /// Sets the tenant whose share of the provider's budget this signer's
+    /// requests count against, see [`GcpKmsProvider::with_tenant_limits`]
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.signing_context.tenant = Some(tenant.into());
+        self
+    }
+
+    /// Adds a sink which receives an [`AuditEvent`] for every signing
+    /// operation, including ones refused by policy
+    pub fn with_audit_sink(mut self, sink: Arc<dyn AuditSink>) -> Self {
+        self.audit_sinks.push(sink);
+        self
+    }
+
+    /// Makes [`Signer::sign_message`] return an EIP-155 `v` for the signer's
+    /// chain id rather than 27/28, as earlier versions did. Most verifiers,
+    /// including OpenZeppelin's `ECDSA`, only accept 27/28.
+    pub fn with_eip155_message_v(self, enabled: bool) -> Self {
+        self.reconfigured(|snapshot| snapshot.eip155_message_v = enabled)
+    }
+
+    /// Signs legacy transactions without EIP-155 replay protection: the
+    /// sighash commits to no chain id, even if the transaction has one, and
+    /// `v` is 27/28. Such a transaction is valid on every chain which accepts
+    /// it, so only use this for chains or tooling which require it. Typed
+    /// transactions always commit to their chain id and are unaffected.
+    pub fn without_replay_protection(self) -> Self {
+        self.reconfigured(|snapshot| snapshot.replay_protection = false)
+    }
+
+    /// Refuses transactions whose chain id is set and differs from the
+    /// signer's with [`CKMSError::SigningDenied`], rather than
+    /// signing them for the chain they name. Transactions without a chain id
+    /// still get the signer's.
+    pub fn with_strict_chain_id(self, enabled: bool) -> Self {
+        self.reconfigured(|snapshot| snapshot.strict_chain_id = enabled)
+    }
+
+    /// Signs a transaction like [`Signer::sign_transaction`], but for
+    /// `chain_id` rather than the signer's chain, so one signer can serve
+    /// several chains. A transaction which carries a different chain id is
+    /// refused with [`CKMSError::SigningDenied`].
+    pub async fn sign_transaction_for_chain(
+        &self,
+        tx: &TypedTransaction,
+        chain_id: u64,
+    ) -> Result<Signature, CKMSError> {
+        validate_chain_id(chain_id)?;
+        if let Some(tx_chain_id) = tx.chain_id().filter(|id| id.as_u64() != chain_id) {
+            return Err(SigningDenied::new("chain_id_override")
+                .with_value("tx_chain_id", tx_chain_id)
+                .with_value("chain_id", chain_id)
+                .with_remediation("clear the transaction's chain id or sign for its chain")
+                .into());
+        }
+        self.sign_transaction_with_default_chain(&self.snapshot(), tx, chain_id)
+            .await
+    }
+
+    /// Signs a message like [`Signer::sign_message`], but for `chain_id`
+    /// rather than the signer's chain. The chain only affects the signature
+    /// with [`GcpKmsSigner::with_eip155_message_v`]; otherwise it is just
+    /// recorded in the audit event.
+    pub async fn sign_message_for_chain<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
         chain_id: u64,
     ) -> Result<Signature, CKMSError> {
-        let sig = self.sign_digest(digest.into()).await?;
-        let mut sig =
-            sig_from_digest_bytes_trial_recovery(&sig, digest.into(), &self.verifying_key);
-        apply_eip155(&mut sig, chain_id);
+        validate_chain_id(chain_id)?;
+        self.sign_message_with_chain(&self.snapshot(), message.as_ref(), chain_id)
+            .await
+    }
+
+    pub(crate) async fn sign_message_with_chain(
+        &self,
+        snapshot: &Snapshot,
+        message: &[u8],
+        chain_id: u64,
+    ) -> Result<Signature, CKMSError> {
+        let message_hash = hash_message(message);
+        let sign = async {
+            if snapshot.eip155_message_v {
+                self.sign_digest_with_eip155(snapshot, message_hash, chain_id)
+                    .await
+            } else {
+                self.sign_with_27_28_v(snapshot, message_hash).await
+            }
+        };
+        // messages are not bound to a chain, even with an EIP-155 `v`
+        let request = scope::ScopeRequest::new(AuditOperation::Message, None);
+        let result = self.within_scopes(request, sign).await;
+        self.audited(
+            snapshot,
+            AuditOperation::Message,
+            message_hash,
+            Some(chain_id),
+            result,
+            Vec::new(),
+        )
+    }
+
+    /// Signs a transaction, which gets `default_chain_id` if it has none
+    pub(crate) async fn sign_transaction_with_default_chain(
+        &self,
+        snapshot: &Snapshot,
+        tx: &TypedTransaction,
+        default_chain_id: u64,
+    ) -> Result<Signature, CKMSError> {
+        let (sighash, chain_id) =
+            transaction_sighash(tx, default_chain_id, snapshot.replay_protection);
+        let result = match &snapshot.allowed_tx_types {
+            Some(allowed) => {
+                policy::check_tx_type(allowed, TxType::of(tx)).map_err(CKMSError::from)
+            }
+            None => Ok(()),
+        };
+        let result = result.and_then(|()| match snapshot.strict_chain_id {
+            true => {
+                let tx_chain_id = tx.chain_id().map(|id| id.as_u64());
+                check_tx_chain_id(tx_chain_id, default_chain_id).map_err(CKMSError::from)
+            }
+            false => Ok(()),
+        });
+        let sign = async {
+            let (mut sig, high_s) = self.sign_recoverable(snapshot, sighash.into()).await?;
+            match chain_id {
+                Some(chain_id) => apply_transaction_v(&mut sig, tx, chain_id)?,
+                None => sig.v += 27,
+            }
+            Ok((sig, high_s))
+        };
+        let request = scope::ScopeRequest::new(AuditOperation::Transaction, chain_id);
+        let result = match result {
+            Ok(()) => self.within_scopes(request, sign).await,
+            Err(e) => Err(e),
+        };
+        self.audited(
+            snapshot,
+            AuditOperation::Transaction,
+            sighash,
+            chain_id,
+            result,
+            Vec::new(),
+        )
+    }
+
+    /// Signs the sighash of an envelope ethers does not model, with `v` =
+    /// the bare y-parity, subject to the transaction policies as `tx_type`.
+    /// `checked` is the result of the envelope's own validation.
+    pub(crate) async fn sign_envelope_sighash(
+        &self,
+        snapshot: &Snapshot,
+        sighash: H256,
+        tx_type: TxType,
+        chain_id: Option<u64>,
+        checked: Result<(), CKMSError>,
+    ) -> Result<Signature, CKMSError> {
+        let result = checked.and_then(|()| match &snapshot.allowed_tx_types {
+            Some(allowed) => policy::check_tx_type(allowed, tx_type).map_err(CKMSError::from),
+            None => Ok(()),
+        });
+        let result = result.and_then(|()| match snapshot.strict_chain_id {
+            true => check_tx_chain_id(chain_id, snapshot.chain_id).map_err(CKMSError::from),
+            false => Ok(()),
+        });
+        let request = scope::ScopeRequest::new(AuditOperation::Transaction, chain_id);
+        let result = match result {
+            Ok(()) => {
+                self.within_scopes(request, self.sign_recoverable(snapshot, sighash.into()))
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        self.audited(
+            snapshot,
+            AuditOperation::Transaction,
+            sighash,
+            chain_id,
+            result,
+            vec![format!("tx_type={tx_type}")],
+        )
+    }
+
+    /// Sets the encoder [`GcpKmsSigner::sign_transaction_raw`] serializes
+    /// signed transactions with. Defaults to [`StandardEncoder`].
+    pub fn with_transaction_encoder(mut self, encoder: Arc<dyn TransactionEncoder>) -> Self {
+        self.encoder = encoder;
+        self
+    }
+
+    /// Signs a transaction and serializes it with the configured
+    /// [`TransactionEncoder`], ready for `eth_sendRawTransaction`
+    pub async fn sign_transaction_raw(&self, tx: &TypedTransaction) -> Result<Bytes, CKMSError> {
+        let snapshot = self.snapshot();
+        let signature = self
+            .sign_transaction_with_default_chain(&snapshot, tx, snapshot.chain_id)
+            .await?;
+        // encode the chain id which was signed, not the caller's missing one
+        let signed = signed_transaction(tx, snapshot.chain_id, snapshot.replay_protection);
+        self.encoder.encode(&signed, &signature)
+    }
+
+    /// Signs transactions with up to `max_concurrency` KMS requests in
+    /// flight, returning each transaction's result in input order. One
+    /// failure does not stop the rest of the batch.
+    ///
+    /// The provider's concurrency limit, if any, still applies across all
+    /// requests.
+    pub async fn sign_transactions(
+        &self,
+        txs: &[TypedTransaction],
+        max_concurrency: usize,
+    ) -> Vec<Result<Signature, CKMSError>> {
+        futures::stream::iter(txs)
+            .map(|tx| self.sign_transaction(tx))
+            .buffered(max_concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Sets what happens when KMS returns a high-s signature. The handling
+    /// is recorded in each audit event's notes.
+    pub fn with_high_s_policy(self, policy: HighSPolicy) -> Self {
+        self.reconfigured(|snapshot| snapshot.high_s_policy = policy)
+    }
+
+    /// Sets the time source for replay windows, receipts and audit
+    /// timestamps. Defaults to [`SystemClock`].
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Remembers the EIP-712 payloads this signer signs and warns about or
+    /// refuses exact repeats within the window. Clones made after this call
+    /// share the history, which is kept in memory.
+    pub fn with_typed_data_replay_protection(self, config: ReplayProtection) -> Self {
+        let store = MemoryStore::new().with_clock(self.clock.clone());
+        self.with_typed_data_replay_store(config, Arc::new(store))
+    }
+
+    /// Like [`GcpKmsSigner::with_typed_data_replay_protection`], keeping the
+    /// history in `store`. Processes sharing a store share the history.
+    pub fn with_typed_data_replay_store(
+        mut self,
+        config: ReplayProtection,
+        store: Arc<dyn Store>,
+    ) -> Self {
+        let namespace = format!(
+            "typed_data_replay/{}/{}",
+            self.key_name(),
+            self.key_version()
+        );
+        self.replay_guard = Some(Arc::new(replay::ReplayGuard::new(config, store, namespace)));
+        self
+    }
+
+    /// Derives a signer which can only sign within `scope`, to hand to less
+    /// trusted parts of an application. The restrictions are enforced
+    /// locally, before KMS is called, and add to any scopes this signer
+    /// already has; there is no way to remove them from the derived signer or
+    /// its clones, which share the signature budget.
+    pub fn scoped(&self, scope: Scope) -> Self {
+        let mut signer = self.clone();
+        signer.scopes.push(Arc::new(scope::ScopeGuard::new(scope)));
+        signer
+    }
+
+    /// Returns how many more signatures this signer's scopes allow, if any
+    /// limit them
+    pub fn remaining_signatures(&self) -> Option<u64> {
+        self.scopes
+            .iter()
+            .filter_map(|guard| guard.remaining())
+            .min()
+    }
+
+    /// Runs `sign` if the request is within every scope, counting it against
+    /// their budgets if it succeeds
+    async fn within_scopes<T>(
+        &self,
+        request: scope::ScopeRequest,
+        sign: impl Future<Output = Result<T, CKMSError>>,
+    ) -> Result<T, CKMSError> {
+        let reservation = scope::reserve(&self.scopes, &request)?;
+        let result = sign.await;
+        if result.is_ok() {
+            reservation.commit();
+        }
+        result
+    }
+
+    /// Signs typed data like [`Signer::sign_typed_data`], but without replay
+    /// protection, for payloads which are intentionally signed again
+    pub async fn sign_typed_data_allowing_replay<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, CKMSError> {
+        let digest = payload
+            .encode_eip712()
+            .map_err(|e| CKMSError::Eip712Error(e.to_string()))?;
+
+        let snapshot = self.snapshot();
+        let result = self
+            .within_scopes(
+                typed_data_scope_request(payload),
+                self.sign_recoverable(&snapshot, digest),
+            )
+            .await;
+        self.audited(
+            &snapshot,
+            AuditOperation::TypedData,
+            digest.into(),
+            None,
+            result,
+            vec!["typed_data_replay=allowed".to_string()],
+        )
+    }
+
+    /// Sign a digest with this signer's key
+    pub async fn sign_digest(&self, digest: [u8; 32]) -> Result<KSig, CKMSError> {
+        let snapshot = self.snapshot();
+        let sign = async {
+            let sig = self.kms_sign(&snapshot, digest).await?;
+            Ok((k256_output(snapshot.high_s_policy, &sig), sig.is_high_s()))
+        };
+        let result = self
+            .within_scopes(scope::ScopeRequest::new(AuditOperation::Digest, None), sign)
+            .await;
+        self.audited(
+            &snapshot,
+            AuditOperation::Digest,
+            digest.into(),
+            None,
+            result,
+            Vec::new(),
+        )
+    }
+
+    /// Sign a digest like [`GcpKmsSigner::sign_digest`], also returning the
+    /// recovery id of the signature, for stacks which do not use ethers types
+    pub async fn sign_digest_recoverable(
+        &self,
+        digest: [u8; 32],
+    ) -> Result<(KSig, RecoveryId), CKMSError> {
+        let snapshot = self.snapshot();
+        let sign = async {
+            let sig = self.kms_sign(&snapshot, digest).await?;
+            let verifying_key = self.resolve_snapshot(&snapshot).await?;
+            let recovery_id = find_recovery_id(&sig.normalized, digest, &verifying_key)
+                .ok_or(CKMSError::RecoveryError)?;
+            let output = sig.recoverable_output(snapshot.high_s_policy, recovery_id);
+            Ok((output, sig.is_high_s()))
+        };
+        let result = self
+            .within_scopes(scope::ScopeRequest::new(AuditOperation::Digest, None), sign)
+            .await;
+        self.audited(
+            &snapshot,
+            AuditOperation::Digest,
+            digest.into(),
+            None,
+            result,
+            Vec::new(),
+        )
+    }
+
+    /// Signs a pre-computed digest, returning a signature with `v` = 27/28
+    /// like ethers' `Wallet::sign_hash`. No message prefix or EIP-155 chain id
+    /// is applied.
+    pub async fn sign_hash(&self, hash: H256) -> Result<Signature, CKMSError> {
+        let snapshot = self.snapshot();
+        let result = self
+            .within_scopes(
+                scope::ScopeRequest::new(AuditOperation::Digest, None),
+                self.sign_with_27_28_v(&snapshot, hash),
+            )
+            .await;
+        self.audited(
+            &snapshot,
+            AuditOperation::Digest,
+            hash,
+            None,
+            result,
+            Vec::new(),
+        )
+    }
+
+    /// Audits the result of an operation, which carries whether KMS returned
+    /// a high-s signature, and strips that flag
+    fn audited<T>(
+        &self,
+        snapshot: &Snapshot,
+        operation: AuditOperation,
+        digest: H256,
+        chain_id: Option<u64>,
+        result: Result<(T, bool), CKMSError>,
+        mut notes: Vec<String>,
+    ) -> Result<T, CKMSError> {
+        if let Ok((_, high_s)) = &result {
+            notes.push(policy::high_s_note(snapshot.high_s_policy, *high_s));
+        }
+        let result = result.map(|(value, _)| value);
+        self.audit(snapshot, operation, digest, chain_id, &result, notes);
+        result
+    }
+
+    /// Delivers an audit event for a finished operation to the configured sinks
+    fn audit<T>(
+        &self,
+        snapshot: &Snapshot,
+        operation: AuditOperation,
+        digest: H256,
+        chain_id: Option<u64>,
+        result: &Result<T, CKMSError>,
+        notes: Vec<String>,
+    ) {
+        if self.audit_sinks.is_empty() {
+            return;
+        }
+
+        let (outcome, error) = match result {
+            Ok(_) => (AuditOutcome::Signed, None),
+            Err(e @ CKMSError::SigningDenied(_)) => (AuditOutcome::Denied, Some(e.to_string())),
+            Err(e) => (AuditOutcome::Failed, Some(e.to_string())),
+        };
+        self.audit_sinks.record(&AuditEvent {
+            timestamp_ms: self.clock.now_ms(),
+            operation,
+            key_name: self.key_name(),
+            key_version: snapshot.key_version,
+            // unresolved if fetching a lazy signer's key failed
+            address: snapshot.address().unwrap_or_default(),
+            chain_id,
+            digest,
+            outcome,
+            error,
+            notes,
+        });
+    }
+
+    /// Signs a digest with KMS, applying the high-s policy
+    async fn kms_sign(
+        &self,
+        snapshot: &Snapshot,
+        digest: [u8; 32],
+    ) -> Result<KmsSignature, CKMSError> {
+        let sig = self.kms_sign_unchecked(snapshot, digest).await?;
+        policy::check_high_s(snapshot.high_s_policy, sig.is_high_s())?;
         Ok(sig)
     }
+
+    /// Signs a digest with KMS, ignoring the high-s policy
+    async fn kms_sign_unchecked(
+        &self,
+        snapshot: &Snapshot,
+        digest: [u8; 32],
+    ) -> Result<KmsSignature, CKMSError> {
+        let (signature, _) = self
+            .backend
+            .sign_digest(
+                &self.key_id,
+                snapshot.key_version,
+                digest,
+                &self.signing_context,
+            )
+            .await?;
+        KmsSignature::from_der(&signature)
+    }
+
+    /// Signs a digest, with a 0/1 recovery id as `v`, returning whether KMS
+    /// produced a high-s signature
+    async fn sign_recoverable(
+        &self,
+        snapshot: &Snapshot,
+        digest: [u8; 32],
+    ) -> Result<(Signature, bool), CKMSError> {
+        let sig = self.kms_sign(snapshot, digest).await?;
+        let verifying_key = self.resolve_snapshot(snapshot).await?;
+        let mut recoverable =
+            sig_from_digest_bytes_trial_recovery(&sig.normalized, digest, &verifying_key)?;
+        if sig.is_high_s() && snapshot.high_s_policy == HighSPolicy::PassThrough {
+            recoverable = sig.raw_recoverable(recoverable);
+        }
+        Ok((recoverable, sig.is_high_s()))
+    }
+
+    /// Signs a digest, with `v` = 27/28
+    async fn sign_with_27_28_v(
+        &self,
+        snapshot: &Snapshot,
+        digest: H256,
+    ) -> Result<(Signature, bool), CKMSError> {
+        let (mut sig, high_s) = self.sign_recoverable(snapshot, digest.into()).await?;
+        sig.v += 27;
+        Ok((sig, high_s))
+    }
+
+    /// Sign a digest with an explicit version of this signer's key rather than
+    /// its pinned version, returning the version which actually signed. The
+    /// signature is not checked against this signer's public key. The audit
+    /// event records the version which signed.
+    pub async fn sign_digest_with_key_version(
+        &self,
+        digest: [u8; 32],
+        key_version: u64,
+    ) -> Result<(KSig, u64), CKMSError> {
+        let snapshot = self.snapshot();
+        let high_s_policy = snapshot.high_s_policy;
+        let sign = async {
+            let (signature, signed_version) = self
+                .backend
+                .sign_digest(&self.key_id, key_version, digest, &self.signing_context)
+                .await?;
+            let sig = KmsSignature::from_der(&signature)?;
+            policy::check_high_s(high_s_policy, sig.is_high_s())?;
+            let output = (k256_output(high_s_policy, &sig), signed_version);
+            Ok((output, sig.is_high_s()))
+        };
+        let result = self
+            .within_scopes(scope::ScopeRequest::new(AuditOperation::Digest, None), sign)
+            .await;
+
+        // the event names the version which signed, not the pinned one
+        let mut audited = Snapshot::clone(&snapshot);
+        audited.key_version = match &result {
+            Ok(((_, signed_version), _)) => *signed_version,
+            Err(_) => key_version,
+        };
+        self.audited(
+            &audited,
+            AuditOperation::Digest,
+            digest.into(),
+            None,
+            result,
+            vec![format!("key_version_override={key_version}")],
+        )
+    }
+
+    /// Sign a digest with this signer's key and add the eip155 `v` value
+    /// corresponding to the input chain_id
+    #[instrument(err, skip(snapshot, digest))]
+    async fn sign_digest_with_eip155(
+        &self,
+        snapshot: &Snapshot,
+        digest: H256,
+        chain_id: u64,
+    ) -> Result<(Signature, bool), CKMSError> {
+        let (mut sig, high_s) = self.sign_recoverable(snapshot, digest.into()).await?;
+        apply_eip155(&mut sig, chain_id)?;
+        Ok((sig, high_s))
+    }
 }
 
 #[async_trait]
@@ -256,24 +1906,17 @@ impl Signer for GcpKmsSigner {
         &self,
         message: S,
     ) -> Result<Signature, Self::Error> {
-        let message = message.as_ref();
-        let message_hash = hash_message(message);
-        self.sign_digest_with_eip155(message_hash, self.chain_id)
+        let snapshot = self.snapshot();
+        self.sign_message_with_chain(&snapshot, message.as_ref(), snapshot.chain_id)
             .await
     }
 
     /// Signs the transaction
     #[instrument(err)]
     async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
-        let mut tx_with_chain = tx.clone();
-        let chain_id = tx_with_chain
-            .chain_id()
-            .map(|id| id.as_u64())
-            .unwrap_or(self.chain_id);
-        tx_with_chain.set_chain_id(chain_id);
-
-        let sighash = tx_with_chain.sighash();
-        self.sign_digest_with_eip155(sighash, chain_id).await
+        let snapshot = self.snapshot();
+        self.sign_transaction_with_default_chain(&snapshot, tx, snapshot.chain_id)
+            .await
     }
 
     /// Encodes and signs the typed data according EIP-712.
@@ -282,32 +1925,109 @@ impl Signer for GcpKmsSigner {
         &self,
         payload: &T,
     ) -> Result<Signature, Self::Error> {
-        let digest = payload
-            .encode_eip712()
-            .map_err(|e| CKMSError::Eip712Error(e.to_string()))?;
-
-        let sig = self.sign_digest(digest).await?;
-        let sig = sig_from_digest_bytes_trial_recovery(&sig, digest, &self.verifying_key);
-
-        Ok(sig)
+        self.sign_typed_data_with_snapshot(&self.snapshot(), payload)
+            .await
     }
 
-    /// Returns the signer's Ethereum Address
+    /// Returns the signer's Ethereum Address. Panics for a lazy signer which
+    /// has not been resolved; see [`GcpKmsSigner::resolve_address`].
     fn address(&self) -> Address {
-        verifying_key_to_address(&self.verifying_key)
+        verifying_key_to_address(&self.verifying_key())
     }
 
     /// Returns the signer's chain id
     fn chain_id(&self) -> u64 {
-        self.chain_id
+        self.snapshot().chain_id
     }
 
-    /// Sets the signer's chain id
-    #[must_use]
+    /// Sets the chain id of this signer, but not of other clones. This cannot
+    /// fail, so a chain id above [`MAX_EIP155_CHAIN_ID`] is only reported
+    /// when signing.
     fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
-        let mut this = self;
-        this.chain_id = chain_id.into();
-        this
+        let chain_id = chain_id.into();
+        self.reconfigured(|snapshot| snapshot.chain_id = chain_id)
+    }
+}
+
+impl GcpKmsSigner {
+    pub(crate) async fn sign_typed_data_with_snapshot<T: Eip712 + Send + Sync>(
+        &self,
+        snapshot: &Snapshot,
+        payload: &T,
+    ) -> Result<Signature, CKMSError> {
+        let eip712_error = |e: T::Error| CKMSError::Eip712Error(e.to_string());
+        let digest = payload.encode_eip712().map_err(eip712_error)?;
+        let domain_separator = payload.domain_separator().map_err(eip712_error)?;
+        let struct_hash = payload.struct_hash().map_err(eip712_error)?;
+        self.sign_typed_digest(
+            snapshot,
+            digest.into(),
+            domain_separator.into(),
+            struct_hash.into(),
+            typed_data_scope_request(payload),
+        )
+        .await
+    }
+
+    /// Signs the EIP-712 digest of a struct, applying replay protection to
+    /// its domain separator and struct hash
+    pub(crate) async fn sign_typed_digest(
+        &self,
+        snapshot: &Snapshot,
+        digest: H256,
+        domain_separator: H256,
+        struct_hash: H256,
+        request: scope::ScopeRequest,
+    ) -> Result<Signature, CKMSError> {
+        let Some(guard) = &self.replay_guard else {
+            let result = self
+                .within_scopes(request, self.sign_recoverable(snapshot, digest.into()))
+                .await;
+            return self.audited(
+                snapshot,
+                AuditOperation::TypedData,
+                digest,
+                None,
+                result,
+                Vec::new(),
+            );
+        };
+
+        let mut notes = Vec::new();
+        let result = match guard
+            .check(domain_separator, struct_hash, self.clock.now())
+            .await
+        {
+            Ok(check) => {
+                if check == replay::ReplayCheck::Repeated {
+                    notes.push("typed_data_replay=repeated".to_string());
+                }
+                let result = self
+                    .within_scopes(request, self.sign_recoverable(snapshot, digest.into()))
+                    .await;
+                if result.is_err() && check == replay::ReplayCheck::Fresh {
+                    guard.forget(domain_separator, struct_hash).await;
+                }
+                result
+            }
+            Err(e) => Err(e),
+        };
+        self.audited(
+            snapshot,
+            AuditOperation::TypedData,
+            digest,
+            None,
+            result,
+            notes,
+        )
+    }
+}
+
+/// The k256 signature to return under the high-s policy
+fn k256_output(policy: HighSPolicy, sig: &KmsSignature) -> KSig {
+    match policy {
+        HighSPolicy::PassThrough => sig.raw,
+        HighSPolicy::Normalize | HighSPolicy::Reject => sig.normalized,
     }
 }
 
@@ -315,6 +2035,246 @@ impl Signer for GcpKmsSigner {
 mod tests {
     use super::*;
 
+    proptest::proptest! {
+        #[test]
+        fn eip155_round_trips(chain_id in 0..=MAX_EIP155_CHAIN_ID, recovery_id in 0u64..=1) {
+            let mut sig = Signature { r: U256::one(), s: U256::one(), v: recovery_id };
+            apply_eip155(&mut sig, chain_id).unwrap();
+            proptest::prop_assert_eq!(sig.v, chain_id * 2 + 35 + recovery_id);
+            proptest::prop_assert_eq!(
+                recovery_id_from_eip155(sig.v, chain_id).unwrap() as u64,
+                recovery_id
+            );
+        }
+
+        #[test]
+        fn eip155_overflow_is_an_error(chain_id in MAX_EIP155_CHAIN_ID + 1..) {
+            let mut sig = Signature { r: U256::one(), s: U256::one(), v: 1 };
+            proptest::prop_assert!(matches!(
+                apply_eip155(&mut sig, chain_id),
+                Err(CKMSError::UnsupportedChainId(id)) if id == chain_id
+            ));
+        }
+    }
+
+    #[test]
+    fn eip155_boundary_chain_ids() {
+        for chain_id in [
+            u32::MAX as u64 - 1,
+            u32::MAX as u64,
+            u32::MAX as u64 + 1,
+            MAX_EIP155_CHAIN_ID,
+        ] {
+            let mut sig = Signature {
+                r: U256::one(),
+                s: U256::one(),
+                v: 1,
+            };
+            apply_eip155(&mut sig, chain_id).unwrap();
+            assert_eq!(sig.v, chain_id * 2 + 36);
+            // ethers derives the recovery id from v alone
+            assert_eq!(sig.recovery_id().unwrap().to_byte(), 1);
+            assert!(recovery_id_from_eip155(sig.v, chain_id - 1).is_err());
+        }
+
+        let mut sig = Signature {
+            r: U256::one(),
+            s: U256::one(),
+            v: 1,
+        };
+        assert!(apply_eip155(&mut sig, u64::MAX).is_err());
+        assert_eq!(sig.v, 1);
+
+        assert!(validate_chain_id(MAX_EIP155_CHAIN_ID).is_ok());
+        assert!(matches!(
+            validate_chain_id(MAX_EIP155_CHAIN_ID + 1),
+            Err(CKMSError::UnsupportedChainId(_))
+        ));
+    }
+
+    #[test]
+    fn trial_recovery_rejects_foreign_signature() {
+        let key = ethers::prelude::k256::ecdsa::SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let other = ethers::prelude::k256::ecdsa::SigningKey::from_slice(&[8u8; 32]).unwrap();
+        let digest = keccak256(b"recover me");
+        let (sig, _) = key.sign_prehash_recoverable(&digest).unwrap();
+
+        assert!(sig_from_digest_bytes_trial_recovery(&sig, digest, key.verifying_key()).is_ok());
+        assert!(matches!(
+            sig_from_digest_bytes_trial_recovery(&sig, digest, other.verifying_key()),
+            Err(CKMSError::RecoveryError)
+        ));
+    }
+
+    #[test]
+    fn high_s_pass_through_keeps_recoverability() {
+        let key = ethers::prelude::k256::ecdsa::SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let digest = keccak256(b"recover me");
+        let (low, _) = key.sign_prehash_recoverable(&digest).unwrap();
+        let high = KSig::from_scalars(low.r(), -*low.s()).unwrap();
+
+        let sig = KmsSignature::from_der(high.to_der().as_bytes()).unwrap();
+        assert!(sig.is_high_s());
+        assert_eq!(sig.normalized, low);
+
+        let normalized =
+            sig_from_digest_bytes_trial_recovery(&sig.normalized, digest, key.verifying_key())
+                .unwrap();
+        let raw = sig.raw_recoverable(normalized);
+        assert_eq!(raw.r, normalized.r);
+        assert_eq!(raw.v, normalized.v ^ 1);
+        assert_eq!(
+            raw.s,
+            ethers::types::U256::from_big_endian(&high.s().to_bytes())
+        );
+
+        // the raw signature recovers the same key, with its own parity
+        let recovered = raw.recover(H256::from(digest));
+        assert_eq!(
+            recovered.unwrap(),
+            verifying_key_to_address(key.verifying_key())
+        );
+    }
+
+    #[test]
+    fn transaction_v_matches_envelope() {
+        use ethers::{
+            signers::LocalWallet,
+            types::{Eip1559TransactionRequest, Eip2930TransactionRequest, TransactionRequest},
+            utils::rlp::Rlp,
+        };
+
+        let wallet = LocalWallet::from_bytes(&[7u8; 32]).unwrap();
+        let chain_id = 5_000_000_000u64;
+        let legacy = TransactionRequest::new().nonce(1).chain_id(chain_id);
+        let txs: [TypedTransaction; 3] = [
+            legacy.clone().into(),
+            Eip2930TransactionRequest::new(legacy, Default::default()).into(),
+            Eip1559TransactionRequest::new()
+                .nonce(1)
+                .chain_id(chain_id)
+                .into(),
+        ];
+
+        for tx in txs {
+            let mut sig = wallet.sign_hash(tx.sighash()).unwrap();
+            sig.v -= 27;
+            apply_transaction_v(&mut sig, &tx, chain_id).unwrap();
+            match TxType::of(&tx) {
+                TxType::Legacy => assert!(sig.v >= chain_id * 2 + 35),
+                _ => assert!(sig.v <= 1),
+            }
+
+            let encoded = tx.rlp_signed(&sig);
+            let (decoded, decoded_sig) =
+                TypedTransaction::decode_signed(&Rlp::new(&encoded)).unwrap();
+            assert_eq!(decoded_sig.v, sig.v);
+            assert_eq!(
+                decoded_sig.recover(decoded.sighash()).unwrap(),
+                wallet.address()
+            );
+        }
+    }
+
+    #[test]
+    fn unprotected_legacy_sighash_has_no_chain_id() {
+        use ethers::{
+            signers::LocalWallet,
+            types::{Eip1559TransactionRequest, TransactionRequest},
+            utils::rlp::Rlp,
+        };
+
+        let wallet = LocalWallet::from_bytes(&[7u8; 32]).unwrap();
+        let tx: TypedTransaction = TransactionRequest::new().nonce(1).chain_id(5).into();
+
+        assert_eq!(transaction_sighash(&tx, 1, true), (tx.sighash(), Some(5)));
+        let (sighash, chain_id) = transaction_sighash(&tx, 1, false);
+        assert_eq!(chain_id, None);
+
+        let sig = wallet.sign_hash(sighash).unwrap();
+        assert!(sig.v == 27 || sig.v == 28);
+        let (decoded, decoded_sig) =
+            TypedTransaction::decode_signed(&Rlp::new(&tx.rlp_signed(&sig))).unwrap();
+        assert_eq!(decoded.chain_id(), None);
+        assert_eq!(
+            decoded_sig.recover(decoded.sighash()).unwrap(),
+            wallet.address()
+        );
+
+        // typed transactions always commit to a chain id
+        let typed: TypedTransaction = Eip1559TransactionRequest::new().into();
+        assert_eq!(transaction_sighash(&typed, 1, false).1, Some(1));
+    }
+
+    #[test]
+    fn pass_through_flips_recovery_id_of_raw_signature() {
+        use ethers::prelude::k256::ecdsa::SigningKey;
+
+        let key = SigningKey::from_bytes(&[7u8; 32].into()).unwrap();
+        let (normalized, id) = key.sign_prehash_recoverable(&[9u8; 32]).unwrap();
+        let raw = KSig::from_scalars(normalized.r(), -*normalized.s()).unwrap();
+        let sig = KmsSignature { raw, normalized };
+
+        assert_eq!(
+            sig.recoverable_output(HighSPolicy::Normalize, id),
+            (normalized, id)
+        );
+        let (output, raw_id) = sig.recoverable_output(HighSPolicy::PassThrough, id);
+        assert_eq!(output, raw);
+        assert_eq!(raw_id.is_y_odd(), !id.is_y_odd());
+    }
+
+    #[test]
+    fn strict_chain_id_refuses_other_chains() {
+        use ethers::types::TransactionRequest;
+
+        let tx: TypedTransaction = TransactionRequest::new().chain_id(5).into();
+        let tx_chain_id = tx.chain_id().map(|id| id.as_u64());
+        assert!(check_tx_chain_id(tx_chain_id, 5).is_ok());
+        let denied = check_tx_chain_id(tx_chain_id, 1).unwrap_err();
+        assert_eq!(denied.rule, "strict_chain_id");
+        assert_eq!(
+            denied.evaluated,
+            [
+                ("tx_chain_id".to_string(), "5".to_string()),
+                ("chain_id".to_string(), "1".to_string())
+            ]
+        );
+        assert!(denied.remediation.is_some());
+        assert!(check_tx_chain_id(None, 1).is_ok());
+    }
+
+    #[test]
+    fn finds_recovery_id() {
+        let key = ethers::prelude::k256::ecdsa::SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let digest = keccak256(b"recover me");
+        let (sig, expected) = key.sign_prehash_recoverable(&digest).unwrap();
+
+        let found = find_recovery_id(&sig, digest, key.verifying_key());
+        assert_eq!(found, Some(expected));
+
+        let other = ethers::prelude::k256::ecdsa::SigningKey::from_slice(&[8u8; 32]).unwrap();
+        assert_eq!(find_recovery_id(&sig, digest, other.verifying_key()), None);
+    }
+
+    #[test]
+    fn parses_pem_and_der_public_keys() {
+        let key = ethers::prelude::k256::ecdsa::SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let verifying_key = *key.verifying_key();
+        let pem = verifying_key.to_public_key_pem(LineEnding::LF).unwrap();
+        let der = verifying_key.to_public_key_der().unwrap();
+
+        assert_eq!(parse_public_key(pem.as_bytes()).unwrap(), verifying_key);
+        assert_eq!(parse_public_key(der.as_bytes()).unwrap(), verifying_key);
+        assert!(parse_public_key(b"not a key").is_err());
+    }
+
+    #[test]
+    fn region_from_zone_strips_suffix() {
+        assert_eq!(region_from_zone("us-central1-a"), "us-central1");
+        assert_eq!(region_from_zone("europe-west3-b"), "europe-west3");
+    }
+
     #[test_log::test(tokio::test)]
     async fn it_works() {
         // skip test if no credentials are provided
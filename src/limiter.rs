@@ -0,0 +1,349 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::{sync::oneshot, time::Instant};
+
+use crate::CKMSError;
+
+/// The priority of a signing request. Queued requests are granted in priority
+/// order, and lower priorities are shed first under load.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Critical,
+}
+
+/// Per-call options for provider signing APIs
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SigningContext {
+    pub priority: Priority,
+    /// The tenant whose concurrency budget the request counts against
+    pub tenant: Option<String>,
+    /// When the caller stops waiting. Sent to KMS as the RPC timeout, and no
+    /// hedge is issued once less than the hedge delay remains.
+    pub deadline: Option<Instant>,
+}
+
+impl SigningContext {
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+}
+
+/// A snapshot of a concurrency limit's usage
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LimiterStats {
+    pub in_flight: usize,
+    pub queued: usize,
+    /// Requests which have been given a slot
+    pub granted: u64,
+    /// Requests refused or shed with [`CKMSError::Backpressure`]
+    pub rejected: u64,
+}
+
+/// What to do with a sign request when the concurrency limit is saturated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BackpressurePolicy {
+    /// Wait for a slot, queueing at most `max_depth` requests; further
+    /// requests fail with [`CKMSError::Backpressure`]
+    Queue { max_depth: usize },
+    /// Fail immediately with [`CKMSError::Backpressure`]
+    FailFast,
+    /// Queue like [`BackpressurePolicy::Queue`], but when the queue is full a
+    /// request may evict the newest queued request of lower priority
+    ShedLowestPriority { max_depth: usize },
+}
+
+/// Bounds the number of concurrent sign requests a provider makes to KMS
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConcurrencyLimit {
+    pub max_in_flight: usize,
+    pub policy: BackpressurePolicy,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max_in_flight: usize, policy: BackpressurePolicy) -> Self {
+        Self {
+            max_in_flight: max_in_flight.max(1),
+            policy,
+        }
+    }
+}
+
+struct Waiter {
+    priority: Priority,
+    seq: u64,
+    tx: oneshot::Sender<Result<Permit, CKMSError>>,
+}
+
+#[derive(Default)]
+struct State {
+    in_flight: usize,
+    queue: Vec<Waiter>,
+    next_seq: u64,
+    granted: u64,
+    rejected: u64,
+}
+
+pub(crate) struct Limiter {
+    limit: ConcurrencyLimit,
+    state: Mutex<State>,
+}
+
+/// A slot in the limiter, released on drop
+pub(crate) struct Permit {
+    limiter: Option<Arc<Limiter>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.limiter.take() {
+            limiter.release();
+        }
+    }
+}
+
+impl Limiter {
+    pub(crate) fn new(limit: ConcurrencyLimit) -> Arc<Self> {
+        Arc::new(Self {
+            limit,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    pub(crate) async fn acquire(self: &Arc<Self>, priority: Priority) -> Result<Permit, CKMSError> {
+        let rx = {
+            let mut state = self.state.lock().unwrap();
+            if state.in_flight < self.limit.max_in_flight {
+                state.in_flight += 1;
+                state.granted += 1;
+                return Ok(self.permit());
+            }
+
+            let max_depth = match self.limit.policy {
+                BackpressurePolicy::FailFast => {
+                    state.rejected += 1;
+                    return Err(CKMSError::Backpressure(format!(
+                        "{} sign requests in flight",
+                        state.in_flight
+                    )));
+                }
+                BackpressurePolicy::Queue { max_depth }
+                | BackpressurePolicy::ShedLowestPriority { max_depth } => max_depth,
+            };
+
+            if state.queue.len() >= max_depth {
+                let shed = matches!(
+                    self.limit.policy,
+                    BackpressurePolicy::ShedLowestPriority { .. }
+                );
+                // the newest request of the lowest priority is shed first
+                let victim = state
+                    .queue
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, waiter)| shed && waiter.priority < priority)
+                    .min_by_key(|(_, waiter)| (waiter.priority, std::cmp::Reverse(waiter.seq)))
+                    .map(|(index, _)| index);
+
+                match victim {
+                    Some(index) => {
+                        state.rejected += 1;
+                        let waiter = state.queue.remove(index);
+                        let _ = waiter.tx.send(Err(CKMSError::Backpressure(format!(
+                            "shed in favour of a {priority:?} priority request"
+                        ))));
+                    }
+                    None => {
+                        state.rejected += 1;
+                        return Err(CKMSError::Backpressure(format!(
+                            "sign queue full ({max_depth} waiting)"
+                        )));
+                    }
+                }
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.queue.push(Waiter { priority, seq, tx });
+            rx
+        };
+
+        rx.await
+            .unwrap_or_else(|_| Err(CKMSError::Backpressure("limiter dropped".to_string())))
+    }
+
+    /// Takes a free slot without queueing, for work which is only worth doing
+    /// when there is spare capacity. Queued requests keep precedence.
+    pub(crate) fn try_acquire(self: &Arc<Self>) -> Option<Permit> {
+        let mut state = self.state.lock().unwrap();
+        if state.in_flight < self.limit.max_in_flight && state.queue.is_empty() {
+            state.in_flight += 1;
+            state.granted += 1;
+            return Some(self.permit());
+        }
+        None
+    }
+
+    pub(crate) fn limit(&self) -> ConcurrencyLimit {
+        self.limit
+    }
+
+    pub(crate) fn stats(&self) -> LimiterStats {
+        let state = self.state.lock().unwrap();
+        LimiterStats {
+            in_flight: state.in_flight,
+            queued: state.queue.len(),
+            granted: state.granted,
+            rejected: state.rejected,
+        }
+    }
+
+    fn permit(self: &Arc<Self>) -> Permit {
+        Permit {
+            limiter: Some(self.clone()),
+        }
+    }
+
+    /// Hands the released slot to the highest-priority (then oldest) waiter,
+    /// or frees it
+    fn release(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(index) = state
+            .queue
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, waiter)| (waiter.priority, std::cmp::Reverse(waiter.seq)))
+            .map(|(index, _)| index)
+        {
+            let waiter = state.queue.remove(index);
+            match waiter.tx.send(Ok(self.permit())) {
+                Ok(()) => {
+                    state.granted += 1;
+                    return;
+                }
+                // the waiter went away; disarm the permit without re-entering
+                Err(Ok(mut permit)) => drop(permit.limiter.take()),
+                Err(Err(_)) => {}
+            }
+        }
+        state.in_flight -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn fail_fast_when_saturated() {
+        let limiter = Limiter::new(ConcurrencyLimit::new(1, BackpressurePolicy::FailFast));
+        let permit = limiter.acquire(Priority::Normal).await.unwrap();
+        assert!(matches!(
+            limiter.acquire(Priority::Normal).await,
+            Err(CKMSError::Backpressure(_))
+        ));
+        drop(permit);
+        assert!(limiter.acquire(Priority::Normal).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn queued_request_gets_released_slot() {
+        let limiter = Limiter::new(ConcurrencyLimit::new(
+            1,
+            BackpressurePolicy::Queue { max_depth: 1 },
+        ));
+        let permit = limiter.acquire(Priority::Normal).await.unwrap();
+
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(Priority::Normal).await.map(|_| ()) }
+        });
+        tokio::task::yield_now().await;
+        assert!(matches!(
+            limiter.acquire(Priority::Normal).await,
+            Err(CKMSError::Backpressure(_))
+        ));
+
+        drop(permit);
+        assert!(waiting.await.unwrap().is_ok());
+        assert_eq!(
+            limiter.stats(),
+            LimiterStats {
+                in_flight: 0,
+                queued: 0,
+                granted: 2,
+                rejected: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn higher_priority_is_granted_first() {
+        let limiter = Limiter::new(ConcurrencyLimit::new(
+            1,
+            BackpressurePolicy::Queue { max_depth: 2 },
+        ));
+        let permit = limiter.acquire(Priority::Normal).await.unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        for priority in [Priority::Low, Priority::Critical] {
+            let limiter = limiter.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _permit = limiter.acquire(priority).await.unwrap();
+                tx.send(priority).unwrap();
+            });
+            tokio::task::yield_now().await;
+        }
+
+        drop(permit);
+        assert_eq!(rx.recv().await, Some(Priority::Critical));
+        assert_eq!(rx.recv().await, Some(Priority::Low));
+    }
+
+    #[tokio::test]
+    async fn lower_priority_is_shed() {
+        let limiter = Limiter::new(ConcurrencyLimit::new(
+            1,
+            BackpressurePolicy::ShedLowestPriority { max_depth: 1 },
+        ));
+        let permit = limiter.acquire(Priority::Normal).await.unwrap();
+
+        let low = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(Priority::Low).await.map(|_| ()) }
+        });
+        tokio::task::yield_now().await;
+
+        let high = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(Priority::High).await.map(|_| ()) }
+        });
+        assert!(matches!(
+            low.await.unwrap(),
+            Err(CKMSError::Backpressure(_))
+        ));
+
+        drop(permit);
+        assert!(high.await.unwrap().is_ok());
+    }
+}
@@ -0,0 +1,99 @@
+use ethers::{
+    types::{Address, Signature, H256},
+    utils::keccak256,
+};
+
+use crate::{audit::AuditOperation, scope, CKMSError, GcpKmsSigner};
+
+/// The EIP-191 version 0x00 hash of `data` for an intended validator:
+/// `keccak256(0x19 0x00 || validator || data)`, as OpenZeppelin's
+/// `MessageHashUtils.toDataWithIntendedValidatorHash` computes it
+pub fn intended_validator_hash(validator: Address, data: &[u8]) -> H256 {
+    let mut payload = Vec::with_capacity(22 + data.len());
+    payload.extend_from_slice(&[0x19, 0x00]);
+    payload.extend_from_slice(validator.as_bytes());
+    payload.extend_from_slice(data);
+    keccak256(payload).into()
+}
+
+impl GcpKmsSigner {
+    /// Signs `data` for the contract at `validator` as an EIP-191 version
+    /// 0x00 payload, with `v` = 27/28. The signature is only meaningful to
+    /// that contract, e.g. a wallet validating its own delegate calls.
+    pub async fn sign_intended_validator_message(
+        &self,
+        validator: Address,
+        data: impl AsRef<[u8]>,
+    ) -> Result<Signature, CKMSError> {
+        let digest = intended_validator_hash(validator, data.as_ref());
+        self.sign_message_digest(digest, vec!["eip191_version=0x00".to_string()])
+            .await
+    }
+
+    /// Signs `keccak256(message)` without the EIP-191
+    /// `"\x19Ethereum Signed Message:\n"` prefix, with `v` = 27/28, for
+    /// counterparties which expect that.
+    ///
+    /// This is dangerous: without the prefix, a message which is the
+    /// signing payload of a transaction, or of any other scheme hashing with
+    /// keccak256, yields a valid signature over it. Only sign messages whose
+    /// content you control, and prefer [`Signer::sign_message`].
+    ///
+    /// [`Signer::sign_message`]: ethers::signers::Signer::sign_message
+    pub async fn sign_raw_message(
+        &self,
+        message: impl AsRef<[u8]>,
+    ) -> Result<Signature, CKMSError> {
+        let digest = keccak256(message.as_ref()).into();
+        self.sign_message_digest(digest, vec!["message_prefix=none".to_string()])
+            .await
+    }
+
+    /// Signs a message digest with `v` = 27/28, whatever the signer's
+    /// [`GcpKmsSigner::with_eip155_message_v`] setting, auditing it as a
+    /// message
+    pub(crate) async fn sign_message_digest(
+        &self,
+        digest: H256,
+        notes: Vec<String>,
+    ) -> Result<Signature, CKMSError> {
+        let snapshot = self.snapshot();
+        let result = self
+            .within_scopes(
+                scope::ScopeRequest::new(AuditOperation::Message, None),
+                self.sign_with_27_28_v(&snapshot, digest),
+            )
+            .await;
+        self.audited(
+            &snapshot,
+            AuditOperation::Message,
+            digest,
+            None,
+            result,
+            notes,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intended_validator_hash_layout() {
+        let validator: Address = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+            .parse()
+            .unwrap();
+        let mut expected = vec![0x19, 0x00];
+        expected.extend_from_slice(validator.as_bytes());
+        expected.extend_from_slice(b"payload");
+        assert_eq!(
+            intended_validator_hash(validator, b"payload"),
+            H256::from(keccak256(expected))
+        );
+        assert_ne!(
+            intended_validator_hash(validator, b"payload"),
+            intended_validator_hash(Address::zero(), b"payload")
+        );
+    }
+}
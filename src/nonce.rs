@@ -0,0 +1,329 @@
+use std::collections::BTreeSet;
+
+use async_trait::async_trait;
+use ethers::{
+    middleware::{
+        nonce_manager::NonceManagerError, signer::SignerMiddlewareError, SignerMiddleware,
+    },
+    providers::{Middleware, MiddlewareError, PendingTransaction},
+    signers::Signer,
+    types::{transaction::eip2718::TypedTransaction, Address, BlockId, BlockNumber, U256},
+};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::GcpKmsSigner;
+
+/// Assigns nonces locally for a signer, in place of stacking ethers'
+/// `NonceManagerMiddleware` over a `SignerMiddleware`, and releases nonces
+/// depending on where a transaction failed:
+///
+/// - if filling or signing fails, including [`CKMSError::Backpressure`] and
+///   exhausted KMS retries, nothing was sent, so the nonce is released
+/// - if the node rejects the broadcast, the nonce is released unless the
+///   node's pending transaction count shows it used, e.g. for "nonce too low"
+/// - if the broadcast's outcome is unknown, such as on a timeout, the nonce is
+///   kept, since the transaction may be in the mempool; see
+///   [`NonceManagedSigner::resync`]
+///
+/// Released nonces are reused lowest first, so a failed transaction does not
+/// leave a gap which stalls the ones after it. Retries inside the provider,
+/// like hedging and the [`OutageQueue`], re-sign the same transaction and keep
+/// its nonce.
+///
+/// Transactions which already have a nonce, or are from another address, are
+/// passed through untouched.
+///
+/// [`CKMSError::Backpressure`]: crate::CKMSError::Backpressure
+/// [`OutageQueue`]: crate::OutageQueue
+#[derive(Debug)]
+pub struct NonceManagedSigner<M, S = GcpKmsSigner> {
+    inner: SignerMiddleware<M, S>,
+    address: Address,
+    nonces: Mutex<Nonces>,
+}
+
+#[derive(Debug, Default)]
+struct Nonces {
+    /// The lowest nonce never handed out, once read from the node
+    next: Option<U256>,
+    released: BTreeSet<U256>,
+}
+
+type Error<M, S> = NonceManagerError<SignerMiddleware<M, S>>;
+
+impl<M: Middleware, S: Signer> NonceManagedSigner<M, S> {
+    /// Wraps `inner` to sign with `signer`. A lazy [`GcpKmsSigner`] must be
+    /// resolved first, as this reads its address.
+    pub fn new(inner: M, signer: S) -> Self {
+        let address = signer.address();
+        Self {
+            inner: SignerMiddleware::new(inner, signer),
+            address,
+            nonces: Mutex::default(),
+        }
+    }
+
+    pub fn signer(&self) -> &S {
+        self.inner.signer()
+    }
+
+    /// Re-reads the account's pending transaction count and continues from
+    /// it, forgetting released nonces. Use this after transactions were sent
+    /// from the account elsewhere, or when a broadcast whose outcome was
+    /// unknown turns out to have failed.
+    pub async fn resync(&self) -> Result<U256, Error<M, S>> {
+        let mut nonces = self.nonces.lock().await;
+        let count = self.pending_count().await?;
+        *nonces = Nonces {
+            next: Some(count),
+            released: BTreeSet::new(),
+        };
+        Ok(count)
+    }
+
+    async fn pending_count(&self) -> Result<U256, Error<M, S>> {
+        self.inner
+            .get_transaction_count(self.address, Some(BlockNumber::Pending.into()))
+            .await
+            .map_err(MiddlewareError::from_err)
+    }
+
+    /// Hands out the lowest released nonce, or the next unused one
+    async fn allocate(&self) -> Result<U256, Error<M, S>> {
+        let mut nonces = self.nonces.lock().await;
+        if let Some(nonce) = nonces.released.pop_first() {
+            return Ok(nonce);
+        }
+        let next = match nonces.next {
+            Some(next) => next,
+            None => self.pending_count().await?,
+        };
+        nonces.next = Some(next + 1);
+        Ok(next)
+    }
+
+    async fn release(&self, nonce: U256) {
+        debug!(%nonce, "Releasing nonce of unsent transaction");
+        self.nonces.lock().await.released.insert(nonce);
+    }
+
+    /// Releases the nonce of a rejected broadcast unless the node counts it
+    /// as used, catching up with the node's count if it is ahead
+    async fn reconcile_rejected(&self, nonce: U256) {
+        let count = match self.pending_count().await {
+            Ok(count) => count,
+            Err(e) => {
+                warn!(%nonce, "Keeping nonce, transaction count unavailable: {e}");
+                return;
+            }
+        };
+        let mut nonces = self.nonces.lock().await;
+        if count <= nonce {
+            nonces.released.insert(nonce);
+            return;
+        }
+        nonces.released.retain(|released| *released >= count);
+        if nonces.next.is_some_and(|next| next < count) {
+            nonces.next = Some(count);
+        }
+    }
+}
+
+#[async_trait]
+impl<M: Middleware, S: Signer> Middleware for NonceManagedSigner<M, S> {
+    type Error = Error<M, S>;
+    type Provider = M::Provider;
+    type Inner = SignerMiddleware<M, S>;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn send_transaction<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        tx: T,
+        block: Option<BlockId>,
+    ) -> Result<PendingTransaction<'_, Self::Provider>, Self::Error> {
+        let mut tx = tx.into();
+        if tx.nonce().is_some() || tx.from().is_some_and(|from| *from != self.address) {
+            return self
+                .inner
+                .send_transaction(tx, block)
+                .await
+                .map_err(MiddlewareError::from_err);
+        }
+
+        let nonce = self.allocate().await?;
+        tx.set_nonce(nonce);
+        // filled here so that its errors are known to precede the broadcast
+        if let Err(e) = self.inner.fill_transaction(&mut tx, block).await {
+            self.release(nonce).await;
+            return Err(MiddlewareError::from_err(e));
+        }
+
+        match self.inner.send_transaction(tx, block).await {
+            Ok(pending) => Ok(pending),
+            Err(SignerMiddlewareError::MiddlewareError(e)) if e.as_error_response().is_none() => {
+                warn!(%nonce, "Keeping nonce, broadcast outcome unknown: {e}");
+                Err(MiddlewareError::from_err(
+                    SignerMiddlewareError::MiddlewareError(e),
+                ))
+            }
+            Err(e @ SignerMiddlewareError::MiddlewareError(_)) => {
+                self.reconcile_rejected(nonce).await;
+                Err(MiddlewareError::from_err(e))
+            }
+            Err(e) => {
+                self.release(nonce).await;
+                Err(MiddlewareError::from_err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use ethers::{
+        providers::{JsonRpcError, MockProvider, MockResponse, Provider},
+        signers::LocalWallet,
+        types::{transaction::eip712::Eip712, Signature, TransactionRequest, H256},
+    };
+
+    use super::*;
+    use crate::CKMSError;
+
+    /// A wallet whose next signature can be made to fail
+    #[derive(Debug)]
+    struct FlakySigner {
+        wallet: LocalWallet,
+        fail: AtomicBool,
+    }
+
+    #[async_trait]
+    impl Signer for FlakySigner {
+        type Error = CKMSError;
+
+        async fn sign_message<T: Send + Sync + AsRef<[u8]>>(
+            &self,
+            message: T,
+        ) -> Result<Signature, CKMSError> {
+            Ok(self.wallet.sign_message(message).await.unwrap())
+        }
+
+        async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, CKMSError> {
+            if self.fail.swap(false, Ordering::SeqCst) {
+                return Err(CKMSError::Backpressure("saturated".to_string()));
+            }
+            Ok(self.wallet.sign_transaction(tx).await.unwrap())
+        }
+
+        async fn sign_typed_data<T: Eip712 + Send + Sync>(
+            &self,
+            payload: &T,
+        ) -> Result<Signature, CKMSError> {
+            Ok(self.wallet.sign_typed_data(payload).await.unwrap())
+        }
+
+        fn address(&self) -> Address {
+            self.wallet.address()
+        }
+
+        fn chain_id(&self) -> u64 {
+            self.wallet.chain_id()
+        }
+
+        fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+            Self {
+                wallet: self.wallet.with_chain_id(chain_id),
+                ..self
+            }
+        }
+    }
+
+    fn manager() -> (
+        NonceManagedSigner<Provider<MockProvider>, FlakySigner>,
+        MockProvider,
+    ) {
+        let (provider, mock) = Provider::mocked();
+        let signer = FlakySigner {
+            wallet: LocalWallet::from_bytes(&[7u8; 32]).unwrap(),
+            fail: AtomicBool::new(false),
+        };
+        (NonceManagedSigner::new(provider, signer), mock)
+    }
+
+    fn tx() -> TransactionRequest {
+        TransactionRequest::new()
+            .to(Address::repeat_byte(1))
+            .gas(21_000)
+            .gas_price(1)
+    }
+
+    fn rejection(message: &str) -> MockResponse {
+        MockResponse::Error(JsonRpcError {
+            code: -32000,
+            message: message.to_string(),
+            data: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn assigns_consecutive_nonces_from_pending_count() {
+        let (manager, mock) = manager();
+        // responses are served last pushed first
+        mock.push(H256::zero()).unwrap();
+        mock.push(U256::from(5)).unwrap();
+        manager.send_transaction(tx(), None).await.unwrap();
+        mock.push(H256::zero()).unwrap();
+        manager.send_transaction(tx(), None).await.unwrap();
+        assert_eq!(manager.allocate().await.unwrap(), U256::from(7));
+    }
+
+    #[tokio::test]
+    async fn failed_signing_releases_nonce() {
+        let (manager, mock) = manager();
+        mock.push(U256::from(5)).unwrap();
+        manager.signer().fail.store(true, Ordering::SeqCst);
+        assert!(manager.send_transaction(tx(), None).await.is_err());
+
+        mock.push(H256::zero()).unwrap();
+        manager.send_transaction(tx(), None).await.unwrap();
+        assert_eq!(manager.allocate().await.unwrap(), U256::from(6));
+    }
+
+    #[tokio::test]
+    async fn rejected_broadcast_releases_unused_nonce() {
+        let (manager, mock) = manager();
+        mock.push(U256::from(5)).unwrap();
+        mock.push_response(rejection("insufficient funds"));
+        mock.push(U256::from(5)).unwrap();
+        assert!(manager.send_transaction(tx(), None).await.is_err());
+        assert_eq!(manager.allocate().await.unwrap(), U256::from(5));
+    }
+
+    #[tokio::test]
+    async fn nonce_too_low_catches_up_with_node() {
+        let (manager, mock) = manager();
+        mock.push(U256::from(9)).unwrap();
+        mock.push_response(rejection("nonce too low"));
+        mock.push(U256::from(5)).unwrap();
+        assert!(manager.send_transaction(tx(), None).await.is_err());
+        assert_eq!(manager.allocate().await.unwrap(), U256::from(9));
+    }
+
+    #[tokio::test]
+    async fn unknown_broadcast_outcome_keeps_nonce() {
+        let (manager, mock) = manager();
+        // no response for the broadcast, which fails without a JSON-RPC error
+        mock.push(U256::from(5)).unwrap();
+        assert!(manager.send_transaction(tx(), None).await.is_err());
+        assert_eq!(manager.allocate().await.unwrap(), U256::from(6));
+
+        mock.push(U256::from(5)).unwrap();
+        assert_eq!(manager.resync().await.unwrap(), U256::from(5));
+        assert_eq!(manager.allocate().await.unwrap(), U256::from(5));
+    }
+}
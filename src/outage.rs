@@ -0,0 +1,187 @@
+use std::{
+    future::Future,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::CKMSError;
+
+/// Parks sign calls which fail because KMS is unavailable, retrying them
+/// until `deadline` instead of failing immediately. At most `max_parked`
+/// calls wait at once; further calls fail with the outage error straight
+/// away, so a long outage cannot build an unbounded backlog.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct OutageQueue {
+    pub max_parked: usize,
+    /// How long a call may wait for KMS to come back, from its first failure
+    pub deadline: Duration,
+    /// The delay before the first retry, doubled after each failure
+    pub retry_interval: Duration,
+}
+
+impl OutageQueue {
+    pub fn new(max_parked: usize, deadline: Duration, retry_interval: Duration) -> Self {
+        Self {
+            max_parked,
+            deadline,
+            retry_interval,
+        }
+    }
+}
+
+/// Whether an error means KMS could not be reached, rather than that it
+/// refused the request
+fn is_outage(error: &CKMSError) -> bool {
+    match error {
+        CKMSError::RequestError(status) => {
+            matches!(status.code(), tonic::Code::Unavailable)
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Parking {
+    config: OutageQueue,
+    parked: AtomicUsize,
+}
+
+/// A parked call, counted until dropped
+struct Slot<'a>(&'a AtomicUsize);
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Parking {
+    pub(crate) fn new(config: OutageQueue) -> Self {
+        Self {
+            config,
+            parked: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn config(&self) -> OutageQueue {
+        self.config
+    }
+
+    pub(crate) fn parked(&self) -> usize {
+        self.parked.load(Ordering::Relaxed)
+    }
+
+    fn park(&self) -> Option<Slot<'_>> {
+        self.parked
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |parked| {
+                (parked < self.config.max_parked).then_some(parked + 1)
+            })
+            .ok()
+            .map(|_| Slot(&self.parked))
+    }
+
+    /// Runs `attempt`, parking and retrying it while KMS is unavailable
+    pub(crate) async fn run<F, Fut, T>(&self, mut attempt: F) -> Result<T, CKMSError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, CKMSError>>,
+    {
+        let error = match attempt().await {
+            Err(error) if is_outage(&error) => error,
+            result => return result,
+        };
+        let Some(_slot) = self.park() else {
+            warn!("KMS unavailable and the outage queue is full: {}", error);
+            return Err(error);
+        };
+
+        let deadline = Instant::now() + self.config.deadline;
+        let mut delay = self.config.retry_interval;
+        let mut error = error;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                warn!("KMS still unavailable at the outage deadline: {}", error);
+                return Err(error);
+            }
+            debug!(?delay, "KMS unavailable, parking sign call: {}", error);
+            tokio::time::sleep(delay.min(deadline - now)).await;
+            delay = delay.saturating_mul(2);
+
+            error = match attempt().await {
+                Err(error) if is_outage(&error) => error,
+                result => return result,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+
+    fn unavailable() -> CKMSError {
+        CKMSError::RequestError(tonic::Status::unavailable("down"))
+    }
+
+    fn parking(max_parked: usize) -> Parking {
+        Parking::new(OutageQueue::new(
+            max_parked,
+            Duration::from_millis(200),
+            Duration::from_millis(5),
+        ))
+    }
+
+    #[tokio::test]
+    async fn resolves_once_kms_returns() {
+        let parking = parking(1);
+        let attempts = AtomicU32::new(0);
+        let result = parking
+            .run(|| async {
+                match attempts.fetch_add(1, Ordering::Relaxed) {
+                    0 | 1 => Err(unavailable()),
+                    _ => Ok(7),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+        assert_eq!(parking.parked(), 0);
+    }
+
+    #[tokio::test]
+    async fn fails_at_deadline_or_when_full() {
+        let result: Result<(), _> = parking(1).run(|| async { Err(unavailable()) }).await;
+        assert!(matches!(result, Err(CKMSError::RequestError(_))));
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = parking(0)
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err(unavailable())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_not_retried() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = parking(1)
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err(CKMSError::RequestError(tonic::Status::permission_denied(
+                    "no",
+                )))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+
+use ethers::types::transaction::eip2718::TypedTransaction;
+use serde::Serialize;
+
+use crate::SigningDenied;
+
+/// A transaction envelope type, as restricted by
+/// [`GcpKmsSigner::with_allowed_tx_types`](crate::GcpKmsSigner::with_allowed_tx_types)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum TxType {
+    /// Type 0x00 legacy transactions
+    Legacy,
+    /// Type 0x01 access list transactions
+    Eip2930,
+    /// Type 0x02 dynamic fee transactions
+    Eip1559,
+    /// Type 0x03 blob transactions, signed with
+    /// [`GcpKmsSigner::sign_blob_transaction`](crate::GcpKmsSigner::sign_blob_transaction)
+    Eip4844,
+    /// Type 0x04 EIP-7702 set-code transactions, signed through alloy's
+    /// `TxSigner` with the `alloy` feature
+    Eip7702,
+    /// Envelopes this crate does not model, such as optimism deposits
+    Other,
+}
+
+impl TxType {
+    pub fn of(tx: &TypedTransaction) -> Self {
+        #[allow(unreachable_patterns)]
+        match tx {
+            TypedTransaction::Legacy(_) => TxType::Legacy,
+            TypedTransaction::Eip2930(_) => TxType::Eip2930,
+            TypedTransaction::Eip1559(_) => TxType::Eip1559,
+            _ => TxType::Other,
+        }
+    }
+}
+
+impl fmt::Display for TxType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxType::Legacy => write!(f, "legacy"),
+            TxType::Eip2930 => write!(f, "eip2930"),
+            TxType::Eip1559 => write!(f, "eip1559"),
+            TxType::Eip4844 => write!(f, "eip4844"),
+            TxType::Eip7702 => write!(f, "eip7702"),
+            TxType::Other => write!(f, "other"),
+        }
+    }
+}
+
+/// Denies transactions whose envelope is not in `allowed`
+pub(crate) fn check_tx_type(allowed: &[TxType], tx_type: TxType) -> Result<(), SigningDenied> {
+    if allowed.contains(&tx_type) {
+        return Ok(());
+    }
+
+    let allowed: Vec<_> = allowed.iter().map(ToString::to_string).collect();
+    Err(SigningDenied::new("tx_type_allowlist")
+        .with_value("tx_type", tx_type)
+        .with_value("allowed", allowed.join("|"))
+        .with_remediation("submit the transaction using one of the allowed envelope types"))
+}
+
+/// What a signer does when KMS returns a signature whose `s` is in the upper
+/// half of the curve order. KMS does not normalize signatures, so this is the
+/// case for about half of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum HighSPolicy {
+    /// Replace `s` with `n - s` and flip the recovery id, as EIP-2 requires
+    #[default]
+    Normalize,
+    /// Refuse with [`CKMSError::SigningDenied`](crate::CKMSError::SigningDenied).
+    /// KMS signatures are randomized, so the request can be retried.
+    Reject,
+    /// Return the signature as KMS produced it. Ethereum transactions with a
+    /// high `s` have been invalid since EIP-2, so this is only for verifiers
+    /// which accept them.
+    PassThrough,
+}
+
+impl fmt::Display for HighSPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HighSPolicy::Normalize => write!(f, "normalize"),
+            HighSPolicy::Reject => write!(f, "reject"),
+            HighSPolicy::PassThrough => write!(f, "pass_through"),
+        }
+    }
+}
+
+/// Denies high-s signatures under [`HighSPolicy::Reject`]
+pub(crate) fn check_high_s(policy: HighSPolicy, high_s: bool) -> Result<(), SigningDenied> {
+    if !high_s || policy != HighSPolicy::Reject {
+        return Ok(());
+    }
+    Err(SigningDenied::new("high_s_policy")
+        .with_value("policy", policy)
+        .with_remediation("retry the request, or use HighSPolicy::Normalize"))
+}
+
+/// The audit note recording how a signature's `s` was handled
+pub(crate) fn high_s_note(policy: HighSPolicy, high_s: bool) -> String {
+    let handling = match (high_s, policy) {
+        (false, _) => "none",
+        (true, HighSPolicy::PassThrough) => "passed_through",
+        (true, _) => "normalized",
+    };
+    format!("high_s={handling}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::{Eip1559TransactionRequest, TransactionRequest};
+
+    #[test]
+    fn allowlist_rejects_legacy() {
+        let allowed = [TxType::Eip1559];
+        let legacy = TypedTransaction::Legacy(TransactionRequest::new());
+        let dynamic = TypedTransaction::Eip1559(Eip1559TransactionRequest::new());
+
+        assert!(check_tx_type(&allowed, TxType::of(&dynamic)).is_ok());
+        assert!(check_tx_type(&allowed, TxType::Eip4844).is_err());
+        let denied = check_tx_type(&allowed, TxType::of(&legacy)).unwrap_err();
+        assert_eq!(denied.rule, "tx_type_allowlist");
+        assert_eq!(denied.evaluated[0], ("tx_type".into(), "legacy".into()));
+    }
+
+    #[test]
+    fn high_s_policy_decisions() {
+        assert!(check_high_s(HighSPolicy::Reject, false).is_ok());
+        assert_eq!(
+            check_high_s(HighSPolicy::Reject, true).unwrap_err().rule,
+            "high_s_policy"
+        );
+        assert!(check_high_s(HighSPolicy::PassThrough, true).is_ok());
+
+        assert_eq!(
+            high_s_note(HighSPolicy::Normalize, true),
+            "high_s=normalized"
+        );
+        assert_eq!(
+            high_s_note(HighSPolicy::PassThrough, true),
+            "high_s=passed_through"
+        );
+        assert_eq!(high_s_note(HighSPolicy::PassThrough, false), "high_s=none");
+    }
+}
@@ -0,0 +1,428 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use ethers::{
+    signers::Signer,
+    types::{Address, Signature, H256},
+};
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::{CKMSError, Clock, GcpKmsSigner, SystemClock};
+
+/// Thresholds for scoring the health of each key in a [`SignerPool`]. A key
+/// becomes unhealthy when its recent error rate or mean latency reaches the
+/// `unhealthy_*` threshold, and healthy again only once both fall to the
+/// lower `healthy_*` thresholds, so keys near a threshold do not flap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct HealthConfig {
+    /// The number of recent requests each key is scored on
+    pub window: usize,
+    /// Requests needed in the window before a key can change state
+    pub min_samples: usize,
+    pub unhealthy_error_rate: f64,
+    pub healthy_error_rate: f64,
+    pub unhealthy_latency: Duration,
+    pub healthy_latency: Duration,
+    /// How often an unhealthy key is sent a single request to probe whether
+    /// it has recovered
+    pub probe_interval: Duration,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            window: 20,
+            min_samples: 5,
+            unhealthy_error_rate: 0.5,
+            healthy_error_rate: 0.1,
+            unhealthy_latency: Duration::from_secs(5),
+            healthy_latency: Duration::from_secs(1),
+            probe_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+impl HealthConfig {
+    pub fn with_window(mut self, window: usize, min_samples: usize) -> Self {
+        self.window = window;
+        self.min_samples = min_samples;
+        self
+    }
+
+    pub fn with_error_rates(mut self, unhealthy: f64, healthy: f64) -> Self {
+        self.unhealthy_error_rate = unhealthy;
+        self.healthy_error_rate = healthy;
+        self
+    }
+
+    pub fn with_latencies(mut self, unhealthy: Duration, healthy: Duration) -> Self {
+        self.unhealthy_latency = unhealthy;
+        self.healthy_latency = healthy;
+        self
+    }
+
+    pub fn with_probe_interval(mut self, probe_interval: Duration) -> Self {
+        self.probe_interval = probe_interval;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HealthState {
+    Healthy,
+    Unhealthy,
+}
+
+/// A key's health as scored over its recent requests
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct KeyHealth {
+    pub key_name: String,
+    pub key_version: u64,
+    /// Zero for a lazy signer which has not been resolved
+    pub address: Address,
+    pub state: HealthState,
+    pub error_rate: f64,
+    pub mean_latency: Duration,
+    pub samples: usize,
+}
+
+/// Emitted by a [`SignerPool`] when a key changes [`HealthState`]
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct HealthEvent {
+    pub from: HealthState,
+    pub to: HealthState,
+    pub health: KeyHealth,
+}
+
+/// The scoring state of one key
+#[derive(Debug)]
+struct Health {
+    state: HealthState,
+    /// `(succeeded, latency)` of recent requests
+    samples: VecDeque<(bool, Duration)>,
+    last_attempt: Option<SystemTime>,
+}
+
+impl Health {
+    fn new() -> Self {
+        Self {
+            state: HealthState::Healthy,
+            samples: VecDeque::new(),
+            last_attempt: None,
+        }
+    }
+
+    fn error_rate(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        let failures = self.samples.iter().filter(|(ok, _)| !ok).count();
+        failures as f64 / self.samples.len() as f64
+    }
+
+    fn mean_latency(&self) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+        let total: Duration = self.samples.iter().map(|(_, latency)| *latency).sum();
+        total / self.samples.len() as u32
+    }
+
+    /// Whether the key may take a request: healthy keys always, unhealthy
+    /// ones once per probe interval
+    fn try_admit(&mut self, config: &HealthConfig, now: SystemTime) -> bool {
+        let due = match self.last_attempt {
+            Some(last) => now.duration_since(last).unwrap_or_default() >= config.probe_interval,
+            None => true,
+        };
+        if self.state == HealthState::Unhealthy && !due {
+            return false;
+        }
+        self.last_attempt = Some(now);
+        true
+    }
+
+    /// Records a request's outcome, returning the new state if it changed
+    fn record(
+        &mut self,
+        config: &HealthConfig,
+        succeeded: bool,
+        latency: Duration,
+    ) -> Option<HealthState> {
+        self.samples.push_back((succeeded, latency));
+        while self.samples.len() > config.window.max(1) {
+            self.samples.pop_front();
+        }
+        if self.samples.len() < config.min_samples {
+            return None;
+        }
+
+        let (error_rate, latency) = (self.error_rate(), self.mean_latency());
+        let next = match self.state {
+            HealthState::Healthy
+                if error_rate >= config.unhealthy_error_rate
+                    || latency >= config.unhealthy_latency =>
+            {
+                HealthState::Unhealthy
+            }
+            HealthState::Unhealthy
+                if error_rate <= config.healthy_error_rate && latency <= config.healthy_latency =>
+            {
+                HealthState::Healthy
+            }
+            state => state,
+        };
+        (next != self.state).then(|| {
+            self.state = next;
+            next
+        })
+    }
+}
+
+struct Member {
+    signer: GcpKmsSigner,
+    health: Mutex<Health>,
+}
+
+/// A set of interchangeable signers, e.g. several keys or key versions kept
+/// as warm standbys. Requests are spread across healthy keys and routed away
+/// from keys which are failing or slow; see [`HealthConfig`].
+///
+/// Members generally have different addresses, so the pool suits workloads
+/// where any member's signature is acceptable, and reports which member
+/// signed.
+#[derive(Clone)]
+pub struct SignerPool {
+    members: Arc<Vec<Member>>,
+    config: HealthConfig,
+    clock: Arc<dyn Clock>,
+    next: Arc<AtomicUsize>,
+    events: broadcast::Sender<HealthEvent>,
+}
+
+impl std::fmt::Debug for SignerPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SignerPool")
+            .field("health", &self.health())
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl SignerPool {
+    pub fn new(signers: impl IntoIterator<Item = GcpKmsSigner>, config: HealthConfig) -> Self {
+        let members = signers
+            .into_iter()
+            .map(|signer| Member {
+                signer,
+                health: Mutex::new(Health::new()),
+            })
+            .collect();
+        Self {
+            members: Arc::new(members),
+            config,
+            clock: Arc::new(SystemClock),
+            next: Arc::new(AtomicUsize::new(0)),
+            events: broadcast::channel(64).0,
+        }
+    }
+
+    /// Sets the time source for probe intervals. Defaults to
+    /// [`SystemClock`].
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Receives a [`HealthEvent`] whenever a key changes state
+    pub fn subscribe(&self) -> broadcast::Receiver<HealthEvent> {
+        self.events.subscribe()
+    }
+
+    /// The current health of every key in the pool
+    pub fn health(&self) -> Vec<KeyHealth> {
+        self.members.iter().map(key_health).collect()
+    }
+
+    /// Picks the next healthy member, round robin, or an unhealthy member due
+    /// a probe. With no such member, the one with the lowest error rate is
+    /// used rather than failing outright.
+    fn select(&self) -> Option<usize> {
+        let len = self.members.len();
+        if len == 0 {
+            return None;
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let now = self.clock.now();
+
+        (0..len)
+            .map(|offset| (start + offset) % len)
+            .find(|&index| {
+                self.members[index]
+                    .health
+                    .lock()
+                    .unwrap()
+                    .try_admit(&self.config, now)
+            })
+            .or_else(|| {
+                (0..len).min_by(|&a, &b| {
+                    let rate =
+                        |index: usize| self.members[index].health.lock().unwrap().error_rate();
+                    rate(a).total_cmp(&rate(b))
+                })
+            })
+    }
+
+    /// Runs `f` with a member chosen by health, and scores the member on the
+    /// outcome. Requests refused by a signer's own policy do not count
+    /// against its key.
+    pub async fn run<'a, F, Fut, T>(&'a self, f: F) -> Result<T, CKMSError>
+    where
+        F: FnOnce(&'a GcpKmsSigner) -> Fut,
+        Fut: Future<Output = Result<T, CKMSError>>,
+    {
+        let index = self
+            .select()
+            .ok_or_else(|| CKMSError::Backpressure("signer pool is empty".to_string()))?;
+        let member = &self.members[index];
+
+        let started = Instant::now();
+        let result = f(&member.signer).await;
+        if matches!(result, Err(CKMSError::SigningDenied(_))) {
+            return result;
+        }
+
+        let transition =
+            member
+                .health
+                .lock()
+                .unwrap()
+                .record(&self.config, result.is_ok(), started.elapsed());
+        if let Some(to) = transition {
+            let health = key_health(member);
+            let from = match to {
+                HealthState::Healthy => HealthState::Unhealthy,
+                HealthState::Unhealthy => HealthState::Healthy,
+            };
+            warn!(
+                key_name = health.key_name.as_str(),
+                key_version = health.key_version,
+                ?from,
+                ?to,
+                error_rate = health.error_rate,
+                "Signer pool key changed health state"
+            );
+            // no subscribers is fine
+            let _ = self.events.send(HealthEvent { from, to, health });
+        }
+        result
+    }
+
+    /// Signs a digest with a healthy member, returning the signer's address
+    /// with the signature
+    pub async fn sign_hash(&self, hash: H256) -> Result<(Address, Signature), CKMSError> {
+        self.run(|signer| async move { Ok((signer.address(), signer.sign_hash(hash).await?)) })
+            .await
+    }
+
+    /// Signs a message with a healthy member, returning the signer's address
+    /// with the signature
+    pub async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<(Address, Signature), CKMSError> {
+        self.run(
+            |signer| async move { Ok((signer.address(), signer.sign_message(message).await?)) },
+        )
+        .await
+    }
+}
+
+fn key_health(member: &Member) -> KeyHealth {
+    let health = member.health.lock().unwrap();
+    KeyHealth {
+        key_name: member.signer.key_name(),
+        key_version: member.signer.key_version(),
+        address: member.signer.cached_address().unwrap_or_default(),
+        state: health.state,
+        error_rate: health.error_rate(),
+        mean_latency: health.mean_latency(),
+        samples: health.samples.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+    use std::time::UNIX_EPOCH;
+
+    const FAST: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn becomes_unhealthy_and_recovers_with_hysteresis() {
+        let config = HealthConfig::default().with_window(10, 4);
+        let mut health = Health::new();
+
+        for _ in 0..3 {
+            assert_eq!(health.record(&config, false, FAST), None);
+        }
+        assert_eq!(
+            health.record(&config, false, FAST),
+            Some(HealthState::Unhealthy)
+        );
+
+        // 30% errors is below the unhealthy threshold but above the healthy one
+        for _ in 0..7 {
+            assert_eq!(health.record(&config, true, FAST), None);
+        }
+        assert_eq!(health.error_rate(), 0.3);
+        assert_eq!(health.state, HealthState::Unhealthy);
+
+        assert_eq!(health.record(&config, true, FAST), None);
+        assert_eq!(
+            health.record(&config, true, FAST),
+            Some(HealthState::Healthy)
+        );
+    }
+
+    #[test]
+    fn slow_keys_are_unhealthy() {
+        let config = HealthConfig {
+            min_samples: 1,
+            ..Default::default()
+        };
+        let mut health = Health::new();
+        assert_eq!(
+            health.record(&config, true, Duration::from_secs(6)),
+            Some(HealthState::Unhealthy)
+        );
+    }
+
+    #[test]
+    fn unhealthy_keys_are_probed_periodically() {
+        let config = HealthConfig::default();
+        let clock = ManualClock::new(UNIX_EPOCH);
+        let mut health = Health::new();
+        health.state = HealthState::Unhealthy;
+
+        assert!(health.try_admit(&config, clock.now()));
+        assert!(!health.try_admit(&config, clock.now()));
+        clock.advance(config.probe_interval);
+        assert!(health.try_admit(&config, clock.now()));
+
+        health.state = HealthState::Healthy;
+        assert!(health.try_admit(&config, clock.now()));
+    }
+}
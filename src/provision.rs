@@ -0,0 +1,126 @@
+//! Creating the secp256k1 signing keys a [`GcpKmsSigner`](crate::GcpKmsSigner)
+//! signs with
+use std::time::Duration;
+
+use gcloud_sdk::google::cloud::kms::v1::{
+    crypto_key::CryptoKeyPurpose,
+    crypto_key_version::{CryptoKeyVersionAlgorithm, CryptoKeyVersionState},
+    CreateCryptoKeyRequest, CryptoKey, CryptoKeyVersionTemplate, ProtectionLevel,
+};
+use tokio::time::Instant;
+use tonic::Request;
+use tracing::info;
+
+use crate::{CKMSError, GcpKmsProvider};
+
+/// How long a new key's first version may stay pending before
+/// [`GcpKmsProvider::create_key`] gives up on it
+const GENERATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where KMS keeps a key's material
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum KeyProtection {
+    #[default]
+    Software,
+    /// In a Cloud HSM, at a higher price and lower signing quota
+    Hsm,
+}
+
+impl From<KeyProtection> for ProtectionLevel {
+    fn from(protection: KeyProtection) -> Self {
+        match protection {
+            KeyProtection::Software => ProtectionLevel::Software,
+            KeyProtection::Hsm => ProtectionLevel::Hsm,
+        }
+    }
+}
+
+fn create_key_request(
+    parent: String,
+    key_id: &str,
+    protection: KeyProtection,
+) -> CreateCryptoKeyRequest {
+    CreateCryptoKeyRequest {
+        parent,
+        crypto_key_id: key_id.to_string(),
+        crypto_key: Some(CryptoKey {
+            purpose: CryptoKeyPurpose::AsymmetricSign as i32,
+            version_template: Some(CryptoKeyVersionTemplate {
+                protection_level: ProtectionLevel::from(protection) as i32,
+                algorithm: CryptoKeyVersionAlgorithm::EcSignSecp256k1Sha256 as i32,
+            }),
+            ..Default::default()
+        }),
+        skip_initial_version_creation: false,
+    }
+}
+
+impl GcpKmsProvider {
+    /// Creates an `EC_SIGN_SECP256K1_SHA256` key in the key ring, and waits
+    /// for KMS to generate its first version, returning that version's
+    /// number
+    pub async fn create_key(
+        &self,
+        key_id: &str,
+        protection: KeyProtection,
+    ) -> Result<u64, CKMSError> {
+        let parent = self.kms_key_ref.to_google_ref();
+        let mut request = Request::new(create_key_request(parent.clone(), key_id, protection));
+
+        // Add metadata for request routing: https://cloud.google.com/kms/docs/grpc
+        request.metadata_mut().insert(
+            "x-goog-request-params",
+            format!("parent={parent}").parse().unwrap(),
+        );
+
+        let request = self.intercept(request)?;
+        self.client.create_crypto_key(request).await?;
+        info!(key_id, ?protection, "Created KMS key");
+
+        // versions of a new key are numbered from 1
+        let key_version = 1;
+        let deadline = Instant::now() + GENERATION_TIMEOUT;
+        loop {
+            let version = self.get_crypto_key_version(key_id, key_version).await?;
+            match CryptoKeyVersionState::from_i32(version.state) {
+                Some(CryptoKeyVersionState::Enabled) => return Ok(key_version),
+                Some(CryptoKeyVersionState::PendingGeneration) if Instant::now() < deadline => {
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+                state => {
+                    return Err(CKMSError::KeyCreationError(format!(
+                        "version {} of {key_id} is {}",
+                        key_version,
+                        state.map_or("in an unknown state", |state| state.as_str_name())
+                    )))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_secp256k1_signing_keys() {
+        let request = create_key_request(
+            "projects/p/locations/l/keyRings/r".to_string(),
+            "k",
+            KeyProtection::Hsm,
+        );
+        assert_eq!(request.crypto_key_id, "k");
+        let key = request.crypto_key.unwrap();
+        assert_eq!(key.purpose, CryptoKeyPurpose::AsymmetricSign as i32);
+        let template = key.version_template.unwrap();
+        assert_eq!(
+            template.algorithm,
+            CryptoKeyVersionAlgorithm::EcSignSecp256k1Sha256 as i32
+        );
+        assert_eq!(template.protection_level, ProtectionLevel::Hsm as i32);
+    }
+}
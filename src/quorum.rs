@@ -0,0 +1,299 @@
+use std::convert::Infallible;
+
+use ethers::{
+    abi::{self, Token},
+    signers::Signer,
+    types::{
+        transaction::eip712::{EIP712Domain, Eip712},
+        Address, Bytes, Signature, H256, U256,
+    },
+    utils::keccak256,
+};
+use futures::future::join_all;
+use tracing::warn;
+
+use crate::{CKMSError, GcpKmsSigner};
+
+const SAFE_TX_TYPE: &str = "SafeTx(address to,uint256 value,bytes data,uint8 operation,uint256 safeTxGas,uint256 baseGas,uint256 gasPrice,address gasToken,address refundReceiver,uint256 nonce)";
+
+/// The domain of the Safe at `safe`, for Safe 1.3.0 and later
+pub fn safe_domain(chain_id: u64, safe: Address) -> EIP712Domain {
+    EIP712Domain {
+        name: None,
+        version: None,
+        chain_id: Some(chain_id.into()),
+        verifying_contract: Some(safe),
+        salt: None,
+    }
+}
+
+/// How a Safe executes a transaction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SafeOperation {
+    #[default]
+    Call,
+    DelegateCall,
+}
+
+/// A transaction for a Safe's `execTransaction`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SafeTransaction {
+    pub to: Address,
+    pub value: U256,
+    pub data: Bytes,
+    pub operation: SafeOperation,
+    pub safe_tx_gas: U256,
+    pub base_gas: U256,
+    pub gas_price: U256,
+    pub gas_token: Address,
+    pub refund_receiver: Address,
+    /// The Safe's nonce, as its `nonce()` returns it
+    pub nonce: U256,
+}
+
+/// A Safe transaction in its Safe's domain
+struct SafeTypedData<'a> {
+    domain: &'a EIP712Domain,
+    tx: &'a SafeTransaction,
+}
+
+impl Eip712 for SafeTypedData<'_> {
+    type Error = Infallible;
+
+    fn domain(&self) -> Result<EIP712Domain, Infallible> {
+        Ok(self.domain.clone())
+    }
+
+    fn type_hash() -> Result<[u8; 32], Infallible> {
+        Ok(keccak256(SAFE_TX_TYPE))
+    }
+
+    fn struct_hash(&self) -> Result<[u8; 32], Infallible> {
+        let tx = self.tx;
+        Ok(keccak256(abi::encode(&[
+            Token::FixedBytes(Self::type_hash()?.to_vec()),
+            Token::Address(tx.to),
+            Token::Uint(tx.value),
+            Token::FixedBytes(keccak256(&tx.data).to_vec()),
+            Token::Uint((tx.operation as u8).into()),
+            Token::Uint(tx.safe_tx_gas),
+            Token::Uint(tx.base_gas),
+            Token::Uint(tx.gas_price),
+            Token::Address(tx.gas_token),
+            Token::Address(tx.refund_receiver),
+            Token::Uint(tx.nonce),
+        ])))
+    }
+}
+
+impl SafeTransaction {
+    /// The hash the Safe's owners sign, as its `getTransactionHash` returns it
+    pub fn safe_tx_hash(&self, domain: &EIP712Domain) -> H256 {
+        let typed = SafeTypedData { domain, tx: self };
+        match typed.encode_eip712() {
+            Ok(digest) => digest.into(),
+            Err(infallible) => match infallible {},
+        }
+    }
+}
+
+/// The signatures a [`QuorumSigner`] collected, sorted by signer address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuorumSignatures {
+    /// One signature per distinct address, with `v` = 27/28
+    pub signatures: Vec<(Address, Signature)>,
+    /// The indices of the signers which failed, with their errors
+    pub failures: Vec<(usize, String)>,
+}
+
+impl QuorumSignatures {
+    /// The signatures concatenated in ascending signer order, as Safe's
+    /// `execTransaction` takes them
+    pub fn to_bytes(&self) -> Bytes {
+        self.signatures
+            .iter()
+            .flat_map(|(_, signature)| signature.to_vec())
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+/// Signs the same payload with several keys concurrently, for multisig
+/// owners such as a Safe's, whose keys may be in different projects
+///
+/// A lazy [`GcpKmsSigner`] is resolved by its first signature, so any
+/// member's address is known once it has signed.
+#[derive(Clone, Debug)]
+pub struct QuorumSigner<S = GcpKmsSigner> {
+    signers: Vec<S>,
+    threshold: usize,
+}
+
+impl<S: Signer> QuorumSigner<S> {
+    /// Requires every signer to sign, unless lowered with
+    /// [`QuorumSigner::with_threshold`]
+    pub fn new(signers: impl IntoIterator<Item = S>) -> Self {
+        let signers: Vec<_> = signers.into_iter().collect();
+        Self {
+            threshold: signers.len(),
+            signers,
+        }
+    }
+
+    /// Succeeds once `threshold` distinct signers have signed. Every signer
+    /// is still asked, so a failure does not hold up the others.
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn signers(&self) -> &[S] {
+        &self.signers
+    }
+
+    /// Signs a Safe transaction with every signer, for `execTransaction` on
+    /// the Safe whose domain is `domain`, e.g. [`safe_domain`]
+    pub async fn sign_safe_transaction(
+        &self,
+        tx: &SafeTransaction,
+        domain: &EIP712Domain,
+    ) -> Result<QuorumSignatures, CKMSError> {
+        let typed = SafeTypedData { domain, tx };
+        let results = join_all(self.signers.iter().map(|signer| async {
+            let signature = signer.sign_typed_data(&typed).await?;
+            Ok::<_, S::Error>((signer.address(), signature))
+        }))
+        .await;
+        self.collect(results)
+    }
+
+    /// Sorts and deduplicates the signatures, and checks the threshold
+    fn collect<E: std::fmt::Display>(
+        &self,
+        results: Vec<Result<(Address, Signature), E>>,
+    ) -> Result<QuorumSignatures, CKMSError> {
+        let mut signatures = Vec::new();
+        let mut failures = Vec::new();
+        for (index, result) in results.into_iter().enumerate() {
+            match result {
+                Ok((address, mut signature)) => {
+                    if signature.v < 27 {
+                        signature.v += 27;
+                    }
+                    signatures.push((address, signature));
+                }
+                Err(e) => {
+                    warn!(signer = index, "Quorum member failed to sign: {e}");
+                    failures.push((index, e.to_string()));
+                }
+            }
+        }
+        signatures.sort_by_key(|(address, _)| *address);
+        signatures.dedup_by_key(|(address, _)| *address);
+
+        if signatures.len() < self.threshold {
+            return Err(CKMSError::QuorumNotReached {
+                required: self.threshold,
+                signed: signatures.len(),
+                failures: failures.into_iter().map(|(_, e)| e).collect(),
+            });
+        }
+        Ok(QuorumSignatures {
+            signatures,
+            failures,
+        })
+    }
+}
+
+impl QuorumSigner<GcpKmsSigner> {
+    /// Signs a digest with every signer, with `v` = 27/28
+    pub async fn sign_hash(&self, hash: H256) -> Result<QuorumSignatures, CKMSError> {
+        let results = join_all(self.signers.iter().map(|signer| async move {
+            let signature = signer.sign_hash(hash).await?;
+            Ok::<_, CKMSError>((signer.address(), signature))
+        }))
+        .await;
+        self.collect(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::LocalWallet;
+
+    fn wallets() -> Vec<LocalWallet> {
+        (1u8..=3)
+            .map(|i| LocalWallet::from_bytes(&[i; 32]).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn type_hash_matches_safe() {
+        // SafeStorage's SAFE_TX_TYPEHASH
+        let expected: H256 = "0xbb8310d486368db6bd6f849402fdd73ad53d316b5a4b2644ad6efe0f941286d8"
+            .parse()
+            .unwrap();
+        assert_eq!(H256::from(SafeTypedData::type_hash().unwrap()), expected);
+    }
+
+    #[tokio::test]
+    async fn signatures_are_sorted_by_owner() {
+        let domain = safe_domain(1, Address::repeat_byte(0x5a));
+        let tx = SafeTransaction {
+            to: Address::repeat_byte(1),
+            value: 1.into(),
+            nonce: 4.into(),
+            ..Default::default()
+        };
+        let quorum = QuorumSigner::new(wallets());
+        let signed = quorum.sign_safe_transaction(&tx, &domain).await.unwrap();
+
+        let addresses: Vec<_> = signed.signatures.iter().map(|(a, _)| *a).collect();
+        let mut sorted = addresses.clone();
+        sorted.sort();
+        assert_eq!(addresses, sorted);
+
+        let bytes = signed.to_bytes();
+        assert_eq!(bytes.len(), 3 * 65);
+        let hash = tx.safe_tx_hash(&domain);
+        for (i, (address, signature)) in signed.signatures.iter().enumerate() {
+            assert_eq!(&bytes[i * 65..(i + 1) * 65], signature.to_vec().as_slice());
+            assert!(signature.v == 27 || signature.v == 28);
+            assert_eq!(signature.recover(hash).unwrap(), *address);
+        }
+    }
+
+    #[test]
+    fn threshold_counts_distinct_signers() {
+        let quorum = QuorumSigner::new(wallets()).with_threshold(2);
+        let signature = Signature {
+            r: 1.into(),
+            s: 1.into(),
+            v: 0,
+        };
+        let duplicate = (Address::repeat_byte(1), signature);
+        let denied = quorum
+            .collect(vec![Ok(duplicate), Ok(duplicate), Err("unavailable")])
+            .unwrap_err();
+        assert!(matches!(
+            denied,
+            CKMSError::QuorumNotReached {
+                required: 2,
+                signed: 1,
+                ..
+            }
+        ));
+
+        let signed = quorum
+            .collect(vec![
+                Ok(duplicate),
+                Err("unavailable"),
+                Ok((Address::repeat_byte(0), signature)),
+            ])
+            .unwrap();
+        assert_eq!(signed.signatures[0].0, Address::repeat_byte(0));
+        assert_eq!(signed.signatures[0].1.v, 27);
+        assert_eq!(signed.failures, vec![(1, "unavailable".to_string())]);
+    }
+}
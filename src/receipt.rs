@@ -0,0 +1,287 @@
+use ethers::{
+    abi::{self, Token},
+    core::rand,
+    types::{
+        transaction::{eip2718::TypedTransaction, eip712::Eip712},
+        Address, RecoveryMessage, Signature, H256, U256,
+    },
+    utils::{hash_message, keccak256},
+};
+
+use crate::{
+    audit::AuditOperation, snapshot::Snapshot, transaction_sighash, CKMSError, GcpKmsSigner,
+};
+
+/// A record of why and how a signature was produced, attested by a second
+/// KMS signature over its [hash](SigningReceipt::hash) so it can be archived
+/// as non-repudiable proof
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SigningReceipt {
+    /// Random identifier of the signing request
+    pub request_id: H256,
+    /// The digest which was signed
+    pub digest: H256,
+    /// Resource name of the crypto key
+    pub key_name: String,
+    pub key_version: u64,
+    /// Milliseconds since the unix epoch
+    pub timestamp_ms: u64,
+    /// Policies evaluated before signing, as `rule=decision`
+    pub policy_decisions: Vec<String>,
+    pub signature: Signature,
+    /// Signature by the same key over [`SigningReceipt::hash`]
+    pub attestation: Signature,
+}
+
+impl SigningReceipt {
+    /// The keccak256 of the ABI-encoded receipt fields, excluding the
+    /// attestation
+    pub fn hash(&self) -> H256 {
+        let encoded = abi::encode(&[
+            Token::FixedBytes(self.request_id.as_bytes().to_vec()),
+            Token::FixedBytes(self.digest.as_bytes().to_vec()),
+            Token::String(self.key_name.clone()),
+            Token::Uint(U256::from(self.key_version)),
+            Token::Uint(U256::from(self.timestamp_ms)),
+            Token::Array(
+                self.policy_decisions
+                    .iter()
+                    .cloned()
+                    .map(Token::String)
+                    .collect(),
+            ),
+            Token::Bytes(self.signature.to_vec()),
+        ]);
+        keccak256(encoded).into()
+    }
+
+    /// Checks that the attestation over this receipt was made by `address`
+    pub fn verify(&self, address: Address) -> Result<(), CKMSError> {
+        self.attestation
+            .verify(RecoveryMessage::Hash(self.hash()), address)?;
+        Ok(())
+    }
+}
+
+impl GcpKmsSigner {
+    /// Issues an attested [`SigningReceipt`] for a signature one of the
+    /// `*_with_receipt` methods just produced over `digest`. The attestation
+    /// makes one additional KMS call, which is audited as
+    /// [`AuditOperation::Receipt`].
+    async fn issue_receipt(
+        &self,
+        snapshot: &Snapshot,
+        digest: H256,
+        signature: Signature,
+        policy_decisions: Vec<String>,
+    ) -> Result<SigningReceipt, CKMSError> {
+        let mut receipt = SigningReceipt {
+            request_id: H256::from(rand::random::<[u8; 32]>()),
+            digest,
+            key_name: self.key_name(),
+            key_version: snapshot.key_version,
+            timestamp_ms: self.clock.now_ms(),
+            policy_decisions,
+            signature,
+            attestation: Signature {
+                r: U256::zero(),
+                s: U256::zero(),
+                v: 0,
+            },
+        };
+
+        let hash = receipt.hash();
+        let attestation = async {
+            // attestations are always low-s so they verify everywhere
+            let sig = self.kms_sign_unchecked(snapshot, hash.into()).await?;
+            crate::sig_from_digest_bytes_trial_recovery(
+                &sig.normalized,
+                hash.into(),
+                &self.resolve_snapshot(snapshot).await?,
+            )
+        }
+        .await;
+        self.audit(
+            snapshot,
+            AuditOperation::Receipt,
+            hash,
+            Some(snapshot.chain_id),
+            &attestation,
+            vec![
+                format!("receipt_request_id={:?}", receipt.request_id),
+                format!("receipt_digest={digest:?}"),
+            ],
+        );
+        receipt.attestation = attestation?;
+        Ok(receipt)
+    }
+
+    /// Signs a message and issues a [`SigningReceipt`] for it
+    pub async fn sign_message_with_receipt<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<(Signature, SigningReceipt), CKMSError> {
+        let snapshot = self.snapshot();
+        let digest = hash_message(message.as_ref());
+        let signature = self
+            .sign_message_with_chain(&snapshot, message.as_ref(), snapshot.chain_id)
+            .await?;
+        let receipt = self
+            .issue_receipt(
+                &snapshot,
+                digest,
+                signature,
+                snapshot.policy_decisions(None),
+            )
+            .await?;
+        Ok((signature, receipt))
+    }
+
+    /// Signs a transaction and issues a [`SigningReceipt`] for it
+    pub async fn sign_transaction_with_receipt(
+        &self,
+        tx: &TypedTransaction,
+    ) -> Result<(Signature, SigningReceipt), CKMSError> {
+        let snapshot = self.snapshot();
+        let (digest, _) = transaction_sighash(tx, snapshot.chain_id, snapshot.replay_protection);
+
+        let signature = self
+            .sign_transaction_with_default_chain(&snapshot, tx, snapshot.chain_id)
+            .await?;
+        let receipt = self
+            .issue_receipt(
+                &snapshot,
+                digest,
+                signature,
+                snapshot.policy_decisions(Some(tx)),
+            )
+            .await?;
+        Ok((signature, receipt))
+    }
+
+    /// Signs EIP-712 typed data and issues a [`SigningReceipt`] for it
+    pub async fn sign_typed_data_with_receipt<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<(Signature, SigningReceipt), CKMSError> {
+        let snapshot = self.snapshot();
+        let digest = payload
+            .encode_eip712()
+            .map_err(|e| CKMSError::Eip712Error(e.to_string()))?;
+        let signature = self
+            .sign_typed_data_with_snapshot(&snapshot, payload)
+            .await?;
+        let receipt = self
+            .issue_receipt(
+                &snapshot,
+                digest.into(),
+                signature,
+                snapshot.policy_decisions(None),
+            )
+            .await?;
+        Ok((signature, receipt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use ethers::prelude::k256::ecdsa::{
+        signature::hazmat::PrehashSigner, SigningKey, VerifyingKey,
+    };
+
+    use super::*;
+    use crate::{
+        audit::{AuditEvent, AuditOutcome, AuditSink},
+        KmsKeyBackend, SigningContext,
+    };
+    use ethers::signers::LocalWallet;
+    use ethers::signers::Signer;
+
+    #[derive(Debug)]
+    struct LocalBackend(SigningKey);
+
+    #[async_trait]
+    impl KmsKeyBackend for LocalBackend {
+        async fn get_public_key(&self, _: &str, _: u64) -> Result<VerifyingKey, CKMSError> {
+            Ok(*self.0.verifying_key())
+        }
+
+        async fn sign_digest(
+            &self,
+            _: &str,
+            key_version: u64,
+            digest: [u8; 32],
+            _: &SigningContext,
+        ) -> Result<(Vec<u8>, u64), CKMSError> {
+            let signature: ethers::prelude::k256::ecdsa::Signature =
+                self.0.sign_prehash(&digest)?;
+            Ok((signature.to_der().as_bytes().to_vec(), key_version))
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingSink(Mutex<Vec<AuditEvent>>);
+
+    impl AuditSink for RecordingSink {
+        fn record(&self, event: &AuditEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn attestations_are_audited() {
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let signer = GcpKmsSigner::new(Arc::new(LocalBackend(key)), "local".to_string(), 1, 5)
+            .await
+            .unwrap()
+            .with_audit_sink(sink.clone());
+
+        let (_, receipt) = signer.sign_message_with_receipt("hello").await.unwrap();
+        receipt.verify(signer.address()).unwrap();
+
+        let events = sink.0.lock().unwrap();
+        let operations: Vec<_> = events.iter().map(|event| event.operation).collect();
+        assert_eq!(
+            operations,
+            [AuditOperation::Message, AuditOperation::Receipt]
+        );
+        let attestation = &events[1];
+        assert_eq!(attestation.outcome, AuditOutcome::Signed);
+        assert_eq!(attestation.digest, receipt.hash());
+        assert!(attestation
+            .notes
+            .contains(&format!("receipt_digest={:?}", receipt.digest)));
+    }
+
+    #[tokio::test]
+    async fn receipt_attestation_verifies() {
+        let wallet = LocalWallet::from_bytes(&[7u8; 32]).unwrap();
+        let mut receipt = SigningReceipt {
+            request_id: H256::repeat_byte(1),
+            digest: H256::repeat_byte(2),
+            key_name: "projects/p/locations/l/keyRings/r/cryptoKeys/k".to_string(),
+            key_version: 1,
+            timestamp_ms: 3,
+            policy_decisions: vec!["tx_type_allowlist=allowed".to_string()],
+            signature: Signature {
+                r: U256::one(),
+                s: U256::one(),
+                v: 27,
+            },
+            attestation: Signature {
+                r: U256::zero(),
+                s: U256::zero(),
+                v: 0,
+            },
+        };
+        receipt.attestation = wallet.sign_hash(receipt.hash()).unwrap();
+        receipt.verify(wallet.address()).unwrap();
+
+        receipt.timestamp_ms += 1;
+        assert!(receipt.verify(wallet.address()).is_err());
+    }
+}
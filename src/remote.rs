@@ -0,0 +1,372 @@
+use async_trait::async_trait;
+use ethers::{
+    signers::Signer,
+    types::{
+        transaction::{eip2718::TypedTransaction, eip712::Eip712},
+        Address, Signature, H256,
+    },
+    utils::{hex, keccak256},
+};
+use serde_json::json;
+
+use crate::{apply_transaction_v, validate_chain_id, CKMSError};
+
+/// Signs through a remote signer which serves the Web3Signer ETH1 API, such
+/// as Web3Signer itself or a [`crate::server::SignerServer`], so a service can
+/// switch between signing with KMS directly and through a signing server.
+///
+/// Its signatures follow [`crate::GcpKmsSigner`]'s conventions: messages have
+/// `v` = 27/28, typed data 0/1, legacy transactions their EIP-155 `v` and
+/// typed transactions their y-parity. Each is checked to recover to the
+/// key's address.
+#[derive(Clone, Debug)]
+pub struct RemoteSigner {
+    http: reqwest::Client,
+    bearer_token: Option<String>,
+    /// The server's base URL, without a trailing `/`
+    url: String,
+    /// The key's Web3Signer identifier, its 64-byte public key as `0x` hex
+    identifier: String,
+    address: Address,
+    chain_id: u64,
+}
+
+impl RemoteSigner {
+    /// Signs with the key whose uncompressed public key, with or without its
+    /// SEC1 tag, is `public_key`, on the server at `url`
+    pub fn new(url: &str, public_key: &[u8], chain_id: u64) -> Result<Self, CKMSError> {
+        let public_key = match public_key {
+            [0x04, raw @ ..] if raw.len() == 64 => raw,
+            raw if raw.len() == 64 => raw,
+            _ => {
+                return Err(CKMSError::RemoteSignerError(format!(
+                    "expected a 64 or 65-byte public key, got {} bytes",
+                    public_key.len()
+                )))
+            }
+        };
+        Ok(Self {
+            http: reqwest::Client::new(),
+            bearer_token: None,
+            url: url.trim_end_matches('/').to_string(),
+            identifier: format!("0x{}", hex::encode(public_key)),
+            address: Address::from_slice(&keccak256(public_key)[12..]),
+            chain_id: validate_chain_id(chain_id)?,
+        })
+    }
+
+    /// Signs with the key for `address`, finding it among the server's keys
+    pub async fn connect(url: &str, address: Address, chain_id: u64) -> Result<Self, CKMSError> {
+        Self::connect_with_client(reqwest::Client::new(), url, address, chain_id).await
+    }
+
+    /// Like [`RemoteSigner::connect`], sending requests with `http`. A
+    /// server which authenticates callers needs `http` to send credentials
+    /// by default, or [`RemoteSigner::new`] and
+    /// [`RemoteSigner::with_bearer_token`].
+    pub async fn connect_with_client(
+        http: reqwest::Client,
+        url: &str,
+        address: Address,
+        chain_id: u64,
+    ) -> Result<Self, CKMSError> {
+        let url = url.trim_end_matches('/');
+        let list = http.get(format!("{url}/api/v1/eth1/publicKeys"));
+        for public_key in public_keys(list).await? {
+            let candidate = Self::new(url, &public_key, chain_id)?;
+            if candidate.address == address {
+                return Ok(candidate.with_http_client(http));
+            }
+        }
+        Err(CKMSError::RemoteSignerError(format!(
+            "{url} has no key for {address:?}"
+        )))
+    }
+
+    /// Sends requests with `http`, e.g. one with timeouts or client
+    /// certificates
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Sends `token`, an API key or a JWT, as `authorization: Bearer …`
+    pub fn with_bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(token.into());
+        self
+    }
+
+    /// The uncompressed public keys of the server's keys, without SEC1 tags
+    pub async fn public_keys(&self) -> Result<Vec<Vec<u8>>, CKMSError> {
+        let url = format!("{}/api/v1/eth1/publicKeys", self.url);
+        public_keys(self.authorized(self.http.get(url))).await
+    }
+
+    fn authorized(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.bearer_token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Has the server sign `keccak256(data)`, returning the signature with
+    /// its 0/1 recovery id as `v`
+    async fn sign_preimage(&self, data: &[u8]) -> Result<Signature, CKMSError> {
+        let url = format!("{}/api/v1/eth1/sign/{}", self.url, self.identifier);
+        let body = json!({ "data": format!("0x{}", hex::encode(data)) });
+        let mut signature: Signature = send(self.authorized(self.http.post(&url).json(&body)))
+            .await?
+            .text()
+            .await
+            .map_err(|e| CKMSError::RemoteSignerError(format!("{url}: {e}")))?
+            .trim()
+            .parse()?;
+
+        signature.v = match signature.v {
+            0 | 1 => signature.v,
+            27 | 28 => signature.v - 27,
+            v => return Err(CKMSError::InvalidSignature(format!("unexpected v {v}"))),
+        };
+        if signature.recover(H256(keccak256(data))).ok() != Some(self.address) {
+            return Err(CKMSError::RecoveryError);
+        }
+        Ok(signature)
+    }
+}
+
+async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response, CKMSError> {
+    let response = request
+        .send()
+        .await
+        .map_err(|e| CKMSError::RemoteSignerError(e.to_string()))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(CKMSError::RemoteSignerError(format!("{status}: {body}")));
+    }
+    Ok(response)
+}
+
+async fn public_keys(request: reqwest::RequestBuilder) -> Result<Vec<Vec<u8>>, CKMSError> {
+    let keys: Vec<String> = send(request)
+        .await?
+        .json()
+        .await
+        .map_err(|e| CKMSError::RemoteSignerError(e.to_string()))?;
+    keys.iter()
+        .map(|key| {
+            hex::decode(key).map_err(|e| CKMSError::RemoteSignerError(format!("{key}: {e}")))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Signer for RemoteSigner {
+    type Error = CKMSError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        let message = message.as_ref();
+        let mut preimage = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+        preimage.extend_from_slice(message);
+        let mut signature = self.sign_preimage(&preimage).await?;
+        signature.v += 27;
+        Ok(signature)
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        let chain_id = tx.chain_id().map(|id| id.as_u64()).unwrap_or(self.chain_id);
+        let mut tx = tx.clone();
+        tx.set_chain_id(chain_id);
+        let mut signature = self.sign_preimage(&tx.rlp()).await?;
+        apply_transaction_v(&mut signature, &tx, chain_id)?;
+        Ok(signature)
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        let eip712 = |e: T::Error| CKMSError::Eip712Error(e.to_string());
+        let mut preimage = vec![0x19, 0x01];
+        preimage.extend_from_slice(&payload.domain_separator().map_err(eip712)?);
+        preimage.extend_from_slice(&payload.struct_hash().map_err(eip712)?);
+        self.sign_preimage(&preimage).await
+    }
+
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    fn with_chain_id<T: Into<u64>>(mut self, chain_id: T) -> Self {
+        self.chain_id = chain_id.into();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        signers::LocalWallet,
+        types::{transaction::eip712::TypedData, Eip1559TransactionRequest, TransactionRequest},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    fn wallet() -> LocalWallet {
+        LocalWallet::from_bytes(&[9u8; 32]).unwrap()
+    }
+
+    fn public_key(wallet: &LocalWallet) -> Vec<u8> {
+        wallet
+            .signer()
+            .verifying_key()
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec()
+    }
+
+    /// Answers one HTTP request in the manner of Web3Signer
+    async fn answer(mut stream: TcpStream, wallet: &LocalWallet) {
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        let (head_len, content_length) = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request).to_lowercase();
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length: "))
+                    .map_or(0, |length| length.trim().parse().unwrap());
+                break (end + 4, length);
+            }
+        };
+        while request.len() < head_len + content_length {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+
+        let head = String::from_utf8_lossy(&request[..head_len]).to_string();
+        let body = if head.starts_with("GET /api/v1/eth1/publicKeys") {
+            json!([format!("0x{}", hex::encode(&public_key(wallet)[1..]))]).to_string()
+        } else {
+            let body: serde_json::Value = serde_json::from_slice(&request[head_len..]).unwrap();
+            let data = hex::decode(body["data"].as_str().unwrap()).unwrap();
+            let signature = wallet.sign_hash(H256(keccak256(data))).unwrap();
+            format!("0x{signature}")
+        };
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+    }
+
+    async fn fake_web3signer() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let wallet = wallet();
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                answer(stream, &wallet).await;
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn signs_like_a_kms_signer() {
+        let url = fake_web3signer().await;
+        let wallet = wallet().with_chain_id(5u64);
+        let remote = RemoteSigner::connect(&url, wallet.address(), 5)
+            .await
+            .unwrap();
+        assert_eq!(remote.address(), wallet.address());
+
+        let message = remote.sign_message("hello").await.unwrap();
+        assert_eq!(message, wallet.sign_message("hello").await.unwrap());
+
+        let legacy: TypedTransaction = TransactionRequest::new()
+            .to(Address::repeat_byte(1))
+            .nonce(3)
+            .into();
+        assert_eq!(
+            remote.sign_transaction(&legacy).await.unwrap(),
+            wallet.sign_transaction(&legacy).await.unwrap()
+        );
+
+        let eip1559: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(Address::repeat_byte(1))
+            .nonce(3)
+            .into();
+        let signature = remote.sign_transaction(&eip1559).await.unwrap();
+        assert!(signature.v <= 1);
+        let mut with_chain = eip1559.clone();
+        with_chain.set_chain_id(5);
+        assert_eq!(
+            signature.recover(with_chain.sighash()).unwrap(),
+            wallet.address()
+        );
+
+        let typed: TypedData = serde_json::from_value(json!({
+            "types": {
+                "EIP712Domain": [{"name": "name", "type": "string"}],
+                "Mail": [{"name": "contents", "type": "string"}]
+            },
+            "primaryType": "Mail",
+            "domain": {"name": "Example"},
+            "message": {"contents": "hi"}
+        }))
+        .unwrap();
+        let signature = remote.sign_typed_data(&typed).await.unwrap();
+        assert!(signature.v <= 1);
+        assert_eq!(
+            signature
+                .recover(H256(typed.encode_eip712().unwrap()))
+                .unwrap(),
+            wallet.address()
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_signatures_from_other_keys() {
+        let url = fake_web3signer().await;
+        let other = LocalWallet::from_bytes(&[8u8; 32]).unwrap();
+        let remote = RemoteSigner::new(&url, &public_key(&other), 1).unwrap();
+        assert!(matches!(
+            remote.sign_message("hello").await,
+            Err(CKMSError::RecoveryError)
+        ));
+        assert!(matches!(
+            RemoteSigner::connect(&url, other.address(), 1).await,
+            Err(CKMSError::RemoteSignerError(_))
+        ));
+    }
+
+    #[test]
+    fn derives_the_address_from_the_public_key() {
+        let wallet = wallet();
+        let tagged = public_key(&wallet);
+        let remote = RemoteSigner::new("http://signer/", &tagged, 1).unwrap();
+        assert_eq!(remote.address(), wallet.address());
+        assert_eq!(remote.url, "http://signer");
+        assert_eq!(
+            RemoteSigner::new("http://signer", &tagged[1..], 1)
+                .unwrap()
+                .identifier,
+            remote.identifier
+        );
+        assert!(RemoteSigner::new("http://signer", &tagged[..33], 1).is_err());
+    }
+}
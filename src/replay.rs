@@ -0,0 +1,216 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ethers::types::H256;
+use tracing::warn;
+
+use crate::{store::Store, CKMSError, SigningDenied};
+
+/// What to do when typed data is signed again within the replay window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReplayAction {
+    /// Log a warning and sign anyway
+    Warn,
+    /// Refuse with [`CKMSError::SigningDenied`]
+    Refuse,
+}
+
+/// Configures detection of EIP-712 payloads which are signed more than once,
+/// keyed on the exact `(domain separator, struct hash)` pair
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReplayProtection {
+    /// How long a signed payload is remembered
+    pub window: Duration,
+    pub action: ReplayAction,
+}
+
+impl ReplayProtection {
+    pub fn new(window: Duration, action: ReplayAction) -> Self {
+        Self { window, action }
+    }
+}
+
+/// The outcome of checking a payload against the replay store
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ReplayCheck {
+    Fresh,
+    /// A repeat which is signed anyway, with a warning
+    Repeated,
+}
+
+/// Payloads signed within the window, kept in a [`Store`] as the time they
+/// were signed. Clones of a signer share one guard.
+#[derive(Debug)]
+pub(crate) struct ReplayGuard {
+    config: ReplayProtection,
+    store: Arc<dyn Store>,
+    /// Prefixes store keys, so signers can share a store
+    namespace: String,
+}
+
+impl ReplayGuard {
+    pub(crate) fn new(config: ReplayProtection, store: Arc<dyn Store>, namespace: String) -> Self {
+        Self {
+            config,
+            store,
+            namespace,
+        }
+    }
+
+    fn key(&self, domain_separator: H256, struct_hash: H256) -> String {
+        format!("{}/{domain_separator:?}/{struct_hash:?}", self.namespace)
+    }
+
+    /// Records the payload as signed, or refuses a repeat. The entry is
+    /// recorded before signing so concurrent repeats are caught too; call
+    /// [`ReplayGuard::forget`] if signing then fails.
+    pub(crate) async fn check(
+        &self,
+        domain_separator: H256,
+        struct_hash: H256,
+        now: SystemTime,
+    ) -> Result<ReplayCheck, CKMSError> {
+        let key = self.key(domain_separator, struct_hash);
+        let now_ms = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let signed_at = now_ms.to_be_bytes().to_vec();
+
+        loop {
+            let current = self.store.get(&key).await?;
+            let signed_at_ms = current.as_deref().map(decode_timestamp_ms).transpose()?;
+            // entries from the future count as just signed, so a clock
+            // stepping back does not expire them early
+            let ago = signed_at_ms
+                .map(|at| Duration::from_millis(now_ms.saturating_sub(at)))
+                .filter(|ago| *ago < self.config.window);
+
+            let check = match (ago, self.config.action) {
+                (None, _) => ReplayCheck::Fresh,
+                (Some(ago), ReplayAction::Warn) => {
+                    warn!(
+                        ?domain_separator,
+                        ?struct_hash,
+                        ?ago,
+                        "Signing typed data which was already signed"
+                    );
+                    ReplayCheck::Repeated
+                }
+                (Some(ago), ReplayAction::Refuse) => {
+                    return Err(SigningDenied::new("typed_data_replay")
+                        .with_value("domain_separator", format!("{domain_separator:?}"))
+                        .with_value("struct_hash", format!("{struct_hash:?}"))
+                        .with_value("signed_ms_ago", ago.as_millis())
+                        .with_remediation(
+                            "use sign_typed_data_allowing_replay if the payload is meant to be signed again",
+                        )
+                        .into())
+                }
+            };
+
+            let swapped = self
+                .store
+                .compare_and_swap(
+                    &key,
+                    current.as_deref(),
+                    Some(signed_at.clone()),
+                    Some(self.config.window),
+                )
+                .await?;
+            // otherwise a concurrent check changed the entry; look again
+            if swapped {
+                return Ok(check);
+            }
+        }
+    }
+
+    /// Removes a payload recorded by a check whose signing failed
+    pub(crate) async fn forget(&self, domain_separator: H256, struct_hash: H256) {
+        let key = self.key(domain_separator, struct_hash);
+        if let Err(e) = self.store.delete(&key).await {
+            warn!(
+                ?domain_separator,
+                ?struct_hash,
+                "Failed to forget typed data payload: {}",
+                e
+            );
+        }
+    }
+}
+
+fn decode_timestamp_ms(value: &[u8]) -> Result<u64, CKMSError> {
+    value
+        .try_into()
+        .map(u64::from_be_bytes)
+        .map_err(|_| CKMSError::StoreError(format!("expected a timestamp, got {value:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Clock, ManualClock, MemoryStore};
+
+    fn guard(action: ReplayAction, clock: &Arc<ManualClock>) -> ReplayGuard {
+        ReplayGuard::new(
+            ReplayProtection::new(Duration::from_secs(60), action),
+            Arc::new(MemoryStore::new().with_clock(clock.clone())),
+            "test".to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn refuses_repeats_within_window() {
+        let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+        let guard = guard(ReplayAction::Refuse, &clock);
+        let (domain, a, b) = (
+            H256::repeat_byte(1),
+            H256::repeat_byte(2),
+            H256::repeat_byte(3),
+        );
+
+        assert_eq!(
+            guard.check(domain, a, clock.now()).await.unwrap(),
+            ReplayCheck::Fresh
+        );
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(
+            guard.check(domain, b, clock.now()).await.unwrap(),
+            ReplayCheck::Fresh
+        );
+        let denied = guard.check(domain, a, clock.now()).await.unwrap_err();
+        assert!(matches!(denied, CKMSError::SigningDenied(d) if d.rule == "typed_data_replay"));
+
+        guard.forget(domain, b).await;
+        assert_eq!(
+            guard.check(domain, b, clock.now()).await.unwrap(),
+            ReplayCheck::Fresh
+        );
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(
+            guard.check(domain, a, clock.now()).await.unwrap(),
+            ReplayCheck::Fresh
+        );
+        assert!(guard.check(domain, b, clock.now()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn warns_on_repeats() {
+        let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+        let guard = guard(ReplayAction::Warn, &clock);
+        let (domain, hash) = (H256::repeat_byte(1), H256::repeat_byte(2));
+        assert_eq!(
+            guard.check(domain, hash, clock.now()).await.unwrap(),
+            ReplayCheck::Fresh
+        );
+        assert_eq!(
+            guard.check(domain, hash, clock.now()).await.unwrap(),
+            ReplayCheck::Repeated
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+
+use ethers::types::Address;
+use gcloud_sdk::google::cloud::kms::v1::{
+    crypto_key_version::{CryptoKeyVersionAlgorithm, CryptoKeyVersionState},
+    ProtectionLevel,
+};
+
+use crate::{verifying_key_to_address, CKMSError, CredentialSource, GcpKmsSigner};
+
+/// A check performed while assembling a [`SignerReport`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Validation {
+    pub name: &'static str,
+    pub passed: bool,
+}
+
+/// A summary of a signer's identity and the checks it passed, intended to be
+/// logged when a service starts
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct SignerReport {
+    /// Full resource name of the crypto key version
+    pub key_name: String,
+    pub key_version: u64,
+    /// KMS algorithm name, e.g. `EC_SIGN_SECP256K1_SHA256`
+    pub algorithm: String,
+    /// KMS protection level name, e.g. `HSM`
+    pub protection_level: String,
+    /// KMS key version state name, e.g. `ENABLED`
+    pub state: String,
+    pub address: Address,
+    pub chain_id: u64,
+    /// `None` for a provider over a caller's client
+    pub credential_source: Option<CredentialSource>,
+    /// The account the credentials belong to, when it can be determined
+    pub principal: Option<String>,
+    /// `None` for a provider over a caller's client
+    pub endpoint: Option<String>,
+    pub validations: Vec<Validation>,
+}
+
+impl SignerReport {
+    /// Returns true if every validation passed
+    pub fn is_healthy(&self) -> bool {
+        self.validations.iter().all(|validation| validation.passed)
+    }
+}
+
+impl fmt::Display for SignerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "key:              {}", self.key_name)?;
+        writeln!(f, "algorithm:        {}", self.algorithm)?;
+        writeln!(f, "protection level: {}", self.protection_level)?;
+        writeln!(f, "state:            {}", self.state)?;
+        writeln!(f, "address:          {:?}", self.address)?;
+        writeln!(f, "chain id:         {}", self.chain_id)?;
+        if let Some(credential_source) = &self.credential_source {
+            writeln!(f, "credentials:      {credential_source:?}")?;
+        }
+        if let Some(principal) = &self.principal {
+            writeln!(f, "principal:        {principal}")?;
+        }
+        if let Some(endpoint) = &self.endpoint {
+            writeln!(f, "endpoint:         {endpoint}")?;
+        }
+        for validation in &self.validations {
+            let status = if validation.passed { "ok" } else { "FAILED" };
+            writeln!(f, "check {}: {status}", validation.name)?;
+        }
+        Ok(())
+    }
+}
+
+impl GcpKmsSigner {
+    /// Assembles a [`SignerReport`] for this signer, fetching the key version
+    /// metadata and public key from KMS. Fails with
+    /// [`CKMSError::UnsupportedByBackend`] for a signer of another backend.
+    pub async fn report(&self) -> Result<SignerReport, CKMSError> {
+        let Some(provider) = &self.provider else {
+            return Err(CKMSError::UnsupportedByBackend("report".to_string()));
+        };
+        let snapshot = self.snapshot();
+        let key_version = provider
+            .get_crypto_key_version(&self.key_id, snapshot.key_version)
+            .await?;
+        let public_key = provider
+            .get_verifying_key(&self.key_id, snapshot.key_version)
+            .await?;
+        let verifying_key = self.resolve_snapshot(&snapshot).await?;
+
+        let algorithm = CryptoKeyVersionAlgorithm::from_i32(key_version.algorithm)
+            .unwrap_or(CryptoKeyVersionAlgorithm::Unspecified);
+        let state = CryptoKeyVersionState::from_i32(key_version.state)
+            .unwrap_or(CryptoKeyVersionState::Unspecified);
+        let protection_level = ProtectionLevel::from_i32(key_version.protection_level)
+            .unwrap_or(ProtectionLevel::Unspecified);
+
+        let validations = vec![
+            Validation {
+                name: "algorithm_is_secp256k1",
+                passed: algorithm == CryptoKeyVersionAlgorithm::EcSignSecp256k1Sha256,
+            },
+            Validation {
+                name: "key_version_enabled",
+                passed: state == CryptoKeyVersionState::Enabled,
+            },
+            Validation {
+                name: "public_key_matches",
+                passed: public_key == verifying_key,
+            },
+        ];
+
+        Ok(SignerReport {
+            key_name: key_version.name,
+            key_version: snapshot.key_version,
+            algorithm: algorithm.as_str_name().to_string(),
+            protection_level: protection_level.as_str_name().to_string(),
+            state: state.as_str_name().to_string(),
+            address: verifying_key_to_address(&verifying_key),
+            chain_id: snapshot.chain_id,
+            credential_source: provider.credential_source().cloned(),
+            principal: match provider.credential_source() {
+                Some(credential_source) => credential_source.principal().await,
+                None => None,
+            },
+            endpoint: provider.endpoint().map(ToString::to_string),
+            validations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use ethers::prelude::k256::ecdsa::{
+        signature::hazmat::PrehashSigner, SigningKey, VerifyingKey,
+    };
+
+    use super::*;
+    use crate::{KmsKeyBackend, SigningContext};
+
+    #[derive(Debug)]
+    struct LocalBackend(SigningKey);
+
+    #[async_trait]
+    impl KmsKeyBackend for LocalBackend {
+        async fn get_public_key(&self, _: &str, _: u64) -> Result<VerifyingKey, CKMSError> {
+            Ok(*self.0.verifying_key())
+        }
+
+        async fn sign_digest(
+            &self,
+            _: &str,
+            key_version: u64,
+            digest: [u8; 32],
+            _: &SigningContext,
+        ) -> Result<(Vec<u8>, u64), CKMSError> {
+            let signature: ethers::prelude::k256::ecdsa::Signature =
+                self.0.sign_prehash(&digest)?;
+            Ok((signature.to_der().as_bytes().to_vec(), key_version))
+        }
+    }
+
+    fn report() -> SignerReport {
+        SignerReport {
+            key_name: "projects/p/locations/global/keyRings/r/cryptoKeys/k/cryptoKeyVersions/2"
+                .to_string(),
+            key_version: 2,
+            algorithm: "EC_SIGN_SECP256K1_SHA256".to_string(),
+            protection_level: "HSM".to_string(),
+            state: "ENABLED".to_string(),
+            address: Address::repeat_byte(0xab),
+            chain_id: 5,
+            credential_source: Some(CredentialSource::MetadataServer),
+            principal: Some("signer@p.iam.gserviceaccount.com".to_string()),
+            endpoint: Some("https://cloudkms.googleapis.com".to_string()),
+            validations: vec![
+                Validation {
+                    name: "algorithm_is_secp256k1",
+                    passed: true,
+                },
+                Validation {
+                    name: "key_version_enabled",
+                    passed: true,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn displays_every_field() {
+        assert_eq!(
+            report().to_string(),
+            "key:              projects/p/locations/global/keyRings/r/cryptoKeys/k/cryptoKeyVersions/2\n\
+             algorithm:        EC_SIGN_SECP256K1_SHA256\n\
+             protection level: HSM\n\
+             state:            ENABLED\n\
+             address:          0xabababababababababababababababababababab\n\
+             chain id:         5\n\
+             credentials:      MetadataServer\n\
+             principal:        signer@p.iam.gserviceaccount.com\n\
+             endpoint:         https://cloudkms.googleapis.com\n\
+             check algorithm_is_secp256k1: ok\n\
+             check key_version_enabled: ok\n"
+        );
+    }
+
+    #[test]
+    fn omits_what_a_caller_client_cannot_tell() {
+        let report = SignerReport {
+            credential_source: None,
+            principal: None,
+            endpoint: None,
+            ..report()
+        };
+        let display = report.to_string();
+        assert!(display.ends_with("chain id:         5\ncheck algorithm_is_secp256k1: ok\ncheck key_version_enabled: ok\n"));
+        assert!(!display.contains("credentials:"));
+    }
+
+    #[test]
+    fn failed_validations_are_unhealthy() {
+        let mut report = report();
+        assert!(report.is_healthy());
+
+        report.validations.push(Validation {
+            name: "public_key_matches",
+            passed: false,
+        });
+        assert!(!report.is_healthy());
+        assert!(report
+            .to_string()
+            .ends_with("check public_key_matches: FAILED\n"));
+    }
+
+    #[tokio::test]
+    async fn needs_a_kms_provider() {
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let signer = GcpKmsSigner::new(Arc::new(LocalBackend(key)), "local".to_string(), 1, 5)
+            .await
+            .unwrap();
+        assert!(matches!(
+            signer.report().await,
+            Err(CKMSError::UnsupportedByBackend(operation)) if operation == "report"
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+//! RustCrypto's async signer traits, for libraries generic over a secp256k1
+//! signer. As with k256's `SigningKey`, messages are hashed with SHA-256;
+//! [`GcpKmsSigner::sign_digest`] signs other 32-byte prehashes. Signatures
+//! follow the signer's high-s policy, so only
+//! [`HighSPolicy::Normalize`](crate::HighSPolicy::Normalize) guarantees the
+//! low-s signatures k256 verifies.
+
+use async_signature::{digest::Digest, AsyncDigestSigner, AsyncSigner, Error};
+use ethers::prelude::k256::{
+    ecdsa::{RecoveryId, Signature as KSig},
+    sha2::Sha256,
+};
+
+use crate::GcpKmsSigner;
+
+impl AsyncDigestSigner<Sha256, KSig> for GcpKmsSigner {
+    async fn sign_digest_async(&self, digest: Sha256) -> Result<KSig, Error> {
+        self.sign_digest(digest.finalize().into())
+            .await
+            .map_err(Error::from_source)
+    }
+}
+
+impl AsyncDigestSigner<Sha256, (KSig, RecoveryId)> for GcpKmsSigner {
+    async fn sign_digest_async(&self, digest: Sha256) -> Result<(KSig, RecoveryId), Error> {
+        self.sign_digest_recoverable(digest.finalize().into())
+            .await
+            .map_err(Error::from_source)
+    }
+}
+
+impl AsyncSigner<KSig> for GcpKmsSigner {
+    async fn sign_async(&self, msg: &[u8]) -> Result<KSig, Error> {
+        self.sign_digest(Sha256::digest(msg).into())
+            .await
+            .map_err(Error::from_source)
+    }
+}
+
+impl AsyncSigner<(KSig, RecoveryId)> for GcpKmsSigner {
+    async fn sign_async(&self, msg: &[u8]) -> Result<(KSig, RecoveryId), Error> {
+        self.sign_digest_recoverable(Sha256::digest(msg).into())
+            .await
+            .map_err(Error::from_source)
+    }
+}
+
+#[cfg(all(test, feature = "test-utils"))]
+mod tests {
+    use ethers::prelude::k256::ecdsa::{
+        signature::{hazmat::PrehashVerifier, Verifier},
+        VerifyingKey,
+    };
+
+    use super::*;
+    use crate::{
+        test_utils::{MockKmsProvider, MockKmsSigner},
+        GcpKeyRingRef, HighSPolicy,
+    };
+
+    async fn signer() -> (GcpKmsSigner, VerifyingKey) {
+        let provider = MockKmsProvider::new(GcpKeyRingRef::new("project", "global", "ring"))
+            .await
+            .unwrap();
+        let verifying_key = *provider.signing_key("key", 1).verifying_key();
+        let signer = MockKmsSigner::new(provider, "key".to_string(), 1, 5)
+            .await
+            .unwrap()
+            .with_high_s_policy(HighSPolicy::Normalize);
+        (signer, verifying_key)
+    }
+
+    #[tokio::test]
+    async fn signatures_verify_with_k256() {
+        let (signer, verifying_key) = signer().await;
+        for message in [&b"hello"[..], b"", &[0xff; 100]] {
+            let signature = AsyncSigner::<KSig>::sign_async(&signer, message)
+                .await
+                .unwrap();
+            verifying_key.verify(message, &signature).unwrap();
+
+            let digest = Sha256::new_with_prefix(message);
+            let signature = AsyncDigestSigner::<_, KSig>::sign_digest_async(&signer, digest)
+                .await
+                .unwrap();
+            verifying_key
+                .verify_prehash(&Sha256::digest(message), &signature)
+                .unwrap();
+        }
+        let signature = AsyncSigner::<KSig>::sign_async(&signer, b"hello")
+            .await
+            .unwrap();
+        assert!(verifying_key.verify(b"goodbye", &signature).is_err());
+    }
+
+    #[tokio::test]
+    async fn recovery_ids_recover_the_key() {
+        let (signer, verifying_key) = signer().await;
+        let (signature, recovery_id) =
+            AsyncSigner::<(KSig, RecoveryId)>::sign_async(&signer, b"hello")
+                .await
+                .unwrap();
+        let recovered = VerifyingKey::recover_from_msg(b"hello", &signature, recovery_id).unwrap();
+        assert_eq!(recovered, verifying_key);
+
+        let (signature, recovery_id) =
+            AsyncDigestSigner::<_, (KSig, RecoveryId)>::sign_digest_async(
+                &signer,
+                Sha256::new_with_prefix(b"hello"),
+            )
+            .await
+            .unwrap();
+        let recovered = VerifyingKey::recover_from_digest(
+            Sha256::new_with_prefix(b"hello"),
+            &signature,
+            recovery_id,
+        )
+        .unwrap();
+        assert_eq!(recovered, verifying_key);
+    }
+}
@@ -0,0 +1,255 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use ethers::types::H256;
+
+use crate::{audit::AuditOperation, SigningDenied};
+
+/// Restrictions for a signer derived with
+/// [`GcpKmsSigner::scoped`](crate::GcpKmsSigner::scoped). Unset fields
+/// leave that aspect unrestricted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Scope {
+    /// Payloads which are not bound to a chain, such as messages, raw
+    /// digests and unprotected legacy transactions, are refused under a
+    /// chain restriction
+    pub chain_ids: Option<Vec<u64>>,
+    pub operations: Option<Vec<AuditOperation>>,
+    /// EIP-712 domain separators typed data may be signed for. Does not
+    /// restrict other operations.
+    pub typed_data_domains: Option<Vec<H256>>,
+    /// Successful signatures the scoped signer and its clones may make
+    pub max_signatures: Option<u64>,
+}
+
+impl Scope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_chain_ids(mut self, chain_ids: impl IntoIterator<Item = u64>) -> Self {
+        self.chain_ids = Some(chain_ids.into_iter().collect());
+        self
+    }
+
+    pub fn with_operations(mut self, operations: impl IntoIterator<Item = AuditOperation>) -> Self {
+        self.operations = Some(operations.into_iter().collect());
+        self
+    }
+
+    pub fn with_typed_data_domains(mut self, domains: impl IntoIterator<Item = H256>) -> Self {
+        self.typed_data_domains = Some(domains.into_iter().collect());
+        self
+    }
+
+    pub fn with_max_signatures(mut self, max_signatures: u64) -> Self {
+        self.max_signatures = Some(max_signatures);
+        self
+    }
+}
+
+/// What a signing request would sign, as evaluated against scopes
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ScopeRequest {
+    pub(crate) operation: AuditOperation,
+    pub(crate) chain_id: Option<u64>,
+    pub(crate) domain_separator: Option<H256>,
+}
+
+impl ScopeRequest {
+    pub(crate) fn new(operation: AuditOperation, chain_id: Option<u64>) -> Self {
+        Self {
+            operation,
+            chain_id,
+            domain_separator: None,
+        }
+    }
+}
+
+/// A scope and the signatures made under it, shared by clones of the scoped
+/// signer
+#[derive(Debug)]
+pub(crate) struct ScopeGuard {
+    scope: Scope,
+    used: AtomicU64,
+}
+
+impl ScopeGuard {
+    pub(crate) fn new(scope: Scope) -> Self {
+        Self {
+            scope,
+            used: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn scope(&self) -> &Scope {
+        &self.scope
+    }
+
+    pub(crate) fn remaining(&self) -> Option<u64> {
+        self.scope
+            .max_signatures
+            .map(|max| max.saturating_sub(self.used.load(Ordering::Relaxed)))
+    }
+
+    fn check(&self, request: &ScopeRequest) -> Result<(), SigningDenied> {
+        let scope = &self.scope;
+        if let Some(operations) = &scope.operations {
+            if !operations.contains(&request.operation) {
+                let allowed: Vec<_> = operations.iter().map(|op| format!("{op:?}")).collect();
+                return Err(SigningDenied::new("scope_operation")
+                    .with_value("operation", format!("{:?}", request.operation))
+                    .with_value("allowed", allowed.join("|")));
+            }
+        }
+        if let Some(chain_ids) = &scope.chain_ids {
+            if !request.chain_id.is_some_and(|id| chain_ids.contains(&id)) {
+                let allowed: Vec<_> = chain_ids.iter().map(ToString::to_string).collect();
+                return Err(SigningDenied::new("scope_chain_id")
+                    .with_value(
+                        "chain_id",
+                        request
+                            .chain_id
+                            .map_or("none".to_string(), |id| id.to_string()),
+                    )
+                    .with_value("allowed", allowed.join("|")));
+            }
+        }
+        if let (Some(domains), AuditOperation::TypedData) =
+            (&scope.typed_data_domains, request.operation)
+        {
+            if !request
+                .domain_separator
+                .is_some_and(|domain| domains.contains(&domain))
+            {
+                return Err(SigningDenied::new("scope_typed_data_domain").with_value(
+                    "domain_separator",
+                    format!("{:?}", request.domain_separator.unwrap_or_default()),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn reserve(&self) -> Result<(), SigningDenied> {
+        let Some(max) = self.scope.max_signatures else {
+            return Ok(());
+        };
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (used < max).then_some(used + 1)
+            })
+            .map(|_| ())
+            .map_err(|_| SigningDenied::new("scope_max_signatures").with_value("max", max))
+    }
+
+    fn refund(&self) {
+        if self.scope.max_signatures.is_some() {
+            self.used.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Signatures reserved against every scope of a signer, refunded on drop
+/// unless committed
+pub(crate) struct Reservation<'a> {
+    reserved: Vec<&'a ScopeGuard>,
+}
+
+impl Reservation<'_> {
+    pub(crate) fn commit(mut self) {
+        self.reserved.clear();
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        for guard in &self.reserved {
+            guard.refund();
+        }
+    }
+}
+
+/// Checks a request against every scope, reserving a signature in each
+pub(crate) fn reserve<'a>(
+    scopes: &'a [Arc<ScopeGuard>],
+    request: &ScopeRequest,
+) -> Result<Reservation<'a>, SigningDenied> {
+    for guard in scopes {
+        guard.check(request)?;
+    }
+    let mut reservation = Reservation {
+        reserved: Vec::with_capacity(scopes.len()),
+    };
+    for guard in scopes {
+        guard.reserve()?;
+        reservation.reserved.push(guard);
+    }
+    Ok(reservation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enforces_chain_and_operation() {
+        let scopes = [Arc::new(ScopeGuard::new(
+            Scope::new()
+                .with_chain_ids([8453])
+                .with_operations([AuditOperation::Transaction]),
+        ))];
+
+        let allowed = ScopeRequest::new(AuditOperation::Transaction, Some(8453));
+        assert!(reserve(&scopes, &allowed).is_ok());
+
+        let other_chain = ScopeRequest::new(AuditOperation::Transaction, Some(1));
+        let denied = reserve(&scopes, &other_chain).err().unwrap();
+        assert_eq!(denied.rule, "scope_chain_id");
+
+        let unbound = ScopeRequest::new(AuditOperation::Transaction, None);
+        assert!(reserve(&scopes, &unbound).is_err());
+
+        let message = ScopeRequest::new(AuditOperation::Message, Some(8453));
+        let denied = reserve(&scopes, &message).err().unwrap();
+        assert_eq!(denied.rule, "scope_operation");
+    }
+
+    #[test]
+    fn restricts_typed_data_domains() {
+        let domain = H256::repeat_byte(1);
+        let scopes = [Arc::new(ScopeGuard::new(
+            Scope::new().with_typed_data_domains([domain]),
+        ))];
+
+        let mut request = ScopeRequest::new(AuditOperation::TypedData, None);
+        request.domain_separator = Some(domain);
+        assert!(reserve(&scopes, &request).is_ok());
+
+        request.domain_separator = Some(H256::repeat_byte(2));
+        let denied = reserve(&scopes, &request).err().unwrap();
+        assert_eq!(denied.rule, "scope_typed_data_domain");
+    }
+
+    #[test]
+    fn budget_counts_committed_signatures() {
+        let parent = Arc::new(ScopeGuard::new(Scope::new().with_max_signatures(2)));
+        let child = Arc::new(ScopeGuard::new(Scope::new().with_max_signatures(5)));
+        let scopes = [child.clone(), parent.clone()];
+        let request = ScopeRequest::new(AuditOperation::Digest, None);
+
+        // a failed signature is refunded
+        drop(reserve(&scopes, &request).unwrap());
+        assert_eq!(parent.remaining(), Some(2));
+
+        reserve(&scopes, &request).unwrap().commit();
+        reserve(&scopes, &request).unwrap().commit();
+        let denied = reserve(&scopes, &request).err().unwrap();
+        assert_eq!(denied.rule, "scope_max_signatures");
+        // the child's reservation is refunded when the parent refuses
+        assert_eq!(child.remaining(), Some(3));
+    }
+}
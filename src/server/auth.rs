@@ -0,0 +1,414 @@
+//! Authenticating a [`SignerServer`]'s callers, and the keys each may use
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::{header::AUTHORIZATION, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ethers::{
+    prelude::k256::sha2::{Digest, Sha256},
+    types::{Address, H256},
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use tracing::debug;
+
+use super::SignerServer;
+use crate::CKMSError;
+
+/// An authenticated caller, and the keys it may sign with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Principal {
+    pub name: String,
+    /// `None` allows all of the server's keys
+    keys: Option<Vec<Address>>,
+}
+
+impl Principal {
+    /// A caller which may use all of the server's keys
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            keys: None,
+        }
+    }
+
+    /// Limits the caller to the keys of `addresses`
+    pub fn with_keys(mut self, addresses: impl IntoIterator<Item = Address>) -> Self {
+        self.keys.get_or_insert_with(Vec::new).extend(addresses);
+        self
+    }
+
+    pub fn allows(&self, address: Address) -> bool {
+        self.keys
+            .as_ref()
+            .is_none_or(|keys| keys.contains(&address))
+    }
+
+    /// The caller of a server without authenticators
+    pub(crate) fn anonymous() -> Self {
+        Self::new("anonymous")
+    }
+}
+
+/// The DER certificate a client presented over mutual TLS, which the
+/// server's TLS listener attaches to each request
+#[derive(Clone, Debug)]
+pub(crate) struct ClientCertificate(pub(crate) Vec<u8>);
+
+/// The credentials a request carries
+#[derive(Clone, Copy, Debug)]
+pub struct Credentials<'a> {
+    headers: &'a HeaderMap,
+    client_certificate: Option<&'a [u8]>,
+}
+
+impl<'a> Credentials<'a> {
+    pub(crate) fn new(headers: &'a HeaderMap, client_certificate: Option<&'a [u8]>) -> Self {
+        Self {
+            headers,
+            client_certificate,
+        }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+
+    /// The token of an `authorization: Bearer …` header
+    pub fn bearer_token(&self) -> Option<&'a str> {
+        let value = self.headers.get(AUTHORIZATION)?.to_str().ok()?;
+        let (scheme, token) = value.split_once(' ')?;
+        scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+    }
+
+    /// The DER certificate the client presented over mutual TLS
+    pub fn client_certificate(&self) -> Option<&'a [u8]> {
+        self.client_certificate
+    }
+}
+
+/// Identifies the callers of a [`SignerServer`].
+///
+/// A server with authenticators serves a request only if one of them
+/// identifies its caller, and only with the keys the caller is allowed.
+pub trait Authenticator: fmt::Debug + Send + Sync + 'static {
+    /// The caller whose credentials these are, or `None` if they include
+    /// none this authenticator accepts
+    fn authenticate(&self, credentials: &Credentials<'_>) -> Option<Principal>;
+}
+
+fn sha256(bytes: &[u8]) -> H256 {
+    H256(Sha256::digest(bytes).into())
+}
+
+/// Static API keys, sent as `authorization: Bearer …` or `x-api-key: …`.
+/// Only the keys' hashes are kept.
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeys {
+    principals: HashMap<H256, Principal>,
+}
+
+impl ApiKeys {
+    pub fn with_key(mut self, api_key: &str, principal: Principal) -> Self {
+        self.principals
+            .insert(sha256(api_key.as_bytes()), principal);
+        self
+    }
+}
+
+impl Authenticator for ApiKeys {
+    fn authenticate(&self, credentials: &Credentials<'_>) -> Option<Principal> {
+        [credentials.bearer_token(), credentials.header("x-api-key")]
+            .into_iter()
+            .flatten()
+            .find_map(|api_key| self.principals.get(&sha256(api_key.as_bytes())))
+            .cloned()
+    }
+}
+
+/// The claims of a signer server's JWTs. `keys`, if present, limits the
+/// caller to those keys' addresses.
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+    #[serde(default)]
+    keys: Option<Vec<Address>>,
+}
+
+/// Bearer JWTs, whose `sub` names the caller and whose `keys` claim, if
+/// present, lists the addresses it may sign with. `exp` is required.
+#[derive(Clone)]
+pub struct JwtAuthenticator {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+impl fmt::Debug for JwtAuthenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtAuthenticator")
+            .field("validation", &self.validation)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JwtAuthenticator {
+    fn new(key: DecodingKey, algorithm: Algorithm) -> Self {
+        Self {
+            key,
+            validation: Validation::new(algorithm),
+        }
+    }
+
+    /// Accepts HS256 tokens signed with `secret`
+    pub fn hs256(secret: &[u8]) -> Self {
+        Self::new(DecodingKey::from_secret(secret), Algorithm::HS256)
+    }
+
+    /// Accepts RS256 tokens signed by the RSA key whose public key is `pem`
+    pub fn rs256(pem: &[u8]) -> Result<Self, CKMSError> {
+        let key =
+            DecodingKey::from_rsa_pem(pem).map_err(|e| CKMSError::AuthError(e.to_string()))?;
+        Ok(Self::new(key, Algorithm::RS256))
+    }
+
+    /// Accepts ES256 tokens signed by the P-256 key whose public key is
+    /// `pem`
+    pub fn es256(pem: &[u8]) -> Result<Self, CKMSError> {
+        let key = DecodingKey::from_ec_pem(pem).map_err(|e| CKMSError::AuthError(e.to_string()))?;
+        Ok(Self::new(key, Algorithm::ES256))
+    }
+
+    /// Requires tokens' `iss` to be `issuer`
+    pub fn with_issuer(mut self, issuer: &str) -> Self {
+        self.validation.set_issuer(&[issuer]);
+        self
+    }
+
+    /// Requires tokens' `aud` to include `audience`
+    pub fn with_audience(mut self, audience: &str) -> Self {
+        self.validation.set_audience(&[audience]);
+        self
+    }
+}
+
+impl Authenticator for JwtAuthenticator {
+    fn authenticate(&self, credentials: &Credentials<'_>) -> Option<Principal> {
+        let token = credentials.bearer_token()?;
+        match jsonwebtoken::decode::<Claims>(token, &self.key, &self.validation) {
+            Ok(token) => {
+                let principal = Principal::new(token.claims.sub);
+                Some(match token.claims.keys {
+                    Some(keys) => principal.with_keys(keys),
+                    None => principal,
+                })
+            }
+            Err(e) => {
+                debug!("Rejected a JWT: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// Mutual TLS client certificates, by their SHA-256 fingerprints. The
+/// server's TLS must verify them against a client CA.
+#[derive(Clone, Debug, Default)]
+pub struct ClientCertificates {
+    principals: HashMap<H256, Principal>,
+}
+
+impl ClientCertificates {
+    /// Identifies the certificate whose DER encoding is `certificate`
+    pub fn with_certificate(self, certificate: &[u8], principal: Principal) -> Self {
+        self.with_fingerprint(sha256(certificate), principal)
+    }
+
+    /// Identifies the certificate whose SHA-256 fingerprint is `fingerprint`
+    pub fn with_fingerprint(mut self, fingerprint: H256, principal: Principal) -> Self {
+        self.principals.insert(fingerprint, principal);
+        self
+    }
+}
+
+impl Authenticator for ClientCertificates {
+    fn authenticate(&self, credentials: &Credentials<'_>) -> Option<Principal> {
+        let certificate = credentials.client_certificate()?;
+        self.principals.get(&sha256(certificate)).cloned()
+    }
+}
+
+/// A server's authenticators, of which any may identify a caller
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Authenticators(Vec<Arc<dyn Authenticator>>);
+
+impl Authenticators {
+    /// The caller, who is anonymous and allowed every key when there are no
+    /// authenticators
+    pub(crate) fn authenticate(&self, credentials: &Credentials<'_>) -> Option<Principal> {
+        if self.0.is_empty() {
+            return Some(Principal::anonymous());
+        }
+        self.0
+            .iter()
+            .find_map(|authenticator| authenticator.authenticate(credentials))
+    }
+}
+
+impl SignerServer {
+    /// Requires callers to be identified by `authenticator`, or by any other
+    /// added this way
+    pub fn with_authenticator(mut self, authenticator: impl Authenticator) -> Self {
+        self.authenticators.0.push(Arc::new(authenticator));
+        self
+    }
+}
+
+/// Identifies a request's caller for its handler, or refuses it
+pub(crate) async fn authenticate<B>(
+    State(authenticators): State<Authenticators>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let certificate = request.extensions().get::<ClientCertificate>();
+    let credentials = Credentials::new(
+        request.headers(),
+        certificate.map(|certificate| certificate.0.as_slice()),
+    );
+    match authenticators.authenticate(&credentials) {
+        Some(principal) => {
+            request.extensions_mut().insert(principal);
+            next.run(request).await
+        }
+        None => (StatusCode::UNAUTHORIZED, "unauthenticated").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn api_keys_by_either_header() {
+        let bot = Principal::new("bot").with_keys([Address::repeat_byte(1)]);
+        let api_keys = ApiKeys::default().with_key("s3cret", bot.clone());
+
+        let bearer = headers(&[("authorization", "Bearer s3cret")]);
+        assert_eq!(
+            api_keys.authenticate(&Credentials::new(&bearer, None)),
+            Some(bot.clone())
+        );
+        let header = headers(&[("x-api-key", "s3cret")]);
+        assert_eq!(
+            api_keys.authenticate(&Credentials::new(&header, None)),
+            Some(bot)
+        );
+        let wrong = headers(&[("authorization", "Bearer guess")]);
+        assert_eq!(api_keys.authenticate(&Credentials::new(&wrong, None)), None);
+
+        let bot = api_keys
+            .authenticate(&Credentials::new(&bearer, None))
+            .unwrap();
+        assert!(bot.allows(Address::repeat_byte(1)));
+        assert!(!bot.allows(Address::repeat_byte(2)));
+    }
+
+    #[test]
+    fn jwts_carry_the_caller_and_its_keys() {
+        let jwt = JwtAuthenticator::hs256(b"shared").with_issuer("issuer");
+        let token = |claims: serde_json::Value, secret: &[u8]| {
+            let token = encode(
+                &Header::default(),
+                &claims,
+                &EncodingKey::from_secret(secret),
+            )
+            .unwrap();
+            headers(&[("authorization", &format!("bearer {token}"))])
+        };
+        let exp = 4_000_000_000u64;
+
+        let limited = token(
+            json!({"sub": "relayer", "iss": "issuer", "exp": exp, "keys": [Address::repeat_byte(1)]}),
+            b"shared",
+        );
+        let principal = jwt.authenticate(&Credentials::new(&limited, None)).unwrap();
+        assert_eq!(principal.name, "relayer");
+        assert!(principal.allows(Address::repeat_byte(1)));
+        assert!(!principal.allows(Address::repeat_byte(2)));
+
+        let unlimited = token(
+            json!({"sub": "ops", "iss": "issuer", "exp": exp}),
+            b"shared",
+        );
+        let principal = jwt
+            .authenticate(&Credentials::new(&unlimited, None))
+            .unwrap();
+        assert!(principal.allows(Address::repeat_byte(2)));
+
+        for rejected in [
+            token(json!({"sub": "ops", "iss": "issuer", "exp": exp}), b"other"),
+            token(
+                json!({"sub": "ops", "iss": "elsewhere", "exp": exp}),
+                b"shared",
+            ),
+            token(json!({"sub": "ops", "iss": "issuer", "exp": 1}), b"shared"),
+        ] {
+            assert_eq!(jwt.authenticate(&Credentials::new(&rejected, None)), None);
+        }
+    }
+
+    #[test]
+    fn client_certificates_by_fingerprint() {
+        let certificates =
+            ClientCertificates::default().with_certificate(b"der", Principal::new("service"));
+        let none = HeaderMap::new();
+        assert_eq!(
+            certificates
+                .authenticate(&Credentials::new(&none, Some(b"der")))
+                .unwrap()
+                .name,
+            "service"
+        );
+        assert_eq!(
+            certificates.authenticate(&Credentials::new(&none, Some(b"other"))),
+            None
+        );
+        assert_eq!(
+            certificates.authenticate(&Credentials::new(&none, None)),
+            None
+        );
+    }
+
+    #[test]
+    fn any_authenticator_may_identify_the_caller() {
+        let none = HeaderMap::new();
+        let open = Authenticators::default();
+        assert_eq!(
+            open.authenticate(&Credentials::new(&none, None)),
+            Some(Principal::anonymous())
+        );
+
+        let mut closed = Authenticators::default();
+        closed.0.push(Arc::new(ApiKeys::default()));
+        closed.0.push(Arc::new(
+            ClientCertificates::default().with_certificate(b"der", Principal::new("service")),
+        ));
+        assert_eq!(closed.authenticate(&Credentials::new(&none, None)), None);
+        assert!(closed
+            .authenticate(&Credentials::new(&none, Some(b"der")))
+            .is_some());
+    }
+}
@@ -0,0 +1,564 @@
+//! A gRPC signing service and its client, for services which are gRPC
+//! native. `signer.proto` defines the service for clients in other
+//! languages.
+use std::{convert::Infallible, future::Future, net::SocketAddr, sync::Arc};
+
+use ethers::{
+    signers::Signer,
+    types::{transaction::eip2718::TypedTransaction, Address, Bytes, Signature, H256},
+};
+use tonic::{
+    body::BoxBody,
+    client::GrpcService,
+    codec::ProstCodec,
+    codegen::{http, Body, BoxFuture, Context, Poll, Service, StdError},
+    server::{Grpc, NamedService, UnaryService},
+    transport::{Channel, Endpoint},
+    Request, Response, Status,
+};
+
+use super::{
+    auth::{Authenticators, ClientCertificate, Credentials},
+    Key, Keys, Principal, SignerServer,
+};
+use crate::{parse_transaction, CKMSError};
+
+const SERVICE: &str = "ethers_gcp_kms_signer.v1.Signer";
+const LIST_ACCOUNTS: &str = "/ethers_gcp_kms_signer.v1.Signer/ListAccounts";
+const SIGN_DIGEST: &str = "/ethers_gcp_kms_signer.v1.Signer/SignDigest";
+const SIGN_TRANSACTION: &str = "/ethers_gcp_kms_signer.v1.Signer/SignTransaction";
+const SIGN_TYPED_DATA: &str = "/ethers_gcp_kms_signer.v1.Signer/SignTypedData";
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ListAccountsRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct AccountMessage {
+    #[prost(bytes = "vec", tag = "1")]
+    address: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    public_key: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ListAccountsResponse {
+    #[prost(message, repeated, tag = "1")]
+    accounts: Vec<AccountMessage>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct SignDigestRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    address: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    digest: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct SignatureResponse {
+    #[prost(bytes = "vec", tag = "1")]
+    signature: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct SignTransactionRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    address: Vec<u8>,
+    #[prost(string, tag = "2")]
+    transaction_json: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct SignTransactionResponse {
+    #[prost(bytes = "vec", tag = "1")]
+    signature: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    raw_transaction: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct SignTypedDataRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    address: Vec<u8>,
+    #[prost(string, tag = "2")]
+    typed_data_json: String,
+}
+
+/// The gRPC service of a [`SignerServer`], for serving with an
+/// application's own [`tonic::transport::Server`]
+#[derive(Clone, Debug)]
+pub struct GrpcSignerService {
+    keys: Arc<Keys>,
+    authenticators: Authenticators,
+}
+
+impl SignerServer {
+    pub fn grpc_service(&self) -> GrpcSignerService {
+        GrpcSignerService {
+            keys: self.keys.clone(),
+            authenticators: self.authenticators.clone(),
+        }
+    }
+
+    /// Serves the gRPC service on `addr` until the process exits
+    pub async fn serve_grpc(self, addr: SocketAddr) -> Result<(), CKMSError> {
+        self.serve_grpc_with_shutdown(addr, std::future::pending())
+            .await
+    }
+
+    /// Serves the gRPC service on `addr` until `signal` completes
+    pub async fn serve_grpc_with_shutdown(
+        self,
+        addr: SocketAddr,
+        signal: impl Future<Output = ()>,
+    ) -> Result<(), CKMSError> {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            let listener = super::bind(addr).await?;
+            return self.serve_grpc_tls(listener, tls, signal).await;
+        }
+        tonic::transport::Server::builder()
+            .add_service(self.grpc_service())
+            .serve_with_shutdown(addr, signal)
+            .await
+            .map_err(|e| CKMSError::ServerError(format!("{addr}: {e}")))
+    }
+}
+
+impl NamedService for GrpcSignerService {
+    const NAME: &'static str = SERVICE;
+}
+
+impl<B> Service<http::Request<B>> for GrpcSignerService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Infallible>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let certificate = request.extensions().get::<ClientCertificate>();
+        let credentials = Credentials::new(
+            request.headers(),
+            certificate.map(|certificate| certificate.0.as_slice()),
+        );
+        let method = [
+            LIST_ACCOUNTS,
+            SIGN_DIGEST,
+            SIGN_TRANSACTION,
+            SIGN_TYPED_DATA,
+        ]
+        .into_iter()
+        .find(|method| *method == request.uri().path())
+        .unwrap_or("unknown");
+        let Some(caller) = self.authenticators.authenticate(&credentials) else {
+            let status = Status::unauthenticated("unauthenticated");
+            self.keys
+                .metrics
+                .count_request("grpc", method, code(&status));
+            return Box::pin(async move { Ok(status.to_http()) });
+        };
+        let keys = self.keys.clone();
+        match method {
+            LIST_ACCOUNTS => unary(Unary(keys, caller, method, list_accounts), request),
+            SIGN_DIGEST => unary(Unary(keys, caller, method, sign_digest), request),
+            SIGN_TRANSACTION => unary(Unary(keys, caller, method, sign_transaction), request),
+            SIGN_TYPED_DATA => unary(Unary(keys, caller, method, sign_typed_data), request),
+            _ => {
+                let status = Status::unimplemented(format!("no method {}", request.uri().path()));
+                keys.metrics.count_request("grpc", method, code(&status));
+                Box::pin(async move { Ok(status.to_http()) })
+            }
+        }
+    }
+}
+
+/// A method's handler, with its caller and its path for metrics, as a
+/// [`UnaryService`]
+struct Unary<F>(Arc<Keys>, Principal, &'static str, F);
+
+impl<F, Fut, Req, Res> UnaryService<Req> for Unary<F>
+where
+    F: Fn(Arc<Keys>, Principal, Req) -> Fut,
+    Fut: Future<Output = Result<Res, Status>> + Send + 'static,
+{
+    type Response = Res;
+    type Future = BoxFuture<Response<Res>, Status>;
+
+    fn call(&mut self, request: Request<Req>) -> Self::Future {
+        let Unary(keys, caller, method, handler) = self;
+        let response = handler(keys.clone(), caller.clone(), request.into_inner());
+        let (keys, method) = (keys.clone(), *method);
+        Box::pin(async move {
+            let response = response.await;
+            let code = response.as_ref().map_or_else(code, |_| "Ok".to_string());
+            keys.metrics.count_request("grpc", method, code);
+            response.map(Response::new)
+        })
+    }
+}
+
+fn unary<S, Req, Res, B>(
+    service: S,
+    request: http::Request<B>,
+) -> BoxFuture<http::Response<BoxBody>, Infallible>
+where
+    S: UnaryService<Req, Response = Res> + Send + 'static,
+    S::Future: Send,
+    Req: prost::Message + Default + Send + 'static,
+    Res: prost::Message + Send + 'static,
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    Box::pin(async move {
+        let mut grpc = Grpc::new(ProstCodec::<Res, Req>::default());
+        Ok(grpc.unary(service, request).await)
+    })
+}
+
+/// A status's code, by name, for metrics
+fn code(status: &Status) -> String {
+    format!("{:?}", status.code())
+}
+
+fn status(e: CKMSError) -> Status {
+    match e {
+        CKMSError::SigningDenied(denied) => Status::permission_denied(denied.to_string()),
+        CKMSError::Backpressure(e) => Status::resource_exhausted(e),
+        e @ (CKMSError::Eip712Error(_) | CKMSError::UnsupportedChainId(_)) => {
+            Status::invalid_argument(e.to_string())
+        }
+        e => Status::internal(e.to_string()),
+    }
+}
+
+fn key<'a>(keys: &'a Keys, caller: &Principal, address: &[u8]) -> Result<&'a Key, Status> {
+    if address.len() != 20 {
+        return Err(Status::invalid_argument("address must be 20 bytes"));
+    }
+    let address = Address::from_slice(address);
+    keys.by_address(address, caller)
+        .ok_or_else(|| Status::not_found(format!("no key {address:?}")))
+}
+
+/// Sets `v` to 27/28, as the service returns digest and typed data
+/// signatures
+fn with_27_28_v(mut signature: Signature) -> Signature {
+    if signature.v < 27 {
+        signature.v += 27;
+    }
+    signature
+}
+
+async fn list_accounts(
+    keys: Arc<Keys>,
+    caller: Principal,
+    _: ListAccountsRequest,
+) -> Result<ListAccountsResponse, Status> {
+    let accounts = keys
+        .iter(&caller)
+        .map(|key| AccountMessage {
+            address: key.address.as_bytes().to_vec(),
+            public_key: key.signer.public_key_bytes(false),
+        })
+        .collect();
+    Ok(ListAccountsResponse { accounts })
+}
+
+async fn sign_digest(
+    keys: Arc<Keys>,
+    caller: Principal,
+    request: SignDigestRequest,
+) -> Result<SignatureResponse, Status> {
+    let key = key(&keys, &caller, &request.address)?;
+    if request.digest.len() != 32 {
+        return Err(Status::invalid_argument("digest must be 32 bytes"));
+    }
+    let signature = keys
+        .metrics
+        .time_sign(key, key.signer.sign_hash(H256::from_slice(&request.digest)))
+        .await
+        .map_err(status)?;
+    Ok(SignatureResponse {
+        signature: signature.to_vec(),
+    })
+}
+
+async fn sign_transaction(
+    keys: Arc<Keys>,
+    caller: Principal,
+    request: SignTransactionRequest,
+) -> Result<SignTransactionResponse, Status> {
+    let key = key(&keys, &caller, &request.address)?;
+    let mut tx = serde_json::from_str(&request.transaction_json)
+        .and_then(parse_transaction)
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+    match tx.from() {
+        Some(from) if *from != key.address => {
+            return Err(Status::invalid_argument(format!(
+                "transaction is from {from:?}, not {:?}",
+                key.address
+            )))
+        }
+        Some(_) => {}
+        None => {
+            tx.set_from(key.address);
+        }
+    }
+    if tx.chain_id().is_none() {
+        tx.set_chain_id(key.signer.chain_id());
+    }
+    let signature = keys
+        .metrics
+        .time_sign(key, key.signer.sign_transaction(&tx))
+        .await
+        .map_err(status)?;
+    let raw = key.signer.encoder.encode(&tx, &signature).map_err(status)?;
+    Ok(SignTransactionResponse {
+        signature: signature.to_vec(),
+        raw_transaction: raw.to_vec(),
+    })
+}
+
+async fn sign_typed_data(
+    keys: Arc<Keys>,
+    caller: Principal,
+    request: SignTypedDataRequest,
+) -> Result<SignatureResponse, Status> {
+    let key = key(&keys, &caller, &request.address)?;
+    let typed_data = serde_json::from_str(&request.typed_data_json)
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+    let signature = keys
+        .metrics
+        .time_sign(key, key.signer.sign_typed_data_json(&typed_data))
+        .await
+        .map_err(status)?;
+    Ok(SignatureResponse {
+        signature: with_27_28_v(signature).to_vec(),
+    })
+}
+
+/// An account a [`GrpcSignerClient`] can sign with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrpcAccount {
+    pub address: Address,
+    /// 65-byte uncompressed SEC1
+    pub public_key: Bytes,
+}
+
+/// A client of a [`GrpcSignerService`], over a [`Channel`] or any other
+/// gRPC transport
+#[derive(Clone, Debug)]
+pub struct GrpcSignerClient<T = Channel> {
+    inner: tonic::client::Grpc<T>,
+    bearer_token: Option<String>,
+}
+
+impl GrpcSignerClient<Channel> {
+    /// Connects to a server at `endpoint`, e.g. `http://127.0.0.1:9001`
+    pub async fn connect(endpoint: &str) -> Result<Self, CKMSError> {
+        let channel = Endpoint::from_shared(endpoint.to_string())
+            .map_err(|e| CKMSError::ServerError(format!("{endpoint}: {e}")))?
+            .connect()
+            .await
+            .map_err(|e| CKMSError::ServerError(format!("{endpoint}: {e}")))?;
+        Ok(Self::new(channel))
+    }
+}
+
+impl<T> GrpcSignerClient<T>
+where
+    T: GrpcService<BoxBody> + Clone,
+    T::Error: Into<StdError>,
+    T::ResponseBody: Body<Data = tonic::codegen::Bytes> + Send + 'static,
+    <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+{
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner: tonic::client::Grpc::new(inner),
+            bearer_token: None,
+        }
+    }
+
+    /// Sends `token`, an API key or a JWT, as `authorization: Bearer …`
+    pub fn with_bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(token.into());
+        self
+    }
+
+    pub async fn list_accounts(&self) -> Result<Vec<GrpcAccount>, CKMSError> {
+        let response: ListAccountsResponse =
+            self.unary(LIST_ACCOUNTS, ListAccountsRequest {}).await?;
+        response
+            .accounts
+            .into_iter()
+            .map(|account| {
+                if account.address.len() != 20 {
+                    return Err(CKMSError::ServerError(
+                        "server sent an invalid address".to_string(),
+                    ));
+                }
+                Ok(GrpcAccount {
+                    address: Address::from_slice(&account.address),
+                    public_key: account.public_key.into(),
+                })
+            })
+            .collect()
+    }
+
+    /// Signs a digest as is, with `v` = 27/28
+    pub async fn sign_digest(
+        &self,
+        address: Address,
+        digest: H256,
+    ) -> Result<Signature, CKMSError> {
+        let request = SignDigestRequest {
+            address: address.as_bytes().to_vec(),
+            digest: digest.as_bytes().to_vec(),
+        };
+        let response: SignatureResponse = self.unary(SIGN_DIGEST, request).await?;
+        Ok(Signature::try_from(response.signature.as_slice())?)
+    }
+
+    /// Signs a transaction, returning its signature and its signed encoding
+    pub async fn sign_transaction(
+        &self,
+        address: Address,
+        tx: &TypedTransaction,
+    ) -> Result<(Signature, Bytes), CKMSError> {
+        let request = SignTransactionRequest {
+            address: address.as_bytes().to_vec(),
+            transaction_json: serde_json::to_string(tx)
+                .map_err(|e| CKMSError::ServerError(e.to_string()))?,
+        };
+        let response: SignTransactionResponse = self.unary(SIGN_TRANSACTION, request).await?;
+        Ok((
+            Signature::try_from(response.signature.as_slice())?,
+            response.raw_transaction.into(),
+        ))
+    }
+
+    /// Signs an `eth_signTypedData_v4` payload, with `v` = 27/28
+    pub async fn sign_typed_data(
+        &self,
+        address: Address,
+        typed_data: &serde_json::Value,
+    ) -> Result<Signature, CKMSError> {
+        let request = SignTypedDataRequest {
+            address: address.as_bytes().to_vec(),
+            typed_data_json: typed_data.to_string(),
+        };
+        let response: SignatureResponse = self.unary(SIGN_TYPED_DATA, request).await?;
+        Ok(Signature::try_from(response.signature.as_slice())?)
+    }
+
+    async fn unary<Req, Res>(&self, path: &'static str, request: Req) -> Result<Res, CKMSError>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Res: prost::Message + Default + Send + Sync + 'static,
+    {
+        let mut request = Request::new(request);
+        if let Some(token) = &self.bearer_token {
+            let value = format!("Bearer {token}")
+                .parse()
+                .map_err(|_| CKMSError::AuthError("invalid bearer token".to_string()))?;
+            request.metadata_mut().insert("authorization", value);
+        }
+        let mut inner = self.inner.clone();
+        inner
+            .ready()
+            .await
+            .map_err(|e| CKMSError::ServerError(e.into().to_string()))?;
+        let response = inner
+            .unary(
+                request,
+                http::uri::PathAndQuery::from_static(path),
+                ProstCodec::default(),
+            )
+            .await?;
+        Ok(response.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> GrpcSignerClient<GrpcSignerService> {
+        GrpcSignerClient::new(GrpcSignerService {
+            keys: Arc::default(),
+            authenticators: Authenticators::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn authenticates_callers() {
+        let server = SignerServer::new([]).await.unwrap().with_authenticator(
+            crate::server::ApiKeys::default().with_key("s3cret", Principal::new("service")),
+        );
+        let anonymous = GrpcSignerClient::new(server.grpc_service());
+        assert!(matches!(
+            anonymous.list_accounts().await,
+            Err(CKMSError::RequestError(status)) if status.code() == tonic::Code::Unauthenticated
+        ));
+        let authenticated = anonymous.with_bearer_token("s3cret");
+        assert!(authenticated.list_accounts().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn client_and_service_round_trip() {
+        let client = client();
+        assert!(client.list_accounts().await.unwrap().is_empty());
+
+        let unknown = client
+            .sign_digest(Address::repeat_byte(1), H256::zero())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            unknown,
+            CKMSError::RequestError(status) if status.code() == tonic::Code::NotFound
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_requests() {
+        let keys = Keys::default();
+        let short = key(&keys, &Principal::anonymous(), &[1; 19]).unwrap_err();
+        assert_eq!(short.code(), tonic::Code::InvalidArgument);
+
+        let mut service = GrpcSignerService {
+            keys: Arc::new(keys),
+            authenticators: Authenticators::default(),
+        };
+        let request = http::Request::post("/ethers_gcp_kms_signer.v1.Signer/Sign")
+            .body(tonic::body::empty_body())
+            .unwrap();
+        let response = Service::call(&mut service, request).await.unwrap();
+        assert_eq!(
+            response.headers()["grpc-status"],
+            (tonic::Code::Unimplemented as i32).to_string().as_str()
+        );
+    }
+
+    #[test]
+    fn messages_match_the_proto() {
+        use prost::Message;
+        // field 1 (address, length-delimited) and field 2 (digest)
+        let request = SignDigestRequest {
+            address: vec![0xaa],
+            digest: vec![0xbb],
+        };
+        assert_eq!(request.encode_to_vec(), [0x0a, 1, 0xaa, 0x12, 1, 0xbb]);
+        let request = SignTransactionRequest {
+            address: vec![],
+            transaction_json: "{}".to_string(),
+        };
+        assert_eq!(request.encode_to_vec(), [0x12, 2, b'{', b'}']);
+    }
+}
@@ -0,0 +1,140 @@
+//! Probes for orchestrators such as Kubernetes: `/healthz` answers while the
+//! process serves, and `/readyz` only while every key can be read from KMS,
+//! so that traffic is not routed to a signer which lost its access
+use std::{sync::Arc, time::Duration};
+
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use futures::future::join_all;
+use tokio::{sync::Mutex, time::Instant};
+use tracing::warn;
+
+use super::{Keys, SignerServer};
+
+/// How long a readiness check's result is reused, unless configured with
+/// [`SignerServer::with_readiness_interval`]
+pub(crate) const DEFAULT_READINESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a key's check may take before the key counts as unavailable
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct Readiness {
+    keys: Arc<Keys>,
+    interval: Duration,
+    /// When KMS was last checked, and how many keys were unavailable
+    last: Mutex<Option<(Instant, usize)>>,
+}
+
+impl Readiness {
+    /// The number of keys which cannot be read from KMS, checking them all
+    /// concurrently
+    async fn check(&self) -> usize {
+        let available = join_all(self.keys.keys.iter().map(|key| async move {
+            match tokio::time::timeout(CHECK_TIMEOUT, key.signer.check_access()).await {
+                Ok(Ok(())) => true,
+                Ok(Err(e)) => {
+                    warn!(address = ?key.address, "Key is unavailable in KMS: {e}");
+                    false
+                }
+                Err(_) => {
+                    warn!(address = ?key.address, "Timed out checking a key in KMS");
+                    false
+                }
+            }
+        }))
+        .await;
+        available.into_iter().filter(|available| !available).count()
+    }
+}
+
+impl SignerServer {
+    /// Reuses a `/readyz` check's result for `interval`, so that frequent
+    /// probes, of several replicas, do not use up the KMS read quota
+    pub fn with_readiness_interval(mut self, interval: Duration) -> Self {
+        self.readiness_interval = interval;
+        self
+    }
+}
+
+pub(crate) fn router(keys: Arc<Keys>, interval: Duration) -> Router {
+    let readiness = Arc::new(Readiness {
+        keys,
+        interval,
+        last: Mutex::new(None),
+    });
+    Router::new()
+        .route("/healthz", get(|| async { "OK" }))
+        .route("/readyz", get(readyz))
+        .with_state(readiness)
+}
+
+/// `OK`, or 503 with how many keys are unavailable. Which keys they are is
+/// only logged, as the probes are unauthenticated.
+async fn readyz(State(readiness): State<Arc<Readiness>>) -> (StatusCode, String) {
+    // held while checking, so concurrent probes wait for one check
+    let mut last = readiness.last.lock().await;
+    let unavailable = match *last {
+        Some((checked, unavailable)) if checked.elapsed() < readiness.interval => unavailable,
+        _ => {
+            let unavailable = readiness.check().await;
+            *last = Some((Instant::now(), unavailable));
+            unavailable
+        }
+    };
+    if unavailable == 0 {
+        (StatusCode::OK, "OK".to_string())
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!(
+                "{unavailable} of {} keys unavailable",
+                readiness.keys.keys.len()
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{ApiKeys, Principal};
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn probes_are_open() {
+        let app = SignerServer::new([])
+            .await
+            .unwrap()
+            .with_authenticator(ApiKeys::default().with_key("s3cret", Principal::new("service")))
+            .router();
+        for path in ["/healthz", "/readyz"] {
+            let response = app
+                .clone()
+                .oneshot(Request::get(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{path}");
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            assert_eq!(&body[..], b"OK");
+        }
+    }
+
+    #[tokio::test]
+    async fn readiness_is_reused_for_the_interval() {
+        // as if a key was unavailable at the last check
+        let readiness = |interval| {
+            State(Arc::new(Readiness {
+                keys: Arc::default(),
+                interval,
+                last: Mutex::new(Some((Instant::now(), 1))),
+            }))
+        };
+        let (status, body) = readyz(readiness(Duration::from_secs(60))).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body, "1 of 0 keys unavailable");
+
+        let (status, _) = readyz(readiness(Duration::ZERO)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,279 @@
+//! A JSON-RPC signer, like Clef, which answers account and signing calls for
+//! the keys by their addresses
+use std::sync::Arc;
+
+use axum::{body::Bytes, extract::State, routing::post, Extension, Json, Router};
+use ethers::{
+    signers::Signer,
+    types::{Address, Bytes as HexBytes, Signature},
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use super::{Key, Keys, Principal};
+use crate::{parse_transaction, CKMSError};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The code geth answers a valid call which failed with
+const SERVER_ERROR: i64 = -32000;
+
+/// The methods served, which metrics count calls by
+const METHODS: &[&str] = &[
+    "eth_accounts",
+    "eth_sign",
+    "personal_sign",
+    "eth_signTransaction",
+    "eth_signTypedData",
+    "eth_signTypedData_v4",
+];
+
+pub(crate) fn router(keys: Arc<Keys>) -> Router {
+    Router::new().route("/", post(handle)).with_state(keys)
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn invalid_params(message: impl std::fmt::Display) -> Self {
+        Self::new(INVALID_PARAMS, message.to_string())
+    }
+}
+
+impl From<CKMSError> for RpcError {
+    fn from(e: CKMSError) -> Self {
+        Self::new(SERVER_ERROR, e.to_string())
+    }
+}
+
+/// Answers a call or a batch of calls
+async fn handle(
+    State(keys): State<Arc<Keys>>,
+    Extension(caller): Extension<Principal>,
+    body: Bytes,
+) -> Json<Value> {
+    let request = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError::new(PARSE_ERROR, e.to_string());
+            return Json(response(Value::Null, Err(error)));
+        }
+    };
+    match request {
+        Value::Array(batch) if !batch.is_empty() => {
+            let mut responses = Vec::with_capacity(batch.len());
+            for request in batch {
+                responses.push(call(&keys, &caller, request).await);
+            }
+            Json(Value::Array(responses))
+        }
+        request => Json(call(&keys, &caller, request).await),
+    }
+}
+
+async fn call(keys: &Keys, caller: &Principal, request: Value) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let method = request.get("method").and_then(Value::as_str);
+    let result = match method {
+        Some(method) => {
+            let params = request.get("params").cloned().unwrap_or_else(|| json!([]));
+            dispatch(keys, caller, method, params).await
+        }
+        None => Err(RpcError::new(INVALID_REQUEST, "not a JSON-RPC call")),
+    };
+    let method = method
+        .and_then(|method| METHODS.iter().find(|known| **known == method))
+        .map_or("unknown", |method| *method);
+    let code = result.as_ref().map_or_else(|e| e.code, |_| 0);
+    keys.metrics.count_request("jsonrpc", method, code);
+    response(id, result)
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": e.code, "message": e.message},
+        }),
+    }
+}
+
+async fn dispatch(
+    keys: &Keys,
+    caller: &Principal,
+    method: &str,
+    params: Value,
+) -> Result<Value, RpcError> {
+    match method {
+        "eth_accounts" => {
+            let accounts: Vec<_> = keys.iter(caller).map(|key| key.address).collect();
+            Ok(json!(accounts))
+        }
+        "eth_sign" => {
+            let (address, data): (Address, HexBytes) = positional(params, 2)?;
+            let key = key(keys, caller, address)?;
+            let signature = keys
+                .metrics
+                .time_sign(key, key.signer.sign_message(&data))
+                .await?;
+            Ok(json!(wallet_signature(signature)))
+        }
+        // personal_sign takes the data first, and a password third, which
+        // is ignored
+        "personal_sign" => {
+            let (data, address): (HexBytes, Address) = positional(params, 2)?;
+            let key = key(keys, caller, address)?;
+            let signature = keys
+                .metrics
+                .time_sign(key, key.signer.sign_message(&data))
+                .await?;
+            Ok(json!(wallet_signature(signature)))
+        }
+        "eth_signTransaction" => {
+            let (tx,): (Value,) = positional(params, 1)?;
+            let mut tx = parse_transaction(tx).map_err(RpcError::invalid_params)?;
+            let from = *tx
+                .from()
+                .ok_or_else(|| RpcError::invalid_params("transaction has no from"))?;
+            let key = key(keys, caller, from)?;
+            if tx.chain_id().is_none() {
+                tx.set_chain_id(key.signer.chain_id());
+            }
+            let raw = keys
+                .metrics
+                .time_sign(key, key.signer.sign_transaction_raw(&tx))
+                .await?;
+            Ok(json!({"raw": raw, "tx": tx}))
+        }
+        "eth_signTypedData" | "eth_signTypedData_v4" => {
+            let (address, typed_data): (Address, Value) = positional(params, 2)?;
+            // wallets send the payload either as an object or as its JSON text
+            let typed_data = match typed_data {
+                Value::String(json) => {
+                    serde_json::from_str(&json).map_err(RpcError::invalid_params)?
+                }
+                typed_data => typed_data,
+            };
+            let key = key(keys, caller, address)?;
+            let signature = keys
+                .metrics
+                .time_sign(key, key.signer.sign_typed_data_json(&typed_data))
+                .await?;
+            Ok(json!(wallet_signature(signature)))
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("the method {method} does not exist"),
+        )),
+    }
+}
+
+/// Parses the first `arity` positional params, ignoring any more
+fn positional<T: DeserializeOwned>(params: Value, arity: usize) -> Result<T, RpcError> {
+    let params = match params {
+        Value::Array(mut params) => {
+            params.truncate(arity);
+            Value::Array(params)
+        }
+        params => params,
+    };
+    serde_json::from_value(params).map_err(RpcError::invalid_params)
+}
+
+fn key<'a>(keys: &'a Keys, caller: &Principal, address: Address) -> Result<&'a Key, RpcError> {
+    keys.by_address(address, caller)
+        .ok_or_else(|| RpcError::new(SERVER_ERROR, format!("unknown account {address:?}")))
+}
+
+/// Formats a signature as wallets return it: `0x`-prefixed hex with
+/// `v` = 27/28
+fn wallet_signature(mut signature: Signature) -> String {
+    if signature.v < 27 {
+        signature.v += 27;
+    }
+    format!("0x{signature}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_calls_and_batches() {
+        let keys = Keys::default();
+        let caller = Principal::anonymous();
+        let accounts = call(
+            &keys,
+            &caller,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "eth_accounts"}),
+        )
+        .await;
+        assert_eq!(accounts["result"], json!([]));
+        assert_eq!(accounts["id"], json!(1));
+
+        let unknown = call(&keys, &caller, json!({"id": 2, "method": "eth_mine"})).await;
+        assert_eq!(unknown["error"]["code"], json!(METHOD_NOT_FOUND));
+
+        let account = format!("{:?}", Address::repeat_byte(1));
+        let sign = call(
+            &keys,
+            &caller,
+            json!({"id": 3, "method": "personal_sign", "params": ["0x01", account, ""]}),
+        )
+        .await;
+        assert_eq!(sign["error"]["code"], json!(SERVER_ERROR));
+        let invalid = call(
+            &keys,
+            &caller,
+            json!({"id": 4, "method": "eth_sign", "params": []}),
+        )
+        .await;
+        assert_eq!(invalid["error"]["code"], json!(INVALID_PARAMS));
+
+        let Json(batch) = handle(
+            State(Arc::new(keys)),
+            Extension(caller),
+            Bytes::from_static(br#"[{"id": 5, "method": "eth_accounts"}, 1]"#),
+        )
+        .await;
+        assert_eq!(batch[0]["result"], json!([]));
+        assert_eq!(batch[1]["error"]["code"], json!(INVALID_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn malformed_bodies_are_parse_errors() {
+        let Json(response) = handle(
+            State(Arc::default()),
+            Extension(Principal::anonymous()),
+            Bytes::from_static(b"{"),
+        )
+        .await;
+        assert_eq!(response["error"]["code"], json!(PARSE_ERROR));
+        assert_eq!(response["id"], Value::Null);
+    }
+
+    #[test]
+    fn wallet_signatures_have_27_28_v() {
+        let signature = Signature {
+            r: 1.into(),
+            s: 2.into(),
+            v: 0,
+        };
+        assert!(wallet_signature(signature).ends_with("1b"));
+    }
+}
@@ -0,0 +1,348 @@
+//! Prometheus metrics, which `GET /metrics` serves in the text exposition
+//! format: requests by API, method and response code, each key's sign
+//! latencies and errors, for alerting on KMS quota exhaustion, and the
+//! depth of its provider's queues
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    extract::{MatchedPath, State},
+    http::{header::CONTENT_TYPE, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ethers::types::Address;
+
+use super::{Key, Keys};
+use crate::{CKMSError, GcpKmsSigner};
+
+/// Upper bounds of the sign latency histogram's buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations at most each bucket's bound, not cumulative
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// A server's metrics, shared by all of its APIs
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// By API, method and response code
+    requests: BTreeMap<(&'static str, String, String), u64>,
+    sign_latencies: BTreeMap<Address, Histogram>,
+    /// By key and error
+    sign_errors: BTreeMap<(Address, String), u64>,
+}
+
+impl Metrics {
+    /// Counts a request to `api`'s `method`, which should be one of a few
+    /// values, as should `code`
+    pub(crate) fn count_request(&self, api: &'static str, method: &str, code: impl ToString) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner
+            .requests
+            .entry((api, method.to_string(), code.to_string()))
+            .or_default() += 1;
+    }
+
+    /// Signs with `key`, recording how long it took and how it failed
+    pub(crate) async fn time_sign<T>(
+        &self,
+        key: &Key,
+        sign: impl Future<Output = Result<T, CKMSError>>,
+    ) -> Result<T, CKMSError> {
+        let start = Instant::now();
+        let result = sign.await;
+        let elapsed = start.elapsed().as_secs_f64();
+
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .sign_latencies
+            .entry(key.address)
+            .or_default()
+            .observe(elapsed);
+        if let Err(e) = &result {
+            *inner
+                .sign_errors
+                .entry((key.address, error_label(e)))
+                .or_default() += 1;
+        }
+        result
+    }
+
+    fn render(&self, keys: &Keys) -> Result<String, fmt::Error> {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        family(
+            &mut out,
+            "requests_total",
+            "counter",
+            "Requests by API, method and response code",
+        )?;
+        for ((api, method, code), count) in &inner.requests {
+            let labels = [("api", *api), ("method", method), ("code", code)];
+            sample(&mut out, "requests_total", &labels, *count)?;
+        }
+
+        family(
+            &mut out,
+            "sign_duration_seconds",
+            "histogram",
+            "How long KMS took to sign, including any queueing",
+        )?;
+        for (address, histogram) in &inner.sign_latencies {
+            let key = format!("{address:?}");
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let le = bound.to_string();
+                let labels = [("key", key.as_str()), ("le", &le)];
+                sample(
+                    &mut out,
+                    "sign_duration_seconds_bucket",
+                    &labels,
+                    cumulative,
+                )?;
+            }
+            let labels = [("key", key.as_str()), ("le", "+Inf")];
+            sample(
+                &mut out,
+                "sign_duration_seconds_bucket",
+                &labels,
+                histogram.count,
+            )?;
+            let labels = [("key", key.as_str())];
+            sample(
+                &mut out,
+                "sign_duration_seconds_sum",
+                &labels,
+                histogram.sum,
+            )?;
+            sample(
+                &mut out,
+                "sign_duration_seconds_count",
+                &labels,
+                histogram.count,
+            )?;
+        }
+
+        family(
+            &mut out,
+            "sign_errors_total",
+            "counter",
+            "Failed signatures by key and error, with KMS errors by their gRPC code",
+        )?;
+        for ((address, error), count) in &inner.sign_errors {
+            let key = format!("{address:?}");
+            let labels = [("key", key.as_str()), ("error", error)];
+            sample(&mut out, "sign_errors_total", &labels, *count)?;
+        }
+
+        // the queues are the key's provider's, which keys may share
+        let gauges: [(&str, &str, Gauge); 3] = [
+            (
+                "in_flight",
+                "Sign calls holding a slot of the key's provider's concurrency limit",
+                |signer| Some(signer.provider.as_ref()?.concurrency_stats()?.in_flight),
+            ),
+            (
+                "queued",
+                "Sign calls waiting for a slot of the key's provider's concurrency limit",
+                |signer| Some(signer.provider.as_ref()?.concurrency_stats()?.queued),
+            ),
+            (
+                "parked",
+                "Sign calls parked by the key's provider's outage queue until KMS recovers",
+                |signer| Some(signer.provider.as_ref()?.parked_requests()),
+            ),
+        ];
+        for (name, help, value) in gauges {
+            family(&mut out, name, "gauge", help)?;
+            for key in &keys.keys {
+                if let Some(value) = value(&key.signer) {
+                    let key = format!("{:?}", key.address);
+                    sample(&mut out, name, &[("key", &key)], value)?;
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+const PREFIX: &str = "gcp_kms_signer_";
+
+/// Reads a gauge of a key's provider, if it has one
+type Gauge = fn(&GcpKmsSigner) -> Option<usize>;
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) -> fmt::Result {
+    writeln!(out, "# HELP {PREFIX}{name} {help}")?;
+    writeln!(out, "# TYPE {PREFIX}{name} {kind}")
+}
+
+fn sample(
+    out: &mut String,
+    name: &str,
+    labels: &[(&str, &str)],
+    value: impl fmt::Display,
+) -> fmt::Result {
+    write!(out, "{PREFIX}{name}{{")?;
+    for (i, (label, value)) in labels.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        let value = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        write!(out, "{separator}{label}=\"{value}\"")?;
+    }
+    writeln!(out, "}} {value}")
+}
+
+/// A label for a signing error with few values: a KMS call's gRPC code, as
+/// `ResourceExhausted` when the quota is, or else the error's variant
+fn error_label(e: &CKMSError) -> String {
+    match e {
+        CKMSError::RequestError(status) => format!("{:?}", status.code()),
+        e => format!("{e:?}")
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+/// Counts HTTP requests by their route, rather than their path, which
+/// would have a value per key
+pub(crate) async fn track<B>(
+    State(keys): State<Arc<Keys>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |route| route.as_str())
+        .to_string();
+    let response = next.run(request).await;
+    keys.metrics
+        .count_request("http", &route, response.status().as_u16());
+    response
+}
+
+pub(crate) async fn metrics(State(keys): State<Arc<Keys>>) -> Response {
+    match keys.metrics.render(&keys) {
+        Ok(text) => ([(CONTENT_TYPE, "text/plain; version=0.0.4")], text).into_response(),
+        Err(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_the_exposition_format() {
+        let metrics = Metrics::default();
+        metrics.count_request("jsonrpc", "eth_sign", 0);
+        metrics.count_request("jsonrpc", "eth_sign", 0);
+        metrics.count_request("grpc", "SignDigest", "Unauthenticated");
+        {
+            let mut inner = metrics.inner.lock().unwrap();
+            let key = Address::repeat_byte(0x11);
+            let histogram = inner.sign_latencies.entry(key).or_default();
+            histogram.observe(0.02);
+            histogram.observe(30.0);
+            inner
+                .sign_errors
+                .insert((key, "ResourceExhausted".to_string()), 1);
+        }
+        let text = metrics.render(&Keys::default()).unwrap();
+        let key = "0x1111111111111111111111111111111111111111";
+        for line in [
+            "# TYPE gcp_kms_signer_requests_total counter".to_string(),
+            r#"gcp_kms_signer_requests_total{api="jsonrpc",method="eth_sign",code="0"} 2"#
+                .to_string(),
+            r#"gcp_kms_signer_requests_total{api="grpc",method="SignDigest",code="Unauthenticated"} 1"#
+                .to_string(),
+            format!(r#"gcp_kms_signer_sign_duration_seconds_bucket{{key="{key}",le="0.01"}} 0"#),
+            format!(r#"gcp_kms_signer_sign_duration_seconds_bucket{{key="{key}",le="0.025"}} 1"#),
+            format!(r#"gcp_kms_signer_sign_duration_seconds_bucket{{key="{key}",le="10"}} 1"#),
+            format!(r#"gcp_kms_signer_sign_duration_seconds_bucket{{key="{key}",le="+Inf"}} 2"#),
+            format!(r#"gcp_kms_signer_sign_duration_seconds_count{{key="{key}"}} 2"#),
+            format!(
+                r#"gcp_kms_signer_sign_errors_total{{key="{key}",error="ResourceExhausted"}} 1"#
+            ),
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line} not in\n{text}");
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_requests_to_the_router() {
+        use crate::server::SignerServer;
+        use axum::body::Body;
+        use tower::ServiceExt;
+
+        let app = SignerServer::new([]).await.unwrap().router();
+        let call = r#"{"jsonrpc":"2.0","id":1,"method":"eth_accounts"}"#;
+        for request in [
+            Request::post("/")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(call))
+                .unwrap(),
+            Request::post("/api/v1/eth1/sign/0x01")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"{"data":"0x"}"#))
+                .unwrap(),
+        ] {
+            app.clone().oneshot(request).await.unwrap();
+        }
+        let response = app
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        for line in [
+            r#"gcp_kms_signer_requests_total{api="http",method="/",code="200"} 1"#,
+            r#"gcp_kms_signer_requests_total{api="http",method="/api/v1/eth1/sign/:identifier",code="404"} 1"#,
+            r#"gcp_kms_signer_requests_total{api="jsonrpc",method="eth_accounts",code="0"} 1"#,
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line} not in\n{text}");
+        }
+    }
+
+    #[test]
+    fn labels_errors_by_kms_code_or_variant() {
+        let quota = CKMSError::RequestError(tonic::Status::resource_exhausted("quota"));
+        assert_eq!(error_label(&quota), "ResourceExhausted");
+        let backpressure = CKMSError::Backpressure("queue full".to_string());
+        assert_eq!(error_label(&backpressure), "Backpressure");
+        assert_eq!(error_label(&CKMSError::RecoveryError), "RecoveryError");
+    }
+}
@@ -0,0 +1,282 @@
+//! Serves KMS signers to other processes over HTTP, for infrastructure which
+//! cannot link this crate, such as besu or services in other languages.
+//!
+//! The server holds the signers' KMS access; its clients need no GCP
+//! credentials.
+use std::{future::Future, net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use ethers::{signers::Signer, types::Address, utils::hex};
+
+use crate::{CKMSError, GcpKmsSigner};
+
+mod auth;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod health;
+mod jsonrpc;
+mod metrics;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(unix)]
+mod unix;
+mod web3signer;
+
+use auth::Authenticators;
+pub use auth::{
+    ApiKeys, Authenticator, ClientCertificates, Credentials, JwtAuthenticator, Principal,
+};
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
+#[cfg(unix)]
+pub use unix::PeerPolicy;
+
+/// A signer the server exposes, with the identifiers clients address it by
+#[derive(Clone, Debug)]
+pub(crate) struct Key {
+    pub(crate) signer: GcpKmsSigner,
+    pub(crate) address: Address,
+    /// The 64-byte uncompressed public key without its SEC1 tag, as `0x` hex,
+    /// which is how Web3Signer identifies secp256k1 keys
+    pub(crate) public_key: String,
+}
+
+/// The signers a server exposes, and the metrics of their use. Each lookup
+/// is for a caller, and finds only the keys it may use.
+#[derive(Debug, Default)]
+pub(crate) struct Keys {
+    keys: Vec<Key>,
+    pub(crate) metrics: metrics::Metrics,
+}
+
+impl Keys {
+    pub(crate) fn iter<'a>(&'a self, caller: &'a Principal) -> impl Iterator<Item = &'a Key> {
+        self.keys.iter().filter(|key| caller.allows(key.address))
+    }
+
+    pub(crate) fn by_address(&self, address: Address, caller: &Principal) -> Option<&Key> {
+        self.keys
+            .iter()
+            .find(|key| key.address == address && caller.allows(key.address))
+    }
+
+    /// Finds a key by its public key, as 64 bytes, 65-byte uncompressed or
+    /// 33-byte compressed SEC1 hex, or by its address
+    pub(crate) fn by_identifier(&self, identifier: &str, caller: &Principal) -> Option<&Key> {
+        let bytes = hex::decode(identifier).ok()?;
+        let key = match bytes.len() {
+            20 => return self.by_address(Address::from_slice(&bytes), caller),
+            33 => self
+                .keys
+                .iter()
+                .find(|key| key.signer.public_key_bytes(true) == bytes),
+            64 | 65 => {
+                let raw = &bytes[bytes.len() - 64..];
+                self.keys
+                    .iter()
+                    .find(|key| key.signer.public_key_bytes(false)[1..] == *raw)
+            }
+            _ => None,
+        };
+        key.filter(|key| caller.allows(key.address))
+    }
+}
+
+/// An HTTP server for a set of signers.
+///
+/// It serves the Web3Signer ETH1 API: `GET /api/v1/eth1/publicKeys`, which
+/// lists the keys, `POST /api/v1/eth1/sign/{identifier}`, which signs the
+/// keccak256 hash of `{"data": "0x…"}` with `v` = 27/28, and `GET /upcheck`.
+///
+/// `POST /` answers JSON-RPC calls like Clef: `eth_accounts`, `eth_sign`,
+/// `personal_sign`, `eth_signTypedData_v4` (also as `eth_signTypedData`),
+/// whose signatures have `v` = 27/28, and `eth_signTransaction`, which
+/// returns `{"raw", "tx"}` as geth does. A transaction without a chain id is
+/// signed for its key's chain.
+///
+/// With the `grpc` feature, [`SignerServer::serve_grpc`] serves the same
+/// keys over gRPC. On Unix, [`SignerServer::serve_unix`] serves the HTTP API
+/// on a Unix socket to the processes a [`PeerPolicy`] allows.
+///
+/// Without authenticators, any caller may use every key. With them, added
+/// by [`SignerServer::with_authenticator`], each request must identify its
+/// caller, as a [`Principal`], and sees only the keys the caller is allowed;
+/// others are refused with 401, or gRPC's `UNAUTHENTICATED`.
+///
+/// `/upcheck` and the Kubernetes probes are always open: `/healthz` answers
+/// while the server runs, and `/readyz` only while every key can be read
+/// from KMS, which it checks at most once per
+/// [`SignerServer::with_readiness_interval`].
+///
+/// `GET /metrics` serves Prometheus metrics of every API and key: requests
+/// by method and response code, sign latencies, sign errors by their KMS
+/// code, and queue depths. It requires authentication like the signing
+/// routes, which Prometheus can send as a bearer token.
+///
+/// With the `tls` feature, [`SignerServer::with_tls`] terminates TLS, and
+/// with a client CA requires client certificates, which
+/// [`ClientCertificates`] can identify callers by.
+#[derive(Clone, Debug)]
+pub struct SignerServer {
+    keys: Arc<Keys>,
+    authenticators: Authenticators,
+    readiness_interval: Duration,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+}
+
+impl SignerServer {
+    /// Serves `signers`, resolving their public keys first
+    pub async fn new(signers: impl IntoIterator<Item = GcpKmsSigner>) -> Result<Self, CKMSError> {
+        let mut keys = Vec::new();
+        for signer in signers {
+            signer.resolve().await?;
+            keys.push(Key {
+                address: signer.address(),
+                public_key: format!("0x{}", hex::encode(&signer.public_key_bytes(false)[1..])),
+                signer,
+            });
+        }
+        Ok(Self {
+            keys: Arc::new(Keys {
+                keys,
+                metrics: metrics::Metrics::default(),
+            }),
+            authenticators: Authenticators::default(),
+            readiness_interval: health::DEFAULT_READINESS_INTERVAL,
+            #[cfg(feature = "tls")]
+            tls: None,
+        })
+    }
+
+    /// The server's routes, for serving them in an application's own
+    /// `axum` server
+    pub fn router(&self) -> Router {
+        let signing = web3signer::router(self.keys.clone())
+            .merge(jsonrpc::router(self.keys.clone()))
+            .route(
+                "/metrics",
+                get(metrics::metrics).with_state(self.keys.clone()),
+            )
+            .route_layer(middleware::from_fn_with_state(
+                self.authenticators.clone(),
+                auth::authenticate,
+            ));
+        Router::new()
+            .route("/upcheck", get(|| async { "OK" }))
+            .merge(health::router(self.keys.clone(), self.readiness_interval))
+            .merge(signing)
+            .layer(middleware::from_fn_with_state(
+                self.keys.clone(),
+                metrics::track,
+            ))
+    }
+
+    /// Serves on `addr` until the process exits
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), CKMSError> {
+        self.serve_with_shutdown(addr, std::future::pending()).await
+    }
+
+    /// Serves on `addr` until `signal` completes, then finishes the requests
+    /// in flight
+    pub async fn serve_with_shutdown(
+        self,
+        addr: SocketAddr,
+        signal: impl Future<Output = ()>,
+    ) -> Result<(), CKMSError> {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            let listener = bind(addr).await?;
+            return self.serve_tls(listener, tls, signal).await;
+        }
+        axum::Server::try_bind(&addr)
+            .map_err(|e| CKMSError::ServerError(format!("{addr}: {e}")))?
+            .serve(self.router().into_make_service())
+            .with_graceful_shutdown(signal)
+            .await
+            .map_err(|e| CKMSError::ServerError(e.to_string()))
+    }
+}
+
+#[cfg(feature = "tls")]
+pub(crate) async fn bind(addr: SocketAddr) -> Result<tokio::net::TcpListener, CKMSError> {
+    tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| CKMSError::ServerError(format!("{addr}: {e}")))
+}
+
+/// An error response, with the status a signing error maps to
+#[derive(Debug)]
+pub(crate) struct ApiError {
+    pub(crate) status: StatusCode,
+    pub(crate) message: String,
+}
+
+impl ApiError {
+    pub(crate) fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn unknown_key(identifier: &str) -> Self {
+        Self::new(StatusCode::NOT_FOUND, format!("no key {identifier}"))
+    }
+}
+
+impl From<CKMSError> for ApiError {
+    fn from(e: CKMSError) -> Self {
+        let status = match &e {
+            CKMSError::SigningDenied(_) => StatusCode::FORBIDDEN,
+            CKMSError::Backpressure(_) => StatusCode::SERVICE_UNAVAILABLE,
+            CKMSError::Eip712Error(_)
+            | CKMSError::InvalidBlobTransaction(_)
+            | CKMSError::UnsupportedChainId(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, self.message).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_errors_to_statuses() {
+        let denied = ApiError::from(CKMSError::SigningDenied(crate::SigningDenied::new(
+            "tx_type_allowlist",
+        )));
+        assert_eq!(denied.status, StatusCode::FORBIDDEN);
+        let saturated = ApiError::from(CKMSError::Backpressure("full".to_string()));
+        assert_eq!(saturated.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            ApiError::from(CKMSError::RecoveryError).status,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn unknown_identifiers_find_no_key() {
+        let keys = Keys::default();
+        let caller = Principal::anonymous();
+        assert!(keys.by_identifier("0x1234", &caller).is_none());
+        assert!(keys.by_identifier("not hex", &caller).is_none());
+        assert!(keys
+            .by_identifier(&format!("{:?}", Address::zero()), &caller)
+            .is_none());
+    }
+}
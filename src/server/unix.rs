@@ -0,0 +1,225 @@
+//! Serving over a Unix domain socket, for processes on the same host which
+//! should be able to request signatures but hold no GCP credentials
+use std::{
+    convert::Infallible,
+    future::Future,
+    io,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use hyper::server::accept::Accept;
+use tokio::{
+    net::{unix::UCred, UnixListener, UnixStream},
+    time::Sleep,
+};
+use tracing::warn;
+
+use super::SignerServer;
+use crate::CKMSError;
+
+/// Which processes may connect to a [`SignerServer`]'s Unix socket, by the
+/// credentials the kernel reports for them.
+///
+/// Allowing no uids or gids, the default, lets any process which can open
+/// the socket connect; the socket is created with mode `0660`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerPolicy {
+    uids: Vec<u32>,
+    gids: Vec<u32>,
+}
+
+impl PeerPolicy {
+    /// Allows processes running as `uid`
+    pub fn with_uid(mut self, uid: u32) -> Self {
+        self.uids.push(uid);
+        self
+    }
+
+    /// Allows processes whose primary group is `gid`
+    pub fn with_gid(mut self, gid: u32) -> Self {
+        self.gids.push(gid);
+        self
+    }
+
+    fn allows(&self, peer: &UCred) -> bool {
+        (self.uids.is_empty() && self.gids.is_empty())
+            || self.uids.contains(&peer.uid())
+            || self.gids.contains(&peer.gid())
+    }
+}
+
+/// Accepts the connections of allowed peers, dropping the rest. Failing to
+/// accept does not stop the server.
+struct PeerAccept {
+    listener: UnixListener,
+    policy: PeerPolicy,
+    /// Pause after a failed accept
+    backoff: Option<Pin<Box<Sleep>>>,
+}
+
+impl Accept for PeerAccept {
+    type Conn = UnixStream;
+    type Error = Infallible;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<UnixStream, Infallible>>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(backoff) = &mut this.backoff {
+                ready!(backoff.as_mut().poll(cx));
+                this.backoff = None;
+            }
+            let stream = match ready!(this.listener.poll_accept(cx)) {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    // e.g. out of file descriptors, which may pass
+                    warn!("Failed to accept a connection: {e}");
+                    this.backoff = Some(Box::pin(tokio::time::sleep(Duration::from_millis(100))));
+                    continue;
+                }
+            };
+            match stream.peer_cred() {
+                Ok(peer) if this.policy.allows(&peer) => return Poll::Ready(Some(Ok(stream))),
+                Ok(peer) => warn!(
+                    uid = peer.uid(),
+                    gid = peer.gid(),
+                    pid = peer.pid(),
+                    "Refused a connection from a disallowed peer"
+                ),
+                Err(e) => warn!("Refused a connection without peer credentials: {e}"),
+            }
+        }
+    }
+}
+
+/// Binds `path`, replacing a socket left there by an earlier server but no
+/// other kind of file
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a file which is not a socket exists there",
+            ))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o660))?;
+    Ok(listener)
+}
+
+impl SignerServer {
+    /// Serves on a Unix socket at `path` until the process exits
+    pub async fn serve_unix(
+        self,
+        path: impl Into<PathBuf>,
+        policy: PeerPolicy,
+    ) -> Result<(), CKMSError> {
+        self.serve_unix_with_shutdown(path, policy, std::future::pending())
+            .await
+    }
+
+    /// Serves on a Unix socket at `path` until `signal` completes, then
+    /// finishes the requests in flight and removes the socket
+    pub async fn serve_unix_with_shutdown(
+        self,
+        path: impl Into<PathBuf>,
+        policy: PeerPolicy,
+        signal: impl Future<Output = ()>,
+    ) -> Result<(), CKMSError> {
+        let path = path.into();
+        let error =
+            |e: &dyn std::fmt::Display| CKMSError::ServerError(format!("{}: {e}", path.display()));
+        let listener = bind(&path).map_err(|e| error(&e))?;
+        let accept = PeerAccept {
+            listener,
+            policy,
+            backoff: None,
+        };
+        let served = axum::Server::builder(accept)
+            .serve(self.router().into_make_service())
+            .with_graceful_shutdown(signal)
+            .await;
+        let _ = std::fs::remove_file(&path);
+        served.map_err(|e| error(&e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("gcp-kms-signer-{}-{name}.sock", std::process::id()))
+    }
+
+    async fn upcheck(path: &Path) -> String {
+        let mut stream = loop {
+            match UnixStream::connect(path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::task::yield_now().await,
+            }
+        };
+        // a disallowed peer's connection may be dropped before the write
+        let _ = stream
+            .write_all(b"GET /upcheck HTTP/1.1\r\nhost: signer\r\nconnection: close\r\n\r\n")
+            .await;
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).await;
+        response
+    }
+
+    async fn serve_and_upcheck(name: &str, policy: PeerPolicy) -> String {
+        let path = socket_path(name);
+        let server = SignerServer::new([]).await.unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(
+            server.serve_unix_with_shutdown(path.clone(), policy, async {
+                let _ = stopped.await;
+            }),
+        );
+        let response = upcheck(&path).await;
+        stop.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+        assert!(!path.exists());
+        response
+    }
+
+    fn own_uid() -> u32 {
+        let (stream, _peer) = UnixStream::pair().unwrap();
+        stream.peer_cred().unwrap().uid()
+    }
+
+    #[tokio::test]
+    async fn serves_allowed_peers() {
+        let response =
+            serve_and_upcheck("allowed", PeerPolicy::default().with_uid(own_uid())).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with("OK"));
+    }
+
+    #[tokio::test]
+    async fn drops_disallowed_peers() {
+        let policy = PeerPolicy::default().with_uid(own_uid().wrapping_add(1));
+        assert_eq!(serve_and_upcheck("disallowed", policy).await, "");
+    }
+
+    #[test]
+    fn does_not_replace_other_files() {
+        let path = socket_path("file");
+        std::fs::write(&path, "").unwrap();
+        let denied = bind(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(denied.kind(), io::ErrorKind::AlreadyExists);
+    }
+}
@@ -0,0 +1,127 @@
+//! The Web3Signer ETH1 API
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use ethers::{types::Bytes, utils::keccak256};
+use serde::Deserialize;
+
+use super::{ApiError, Keys, Principal};
+
+pub(crate) fn router(keys: Arc<Keys>) -> Router {
+    Router::new()
+        .route("/api/v1/eth1/publicKeys", get(public_keys))
+        .route("/api/v1/eth1/sign/:identifier", post(sign))
+        .with_state(keys)
+}
+
+async fn public_keys(
+    State(keys): State<Arc<Keys>>,
+    Extension(caller): Extension<Principal>,
+) -> Json<Vec<String>> {
+    Json(
+        keys.iter(&caller)
+            .map(|key| key.public_key.clone())
+            .collect(),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct SignRequest {
+    data: Bytes,
+}
+
+/// Signs `keccak256(data)`, returning `0x`-prefixed `r || s || v`
+async fn sign(
+    State(keys): State<Arc<Keys>>,
+    Extension(caller): Extension<Principal>,
+    Path(identifier): Path<String>,
+    Json(request): Json<SignRequest>,
+) -> Result<String, ApiError> {
+    let key = keys
+        .by_identifier(&identifier, &caller)
+        .ok_or_else(|| ApiError::unknown_key(&identifier))?;
+    let signature = keys
+        .metrics
+        .time_sign(key, key.signer.sign_hash(keccak256(&request.data).into()))
+        .await?;
+    Ok(format!("0x{signature}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{ApiKeys, SignerServer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn serves_upcheck_and_key_listing() {
+        let app = SignerServer::new([]).await.unwrap().router();
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/upcheck").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/api/v1/eth1/publicKeys")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"[]");
+
+        let response = app
+            .oneshot(
+                Request::post(format!("/api/v1/eth1/sign/0x{}", "ab".repeat(64)))
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"data":"0x01"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn authenticators_guard_all_but_upcheck() {
+        let app = SignerServer::new([])
+            .await
+            .unwrap()
+            .with_authenticator(ApiKeys::default().with_key("s3cret", Principal::new("bot")))
+            .router();
+        let status = |request: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        let upcheck = Request::get("/upcheck").body(Body::empty()).unwrap();
+        assert_eq!(status(upcheck).await, StatusCode::OK);
+        let anonymous = Request::get("/api/v1/eth1/publicKeys")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status(anonymous).await, StatusCode::UNAUTHORIZED);
+        let jsonrpc = Request::post("/")
+            .body(Body::from(r#"{"id":1,"method":"eth_accounts"}"#))
+            .unwrap();
+        assert_eq!(status(jsonrpc).await, StatusCode::UNAUTHORIZED);
+        let authenticated = Request::get("/api/v1/eth1/publicKeys")
+            .header("x-api-key", "s3cret")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status(authenticated).await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,325 @@
+//! Conversions between the signature representations used across the
+//! ecosystem. [`RecoverableSignature`] is the canonical form; every other
+//! representation converts to and from it, and every fallible conversion
+//! returns [`CKMSError::InvalidSignature`] rather than panicking.
+use ethers::{
+    prelude::k256::ecdsa::{RecoveryId, Signature as KSig},
+    types::{Signature, U256},
+};
+
+use crate::CKMSError;
+
+/// A secp256k1 signature with its recovery id (y-parity)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RecoverableSignature {
+    pub r: [u8; 32],
+    pub s: [u8; 32],
+    /// `false` for an even y coordinate of the signature point, `true` for odd
+    pub y_parity: bool,
+}
+
+fn invalid(reason: impl Into<String>) -> CKMSError {
+    CKMSError::InvalidSignature(reason.into())
+}
+
+/// Extracts the y-parity from a `v` value which is either a bare 0/1, a 27/28
+/// value, or an EIP-155 value (`chain_id * 2 + 35 + parity`)
+pub fn y_parity_from_v(v: u64) -> Result<bool, CKMSError> {
+    match v {
+        0 | 1 => Ok(v == 1),
+        27 | 28 => Ok(v == 28),
+        35.. => Ok((v - 35) % 2 == 1),
+        _ => Err(invalid(format!("v value {v} is not 0/1, 27/28 or EIP-155"))),
+    }
+}
+
+impl RecoverableSignature {
+    pub fn new(r: [u8; 32], s: [u8; 32], y_parity: bool) -> Self {
+        Self { r, s, y_parity }
+    }
+
+    /// The `(r, s, parity)` parts
+    pub fn into_parts(self) -> ([u8; 32], [u8; 32], bool) {
+        (self.r, self.s, self.y_parity)
+    }
+
+    /// Converts an ethers signature, accepting any `v` encoding understood
+    /// by [`y_parity_from_v`]
+    pub fn from_ethers(sig: &Signature) -> Result<Self, CKMSError> {
+        let mut r = [0u8; 32];
+        let mut s = [0u8; 32];
+        sig.r.to_big_endian(&mut r);
+        sig.s.to_big_endian(&mut s);
+        Ok(Self::new(r, s, y_parity_from_v(sig.v)?))
+    }
+
+    /// An ethers signature with `v` = 27/28, as used by `personal_sign` and
+    /// EIP-712
+    pub fn to_ethers(&self) -> Signature {
+        self.to_ethers_with_v(27 + self.y_parity as u64)
+    }
+
+    /// An ethers signature with the EIP-155 `v` for `chain_id`
+    pub fn to_ethers_eip155(&self, chain_id: u64) -> Result<Signature, CKMSError> {
+        let mut sig = self.to_ethers_with_v(self.y_parity as u64);
+        crate::apply_eip155(&mut sig, chain_id)?;
+        Ok(sig)
+    }
+
+    /// An ethers signature with `v` = 0/1, as used by typed transactions
+    pub fn to_ethers_y_parity(&self) -> Signature {
+        self.to_ethers_with_v(self.y_parity as u64)
+    }
+
+    fn to_ethers_with_v(self, v: u64) -> Signature {
+        Signature {
+            r: U256::from_big_endian(&self.r),
+            s: U256::from_big_endian(&self.s),
+            v,
+        }
+    }
+
+    /// Parses the 65-byte `r || s || v` form, accepting 0/1 or 27/28 as `v`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CKMSError> {
+        let bytes: &[u8; 65] = bytes
+            .try_into()
+            .map_err(|_| invalid(format!("expected 65 bytes, got {}", bytes.len())))?;
+        let v = bytes[64] as u64;
+        if v > 28 {
+            return Err(invalid(format!("v byte {v} is not 0/1 or 27/28")));
+        }
+        Ok(Self::new(
+            bytes[..32].try_into().unwrap(),
+            bytes[32..64].try_into().unwrap(),
+            y_parity_from_v(v)?,
+        ))
+    }
+
+    /// The 65-byte `r || s || v` form with `v` = 27/28
+    pub fn to_bytes(&self) -> [u8; 65] {
+        let mut out = [0u8; 65];
+        out[..32].copy_from_slice(&self.r);
+        out[32..64].copy_from_slice(&self.s);
+        out[64] = 27 + self.y_parity as u8;
+        out
+    }
+
+    /// Parses the 64-byte EIP-2098 compact form, `r || yParityAndS`
+    pub fn from_compact(bytes: &[u8]) -> Result<Self, CKMSError> {
+        let bytes: &[u8; 64] = bytes
+            .try_into()
+            .map_err(|_| invalid(format!("expected 64 bytes, got {}", bytes.len())))?;
+        let mut s: [u8; 32] = bytes[32..].try_into().unwrap();
+        let y_parity = s[0] & 0x80 != 0;
+        s[0] &= 0x7f;
+        Ok(Self::new(bytes[..32].try_into().unwrap(), s, y_parity))
+    }
+
+    /// The 64-byte EIP-2098 compact form. Only low-s signatures can be
+    /// represented, since the parity is stored in the top bit of `s`.
+    pub fn to_compact(&self) -> Result<[u8; 64], CKMSError> {
+        if self.s[0] & 0x80 != 0 {
+            return Err(invalid("high-s signatures have no EIP-2098 form"));
+        }
+        let mut out = [0u8; 64];
+        out[..32].copy_from_slice(&self.r);
+        out[32..].copy_from_slice(&self.s);
+        out[32] |= (self.y_parity as u8) << 7;
+        Ok(out)
+    }
+
+    /// Parses an ASN.1 DER signature, as returned by KMS. DER carries no
+    /// recovery id, so the parity must be supplied, e.g. from
+    /// [`find_recovery_id`](crate::find_recovery_id).
+    pub fn from_der(der: &[u8], y_parity: bool) -> Result<Self, CKMSError> {
+        let sig = KSig::from_der(der).map_err(|e| invalid(e.to_string()))?;
+        Ok(Self::from_k256(&sig, RecoveryId::new(y_parity, false)))
+    }
+
+    /// The ASN.1 DER form, without the recovery id
+    pub fn to_der(&self) -> Result<Vec<u8>, CKMSError> {
+        Ok(self.to_k256()?.0.to_der().as_bytes().to_vec())
+    }
+
+    pub fn from_k256(sig: &KSig, recovery_id: RecoveryId) -> Self {
+        let (r, s) = sig.split_bytes();
+        Self::new(r.into(), s.into(), recovery_id.is_y_odd())
+    }
+
+    /// The k256 signature and recovery id. Fails if `r` or `s` is zero or not
+    /// below the curve order.
+    pub fn to_k256(&self) -> Result<(KSig, RecoveryId), CKMSError> {
+        let sig = KSig::from_scalars(self.r, self.s).map_err(|e| invalid(e.to_string()))?;
+        Ok((sig, RecoveryId::new(self.y_parity, false)))
+    }
+}
+
+impl From<([u8; 32], [u8; 32], bool)> for RecoverableSignature {
+    fn from((r, s, y_parity): ([u8; 32], [u8; 32], bool)) -> Self {
+        Self::new(r, s, y_parity)
+    }
+}
+
+impl From<RecoverableSignature> for ([u8; 32], [u8; 32], bool) {
+    fn from(sig: RecoverableSignature) -> Self {
+        sig.into_parts()
+    }
+}
+
+impl TryFrom<&Signature> for RecoverableSignature {
+    type Error = CKMSError;
+
+    fn try_from(sig: &Signature) -> Result<Self, Self::Error> {
+        Self::from_ethers(sig)
+    }
+}
+
+impl From<RecoverableSignature> for Signature {
+    fn from(sig: RecoverableSignature) -> Self {
+        sig.to_ethers()
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for ethers::types::Signature {}
+}
+
+/// EIP-2098 and 65-byte helpers on ethers signatures, such as those returned
+/// by [`GcpKmsSigner`](crate::GcpKmsSigner). Sealed, so methods can be added
+/// without breaking downstream code.
+pub trait SignatureExt: sealed::Sealed + Sized {
+    /// The 64-byte EIP-2098 compact form, `r || yParityAndS`
+    fn to_compact(&self) -> Result<[u8; 64], CKMSError>;
+
+    /// Parses an EIP-2098 compact signature, with `v` = 27/28
+    fn from_compact(bytes: &[u8]) -> Result<Self, CKMSError>;
+
+    /// The 65-byte `r || s || v` form with `v` = 27/28, whatever the `v`
+    /// encoding of the signature
+    fn to_rsv_bytes(&self) -> Result<[u8; 65], CKMSError>;
+
+    /// Parses a 65-byte `r || s || v` signature, with `v` = 27/28
+    fn from_rsv_bytes(bytes: &[u8]) -> Result<Self, CKMSError>;
+}
+
+impl SignatureExt for Signature {
+    fn to_compact(&self) -> Result<[u8; 64], CKMSError> {
+        RecoverableSignature::from_ethers(self)?.to_compact()
+    }
+
+    fn from_compact(bytes: &[u8]) -> Result<Self, CKMSError> {
+        Ok(RecoverableSignature::from_compact(bytes)?.to_ethers())
+    }
+
+    fn to_rsv_bytes(&self) -> Result<[u8; 65], CKMSError> {
+        Ok(RecoverableSignature::from_ethers(self)?.to_bytes())
+    }
+
+    fn from_rsv_bytes(bytes: &[u8]) -> Result<Self, CKMSError> {
+        Ok(RecoverableSignature::from_bytes(bytes)?.to_ethers())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::H256,
+        utils::keccak256,
+    };
+
+    fn wallet_signature() -> (LocalWallet, H256, Signature) {
+        let wallet = LocalWallet::from_bytes(&[7u8; 32]).unwrap();
+        let digest = H256::from(keccak256(b"convert me"));
+        let sig = wallet.sign_hash(digest).unwrap();
+        (wallet, digest, sig)
+    }
+
+    #[test]
+    fn round_trips_every_representation() {
+        let (wallet, digest, sig) = wallet_signature();
+        let canonical = RecoverableSignature::from_ethers(&sig).unwrap();
+        assert_eq!(canonical.to_ethers(), sig);
+
+        let bytes = canonical.to_bytes();
+        assert_eq!(bytes.to_vec(), sig.to_vec());
+        assert_eq!(RecoverableSignature::from_bytes(&bytes).unwrap(), canonical);
+
+        let compact = canonical.to_compact().unwrap();
+        assert_eq!(
+            RecoverableSignature::from_compact(&compact).unwrap(),
+            canonical
+        );
+
+        let der = canonical.to_der().unwrap();
+        assert_eq!(
+            RecoverableSignature::from_der(&der, canonical.y_parity).unwrap(),
+            canonical
+        );
+
+        let (k256_sig, recovery_id) = canonical.to_k256().unwrap();
+        assert_eq!(
+            RecoverableSignature::from_k256(&k256_sig, recovery_id),
+            canonical
+        );
+
+        let eip155 = canonical.to_ethers_eip155(1).unwrap();
+        assert_eq!(eip155.v, 37 + canonical.y_parity as u64);
+        assert_eq!(
+            RecoverableSignature::from_ethers(&eip155).unwrap(),
+            canonical
+        );
+        eip155.verify(digest, wallet.address()).unwrap();
+        canonical
+            .to_ethers_y_parity()
+            .verify(digest, wallet.address())
+            .unwrap();
+    }
+
+    #[test]
+    fn signature_ext_round_trips() {
+        let (wallet, digest, sig) = wallet_signature();
+        let compact = sig.to_compact().unwrap();
+        assert_eq!(Signature::from_compact(&compact).unwrap(), sig);
+        assert_eq!(
+            Signature::from_rsv_bytes(&sig.to_rsv_bytes().unwrap()).unwrap(),
+            sig
+        );
+
+        // EIP-155 and y-parity `v` values produce the same bytes
+        let mut eip155 = sig;
+        eip155.v = 37 + (sig.v - 27);
+        assert_eq!(eip155.to_compact().unwrap(), compact);
+        assert_eq!(eip155.to_rsv_bytes().unwrap(), sig.to_rsv_bytes().unwrap());
+        Signature::from_compact(&compact)
+            .unwrap()
+            .verify(digest, wallet.address())
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let (_, _, sig) = wallet_signature();
+        let canonical = RecoverableSignature::from_ethers(&sig).unwrap();
+
+        assert!(RecoverableSignature::from_bytes(&[0u8; 64]).is_err());
+        let mut bytes = canonical.to_bytes();
+        bytes[64] = 29;
+        assert!(RecoverableSignature::from_bytes(&bytes).is_err());
+        assert!(RecoverableSignature::from_compact(&[0u8; 65]).is_err());
+        assert!(RecoverableSignature::from_der(&[0x30, 0x00], false).is_err());
+        assert!(RecoverableSignature::new([0; 32], [0; 32], false)
+            .to_k256()
+            .is_err());
+
+        let high_s = RecoverableSignature::new(canonical.r, [0xff; 32], false);
+        assert!(high_s.to_compact().is_err());
+
+        for v in [2, 26, 29, 34] {
+            assert!(y_parity_from_v(v).is_err());
+        }
+    }
+}
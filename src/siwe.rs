@@ -0,0 +1,431 @@
+//! Sign-In with Ethereum (EIP-4361) messages, for authenticating to services
+//! as the KMS key's address.
+use std::{fmt, str::FromStr, time::SystemTime};
+
+use chrono::{DateTime, FixedOffset, SecondsFormat, Timelike, Utc};
+use ethers::{
+    types::{Address, Signature},
+    utils::{hash_message, to_checksum},
+};
+
+use crate::{CKMSError, GcpKmsSigner};
+
+const PREAMBLE: &str = " wants you to sign in with your Ethereum account:";
+
+fn invalid(reason: impl Into<String>) -> CKMSError {
+    CKMSError::SiweError(reason.into())
+}
+
+/// A Sign-In with Ethereum message, which displays as its EIP-4361 text
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SiweMessage {
+    /// The URI scheme of the origin, if not `https`
+    pub scheme: Option<String>,
+    /// The RFC 3986 authority requesting the signature
+    pub domain: String,
+    pub address: Address,
+    /// A human-readable assertion, on a single line
+    pub statement: Option<String>,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    /// A random value of at least 8 alphanumeric characters, chosen by the
+    /// service to prevent replays
+    pub nonce: String,
+    pub issued_at: DateTime<FixedOffset>,
+    pub expiration_time: Option<DateTime<FixedOffset>>,
+    pub not_before: Option<DateTime<FixedOffset>>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+impl SiweMessage {
+    pub fn new(
+        domain: impl Into<String>,
+        address: Address,
+        uri: impl Into<String>,
+        chain_id: u64,
+        nonce: impl Into<String>,
+        issued_at: DateTime<FixedOffset>,
+    ) -> Self {
+        Self {
+            scheme: None,
+            domain: domain.into(),
+            address,
+            statement: None,
+            uri: uri.into(),
+            version: "1".to_string(),
+            chain_id,
+            nonce: nonce.into(),
+            issued_at,
+            expiration_time: None,
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        }
+    }
+
+    pub fn with_scheme(mut self, scheme: impl Into<String>) -> Self {
+        self.scheme = Some(scheme.into());
+        self
+    }
+
+    pub fn with_statement(mut self, statement: impl Into<String>) -> Self {
+        self.statement = Some(statement.into());
+        self
+    }
+
+    pub fn with_expiration_time(mut self, expiration_time: DateTime<FixedOffset>) -> Self {
+        self.expiration_time = Some(expiration_time);
+        self
+    }
+
+    pub fn with_not_before(mut self, not_before: DateTime<FixedOffset>) -> Self {
+        self.not_before = Some(not_before);
+        self
+    }
+
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    pub fn with_resources(mut self, resources: Vec<String>) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Checks the message against the EIP-4361 grammar's constraints
+    pub fn validate(&self) -> Result<(), CKMSError> {
+        if self.domain.is_empty() || self.domain.contains(char::is_whitespace) {
+            return Err(invalid(format!("invalid domain {:?}", self.domain)));
+        }
+        if self.statement.as_ref().is_some_and(|s| s.contains('\n')) {
+            return Err(invalid("statement must be a single line"));
+        }
+        if self.version != "1" {
+            return Err(invalid(format!("unsupported version {:?}", self.version)));
+        }
+        if self.nonce.len() < 8 || !self.nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(invalid("nonce must be at least 8 alphanumeric characters"));
+        }
+        Ok(())
+    }
+
+    /// Checks that `signature` is this message signed by its address, and
+    /// that the message is valid at `now`. The service must still check the
+    /// domain and nonce against the ones it issued.
+    pub fn verify(&self, signature: &Signature, now: SystemTime) -> Result<(), CKMSError> {
+        self.validate()?;
+        let now: DateTime<Utc> = now.into();
+        if self.expiration_time.is_some_and(|expiry| now >= expiry) {
+            return Err(invalid("message has expired"));
+        }
+        if self.not_before.is_some_and(|not_before| now < not_before) {
+            return Err(invalid("message is not yet valid"));
+        }
+        let signer = signature
+            .recover(hash_message(self.to_string()))
+            .map_err(|e| CKMSError::InvalidSignature(e.to_string()))?;
+        if signer != self.address {
+            return Err(CKMSError::InvalidSignature(format!(
+                "signature is from {signer:?}, not {:?}",
+                self.address
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn timestamp(time: &DateTime<FixedOffset>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+impl fmt::Display for SiweMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(scheme) = &self.scheme {
+            write!(f, "{scheme}://")?;
+        }
+        writeln!(f, "{}{PREAMBLE}", self.domain)?;
+        writeln!(f, "{}", to_checksum(&self.address, None))?;
+        writeln!(f)?;
+        if let Some(statement) = &self.statement {
+            writeln!(f, "{statement}")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "URI: {}", self.uri)?;
+        writeln!(f, "Version: {}", self.version)?;
+        writeln!(f, "Chain ID: {}", self.chain_id)?;
+        writeln!(f, "Nonce: {}", self.nonce)?;
+        write!(f, "Issued At: {}", timestamp(&self.issued_at))?;
+        if let Some(expiration_time) = &self.expiration_time {
+            write!(f, "\nExpiration Time: {}", timestamp(expiration_time))?;
+        }
+        if let Some(not_before) = &self.not_before {
+            write!(f, "\nNot Before: {}", timestamp(not_before))?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, "\nRequest ID: {request_id}")?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, "\nResources:")?;
+            for resource in &self.resources {
+                write!(f, "\n- {resource}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The lines of a message, consumed in grammar order
+struct Lines<'a>(std::iter::Peekable<std::str::Split<'a, char>>);
+
+impl<'a> Lines<'a> {
+    fn tagged(&mut self, tag: &str) -> Option<&'a str> {
+        let value = self.0.peek()?.strip_prefix(tag)?;
+        self.0.next();
+        Some(value)
+    }
+
+    fn required(&mut self, tag: &str) -> Result<&'a str, CKMSError> {
+        self.tagged(tag)
+            .ok_or_else(|| invalid(format!("missing `{}` line", tag.trim_end())))
+    }
+
+    fn time(&mut self, tag: &str) -> Result<Option<DateTime<FixedOffset>>, CKMSError> {
+        self.tagged(tag)
+            .map(|value| {
+                DateTime::parse_from_rfc3339(value)
+                    .map_err(|e| invalid(format!("invalid `{}` {value:?}: {e}", tag.trim_end())))
+            })
+            .transpose()
+    }
+}
+
+impl FromStr for SiweMessage {
+    type Err = CKMSError;
+
+    /// Parses the EIP-4361 text of a message
+    fn from_str(text: &str) -> Result<Self, CKMSError> {
+        let mut lines = Lines(text.split('\n').peekable());
+        let origin = lines
+            .0
+            .next()
+            .and_then(|line| line.strip_suffix(PREAMBLE))
+            .ok_or_else(|| invalid("missing preamble"))?;
+        let (scheme, domain) = match origin.split_once("://") {
+            Some((scheme, domain)) => (Some(scheme.to_string()), domain),
+            None => (None, origin),
+        };
+
+        let address_line = lines.0.next().ok_or_else(|| invalid("missing address"))?;
+        let address: Address = address_line
+            .parse()
+            .map_err(|_| invalid(format!("invalid address {address_line:?}")))?;
+        if to_checksum(&address, None) != address_line {
+            return Err(invalid("address is not EIP-55 checksummed"));
+        }
+
+        if lines.0.next() != Some("") {
+            return Err(invalid("missing blank line after address"));
+        }
+        let statement = match lines.0.next() {
+            Some("") => None,
+            Some(statement) => {
+                if lines.0.next() != Some("") {
+                    return Err(invalid("missing blank line after statement"));
+                }
+                Some(statement.to_string())
+            }
+            None => return Err(invalid("message ends after address")),
+        };
+
+        let uri = lines.required("URI: ")?.to_string();
+        let version = lines.required("Version: ")?.to_string();
+        let chain_id = lines.required("Chain ID: ")?;
+        let chain_id = chain_id
+            .parse()
+            .map_err(|_| invalid(format!("invalid chain id {chain_id:?}")))?;
+        let nonce = lines.required("Nonce: ")?.to_string();
+        let issued_at = lines
+            .time("Issued At: ")?
+            .ok_or_else(|| invalid("missing `Issued At:` line"))?;
+        let expiration_time = lines.time("Expiration Time: ")?;
+        let not_before = lines.time("Not Before: ")?;
+        let request_id = lines.tagged("Request ID: ").map(ToString::to_string);
+        let mut resources = Vec::new();
+        if lines.tagged("Resources:") == Some("") {
+            while let Some(resource) = lines.tagged("- ") {
+                resources.push(resource.to_string());
+            }
+        }
+        if let Some(line) = lines.0.next() {
+            return Err(invalid(format!("unexpected line {line:?}")));
+        }
+
+        let message = SiweMessage {
+            scheme,
+            domain: domain.to_string(),
+            address,
+            statement,
+            uri,
+            version,
+            chain_id,
+            nonce,
+            issued_at,
+            expiration_time,
+            not_before,
+            request_id,
+            resources,
+        };
+        message.validate()?;
+        Ok(message)
+    }
+}
+
+impl GcpKmsSigner {
+    /// Starts a message for signing in to `domain` as this signer's address,
+    /// on its chain, issued now by the signer's clock
+    pub async fn siwe_message(
+        &self,
+        domain: impl Into<String>,
+        uri: impl Into<String>,
+        nonce: impl Into<String>,
+    ) -> Result<SiweMessage, CKMSError> {
+        let issued_at = DateTime::<Utc>::from(self.clock.now())
+            .with_nanosecond(0)
+            .unwrap_or_default();
+        Ok(SiweMessage::new(
+            domain,
+            self.resolve_address().await?,
+            uri,
+            self.snapshot().chain_id,
+            nonce,
+            issued_at.into(),
+        ))
+    }
+
+    /// Signs a Sign-In with Ethereum message as an EIP-191 personal message,
+    /// with `v` = 27/28 as verifiers expect. The message must be for this
+    /// signer's address.
+    pub async fn sign_siwe_message(&self, message: &SiweMessage) -> Result<Signature, CKMSError> {
+        message.validate()?;
+        let address = self.resolve_address().await?;
+        if message.address != address {
+            return Err(invalid(format!(
+                "message is for {:?}, not the signer's {address:?}",
+                message.address
+            )));
+        }
+        let notes = vec![format!("siwe_domain={}", message.domain)];
+        self.sign_message_digest(hash_message(message.to_string()), notes)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// The example from EIP-4361
+    const EXAMPLE: &str = "service.org wants you to sign in with your Ethereum account:
+0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2
+
+I accept the ServiceOrg Terms of Service: https://service.org/tos
+
+URI: https://service.org/login
+Version: 1
+Chain ID: 1
+Nonce: 32891756
+Issued At: 2021-09-30T16:25:24Z
+Resources:
+- ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/
+- https://example.com/my-web2-claim.json";
+
+    fn issued_at() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2021-09-30T16:25:24Z").unwrap()
+    }
+
+    #[test]
+    fn round_trips_the_example() {
+        let message: SiweMessage = EXAMPLE.parse().unwrap();
+        assert_eq!(message.domain, "service.org");
+        assert_eq!(message.nonce, "32891756");
+        assert_eq!(message.resources.len(), 2);
+        assert_eq!(message.to_string(), EXAMPLE);
+
+        let built = SiweMessage::new(
+            "service.org",
+            message.address,
+            "https://service.org/login",
+            1,
+            "32891756",
+            issued_at(),
+        )
+        .with_statement("I accept the ServiceOrg Terms of Service: https://service.org/tos")
+        .with_resources(message.resources.clone());
+        assert_eq!(built, message);
+    }
+
+    #[test]
+    fn without_statement_and_with_optional_fields() {
+        let message = SiweMessage::new(
+            "localhost:4361",
+            Address::repeat_byte(0xab),
+            "https://localhost:4361/",
+            5,
+            "abcdefgh12",
+            issued_at(),
+        )
+        .with_scheme("http")
+        .with_expiration_time(issued_at() + chrono::Duration::hours(1))
+        .with_request_id("req-1");
+        let text = message.to_string();
+        assert!(text.starts_with("http://localhost:4361 wants you"));
+        assert!(text.contains("\n\n\nURI: "));
+        assert!(text.contains("\nExpiration Time: 2021-09-30T17:25:24Z\n"));
+        assert_eq!(text.parse::<SiweMessage>().unwrap(), message);
+
+        assert!(matches!(
+            SiweMessage {
+                nonce: "short".to_string(),
+                ..message.clone()
+            }
+            .validate(),
+            Err(CKMSError::SiweError(_))
+        ));
+        assert!(EXAMPLE
+            .replace("0xC02aaA", "0xc02aaa")
+            .parse::<SiweMessage>()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn verifies_signatures_and_validity() {
+        let wallet = LocalWallet::from_bytes(&[7u8; 32]).unwrap();
+        let message = SiweMessage::new(
+            "service.org",
+            wallet.address(),
+            "https://service.org/login",
+            1,
+            "32891756",
+            issued_at(),
+        )
+        .with_expiration_time(issued_at() + chrono::Duration::minutes(5));
+        let signature = wallet.sign_message(message.to_string()).await.unwrap();
+
+        let at =
+            |secs: u64| UNIX_EPOCH + Duration::from_secs(issued_at().timestamp() as u64 + secs);
+        message.verify(&signature, at(60)).unwrap();
+        assert!(message.verify(&signature, at(600)).is_err());
+
+        let other = SiweMessage {
+            address: Address::repeat_byte(1),
+            ..message
+        };
+        assert!(other.verify(&signature, at(60)).is_err());
+    }
+}
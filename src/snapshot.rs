@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+// `RUSTFLAGS="--cfg kms_loom" cargo test --lib snapshot` model-checks the
+// cell. The cfg is not plain `loom`, which compiles tokio's `net` module out.
+#[cfg(kms_loom)]
+use loom::sync::RwLock;
+#[cfg(not(kms_loom))]
+use std::sync::RwLock;
+
+use ethers::types::{transaction::eip2718::TypedTransaction, Address};
+use k256::ecdsa::VerifyingKey;
+use tokio::sync::OnceCell;
+
+use crate::{policy, verifying_key_to_address, HighSPolicy, TxType};
+
+/// A signer's key and policies as one operation sees them. Operations load
+/// the snapshot once, so a concurrent change never mixes old and new settings
+/// within a signature.
+#[derive(Clone, Debug)]
+pub(crate) struct Snapshot {
+    pub(crate) key_version: u64,
+    /// Shared between snapshots of the same key version, so a lazy signer's
+    /// key is fetched once
+    pub(crate) verifying_key: Arc<OnceCell<VerifyingKey>>,
+    pub(crate) chain_id: u64,
+    pub(crate) allowed_tx_types: Option<Vec<TxType>>,
+    pub(crate) eip155_message_v: bool,
+    pub(crate) high_s_policy: HighSPolicy,
+    pub(crate) replay_protection: bool,
+    pub(crate) strict_chain_id: bool,
+}
+
+impl Snapshot {
+    /// The signer's address, if its public key is known
+    pub(crate) fn address(&self) -> Option<Address> {
+        self.verifying_key.get().map(verifying_key_to_address)
+    }
+
+    /// Describes the policies which apply to an operation, as
+    /// `rule=decision`, for receipts
+    pub(crate) fn policy_decisions(&self, tx: Option<&TypedTransaction>) -> Vec<String> {
+        let mut decisions = Vec::new();
+        if let (Some(allowed), Some(tx)) = (&self.allowed_tx_types, tx) {
+            let decision = match policy::check_tx_type(allowed, TxType::of(tx)) {
+                Ok(()) => "allowed",
+                Err(_) => "denied",
+            };
+            decisions.push(format!("tx_type_allowlist={decision}"));
+        }
+        decisions.push(format!("high_s_policy={}", self.high_s_policy));
+        decisions
+    }
+}
+
+/// Holds the current [`Snapshot`], which is only ever replaced as a whole
+#[derive(Debug)]
+pub(crate) struct SnapshotCell {
+    current: RwLock<Arc<Snapshot>>,
+}
+
+impl SnapshotCell {
+    pub(crate) fn new(snapshot: Snapshot) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(snapshot)),
+        }
+    }
+
+    pub(crate) fn load(&self) -> Arc<Snapshot> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Replaces the snapshot with an updated copy. Concurrent updates are
+    /// applied one after another, so none is lost.
+    pub(crate) fn update(&self, f: impl FnOnce(&mut Snapshot)) {
+        let mut current = self.current.write().unwrap();
+        let mut next = Snapshot::clone(&current);
+        f(&mut next);
+        *current = Arc::new(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(version: u64) -> Snapshot {
+        Snapshot {
+            key_version: version,
+            verifying_key: Arc::new(OnceCell::new()),
+            chain_id: version,
+            allowed_tx_types: None,
+            eip155_message_v: false,
+            high_s_policy: HighSPolicy::default(),
+            replay_protection: true,
+            strict_chain_id: false,
+        }
+    }
+
+    #[cfg(not(kms_loom))]
+    #[test]
+    fn readers_never_see_torn_snapshots() {
+        let cell = Arc::new(SnapshotCell::new(snapshot(1)));
+        let writers: Vec<_> = (0..2)
+            .map(|_| {
+                let cell = cell.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        cell.update(|s| {
+                            s.key_version += 1;
+                            s.chain_id = s.key_version;
+                        });
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let cell = cell.clone();
+                std::thread::spawn(move || {
+                    let mut last = 0;
+                    for _ in 0..10_000 {
+                        let snapshot = cell.load();
+                        assert_eq!(snapshot.key_version, snapshot.chain_id);
+                        assert!(snapshot.key_version >= last);
+                        last = snapshot.key_version;
+                    }
+                })
+            })
+            .collect();
+        for thread in writers.into_iter().chain(readers) {
+            thread.join().unwrap();
+        }
+        assert_eq!(cell.load().key_version, 20_001);
+    }
+
+    /// Every interleaving of two updates and a read: the reader sees a
+    /// whole snapshot from before, between or after them, and neither
+    /// update is lost
+    #[cfg(kms_loom)]
+    #[test]
+    fn loom_updates_and_loads() {
+        loom::model(|| {
+            let cell = loom::sync::Arc::new(SnapshotCell::new(snapshot(1)));
+            let writers: Vec<_> = (0..2)
+                .map(|_| {
+                    let cell = cell.clone();
+                    loom::thread::spawn(move || {
+                        cell.update(|s| {
+                            s.key_version += 1;
+                            s.chain_id = s.key_version;
+                        })
+                    })
+                })
+                .collect();
+
+            let snapshot = cell.load();
+            assert_eq!(snapshot.key_version, snapshot.chain_id);
+            assert!((1..=3).contains(&snapshot.key_version));
+
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            assert_eq!(cell.load().key_version, 3);
+        });
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use ethers::types::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::{CKMSError, Clock, SystemClock};
+
+/// Persistence for stateful signing features, such as typed data replay
+/// protection. Values are opaque bytes; each feature namespaces its keys.
+///
+/// Implementations sharing one backend across processes, e.g. Redis or
+/// Firestore, let those processes share the state.
+/// [`Store::compare_and_swap`] must be atomic with respect to every other
+/// operation on the same key.
+#[async_trait]
+pub trait Store: fmt::Debug + Send + Sync {
+    /// Returns the value of a key, unless it is absent or has expired
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CKMSError>;
+
+    /// Sets a key, which expires after `ttl` if given
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), CKMSError>;
+
+    async fn delete(&self, key: &str) -> Result<(), CKMSError>;
+
+    /// Replaces the value of a key only if it is currently `current`, with
+    /// `None` meaning absent. Setting `new` to `None` deletes the key.
+    /// Returns whether the swap happened.
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        current: Option<&[u8]>,
+        new: Option<Vec<u8>>,
+        ttl: Option<Duration>,
+    ) -> Result<bool, CKMSError>;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Entry {
+    value: Bytes,
+    expires_at_ms: Option<u64>,
+}
+
+/// Entries shared by the in-memory and file stores
+#[derive(Debug, Default)]
+struct Entries(HashMap<String, Entry>);
+
+impl Entries {
+    fn live(&mut self, key: &str, now_ms: u64) -> Option<&Entry> {
+        let expired = self
+            .0
+            .get(key)
+            .and_then(|entry| entry.expires_at_ms)
+            .is_some_and(|expires_at_ms| expires_at_ms <= now_ms);
+        if expired {
+            self.0.remove(key);
+        }
+        self.0.get(key)
+    }
+
+    fn set(&mut self, key: &str, value: Option<Vec<u8>>, ttl: Option<Duration>, now_ms: u64) {
+        let Some(value) = value else {
+            self.0.remove(key);
+            return;
+        };
+        let expires_at_ms = ttl.map(|ttl| now_ms.saturating_add(ttl.as_millis() as u64));
+        self.0.insert(
+            key.to_string(),
+            Entry {
+                value: value.into(),
+                expires_at_ms,
+            },
+        );
+    }
+
+    fn compare_and_swap(
+        &mut self,
+        key: &str,
+        current: Option<&[u8]>,
+        new: Option<Vec<u8>>,
+        ttl: Option<Duration>,
+        now_ms: u64,
+    ) -> bool {
+        let live = self.live(key, now_ms).map(|entry| entry.value.as_ref());
+        if live != current {
+            return false;
+        }
+        self.set(key, new, ttl, now_ms);
+        true
+    }
+
+    fn prune(&mut self, now_ms: u64) {
+        self.0.retain(|_, entry| {
+            entry
+                .expires_at_ms
+                .is_none_or(|expires_at_ms| expires_at_ms > now_ms)
+        });
+    }
+}
+
+/// A [`Store`] held in process memory, and lost when the process exits
+#[derive(Debug)]
+pub struct MemoryStore {
+    entries: Mutex<Entries>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the time source for expiry. Defaults to [`SystemClock`].
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CKMSError> {
+        let mut entries = self.entries.lock().unwrap();
+        Ok(entries
+            .live(key, self.clock.now_ms())
+            .map(|entry| entry.value.to_vec()))
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), CKMSError> {
+        let mut entries = self.entries.lock().unwrap();
+        let now_ms = self.clock.now_ms();
+        entries.prune(now_ms);
+        entries.set(key, Some(value), ttl, now_ms);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), CKMSError> {
+        self.entries.lock().unwrap().0.remove(key);
+        Ok(())
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        current: Option<&[u8]>,
+        new: Option<Vec<u8>>,
+        ttl: Option<Duration>,
+    ) -> Result<bool, CKMSError> {
+        let mut entries = self.entries.lock().unwrap();
+        let now_ms = self.clock.now_ms();
+        entries.prune(now_ms);
+        Ok(entries.compare_and_swap(key, current, new, ttl, now_ms))
+    }
+}
+
+/// A [`Store`] persisted to a JSON file, which survives restarts of a single
+/// process. The whole file is rewritten on every change, so it suits small
+/// amounts of state; it must not be shared between processes.
+pub struct FileStore {
+    path: PathBuf,
+    /// Held across writes, so the file is replaced in the order of changes
+    entries: tokio::sync::Mutex<Entries>,
+    clock: Arc<dyn Clock>,
+}
+
+impl fmt::Debug for FileStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileStore")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FileStore {
+    /// Opens the store at `path`, creating it on the first write if it does
+    /// not exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, CKMSError> {
+        let path = path.as_ref().to_path_buf();
+        let entries = match std::fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| CKMSError::StoreError(format!("{}: {e}", path.display())))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(CKMSError::StoreError(format!("{}: {e}", path.display()))),
+        };
+        Ok(Self {
+            path,
+            entries: tokio::sync::Mutex::new(Entries(entries)),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Sets the time source for expiry. Defaults to [`SystemClock`].
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Writes the entries to a temporary file and renames it over the store,
+    /// so a crash never leaves a partial file
+    async fn persist(&self, entries: &Entries) -> Result<(), CKMSError> {
+        let error =
+            |e: &dyn fmt::Display| CKMSError::StoreError(format!("{}: {e}", self.path.display()));
+        let contents = serde_json::to_vec(&entries.0).map_err(|e| error(&e))?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        tokio::fs::write(&tmp, contents)
+            .await
+            .map_err(|e| error(&e))?;
+        tokio::fs::rename(&tmp, &self.path)
+            .await
+            .map_err(|e| error(&e))
+    }
+}
+
+#[async_trait]
+impl Store for FileStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CKMSError> {
+        let mut entries = self.entries.lock().await;
+        Ok(entries
+            .live(key, self.clock.now_ms())
+            .map(|entry| entry.value.to_vec()))
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), CKMSError> {
+        let mut entries = self.entries.lock().await;
+        let now_ms = self.clock.now_ms();
+        entries.prune(now_ms);
+        entries.set(key, Some(value), ttl, now_ms);
+        self.persist(&entries).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), CKMSError> {
+        let mut entries = self.entries.lock().await;
+        if entries.0.remove(key).is_some() {
+            self.persist(&entries).await?;
+        }
+        Ok(())
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        current: Option<&[u8]>,
+        new: Option<Vec<u8>>,
+        ttl: Option<Duration>,
+    ) -> Result<bool, CKMSError> {
+        let mut entries = self.entries.lock().await;
+        let now_ms = self.clock.now_ms();
+        entries.prune(now_ms);
+        let swapped = entries.compare_and_swap(key, current, new, ttl, now_ms);
+        if swapped {
+            self.persist(&entries).await?;
+        }
+        Ok(swapped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+    use std::time::UNIX_EPOCH;
+
+    async fn exercise(store: &dyn Store, clock: &ManualClock) {
+        assert_eq!(store.get("a").await.unwrap(), None);
+        assert!(store
+            .compare_and_swap("a", None, Some(vec![1]), Some(Duration::from_secs(10)))
+            .await
+            .unwrap());
+        assert!(!store
+            .compare_and_swap("a", None, Some(vec![2]), None)
+            .await
+            .unwrap());
+        assert!(store
+            .compare_and_swap(
+                "a",
+                Some(&[1]),
+                Some(vec![2]),
+                Some(Duration::from_secs(10))
+            )
+            .await
+            .unwrap());
+        assert_eq!(store.get("a").await.unwrap(), Some(vec![2]));
+
+        store.set("b", vec![3], None).await.unwrap();
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(store.get("a").await.unwrap(), None);
+        assert_eq!(store.get("b").await.unwrap(), Some(vec![3]));
+
+        store.delete("b").await.unwrap();
+        assert_eq!(store.get("b").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn memory_store() {
+        let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+        let store = MemoryStore::new().with_clock(clock.clone());
+        exercise(&store, &clock).await;
+    }
+
+    #[tokio::test]
+    async fn file_store_persists() {
+        let path = std::env::temp_dir().join(format!("kms-store-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+
+        let store = FileStore::open(&path).unwrap().with_clock(clock.clone());
+        exercise(&store, &clock).await;
+        store.set("c", vec![4], None).await.unwrap();
+        drop(store);
+
+        let reopened = FileStore::open(&path).unwrap();
+        assert_eq!(reopened.get("c").await.unwrap(), Some(vec![4]));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,266 @@
+//! Record/replay of KMS responses, VCR style. Recording runs a
+//! [`GcpKmsProvider`] against real KMS and saves each public key PEM and DER
+//! signature to a JSON fixture file; replaying serves them from the file,
+//! keyed by key version and request digest. Signers over a replaying
+//! cassette parse the real responses, so tests cover DER and PEM parsing and
+//! recovery id selection deterministically.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use ethers::{
+    prelude::k256::{ecdsa::VerifyingKey, pkcs8::DecodePublicKey},
+    types::{Bytes, H256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    CKMSError, GcpKeyRingRef, GcpKmsProvider, KeyBackend, KeyVersion, KmsKeyBackend, SigningContext,
+};
+
+/// The environment variable which makes [`KmsCassette::from_env`] record
+/// when set to `record`
+pub const CASSETTE_MODE_VAR: &str = "KMS_CASSETTE";
+
+/// A [`KmsKeyBackend`] which records KMS responses to, or replays them from,
+/// a fixture file. Clones share the recording.
+#[derive(Clone, Debug)]
+pub struct KmsCassette {
+    kms_key_ref: GcpKeyRingRef,
+    /// The provider responses are recorded from, or `None` when replaying
+    provider: Option<GcpKmsProvider>,
+    path: PathBuf,
+    recording: Arc<Mutex<Recording>>,
+}
+
+/// The contents of a fixture file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Recording {
+    /// PEM public keys, by key version name
+    public_keys: BTreeMap<String, String>,
+    /// Resolved key versions, by key name and alias
+    key_versions: BTreeMap<String, u64>,
+    /// Signatures, by key version name and digest
+    signatures: BTreeMap<String, RecordedSignature>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RecordedSignature {
+    digest: H256,
+    /// The DER signature, as returned by KMS
+    signature: Bytes,
+    /// The version KMS reported signing with
+    signed_version: u64,
+}
+
+impl KmsCassette {
+    /// Records the responses of `provider` to `path`, adding to any
+    /// responses already recorded there
+    pub fn record(provider: GcpKmsProvider, path: impl Into<PathBuf>) -> Result<Self, CKMSError> {
+        let path = path.into();
+        let recording = if path.exists() {
+            load(&path)?
+        } else {
+            Recording::default()
+        };
+        Ok(Self {
+            kms_key_ref: provider.key_ring_ref().clone(),
+            provider: Some(provider),
+            path,
+            recording: Arc::new(Mutex::new(recording)),
+        })
+    }
+
+    /// Replays the responses recorded to `path` for a key ring. Requests
+    /// which were not recorded fail with [`CKMSError::NoRecordedResponse`].
+    pub fn replay(kms_key_ref: GcpKeyRingRef, path: impl Into<PathBuf>) -> Result<Self, CKMSError> {
+        let path = path.into();
+        Ok(Self {
+            recording: Arc::new(Mutex::new(load(&path)?)),
+            kms_key_ref,
+            provider: None,
+            path,
+        })
+    }
+
+    /// Records with a [`GcpKmsProvider::new`] if [`CASSETTE_MODE_VAR`] is
+    /// `record`, and replays otherwise, so CI replays the fixtures a
+    /// developer with KMS access recorded
+    pub async fn from_env(
+        kms_key_ref: GcpKeyRingRef,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self, CKMSError> {
+        match std::env::var(CASSETTE_MODE_VAR).as_deref() {
+            Ok("record") => Self::record(GcpKmsProvider::new(kms_key_ref).await?, path),
+            _ => Self::replay(kms_key_ref, path),
+        }
+    }
+
+    /// Whether responses are recorded rather than replayed
+    pub fn is_recording(&self) -> bool {
+        self.provider.is_some()
+    }
+
+    /// Looks up a response, or records it with `record` if recording
+    async fn respond<T, R, F>(
+        &self,
+        request: String,
+        lookup: impl Fn(&Recording) -> Option<T>,
+        record: R,
+        save: impl FnOnce(&mut Recording, &T),
+    ) -> Result<T, CKMSError>
+    where
+        R: FnOnce(GcpKmsProvider) -> F,
+        F: std::future::Future<Output = Result<T, CKMSError>>,
+    {
+        let Some(provider) = &self.provider else {
+            let recording = self.recording.lock().unwrap();
+            return lookup(&recording).ok_or(CKMSError::NoRecordedResponse(request));
+        };
+        let response = record(provider.clone()).await?;
+        let mut recording = self.recording.lock().unwrap();
+        save(&mut recording, &response);
+        let json = serde_json::to_string_pretty(&*recording)
+            .map_err(|e| CKMSError::StoreError(e.to_string()))?;
+        std::fs::write(&self.path, json)
+            .map_err(|e| CKMSError::StoreError(format!("{}: {e}", self.path.display())))?;
+        Ok(response)
+    }
+}
+
+fn load(path: &Path) -> Result<Recording, CKMSError> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| CKMSError::StoreError(format!("{}: {e}", path.display())))?;
+    serde_json::from_str(&json).map_err(|e| CKMSError::StoreError(e.to_string()))
+}
+
+impl From<KmsCassette> for KeyBackend {
+    fn from(cassette: KmsCassette) -> Self {
+        Arc::new(cassette).into()
+    }
+}
+
+#[async_trait]
+impl KmsKeyBackend for KmsCassette {
+    async fn get_public_key(
+        &self,
+        key_id: &str,
+        key_version: u64,
+    ) -> Result<VerifyingKey, CKMSError> {
+        let name = self.key_version_name(key_id, key_version);
+        let pem = self
+            .respond(
+                name.clone(),
+                |recording| recording.public_keys.get(&name).cloned(),
+                |provider| async move { provider.get_public_key_pem(key_id, key_version).await },
+                |recording, pem| {
+                    recording.public_keys.insert(name.clone(), pem.clone());
+                },
+            )
+            .await?;
+        Ok(VerifyingKey::from_public_key_pem(&pem)?)
+    }
+
+    async fn sign_digest(
+        &self,
+        key_id: &str,
+        key_version: u64,
+        digest: [u8; 32],
+        context: &SigningContext,
+    ) -> Result<(Vec<u8>, u64), CKMSError> {
+        let request = format!(
+            "{}/{:?}",
+            self.key_version_name(key_id, key_version),
+            H256(digest)
+        );
+        let signed = self
+            .respond(
+                request.clone(),
+                |recording| recording.signatures.get(&request).cloned(),
+                |provider| async move {
+                    let (signature, signed_version) = provider
+                        .sign_digest_with_context(key_id, key_version, &digest, context)
+                        .await?;
+                    Ok(RecordedSignature {
+                        digest: H256(digest),
+                        signature: signature.into(),
+                        signed_version,
+                    })
+                },
+                |recording, signed| {
+                    recording.signatures.insert(request.clone(), signed.clone());
+                },
+            )
+            .await?;
+        Ok((signed.signature.to_vec(), signed.signed_version))
+    }
+
+    async fn resolve_key_version(
+        &self,
+        key_id: &str,
+        key_version: KeyVersion,
+    ) -> Result<u64, CKMSError> {
+        if let Some(version) = key_version.as_pinned() {
+            return Ok(version);
+        }
+        let request = format!("{}@{key_version}", self.key_name(key_id));
+        self.respond(
+            request.clone(),
+            |recording| recording.key_versions.get(&request).copied(),
+            |provider| async move { provider.resolve_key_version(key_id, key_version).await },
+            |recording, version| {
+                recording.key_versions.insert(request.clone(), *version);
+            },
+        )
+        .await
+    }
+
+    fn key_name(&self, key_id: &str) -> String {
+        self.kms_key_ref.to_crypto_key_ref(key_id)
+    }
+
+    fn key_version_name(&self, key_id: &str, key_version: u64) -> String {
+        self.kms_key_ref.to_key_version_ref(key_id, key_version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils::{FakeKmsServer, MockKmsProvider},
+        GcpKmsSigner,
+    };
+    use ethers::signers::Signer;
+
+    #[tokio::test]
+    async fn replays_recorded_responses() {
+        let key_ring = GcpKeyRingRef::new("project", "global", "ring");
+        let path = std::env::temp_dir().join(format!("kms-cassette-{}.json", std::process::id()));
+        let mock = MockKmsProvider::new(key_ring.clone()).await.unwrap();
+        let server = FakeKmsServer::start(mock).await.unwrap();
+
+        let recorder = KmsCassette::record(server.provider().await.unwrap(), &path).unwrap();
+        assert!(recorder.is_recording());
+        let signer = GcpKmsSigner::new(recorder, "key".to_string(), 1, 1)
+            .await
+            .unwrap();
+        let recorded = signer.sign_message("hello world").await.unwrap();
+        drop(server);
+
+        let player = KmsCassette::replay(key_ring, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let signer = GcpKmsSigner::new(player, "key".to_string(), 1, 1)
+            .await
+            .unwrap();
+        assert_eq!(signer.sign_message("hello world").await.unwrap(), recorded);
+        assert!(matches!(
+            signer.sign_message("other").await,
+            Err(CKMSError::NoRecordedResponse(_))
+        ));
+    }
+}
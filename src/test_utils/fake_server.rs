@@ -0,0 +1,413 @@
+//! A fake `KeyManagementService` serving the `GetPublicKey` and
+//! `AsymmetricSign` RPCs over a [`MockKmsProvider`]'s keys
+use std::{convert::Infallible, future::Future, net::SocketAddr};
+
+use ethers::prelude::k256::pkcs8::{EncodePublicKey, LineEnding};
+use gcloud_sdk::google::cloud::kms::v1::{
+    crypto_key_version::CryptoKeyVersionAlgorithm, digest, AsymmetricSignRequest,
+    AsymmetricSignResponse, GetPublicKeyRequest, PublicKey,
+};
+use tokio::{net::TcpListener, sync::oneshot};
+use tonic::{
+    body::BoxBody,
+    codec::ProstCodec,
+    codegen::{http, Body, BoxFuture, Context, Poll, Service, StdError},
+    server::{Grpc, NamedService, UnaryService},
+    Request, Response, Status,
+};
+
+use super::MockKmsProvider;
+use crate::{
+    CKMSError, ConnectionConfig, CredentialSource, GcpKmsProvider, KmsKeyBackend, SigningContext,
+};
+
+const SERVICE: &str = "google.cloud.kms.v1.KeyManagementService";
+const GET_PUBLIC_KEY: &str = "/google.cloud.kms.v1.KeyManagementService/GetPublicKey";
+const ASYMMETRIC_SIGN: &str = "/google.cloud.kms.v1.KeyManagementService/AsymmetricSign";
+
+/// A KMS gRPC server on a localhost port, for tests which run a real
+/// [`GcpKmsProvider`] without `GOOGLE_APPLICATION_CREDENTIALS`. Only
+/// `GetPublicKey` and `AsymmetricSign` are served; other RPCs fail with
+/// `UNIMPLEMENTED`. The server stops when dropped.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), ethers_gcp_kms_signer::CKMSError> {
+/// use ethers_gcp_kms_signer::{
+///     test_utils::{FakeKmsServer, MockKmsProvider},
+///     GcpKeyRingRef, GcpKmsSigner,
+/// };
+///
+/// let mock = MockKmsProvider::new(GcpKeyRingRef::new("project", "global", "ring")).await?;
+/// let server = FakeKmsServer::start(mock).await?;
+/// let signer = GcpKmsSigner::new(server.provider().await?, "key".to_string(), 1, 1).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FakeKmsServer {
+    addr: SocketAddr,
+    mock: MockKmsProvider,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl FakeKmsServer {
+    /// Serves the keys of `mock` on an ephemeral port of 127.0.0.1
+    pub async fn start(mock: MockKmsProvider) -> Result<Self, CKMSError> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| CKMSError::ServerError(format!("fake KMS: {e}")))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| CKMSError::ServerError(format!("fake KMS: {e}")))?;
+        let incoming = Box::pin(futures::stream::unfold(listener, |listener| async move {
+            let connection = listener.accept().await.map(|(stream, _)| stream);
+            Some((connection, listener))
+        }));
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        let server = tonic::transport::Server::builder()
+            .add_service(FakeKmsService(mock.clone()))
+            .serve_with_incoming_shutdown(incoming, async {
+                let _ = stopped.await;
+            });
+        tokio::spawn(server);
+
+        Ok(Self {
+            addr,
+            mock,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// The server's URL, e.g. `http://127.0.0.1:43117`
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The mock whose keys are served, for building the local signer a
+    /// test compares against
+    pub fn mock(&self) -> &MockKmsProvider {
+        &self.mock
+    }
+
+    /// Connects a provider for the mock's key ring to the server
+    pub async fn provider(&self) -> Result<GcpKmsProvider, CKMSError> {
+        GcpKmsProvider::new_with_config(self.mock.key_ring_ref().clone(), self.config()).await
+    }
+
+    /// The connection config of [`FakeKmsServer::provider`], for providers
+    /// of other key rings or with other options
+    pub fn config(&self) -> ConnectionConfig {
+        ConnectionConfig::default()
+            .with_endpoint(self.endpoint())
+            .with_credential_sources(vec![CredentialSource::AccessToken("fake".to_string())])
+    }
+}
+
+impl Drop for FakeKmsServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct FakeKmsService(MockKmsProvider);
+
+impl NamedService for FakeKmsService {
+    const NAME: &'static str = SERVICE;
+}
+
+impl<B> Service<http::Request<B>> for FakeKmsService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Infallible>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let mock = self.0.clone();
+        match request.uri().path() {
+            GET_PUBLIC_KEY => unary(Unary(mock, get_public_key), request),
+            ASYMMETRIC_SIGN => unary(Unary(mock, asymmetric_sign), request),
+            path => {
+                let status = Status::unimplemented(format!("fake KMS has no method {path}"));
+                Box::pin(async move { Ok(status.to_http()) })
+            }
+        }
+    }
+}
+
+/// A method's handler, with the mock it serves, as a [`UnaryService`]
+struct Unary<F>(MockKmsProvider, F);
+
+impl<F, Fut, Req, Res> UnaryService<Req> for Unary<F>
+where
+    F: Fn(MockKmsProvider, Req) -> Fut,
+    Fut: Future<Output = Result<Res, Status>> + Send + 'static,
+{
+    type Response = Res;
+    type Future = BoxFuture<Response<Res>, Status>;
+
+    fn call(&mut self, request: Request<Req>) -> Self::Future {
+        let Unary(mock, handler) = self;
+        let response = handler(mock.clone(), request.into_inner());
+        Box::pin(async move { response.await.map(Response::new) })
+    }
+}
+
+fn unary<S, Req, Res, B>(
+    service: S,
+    request: http::Request<B>,
+) -> BoxFuture<http::Response<BoxBody>, Infallible>
+where
+    S: UnaryService<Req, Response = Res> + Send + 'static,
+    S::Future: Send,
+    Req: prost::Message + Default + Send + 'static,
+    Res: prost::Message + Send + 'static,
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    Box::pin(async move {
+        let mut grpc = Grpc::new(ProstCodec::<Res, Req>::default());
+        Ok(grpc.unary(service, request).await)
+    })
+}
+
+/// Splits a key version's resource name in the mock's key ring into its key
+/// id and version
+fn key_version(mock: &MockKmsProvider, name: &str) -> Result<(String, u64), Status> {
+    let not_found = || Status::not_found(format!("{name} not found"));
+    let keys = format!("{}/cryptoKeys/", mock.key_ring_ref().to_google_ref());
+    let (key_id, version) = name
+        .strip_prefix(&keys)
+        .and_then(|key| key.split_once("/cryptoKeyVersions/"))
+        .ok_or_else(not_found)?;
+    let version = version.parse().map_err(|_| not_found())?;
+    Ok((key_id.to_string(), version))
+}
+
+async fn get_public_key(
+    mock: MockKmsProvider,
+    request: GetPublicKeyRequest,
+) -> Result<PublicKey, Status> {
+    let (key_id, version) = key_version(&mock, &request.name)?;
+    let pem = mock
+        .signing_key(&key_id, version)
+        .verifying_key()
+        .to_public_key_pem(LineEnding::LF)
+        .map_err(|e| Status::internal(e.to_string()))?;
+    Ok(PublicKey {
+        pem,
+        algorithm: CryptoKeyVersionAlgorithm::EcSignSecp256k1Sha256 as i32,
+        name: request.name,
+        ..Default::default()
+    })
+}
+
+async fn asymmetric_sign(
+    mock: MockKmsProvider,
+    request: AsymmetricSignRequest,
+) -> Result<AsymmetricSignResponse, Status> {
+    let (key_id, version) = key_version(&mock, &request.name)?;
+    let digest = match request.digest.and_then(|digest| digest.digest) {
+        Some(digest::Digest::Sha256(digest)) => <[u8; 32]>::try_from(digest)
+            .map_err(|_| Status::invalid_argument("SHA-256 digest must be 32 bytes"))?,
+        _ => return Err(Status::invalid_argument("key signs SHA-256 digests")),
+    };
+    let (signature, _) = mock
+        .sign_digest(&key_id, version, digest, &SigningContext::default())
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+    Ok(AsymmetricSignResponse {
+        signature,
+        name: request.name,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GcpKeyRingRef, GcpKmsSigner, KmsClient};
+    use ethers::signers::{LocalWallet, Signer};
+    use gcloud_sdk::GoogleApi;
+
+    async fn server() -> FakeKmsServer {
+        let mock = MockKmsProvider::new(GcpKeyRingRef::new("project", "global", "ring"))
+            .await
+            .unwrap();
+        FakeKmsServer::start(mock).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn provider_signs_through_the_server() {
+        let server = server().await;
+        let provider = server.provider().await.unwrap();
+        assert_eq!(provider.endpoint(), Some(server.endpoint().as_str()));
+
+        let wallet = LocalWallet::from(server.mock().signing_key("key", 1)).with_chain_id(5u64);
+        let signer = GcpKmsSigner::new(provider, "key".to_string(), 1, 5)
+            .await
+            .unwrap();
+        assert_eq!(signer.address(), wallet.address());
+        assert_eq!(
+            signer.sign_message("hello world").await.unwrap(),
+            wallet.sign_message("hello world").await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_other_key_rings_and_methods() {
+        let server = server().await;
+        let provider = server.provider().await.unwrap();
+
+        let other = GcpKmsProvider::new_with_config(
+            GcpKeyRingRef::new("project", "global", "other"),
+            server.config(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            other.get_verifying_key("key", 1).await,
+            Err(CKMSError::RequestError(status)) if status.code() == tonic::Code::NotFound
+        ));
+        assert!(matches!(
+            provider.get_crypto_key_version("key", 1).await,
+            Err(CKMSError::RequestError(status)) if status.code() == tonic::Code::Unimplemented
+        ));
+    }
+
+    #[tokio::test]
+    async fn provider_reuses_a_prebuilt_client() {
+        let server = server().await;
+        // loading these credentials makes no requests; exchanging them would
+        let user = r#"{"client_id": "id", "client_secret": "secret", "refresh_token": "token"}"#;
+        let client: GoogleApi<KmsClient> = GoogleApi::from_function_with_token_source(
+            |channel| KmsClient::new(channel.into()),
+            server.endpoint(),
+            None,
+            gcloud_sdk::GCP_DEFAULT_SCOPES.clone(),
+            gcloud_sdk::TokenSourceType::Json(user.to_string()),
+        )
+        .await
+        .unwrap();
+        let provider =
+            GcpKmsProvider::new_with_client(client, server.mock().key_ring_ref().clone());
+        assert_eq!(provider.endpoint(), None);
+        assert_eq!(provider.credential_source(), None);
+        assert!(provider.client().is_some());
+    }
+
+    #[tokio::test]
+    async fn provider_reuses_a_stock_gcloud_sdk_client() {
+        use gcloud_sdk::google::cloud::kms::v1::key_management_service_client::KeyManagementServiceClient;
+
+        let server = server().await;
+        let user = r#"{"client_id": "id", "client_secret": "secret", "refresh_token": "token"}"#;
+        let client = GoogleApi::from_function_with_token_source(
+            KeyManagementServiceClient::new,
+            server.endpoint(),
+            None,
+            gcloud_sdk::GCP_DEFAULT_SCOPES.clone(),
+            gcloud_sdk::TokenSourceType::Json(user.to_string()),
+        )
+        .await
+        .unwrap();
+        let provider =
+            GcpKmsProvider::new_with_client(client, server.mock().key_ring_ref().clone());
+        assert_eq!(provider.endpoint(), None);
+        assert!(provider.client().is_none());
+        assert_eq!(
+            provider.key_ring_ref().to_google_ref(),
+            server.mock().key_ring_ref().to_google_ref()
+        );
+    }
+
+    struct CountingTokens(std::sync::atomic::AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl crate::TokenProvider for CountingTokens {
+        async fn access_token(&self) -> Result<crate::ProvidedToken, CKMSError> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let expires_at = chrono::Utc::now() + chrono::Duration::hours(1);
+            Ok(crate::ProvidedToken::new("from-sts", expires_at))
+        }
+    }
+
+    #[tokio::test]
+    async fn authenticates_with_a_token_provider() {
+        let server = server().await;
+        let tokens = std::sync::Arc::new(CountingTokens(Default::default()));
+        let config = server
+            .config()
+            .with_credential_sources(vec![CredentialSource::TokenProvider(tokens.clone())]);
+        let provider =
+            GcpKmsProvider::new_with_config(server.mock().key_ring_ref().clone(), config)
+                .await
+                .unwrap();
+        assert!(matches!(
+            provider.credential_source(),
+            Some(CredentialSource::TokenProvider(_))
+        ));
+        provider.get_verifying_key("key", 1).await.unwrap();
+        assert!(tokens.0.load(std::sync::atomic::Ordering::SeqCst) >= 1);
+    }
+
+    #[tokio::test]
+    async fn runs_requests_through_interceptors() {
+        let server = server().await;
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counted = calls.clone();
+        let provider =
+            server
+                .provider()
+                .await
+                .unwrap()
+                .with_interceptor(move |mut request: Request<()>| {
+                    counted.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    request
+                        .metadata_mut()
+                        .insert("x-request-id", "test".parse().unwrap());
+                    Ok(request)
+                });
+        provider.get_verifying_key("key", 1).await.unwrap();
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let provider = provider.with_interceptor(|_| Err(Status::permission_denied("audit")));
+        assert!(matches!(
+            provider.get_verifying_key("key", 1).await,
+            Err(CKMSError::RequestError(status)) if status.code() == tonic::Code::PermissionDenied
+        ));
+    }
+
+    #[tokio::test]
+    async fn survives_a_panicking_interceptor() {
+        let server = server().await;
+        let panicked = std::sync::atomic::AtomicBool::new(false);
+        let provider =
+            server
+                .provider()
+                .await
+                .unwrap()
+                .with_interceptor(move |request: Request<()>| {
+                    if !panicked.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                        panic!("interceptor bug");
+                    }
+                    Ok(request)
+                });
+
+        let intercepted = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            provider.intercept(Request::new(()))
+        }));
+        assert!(intercepted.is_err());
+        provider.get_verifying_key("key", 1).await.unwrap();
+    }
+}
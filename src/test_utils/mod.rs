@@ -0,0 +1,232 @@
+//! An in-memory stand-in for Cloud KMS, for exercising signing paths in CI
+//! pipelines without GCP access. A [`MockKmsProvider`] is built like a
+//! [`GcpKmsProvider`](crate::GcpKmsProvider), from a [`GcpKeyRingRef`], and
+//! holds k256 keys instead of calling KMS; a [`MockKmsSigner`] over it is a
+//! [`GcpKmsSigner`] with every policy, scope and audit sink.
+//!
+//! Unlike KMS, the keys sign deterministically, with RFC 6979 nonces, so
+//! signatures can be compared with a
+//! [`LocalWallet`](ethers::signers::LocalWallet) of the same key.
+//!
+//! A [`FakeKmsServer`] serves a mock's keys over the KMS gRPC API on
+//! localhost, so a real [`GcpKmsProvider`](crate::GcpKmsProvider) can be
+//! pointed at it, and a [`KmsCassette`] records real KMS responses to a
+//! fixture file and replays them.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use ethers::{
+    prelude::k256::ecdsa::{signature::hazmat::PrehashSigner, Signature, SigningKey, VerifyingKey},
+    utils::keccak256,
+};
+
+use crate::{
+    CKMSError, GcpKeyRingRef, GcpKmsSigner, KeyBackend, KeyVersion, KmsKeyBackend, SigningContext,
+};
+
+mod cassette;
+pub use cassette::{KmsCassette, CASSETTE_MODE_VAR};
+
+mod fake_server;
+pub use fake_server::FakeKmsServer;
+
+/// A [`GcpKmsSigner`] over a [`MockKmsProvider`]. It is the same type, so
+/// code under test takes it unchanged:
+/// `MockKmsSigner::new(provider, key_id, 1, chain_id).await`.
+pub type MockKmsSigner = GcpKmsSigner;
+
+/// A key ring of in-memory keys. Key versions not given with
+/// [`MockKmsProvider::with_key`] are derived from their resource names, so
+/// each has a stable address across runs. Clones share their keys.
+#[derive(Clone, Debug)]
+pub struct MockKmsProvider {
+    kms_key_ref: GcpKeyRingRef,
+    keys: Arc<Mutex<BTreeMap<(String, u64), SigningKey>>>,
+}
+
+impl MockKmsProvider {
+    /// Creates a mock of the key ring. This never fails; it returns a
+    /// `Result` to be shaped like [`GcpKmsProvider::new`](crate::GcpKmsProvider::new).
+    pub async fn new(kms_key_ref: GcpKeyRingRef) -> Result<Self, CKMSError> {
+        Ok(Self {
+            kms_key_ref,
+            keys: Arc::default(),
+        })
+    }
+
+    /// Returns the key ring this provider mocks
+    pub fn key_ring_ref(&self) -> &GcpKeyRingRef {
+        &self.kms_key_ref
+    }
+
+    /// Sets the private key of a key version, e.g. to match a fixture
+    pub fn with_key(self, key_id: &str, key_version: u64, key: SigningKey) -> Self {
+        self.keys
+            .lock()
+            .unwrap()
+            .insert((key_id.to_string(), key_version), key);
+        self
+    }
+
+    /// Returns the private key of a key version, for building the local
+    /// signer a test compares against
+    pub fn signing_key(&self, key_id: &str, key_version: u64) -> SigningKey {
+        let mut keys = self.keys.lock().unwrap();
+        keys.entry((key_id.to_string(), key_version))
+            .or_insert_with(|| {
+                let name = self.kms_key_ref.to_key_version_ref(key_id, key_version);
+                // a keccak256 digest is a valid scalar but for negligible odds
+                SigningKey::from_slice(&keccak256(name)).expect("derived key is a valid scalar")
+            })
+            .clone()
+    }
+}
+
+impl From<MockKmsProvider> for KeyBackend {
+    fn from(provider: MockKmsProvider) -> Self {
+        Arc::new(provider).into()
+    }
+}
+
+#[async_trait]
+impl KmsKeyBackend for MockKmsProvider {
+    async fn get_public_key(
+        &self,
+        key_id: &str,
+        key_version: u64,
+    ) -> Result<VerifyingKey, CKMSError> {
+        Ok(*self.signing_key(key_id, key_version).verifying_key())
+    }
+
+    async fn sign_digest(
+        &self,
+        key_id: &str,
+        key_version: u64,
+        digest: [u8; 32],
+        _: &SigningContext,
+    ) -> Result<(Vec<u8>, u64), CKMSError> {
+        let signature: Signature = self
+            .signing_key(key_id, key_version)
+            .sign_prehash(&digest)?;
+        Ok((signature.to_der().as_bytes().to_vec(), key_version))
+    }
+
+    /// Resolves `latest` and `latest-enabled` to the highest version given
+    /// with [`MockKmsProvider::with_key`] or used so far, or else 1
+    async fn resolve_key_version(
+        &self,
+        key_id: &str,
+        key_version: KeyVersion,
+    ) -> Result<u64, CKMSError> {
+        if let Some(version) = key_version.as_pinned() {
+            return Ok(version);
+        }
+        let keys = self.keys.lock().unwrap();
+        Ok(keys
+            .range((key_id.to_string(), 0)..=(key_id.to_string(), u64::MAX))
+            .map(|((_, version), _)| *version)
+            .next_back()
+            .unwrap_or(1))
+    }
+
+    fn key_name(&self, key_id: &str) -> String {
+        self.kms_key_ref.to_crypto_key_ref(key_id)
+    }
+
+    fn key_version_name(&self, key_id: &str, key_version: u64) -> String {
+        self.kms_key_ref.to_key_version_ref(key_id, key_version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::TransactionRequest,
+    };
+
+    async fn mock() -> MockKmsProvider {
+        MockKmsProvider::new(GcpKeyRingRef::new("project", "global", "ring"))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn signs_like_a_local_wallet() {
+        let provider = mock().await;
+        let wallet = LocalWallet::from(provider.signing_key("key", 1)).with_chain_id(5u64);
+        let signer = MockKmsSigner::new(provider, "key".to_string(), 1, 5)
+            .await
+            .unwrap();
+        assert_eq!(signer.address(), wallet.address());
+        assert_eq!(
+            signer.key_name(),
+            "projects/project/locations/global/keyRings/ring/cryptoKeys/key"
+        );
+
+        let message = b"hello world";
+        assert_eq!(
+            signer.sign_message(message).await.unwrap(),
+            wallet.sign_message(message).await.unwrap()
+        );
+        let tx = TransactionRequest::new()
+            .to(wallet.address())
+            .value(1)
+            .nonce(0)
+            .gas(21_000)
+            .gas_price(1)
+            .into();
+        assert_eq!(
+            signer.sign_transaction(&tx).await.unwrap(),
+            wallet.sign_transaction(&tx).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn keys_are_stable_and_distinct() {
+        let provider = mock().await;
+        let address = |key: SigningKey| LocalWallet::from(key).address();
+        assert_eq!(
+            address(provider.signing_key("key", 1)),
+            address(mock().await.signing_key("key", 1))
+        );
+        assert_ne!(
+            address(provider.signing_key("key", 1)),
+            address(provider.signing_key("key", 2))
+        );
+        assert_ne!(
+            address(provider.signing_key("key", 1)),
+            address(provider.signing_key("other", 1))
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_the_latest_version() {
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let provider = mock().await.with_key("key", 3, key.clone());
+        provider.signing_key("key", 2);
+        provider.signing_key("other", 7);
+        let signer = MockKmsSigner::new_with_key_version(
+            provider.clone(),
+            "key".to_string(),
+            KeyVersion::LatestEnabled,
+            1,
+        )
+        .await
+        .unwrap();
+        assert_eq!(signer.key_version(), 3);
+        assert_eq!(signer.address(), LocalWallet::from(key).address());
+        assert_eq!(
+            provider
+                .resolve_key_version("new", KeyVersion::Latest)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! Known-answer test vectors for the conversion of KMS signatures to
+//! Ethereum ones: DER parsing, low-s normalization, recovery id selection and
+//! EIP-155 `v` values. Each vector is a DER signature, as KMS returns it,
+//! over a digest, with the `r`, `s` and `v` this crate must derive from it
+//! and the address of the signing key. The vectors were computed with an
+//! independent secp256k1 implementation.
+//!
+//! [`TestVector::check`] runs a vector through this crate's conversion, for
+//! auditors; [`check_backend`] signs every vector's digest through a
+//! [`GcpKmsSigner`] over any [`KmsKeyBackend`](crate::KmsKeyBackend), for
+//! conformance tests of downstream backends.
+
+use ethers::{
+    prelude::k256::ecdsa::VerifyingKey,
+    signers::Signer,
+    types::{Address, Signature, H256, U256},
+    utils::hex,
+};
+
+use crate::{
+    apply_eip155, sig_from_digest_bytes_trial_recovery, verifying_key_to_address, CKMSError,
+    GcpKmsSigner, KeyBackend, KmsSignature,
+};
+
+/// Half the secp256k1 group order, the largest low-s `s`
+const HALF_ORDER: &str = "7fffffffffffffffffffffffffffffff5d576e7357a4501ddfe92f46681b20a0";
+
+/// A KMS signature and the Ethereum signature it converts to. Fields are
+/// hex encoded without a `0x` prefix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TestVector {
+    pub name: &'static str,
+    /// The signing key, as uncompressed SEC1
+    pub public_key: &'static str,
+    pub address: &'static str,
+    pub digest: &'static str,
+    /// The signature as KMS returns it, which may be high-s
+    pub der_signature: &'static str,
+    /// The chain whose EIP-155 `v` is expected, or `None` for 27/28
+    pub chain_id: Option<u64>,
+    pub r: &'static str,
+    /// The low-s form of the signature's `s`
+    pub s: &'static str,
+    pub v: u64,
+}
+
+/// The vectors: low-s signatures of either parity, a high-s signature, `v`
+/// for mainnet and a large chain id, and an `r` short of 32 bytes, which DER
+/// encodes in fewer bytes
+pub const VECTORS: &[TestVector] = &[
+    TestVector {
+        name: "low_s_even_y",
+        public_key: "0417b4aa7f75c763671a267c0435c1166c4f0f85f002619bf75bdb3e49da97b9d166467da6272ffd8b0c28370463f6aa3bf90cb72ea536a3a9f83d5c0a97691514",
+        address: "7f4eab3304ba3c2cdc7fd044ae4002a532b4b427",
+        digest: "17d79958a99572dd92c9b81876fae7c07401be0b2fbf076bc78c96c2c2d7d00f",
+        der_signature: "304502210080529e659d196884b15e95ef871e5fd88fb5298f3bc7831b50a0bc984cb5fe0a02204ce8f56ed510b7238b35ca3de086d0079879903ce9ff9f9a2e5f115fc6bb6456",
+        chain_id: None,
+        r: "80529e659d196884b15e95ef871e5fd88fb5298f3bc7831b50a0bc984cb5fe0a",
+        s: "4ce8f56ed510b7238b35ca3de086d0079879903ce9ff9f9a2e5f115fc6bb6456",
+        v: 27,
+    },
+    TestVector {
+        name: "low_s_odd_y",
+        public_key: "0417b4aa7f75c763671a267c0435c1166c4f0f85f002619bf75bdb3e49da97b9d166467da6272ffd8b0c28370463f6aa3bf90cb72ea536a3a9f83d5c0a97691514",
+        address: "7f4eab3304ba3c2cdc7fd044ae4002a532b4b427",
+        digest: "a106fbc1df5cb021a638e37ee813ccd5ac69d36d61b70f6cf54258343e4eb8e2",
+        der_signature: "30450221008d3f06b158ddd609f83b0531466fc2a3da6aa80b433a92ddeeb20435cf33ddae0220760501db6971e61ecf97a5da2ee86b0fa0f4aea0baeeb7d4487654e7089ebf90",
+        chain_id: None,
+        r: "8d3f06b158ddd609f83b0531466fc2a3da6aa80b433a92ddeeb20435cf33ddae",
+        s: "760501db6971e61ecf97a5da2ee86b0fa0f4aea0baeeb7d4487654e7089ebf90",
+        v: 28,
+    },
+    TestVector {
+        name: "high_s_normalized",
+        public_key: "0417b4aa7f75c763671a267c0435c1166c4f0f85f002619bf75bdb3e49da97b9d166467da6272ffd8b0c28370463f6aa3bf90cb72ea536a3a9f83d5c0a97691514",
+        address: "7f4eab3304ba3c2cdc7fd044ae4002a532b4b427",
+        digest: "a61eec500e9dc9ad9f23dc2edda79058708554ee1a8844ac100867a3ddddb2fa",
+        der_signature: "3046022100ed214e8ce499d92a2085e7e6041b4f081c7d29d8770057fc705a131d2918fcdb022100d858d24018e9c01d1c474228c248027de204ca326e8e519d21a0601874ab5a6b",
+        chain_id: None,
+        r: "ed214e8ce499d92a2085e7e6041b4f081c7d29d8770057fc705a131d2918fcdb",
+        s: "27a72dbfe7163fe2e3b8bdd73db7fd80d8aa12b440ba4e9e9e31fe745b8ae6d6",
+        v: 27,
+    },
+    TestVector {
+        name: "eip155_mainnet",
+        public_key: "04ee8e127d054dbf8004f86873b8abf4e809695023641c8b03630b65bfe718465c30baf1df1f026bf803dec7e08b4a9535c0aaf8e1564109be7c33f5526652f347",
+        address: "14913ed7d5ecce73bff411c4a8f8e1a323b22a8b",
+        digest: "c4dcf8c5cab92d6fb0acba9adc148c74c18bd6e46d23641e15d296306e8ff69b",
+        der_signature: "30450220609ae8d31e3b290e74483776c1c8dfc2756b87d9635d654eb9e1ca95c228b1690221009c18d627233bd59826010368b40234595240d35fab456d138f0d1752db31d96d",
+        chain_id: Some(1),
+        r: "609ae8d31e3b290e74483776c1c8dfc2756b87d9635d654eb9e1ca95c228b169",
+        s: "63e729d8dcc42a67d9fefc974bfdcba5686e09870403332830c54739f50467d4",
+        v: 38,
+    },
+    TestVector {
+        name: "eip155_sepolia",
+        public_key: "04ee8e127d054dbf8004f86873b8abf4e809695023641c8b03630b65bfe718465c30baf1df1f026bf803dec7e08b4a9535c0aaf8e1564109be7c33f5526652f347",
+        address: "14913ed7d5ecce73bff411c4a8f8e1a323b22a8b",
+        digest: "dcc0e99b0086540605bf280bf2e821f8daa249008c1dbb389d1cd7bf00b49e67",
+        der_signature: "3046022100ffe558e388852f0120e46af2d1b370f85854a8eb0841811ece0e3e03d282d57c022100b0ec8d8ebcae19d02bd613d79969c387a29ef0279fcc96883b98c055fe3b262c",
+        chain_id: Some(11155111),
+        r: "ffe558e388852f0120e46af2d1b370f85854a8eb0841811ece0e3e03d282d57c",
+        s: "4f1372714351e62fd429ec2866963c77180fecbf0f7c09b384399e36d1fb1b15",
+        v: 22310258,
+    },
+    TestVector {
+        name: "short_r",
+        public_key: "04ee8e127d054dbf8004f86873b8abf4e809695023641c8b03630b65bfe718465c30baf1df1f026bf803dec7e08b4a9535c0aaf8e1564109be7c33f5526652f347",
+        address: "14913ed7d5ecce73bff411c4a8f8e1a323b22a8b",
+        digest: "84c09cfed75791ae7d54bfece345448d47f0d7f30a6a82481e07115aa60ac593",
+        der_signature: "3043021f421e68e474db2b4c2fe92a69f9c996f27448de8e66380b855fe56a21ae660c022065c40f6f93ab78efe727a6801d2eb99ad7c24f9ce74fda8765f9d2b013e8a74f",
+        chain_id: None,
+        r: "00421e68e474db2b4c2fe92a69f9c996f27448de8e66380b855fe56a21ae660c",
+        s: "65c40f6f93ab78efe727a6801d2eb99ad7c24f9ce74fda8765f9d2b013e8a74f",
+        v: 28,
+    },
+];
+
+fn decode(field: &str) -> Vec<u8> {
+    hex::decode(field).expect("test vector fields are hex")
+}
+
+impl TestVector {
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey::from_sec1_bytes(&decode(self.public_key)).expect("SEC1 public key")
+    }
+
+    pub fn address(&self) -> Address {
+        Address::from_slice(&decode(self.address))
+    }
+
+    pub fn digest(&self) -> [u8; 32] {
+        H256::from_slice(&decode(self.digest)).0
+    }
+
+    pub fn der_signature(&self) -> Vec<u8> {
+        decode(self.der_signature)
+    }
+
+    /// The Ethereum signature this crate must derive from the vector
+    pub fn signature(&self) -> Signature {
+        Signature {
+            r: U256::from_big_endian(&decode(self.r)),
+            s: U256::from_big_endian(&decode(self.s)),
+            v: self.v,
+        }
+    }
+
+    /// Converts the DER signature as [`GcpKmsSigner`] does, failing with
+    /// [`CKMSError::TestVectorFailed`] unless it gives the expected address
+    /// and signature
+    pub fn check(&self) -> Result<(), CKMSError> {
+        let verifying_key = self.verifying_key();
+        let address = verifying_key_to_address(&verifying_key);
+        if address != self.address() {
+            return Err(self.failed(format!("key has address {address:?}")));
+        }
+
+        let sig = KmsSignature::from_der(&self.der_signature())?;
+        let mut signature =
+            sig_from_digest_bytes_trial_recovery(&sig.normalized, self.digest(), &verifying_key)?;
+        match self.chain_id {
+            Some(chain_id) => apply_eip155(&mut signature, chain_id)?,
+            None => signature.v += 27,
+        }
+        if signature != self.signature() {
+            return Err(self.failed(format!("converted to {signature:?}")));
+        }
+        Ok(())
+    }
+
+    fn failed(&self, reason: String) -> CKMSError {
+        CKMSError::TestVectorFailed(format!("{}: {reason}", self.name))
+    }
+}
+
+/// Signs the digest of every vector with a key version of `backend`,
+/// through a [`GcpKmsSigner`] for the vector's chain, and checks that the
+/// signature is low-s and recovers to the key's address, with both a 27/28
+/// and, for vectors with a chain id, an EIP-155 `v`. The backend's key
+/// differs from the vectors', so only these properties, not the expected
+/// signatures, are checked.
+pub async fn check_backend(
+    backend: impl Into<KeyBackend>,
+    key_id: &str,
+    key_version: u64,
+) -> Result<(), CKMSError> {
+    let backend = backend.into();
+    let half_order = U256::from_big_endian(&decode(HALF_ORDER));
+    for vector in VECTORS {
+        let chain_id = vector.chain_id.unwrap_or(1);
+        let signer =
+            GcpKmsSigner::new(backend.clone(), key_id.to_string(), key_version, chain_id).await?;
+        let digest = H256(vector.digest());
+        let signature = signer.sign_hash(digest).await?;
+        if signature.s > half_order {
+            return Err(vector.failed(format!("backend signature {signature:?} is high-s")));
+        }
+        let mut signatures = vec![signature];
+        if let Some(chain_id) = vector.chain_id {
+            let mut eip155 = Signature {
+                v: signature.v - 27,
+                ..signature
+            };
+            apply_eip155(&mut eip155, chain_id)?;
+            signatures.push(eip155);
+        }
+        for signature in signatures {
+            if signature.recover(digest).ok() != Some(signer.address()) {
+                return Err(vector.failed(format!(
+                    "backend signature {signature:?} does not recover to {:?}",
+                    signer.address()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CKMSError, KmsKeyBackend, SigningContext};
+    use async_trait::async_trait;
+    use ethers::prelude::k256::ecdsa::{
+        signature::hazmat::PrehashSigner, Signature as KSig, SigningKey,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn vectors_pass() {
+        for vector in VECTORS {
+            vector.check().unwrap();
+        }
+    }
+
+    #[test]
+    fn wrong_answers_fail() {
+        let wrong_v = TestVector {
+            v: VECTORS[0].v ^ 1,
+            ..VECTORS[0]
+        };
+        assert!(matches!(
+            wrong_v.check(),
+            Err(CKMSError::TestVectorFailed(reason)) if reason.starts_with("low_s_even_y")
+        ));
+        let wrong_key = TestVector {
+            public_key: VECTORS[3].public_key,
+            ..VECTORS[0]
+        };
+        assert!(wrong_key.check().is_err());
+    }
+
+    #[derive(Debug)]
+    struct LocalBackend(SigningKey);
+
+    #[async_trait]
+    impl KmsKeyBackend for LocalBackend {
+        async fn get_public_key(&self, _: &str, _: u64) -> Result<VerifyingKey, CKMSError> {
+            Ok(*self.0.verifying_key())
+        }
+
+        async fn sign_digest(
+            &self,
+            _: &str,
+            key_version: u64,
+            digest: [u8; 32],
+            _: &SigningContext,
+        ) -> Result<(Vec<u8>, u64), CKMSError> {
+            let signature: KSig = self.0.sign_prehash(&digest)?;
+            Ok((signature.to_der().as_bytes().to_vec(), key_version))
+        }
+    }
+
+    #[tokio::test]
+    async fn local_backend_conforms() {
+        let backend = LocalBackend(SigningKey::from_slice(&[7; 32]).unwrap());
+        check_backend(Arc::new(backend), "key", 1).await.unwrap();
+    }
+}
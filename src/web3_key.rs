@@ -0,0 +1,121 @@
+//! A [`web3::signing::Key`] for services still on the `web3` crate, e.g.
+//! for `Accounts::sign_transaction`.
+//!
+//! web3's keys sign synchronously, so [`Web3Key`] blocks a tokio worker
+//! thread on KMS with [`tokio::task::block_in_place`], which panics on a
+//! current-thread runtime. web3's signing errors cannot carry KMS errors,
+//! which are logged and reported as
+//! [`SigningError::InvalidMessage`](web3::signing::SigningError), and
+//! `Accounts::sign_transaction` panics when a typed transaction's signature
+//! fails.
+
+use std::future::Future;
+
+use ethers::types::{Signature, H256};
+use tokio::runtime::Handle;
+use tracing::warn;
+use web3::{
+    signing::{Key, SigningError},
+    types::Address,
+};
+
+use crate::{apply_eip155, CKMSError, GcpKmsSigner};
+
+/// A [`GcpKmsSigner`] as a web3 [`Key`], made by [`GcpKmsSigner::web3_key`].
+/// Signatures are of bare digests, which scopes and audit sinks see as
+/// [`AuditOperation::Digest`](crate::audit::AuditOperation::Digest).
+#[derive(Clone, Debug)]
+pub struct Web3Key {
+    signer: GcpKmsSigner,
+    address: Address,
+    handle: Handle,
+}
+
+impl GcpKmsSigner {
+    /// Resolves the signer's address, and adapts it to a web3 [`Key`] which
+    /// signs on the current tokio runtime
+    pub async fn web3_key(&self) -> Result<Web3Key, CKMSError> {
+        let address = self.resolve_address().await?;
+        Ok(Web3Key {
+            signer: self.clone(),
+            address: Address::from(address.0),
+            handle: Handle::current(),
+        })
+    }
+}
+
+impl Web3Key {
+    fn sign_hash(&self, message: &[u8]) -> Result<Signature, SigningError> {
+        let hash = <[u8; 32]>::try_from(message).map_err(|_| SigningError::InvalidMessage)?;
+        self.block_on(self.signer.sign_hash(H256(hash)))
+    }
+
+    fn block_on<T>(
+        &self,
+        sign: impl Future<Output = Result<T, CKMSError>>,
+    ) -> Result<T, SigningError> {
+        tokio::task::block_in_place(|| self.handle.block_on(sign)).map_err(|e| {
+            warn!(address = ?self.address, "KMS signing for web3 failed: {e}");
+            SigningError::InvalidMessage
+        })
+    }
+}
+
+fn to_web3(signature: &Signature) -> web3::signing::Signature {
+    let mut r = [0; 32];
+    let mut s = [0; 32];
+    signature.r.to_big_endian(&mut r);
+    signature.s.to_big_endian(&mut s);
+    web3::signing::Signature {
+        v: signature.v,
+        r: r.into(),
+        s: s.into(),
+    }
+}
+
+impl Key for Web3Key {
+    /// Signs a 32-byte hash with `v` = 27/28, or the EIP-155 value for
+    /// `chain_id`
+    fn sign(
+        &self,
+        message: &[u8],
+        chain_id: Option<u64>,
+    ) -> Result<web3::signing::Signature, SigningError> {
+        let mut signature = self.sign_hash(message)?;
+        if let Some(chain_id) = chain_id {
+            signature.v -= 27;
+            apply_eip155(&mut signature, chain_id).map_err(|_| SigningError::InvalidMessage)?;
+        }
+        Ok(to_web3(&signature))
+    }
+
+    /// Signs a 32-byte hash with `v` = the bare recovery id, as typed
+    /// transactions take it
+    fn sign_message(&self, message: &[u8]) -> Result<web3::signing::Signature, SigningError> {
+        let mut signature = self.sign_hash(message)?;
+        signature.v -= 27;
+        Ok(to_web3(&signature))
+    }
+
+    fn address(&self) -> Address {
+        self.address
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_signatures_to_web3s() {
+        let signature = Signature {
+            r: [0x11; 32].into(),
+            s: [0x22; 32].into(),
+            v: 27,
+        };
+        let converted = to_web3(&signature);
+        assert_eq!(converted.r, web3::types::H256::repeat_byte(0x11));
+        assert_eq!(converted.s, web3::types::H256::repeat_byte(0x22));
+        assert_eq!(converted.v, 27);
+    }
+}